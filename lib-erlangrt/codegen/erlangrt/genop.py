@@ -100,6 +100,15 @@ class OTP22(OTPConfig):
                    biftype=btype)
 
 
+class OTP24(OTP22):
+    """ Same native function table format as OTP22, more opcodes """
+    def __init__(self):
+        OTPConfig.__init__(self, min_opcode=1, max_opcode=176,
+                           atoms_tab="atoms.tab",
+                           bif_tab="implemented_native_funs.tab",
+                           genop_tab="otp24/genop.tab")
+
+
 class Genop:
     def __init__(self, name: str, arity: int, opcode: int):
         self.name = name
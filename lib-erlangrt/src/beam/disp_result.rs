@@ -5,6 +5,11 @@ pub enum YieldType {
   EndOfTheQueue,
   /// The process gives up running for infinite receive or a similar reason.
   InfiniteWait,
+  /// The process waits in receive with a timeout (see `wait_timeout`).
+  TimedWait,
+  /// The process has called `erlang:hibernate/3` and waits for a message (see
+  /// `Process::hibernate`).
+  Hibernate,
 }
 
 /// Enum is used by VM dispatch handlers for opcodes to indicate whether to
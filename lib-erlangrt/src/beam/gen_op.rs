@@ -1,13 +1,13 @@
 //! Generated by `codegen/create_gen_op.py`
 //! Maps genop table from Erlang/OTP source to Rust
-//! Config used: OTP22
+//! Config used: OTP24
 #![allow(dead_code)]
 
 use crate::defs::Word;
 use crate::emulator::code::opcode::RawOpcode;
 
 
-pub const OPCODE_MAX: RawOpcode = RawOpcode(168);
+pub const OPCODE_MAX: RawOpcode = RawOpcode(176);
 
 pub static ARITY_MAP: &'static [u8] = &[
     0, // opcode 0 does not exist
@@ -179,6 +179,14 @@ pub static ARITY_MAP: &'static [u8] = &[
     4, // opcode: 166 (bs_start_match3)
     3, // opcode: 167 (bs_get_position)
     2, // opcode: 168 (bs_set_position)
+    2, // opcode: 169 (swap)
+    4, // opcode: 170 (bs_start_match4)
+    3, // opcode: 171 (make_fun3)
+    1, // opcode: 172 (init_yregs)
+    2, // opcode: 173 (recv_marker_bind)
+    1, // opcode: 174 (recv_marker_clear)
+    1, // opcode: 175 (recv_marker_reserve)
+    1, // opcode: 176 (recv_marker_use)
 ];
 
 #[inline]
@@ -356,6 +364,14 @@ const OPCODE_NAME_MAP: &[&str] = &[
     "bs_start_match3", // opcode: 166
     "bs_get_position", // opcode: 167
     "bs_set_position", // opcode: 168
+    "swap", // opcode: 169
+    "bs_start_match4", // opcode: 170
+    "make_fun3", // opcode: 171
+    "init_yregs", // opcode: 172
+    "recv_marker_bind", // opcode: 173
+    "recv_marker_clear", // opcode: 174
+    "recv_marker_reserve", // opcode: 175
+    "recv_marker_use", // opcode: 176
 ];
 
 pub fn opcode_name(opcode: RawOpcode) -> &'static str {
@@ -530,5 +546,13 @@ pub const OPCODE_BS_GET_TAIL: RawOpcode = RawOpcode(165);
 pub const OPCODE_BS_START_MATCH3: RawOpcode = RawOpcode(166);
 pub const OPCODE_BS_GET_POSITION: RawOpcode = RawOpcode(167);
 pub const OPCODE_BS_SET_POSITION: RawOpcode = RawOpcode(168);
+pub const OPCODE_SWAP: RawOpcode = RawOpcode(169);
+pub const OPCODE_BS_START_MATCH4: RawOpcode = RawOpcode(170);
+pub const OPCODE_MAKE_FUN3: RawOpcode = RawOpcode(171);
+pub const OPCODE_INIT_YREGS: RawOpcode = RawOpcode(172);
+pub const OPCODE_RECV_MARKER_BIND: RawOpcode = RawOpcode(173);
+pub const OPCODE_RECV_MARKER_CLEAR: RawOpcode = RawOpcode(174);
+pub const OPCODE_RECV_MARKER_RESERVE: RawOpcode = RawOpcode(175);
+pub const OPCODE_RECV_MARKER_USE: RawOpcode = RawOpcode(176);
 
 
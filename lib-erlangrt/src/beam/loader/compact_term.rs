@@ -165,9 +165,7 @@ impl CompactTermReader {
       x if x == CteExtTag::Float as u8 => self.parse_ext_float(),
       x if x == CteExtTag::FloatReg as u8 => self.parse_ext_fpreg(reader),
       x if x == CteExtTag::Literal as u8 => self.parse_ext_literal(reader),
-      x if x == CteExtTag::AllocList as u8 => {
-        panic!("Don't know how to decode an alloclist")
-      }
+      x if x == CteExtTag::AllocList as u8 => self.parse_ext_alloclist(reader),
       other => make_err(CompactTermError::BadExtendedTag(format!(
         "Ext tag {} unknown",
         other
@@ -187,9 +185,7 @@ impl CompactTermReader {
 
       // float does not exist after R19
       // x if x == CTEExtTag::Float as u8 => parse_ext_float(hp, r),
-      x if x == CteExtTag::AllocList as u8 => {
-        panic!("Don't know how to decode an alloclist");
-      }
+      x if x == CteExtTag::AllocList as u8 => self.parse_ext_alloclist(reader),
       x if x == CteExtTag::FloatReg as u8 => self.parse_ext_fpreg(reader),
       x if x == CteExtTag::Literal as u8 => self.parse_ext_literal(reader),
       other => {
@@ -228,6 +224,26 @@ impl CompactTermReader {
     Self::make_err(CompactTermError::BadExtendedTag(msg))
   }
 
+  /// Parses an allocation list `{alloc,[{words,W},{floats,F}]}` into a single
+  /// heap word count, where each float takes the size of a boxed float.
+  fn parse_ext_alloclist(&mut self, reader: &mut BinaryReader) -> RtResult<Term> {
+    let n_items = self.read_int(reader)?;
+    let mut n_words = 0;
+    for _ in 0..n_items {
+      let item_type = self.read_int(reader)?;
+      let count = self.read_int(reader)? as usize;
+      n_words += match item_type {
+        0 => count,
+        1 => count * boxed::Float::storage_size().words,
+        other => {
+          let msg = format!("Alloc list item type {} unknown", other);
+          return Self::make_err(CompactTermError::BadExtendedTag(msg));
+        }
+      };
+    }
+    Ok(Term::make_small_unsigned(n_words))
+  }
+
   fn parse_list_as_tuple_initializer(
     &mut self,
     reader: &mut BinaryReader,
@@ -344,3 +360,21 @@ impl CompactTermReader {
     Ok(Term::make_small_signed(n))
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::emulator::heap::Designation;
+
+  #[test]
+  fn test_alloclist_counts_float_words() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let mut ctr = CompactTermReader::new(&mut hp);
+    // {alloc,[{words,3},{floats,2}]}
+    let bytes = vec![CteExtTag::AllocList as u8, 0x20, 0, 0x30, 0x10, 0x20];
+    let mut reader = BinaryReader::from_bytes(bytes);
+    let n_words = ctr.read(&mut reader).unwrap();
+    let float_words = boxed::Float::storage_size().words;
+    assert_eq!(n_words.get_small_unsigned(), 3 + 2 * float_words);
+  }
+}
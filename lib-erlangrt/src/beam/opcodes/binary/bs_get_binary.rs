@@ -47,7 +47,7 @@ impl OpcodeBsGetBinary2 {
       (*match_state).increase_offset(bit_size);
 
       // Return the slice (sub-binary) created
-      runtime_ctx.store_value((*slice).make_term(), dst, proc.get_heap_mut())?;
+      runtime_ctx.store_value((*slice).make_term()?, dst, proc.get_heap_mut())?;
     } else {
       // ignore error here, can't fail
       runtime_ctx
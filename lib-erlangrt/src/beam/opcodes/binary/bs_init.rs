@@ -27,13 +27,13 @@ define_opcode!(
 impl OpcodeBsInit2 {
   #[inline]
   fn bs_init2(
-    vm: &mut VM,
+    _vm: &mut VM,
     runtime_ctx: &mut Context,
     proc: &mut Process,
     fail: Term,
     sz: usize,
     words: usize,
-    _regs: usize,
+    regs: usize,
     _flags: usize,
     dst: Term,
   ) -> RtResult<DispatchResult> {
@@ -46,18 +46,17 @@ impl OpcodeBsInit2 {
       return Ok(DispatchResult::Normal);
     }
 
-    // Show intent to allocate memory; TODO: add GC related args, like live/regs
-    boxed::Binary::ensure_memory_for_binary(
-      vm,
-      proc.get_heap_mut(),
-      BitSize::with_bytes(sz),
-      WordSize::new(words),
-    )?;
-
+    // Ensure memory for the binary (or the reference to it, if it is large)
+    // and the extra words, GC if required
     let binary_size = BitSize::with_bytes(sz);
+    let need =
+      boxed::Binary::storage_size_for_creation(binary_size) + WordSize::new(words);
+    runtime_ctx.live = regs;
+    proc.ensure_heap(runtime_ctx, need, regs, &mut [])?;
+
     let bin = unsafe { boxed::Binary::create_into(binary_size, proc.get_heap_mut())? };
 
-    let bin_term = unsafe { (*bin).make_term() };
+    let bin_term = unsafe { (*bin).make_term()? };
     runtime_ctx.current_bin.reset(bin_term);
     runtime_ctx.store_value(bin_term, dst, proc.get_heap_mut())?;
     Ok(DispatchResult::Normal)
@@ -72,6 +72,7 @@ macro_rules! define_opcode {
 ///   literal_jumptable(n) - the value is a jumptable (special tuple with pairs)
 ///   cp_or_nil(n) - take a term and assert it is either a CP, or a NIL
 ///   yreg(n) - take a term and assert it is an Y register
+///   fpreg(n) - take a float register and get its index
 ///   binary_match_state(n) - extract and assert the boxed is a binary match state
 ///
 /// Example:
@@ -195,6 +196,18 @@ macro_rules! fetch_one_arg {
     );
   };
 
+  // Take a float register from IP, its index is used to access `ctx.fpregs`
+  (
+    $vmarg:ident, $ctxarg:ident, $procarg:ident, $arg_pos:expr,
+    fpreg($arg_ident:ident)
+  ) => {
+    let $arg_ident = {
+      let tmp = $ctxarg.op_arg_read_term_at($arg_pos);
+      debug_assert!(tmp.is_register_float(), "Expected a FP register, got {}", tmp);
+      tmp.get_reg_value()
+    };
+  };
+
   // Take a term from IP, and assert it is a binary match state
   (
     $vmarg:ident, $ctxarg:ident, $procarg:ident, $arg_pos:expr,
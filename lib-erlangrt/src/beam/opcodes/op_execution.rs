@@ -1,7 +1,7 @@
 //! Module implements opcodes related to execution control: Calls, jumps,
 //! returns etc.
 use crate::{
-  beam::disp_result::DispatchResult,
+  beam::disp_result::{DispatchResult, YieldType},
   defs::exc_type::ExceptionType,
   emulator::{
     gen_atoms,
@@ -186,7 +186,7 @@ fn generic_call_ext(
 
   match unsafe { boxed::Import::mut_from_term(dst_import) } {
     Ok(import_ptr) => unsafe {
-      if (*import_ptr).get_is_bif(&vm.code_server) {
+      if (*import_ptr).get_is_bif(&vm.code_server()) {
         // Perform a BIF application
         let cb_target = call_native_fun::CallBifTarget::ImportPointer(import_ptr);
         let native_dispatch_result = find_and_call_native_fun(
@@ -197,10 +197,15 @@ fn generic_call_ext(
           cb_target,
           args,
           Term::make_register_x(0),
-          true,
+          false,
         );
         if save_cp {
           return native_dispatch_result;
+        } else if let Ok(DispatchResult::Yield(YieldType::Hibernate)) =
+          native_dispatch_result
+        {
+          // The stack is about to be discarded, nothing to return to
+          return native_dispatch_result;
         } else {
           // Perform inline return like if it was a tail recursive call
           // Because tail call might happen on an empty stack, the return with
@@ -216,8 +221,7 @@ fn generic_call_ext(
         if save_cp {
           ctx.cp = ctx.ip; // Points at the next opcode after this
         }
-        let code_srv = vm.get_code_server_p();
-        let import_dst = (*import_ptr).resolve(&mut (*code_srv))?;
+        let import_dst = vm.lookup_beam_code_and_load(&(*import_ptr).mfarity)?;
         ctx.jump_ptr(import_dst.get_pointer());
         Ok(DispatchResult::Normal)
       }
@@ -0,0 +1,138 @@
+//! Module implements opcodes for floating point arithmetic on the float
+//! registers `ctx.fpregs`. The compiler brackets a sequence of float ops with
+//! `fclearerror` and `fcheckerror`. Here each op checks its own result instead
+//! and raises `badarith` if it is not finite, so those two have nothing to do.
+use crate::{
+  beam::disp_result::DispatchResult,
+  emulator::{arith::float, process::Process, runtime_ctx::Context},
+  fail::{self, RtResult},
+  term::value::*,
+};
+
+// Reset the floating point error state.
+// Structure: fclearerror()
+define_opcode!(_vm, _ctx, _curr_p,
+  name: OpcodeFclearerror, arity: 0,
+  run: { Ok(DispatchResult::Normal) },
+  args:
+);
+
+// Check the floating point error state, errors were already raised by the
+// float ops.
+// Structure: fcheckerror(fail:CP)
+define_opcode!(_vm, _ctx, _curr_p,
+  name: OpcodeFcheckerror, arity: 1,
+  run: { Ok(DispatchResult::Normal) },
+  args: IGNORE(fail),
+);
+
+// Move a float from a boxed float (a literal, a register or a stack cell) into
+// a float register, or from a float register into a register or a stack cell.
+// Structure: fmove(src:src, dst:dst)
+define_opcode!(_vm, ctx, curr_p,
+  name: OpcodeFmove, arity: 2,
+  run: { Self::fmove(ctx, curr_p, src, dst) },
+  args: term(src), term(dst),
+);
+
+impl OpcodeFmove {
+  #[inline]
+  fn fmove(
+    ctx: &mut Context,
+    curr_p: &mut Process,
+    src: Term,
+    dst: Term,
+  ) -> RtResult<DispatchResult> {
+    if src.is_register_float() {
+      let val = ctx.fpregs[src.get_reg_value()];
+      if dst.is_register_float() {
+        ctx.fpregs[dst.get_reg_value()] = val;
+      } else {
+        let hp = curr_p.get_heap_mut();
+        let boxed = Term::make_float(hp, val)?;
+        ctx.store_value(boxed, dst, hp)?;
+      }
+    } else {
+      debug_assert!(dst.is_register_float(), "fmove: expected a FP register dst");
+      let val = ctx.load(src, curr_p.get_heap()).get_float()?;
+      ctx.fpregs[dst.get_reg_value()] = val;
+    }
+    Ok(DispatchResult::Normal)
+  }
+}
+
+// Convert a number to a float and store it in a float register. A bignum
+// too large for a float is a badarith.
+// Structure: fconv(src:src, dst:fpreg)
+define_opcode!(_vm, ctx, curr_p,
+  name: OpcodeFconv, arity: 2,
+  run: {
+    match float::to_f64(src) {
+      Some(val) if val.is_finite() => ctx.fpregs[dst] = val,
+      _ => return fail::create::badarith(),
+    }
+    Ok(DispatchResult::Normal)
+  },
+  args: load(src), fpreg(dst),
+);
+
+// Structure: fadd(fail:CP, a:fpreg, b:fpreg, dst:fpreg)
+define_opcode!(_vm, ctx, _curr_p,
+  name: OpcodeFadd, arity: 4,
+  run: {
+    let val = ctx.fpregs[a] + ctx.fpregs[b];
+    store_result(ctx, dst, val)
+  },
+  args: IGNORE(fail), fpreg(a), fpreg(b), fpreg(dst),
+);
+
+// Structure: fsub(fail:CP, a:fpreg, b:fpreg, dst:fpreg)
+define_opcode!(_vm, ctx, _curr_p,
+  name: OpcodeFsub, arity: 4,
+  run: {
+    let val = ctx.fpregs[a] - ctx.fpregs[b];
+    store_result(ctx, dst, val)
+  },
+  args: IGNORE(fail), fpreg(a), fpreg(b), fpreg(dst),
+);
+
+// Structure: fmul(fail:CP, a:fpreg, b:fpreg, dst:fpreg)
+define_opcode!(_vm, ctx, _curr_p,
+  name: OpcodeFmul, arity: 4,
+  run: {
+    let val = ctx.fpregs[a] * ctx.fpregs[b];
+    store_result(ctx, dst, val)
+  },
+  args: IGNORE(fail), fpreg(a), fpreg(b), fpreg(dst),
+);
+
+// Division by zero gives an infinity or a NaN, which is then a badarith.
+// Structure: fdiv(fail:CP, a:fpreg, b:fpreg, dst:fpreg)
+define_opcode!(_vm, ctx, _curr_p,
+  name: OpcodeFdiv, arity: 4,
+  run: {
+    let val = ctx.fpregs[a] / ctx.fpregs[b];
+    store_result(ctx, dst, val)
+  },
+  args: IGNORE(fail), fpreg(a), fpreg(b), fpreg(dst),
+);
+
+// Structure: fnegate(fail:CP, a:fpreg, dst:fpreg)
+define_opcode!(_vm, ctx, _curr_p,
+  name: OpcodeFnegate, arity: 3,
+  run: {
+    let val = -ctx.fpregs[a];
+    store_result(ctx, dst, val)
+  },
+  args: IGNORE(fail), fpreg(a), fpreg(dst),
+);
+
+/// Store a result of a float op, or raise `badarith` if it is not finite.
+#[inline]
+fn store_result(ctx: &mut Context, dst: usize, val: f64) -> RtResult<DispatchResult> {
+  if !val.is_finite() {
+    return fail::create::badarith();
+  }
+  ctx.fpregs[dst] = val;
+  Ok(DispatchResult::Normal)
+}
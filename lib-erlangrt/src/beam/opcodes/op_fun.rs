@@ -96,8 +96,7 @@ impl OpcodeApply {
   ) -> RtResult<DispatchResult> {
     let mfa = ModFunArity::new(ctx.get_x(arity), ctx.get_x(arity + 1), arity);
     ctx.live = arity + 2;
-    fixed_apply(vm, ctx, curr_p, &mfa, 0)?;
-    Ok(DispatchResult::Normal)
+    fixed_apply(vm, ctx, curr_p, &mfa, 0)
   }
 }
 
@@ -131,8 +130,7 @@ impl OpcodeApplyLast {
     ctx.live = arity + 2;
 
     let mfa = ModFunArity::new(module, function, arity);
-    fixed_apply(vm, ctx, curr_p, &mfa, dealloc)?;
-    Ok(DispatchResult::Normal)
+    fixed_apply(vm, ctx, curr_p, &mfa, dealloc)
   }
 }
 
@@ -144,18 +142,17 @@ fn fixed_apply(
   curr_p: &mut Process,
   mfa: &ModFunArity,
   dealloc: usize,
-) -> RtResult<()> {
+) -> RtResult<DispatchResult> {
   if mfa.m == gen_atoms::ERLANG && mfa.f == gen_atoms::APPLY && mfa.arity == 3 {
     panic!("TODO special handling for apply on apply/3");
   }
 
   println!("call_mfa {}", mfa);
-  let l_result = vm.code_server.lookup_mfa(mfa, true);
+  let l_result = vm.code_server_mut().lookup_mfa(mfa, true);
   if l_result.is_err() {
     return fail::create::undef();
   }
 
   let args = ctx.registers_slice(0, mfa.arity);
-  ctx.call_mfa(vm, curr_p, &l_result.unwrap(), args, dealloc == 0)?;
-  Ok(())
+  ctx.call_mfa(vm, curr_p, &l_result.unwrap(), args, dealloc == 0)
 }
@@ -2,7 +2,7 @@ use crate::{
   beam::disp_result::DispatchResult,
   defs::sizes::WordSize,
   emulator::{process::Process, runtime_ctx::Context},
-  fail::{RtErr, RtResult},
+  fail::RtResult,
   term::value::Term,
 };
 
@@ -18,22 +18,16 @@ fn gen_alloc(
 ) -> RtResult<()> {
   ctx.live = live;
 
-  let hp = curr_p.get_heap_mut();
-
-  if hp.allocate_intent(heap_need, live).is_err() {
-    return Err(RtErr::HeapIsFull("heap::gen_alloc"));
-  }
+  // Stack and heap share the same memory, so both requests are checked
+  // together, stack also needs one more word for the CP
+  let total_need = heap_need + stack_need + WordSize::one();
+  curr_p.ensure_heap(ctx, total_need, live, &mut [])?;
 
-  if hp.stack_check_available(stack_need + WordSize::one()) {
-    // Stack has enough words, we can allocate unchecked
-    if stack_need.words > 0 {
-      hp.stack_alloc_unchecked(stack_need, zero);
-    }
-    hp.stack_push_lterm_unchecked(ctx.cp.to_cp_term());
-  } else {
-    // Stack has not enough, invoke GC and possibly fail
-    return Err(RtErr::HeapIsFull("heap::gen_alloc/stack"));
+  let hp = curr_p.get_heap_mut();
+  if stack_need.words > 0 {
+    hp.stack_alloc_unchecked(stack_need, zero);
   }
+  hp.stack_push_lterm_unchecked(ctx.cp.to_cp_term());
   Ok(())
 }
 
@@ -96,10 +90,11 @@ define_opcode!(_vm, ctx, curr_p,
 // GC using `live` amount of registers as a part of root set.
 // Arg 'live' will be used for gc.
 // Structure: test_heap(heap_need:int, live:int)
-define_opcode!(_vm, _ctx, curr_p,
+define_opcode!(_vm, ctx, curr_p,
   name: OpcodeTestHeap, arity: 2,
   run: {
-    curr_p.get_heap_mut().allocate_intent(WordSize::new(heap_need), live)?;
+    ctx.live = live;
+    curr_p.ensure_heap(ctx, WordSize::new(heap_need), live, &mut [])?;
     Ok(DispatchResult::Normal)
   },
   args: usize(heap_need), usize(live),
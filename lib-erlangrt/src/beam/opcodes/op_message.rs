@@ -1,13 +1,20 @@
 use crate::{
   beam::disp_result::DispatchResult,
-  emulator::{process::Process, runtime_ctx::Context, vm::VM},
+  emulator::{
+    gen_atoms, mailbox::MarkKey, process::Process, runtime_ctx::Context,
+    timer_wheel::TimerAction, vm::VM,
+  },
   fail::{self, RtResult},
-  term::value::*,
+  term::{
+    boxed::{self, reference},
+    value::*,
+  },
 };
 use crate::beam::disp_result::YieldType;
 
 // Sends to x0 value x1, x1 is moved to x0 as result of the operation.
-// If process with pid x0 does not exist, no error is raised.
+// If process with pid x0 does not exist, no error is raised. If x0 is a name
+// which is not registered, the error is `badarg` (see `VM::send_message`).
 // Structure: send()
 define_opcode!(vm, ctx, _curr_p,
   name: OpcodeSend, arity: 0,
@@ -21,26 +28,16 @@ impl OpcodeSend {
     vm: &mut VM,
     ctx: &mut Context,
   ) -> RtResult<DispatchResult> {
-    // let sched = vm.get_scheduler_p();
     let x1 = ctx.get_x(1);
     let x0 = ctx.get_x(0);
-    if !x0.is_pid() {
-      return fail::create::badarg();
-    }
-    let p = vm.processes.unsafe_lookup_pid_mut(x0);
-    if !p.is_null() {
-      unsafe {
-        (*p).deliver_message(&mut vm.processes, x1)?;
-      }
-    }
-
+    vm.send_message(x0, x1)?;
     ctx.set_x(0, x1);
     Ok(DispatchResult::Normal)
   }
 }
 
-// Picks up next message in the message queue and places it into `x0`.
-// If there is no next message, jumps to `fail` label which points to a `wait`
+// Picks up the message under the save pointer and places it into `x0`.
+// If there is no such message, jumps to `fail` label which points to a `wait`
 // or `wait_timeout` instruction.
 // Structure: loop_rec(fail:cp, _source)
 define_opcode!(_vm, ctx, curr_p,
@@ -65,8 +62,8 @@ impl OpcodeLoopRec {
   }
 }
 
-// Advances message receive pointer to the next message then jumps to label
-// which points to a `loop_rec` instruction.
+// Advances the save pointer to the next message then jumps to label which
+// points to a `loop_rec` instruction.
 // Structure: loop_rec_end(label:cp)
 define_opcode!(_vm, ctx, curr_p,
   name: OpcodeLoopRecEnd, arity: 1,
@@ -81,23 +78,38 @@ impl OpcodeLoopRecEnd {
     curr_p: &mut Process,
     label: Term,
   ) -> RtResult<DispatchResult> {
-    curr_p.mailbox.step_over();
+    curr_p.mailbox.skip_current();
     ctx.jump(label);
     Ok(DispatchResult::Normal)
   }
 }
 
 // Removes the current message in the process message list and moves it to `x0`
+// and cancels the receive timer, if there was one. The next receive starts
+// from the first message.
 // Structure: remove_message()
-define_opcode!(_vm, ctx, curr_p,
+define_opcode!(vm, ctx, curr_p,
   name: OpcodeRemoveMessage, arity: 0,
-  run: {
+  run: { Self::remove_message(vm, ctx, curr_p) },
+  args:
+);
+
+impl OpcodeRemoveMessage {
+  #[inline]
+  pub fn remove_message(
+    vm: &mut VM,
+    ctx: &mut Context,
+    curr_p: &mut Process,
+  ) -> RtResult<DispatchResult> {
     let message = curr_p.mailbox.remove_current();
     ctx.set_x(0, message);
+    if let Some(timer) = curr_p.receive_timer.take() {
+      vm.scheduler.timers().cancel(timer);
+      curr_p.mailbox.clear_timeout();
+    }
     Ok(DispatchResult::Normal)
-  },
-  args:
-);
+  }
+}
 
 // Suspends the current process and sets the ip to the label (beginning of the
 // receive loop).
@@ -118,3 +130,145 @@ impl OpcodeWait {
     Ok(DispatchResult::Yield(YieldType::InfiniteWait))
   }
 }
+
+// Suspends the current process and sets the ip to the label (beginning of the
+// receive loop), like `wait`, but starts a receive timer if there is none.
+// When the timer has expired, execution continues to the next instruction,
+// which is `timeout`.
+// Structure: wait_timeout(label:cp, timeout)
+define_opcode!(vm, ctx, curr_p,
+  name: OpcodeWaitTimeout, arity: 2,
+  run: { Self::wait_timeout(vm, ctx, curr_p, label, timeout) },
+  args: cp_or_nil(label), load(timeout),
+);
+
+impl OpcodeWaitTimeout {
+  #[inline]
+  pub fn wait_timeout(
+    vm: &mut VM,
+    ctx: &mut Context,
+    curr_p: &mut Process,
+    label: Term,
+    timeout: Term,
+  ) -> RtResult<DispatchResult> {
+    if curr_p.mailbox.is_timed_out() {
+      return Ok(DispatchResult::Normal);
+    }
+    if timeout == gen_atoms::INFINITY {
+      ctx.jump(label);
+      return Ok(DispatchResult::Yield(YieldType::InfiniteWait));
+    }
+    if !timeout.is_small() || timeout.get_small_signed() < 0 {
+      return fail::create::timeout_value();
+    }
+    let t = timeout.get_small_signed() as u64;
+    if t == 0 {
+      return Ok(DispatchResult::Normal);
+    }
+    if curr_p.receive_timer.is_none() {
+      let mut timers = vm.scheduler.timers();
+      let expires = timers.monotonic_time() + t;
+      let action = TimerAction::ReceiveTimeout { pid: curr_p.pid };
+      let id = reference::next_ref_id();
+      timers.start(id, expires, action);
+      curr_p.receive_timer = Some(id);
+    }
+    ctx.jump(label);
+    Ok(DispatchResult::Yield(YieldType::TimedWait))
+  }
+}
+
+// Receive timer has expired, reset the receive state and continue to the
+// code in the `after` section.
+// Structure: timeout()
+define_opcode!(_vm, _ctx, curr_p,
+  name: OpcodeTimeout, arity: 0,
+  run: {
+    curr_p.receive_timer = None;
+    curr_p.mailbox.clear_timeout();
+    curr_p.mailbox.reset_save();
+    Ok(DispatchResult::Normal)
+  },
+  args:
+);
+
+// Placed before the creation of a reference which a following receive will
+// match on. Remembers the end of the message queue for the receive loop at
+// `label`, messages before it can not contain the new reference.
+// Structure: recv_mark(label:cp)
+define_opcode!(_vm, _ctx, curr_p,
+  name: OpcodeRecvMark, arity: 1,
+  run: {
+    curr_p.mailbox.set_mark(MarkKey::Label(label));
+    Ok(DispatchResult::Normal)
+  },
+  args: cp_or_nil(label),
+);
+
+// Placed before the receive loop at `label`. If the mark was set for this
+// loop, moves the save pointer to it, so the older messages are skipped.
+// Structure: recv_set(label:cp)
+define_opcode!(_vm, _ctx, curr_p,
+  name: OpcodeRecvSet, arity: 1,
+  run: {
+    curr_p.mailbox.use_mark(MarkKey::Label(label));
+    Ok(DispatchResult::Normal)
+  },
+  args: cp_or_nil(label),
+);
+
+// OTP 24 replacement for `recv_mark`: placed before the creation of a
+// reference, sets the mark and stores the marker which the reference will be
+// bound to.
+// Structure: recv_marker_reserve(marker:dst)
+define_opcode!(_vm, ctx, curr_p,
+  name: OpcodeRecvMarkerReserve, arity: 1,
+  run: {
+    let marker = Term::make_small_unsigned(curr_p.mailbox.reserve_marker());
+    ctx.store_value(marker, dst, curr_p.get_heap_mut())?;
+    Ok(DispatchResult::Normal)
+  },
+  args: term(dst),
+);
+
+// Binds the marker to the newly created reference.
+// Structure: recv_marker_bind(marker:src, reference:src)
+define_opcode!(_vm, _ctx, curr_p,
+  name: OpcodeRecvMarkerBind, arity: 2,
+  run: {
+    if let Some(ref_id) = boxed::Reference::get_id(reference) {
+      if marker.is_small() {
+        curr_p.mailbox.bind_marker(marker.get_small_unsigned(), ref_id);
+      }
+    }
+    Ok(DispatchResult::Normal)
+  },
+  args: load(marker), load(reference),
+);
+
+// Forgets the mark bound to the reference, after the receive is done.
+// Structure: recv_marker_clear(reference:src)
+define_opcode!(_vm, _ctx, curr_p,
+  name: OpcodeRecvMarkerClear, arity: 1,
+  run: {
+    if let Some(ref_id) = boxed::Reference::get_id(reference) {
+      curr_p.mailbox.clear_mark(MarkKey::Reference(ref_id));
+    }
+    Ok(DispatchResult::Normal)
+  },
+  args: load(reference),
+);
+
+// OTP 24 replacement for `recv_set`: placed before the receive loop which
+// matches on the reference, moves the save pointer to the mark bound to it.
+// Structure: recv_marker_use(reference:src)
+define_opcode!(_vm, _ctx, curr_p,
+  name: OpcodeRecvMarkerUse, arity: 1,
+  run: {
+    if let Some(ref_id) = boxed::Reference::get_id(reference) {
+      curr_p.mailbox.use_mark(MarkKey::Reference(ref_id));
+    }
+    Ok(DispatchResult::Normal)
+  },
+  args: load(reference),
+);
@@ -1,6 +1,6 @@
 //! Generated by `codegen/create_vm_dispatch.py`
 //! Dispatch for all opcode types.
-//! Config used: OTP22
+//! Config used: OTP24
 #![allow(dead_code)]
 
 use crate::{
@@ -107,6 +107,11 @@ pub fn dispatch_op_inline(vm: &mut VM, op: RawOpcode, ctx: &mut Context, curr_p:
       return OpcodeRemoveMessage::__run(vm, ctx, curr_p);
     },
 
+    OPCODE_TIMEOUT => {
+      assert_arity(OPCODE_TIMEOUT, OpcodeTimeout::ARITY);
+      return OpcodeTimeout::__run(vm, ctx, curr_p);
+    },
+
     OPCODE_LOOP_REC => {
       assert_arity(OPCODE_LOOP_REC, OpcodeLoopRec::ARITY);
       return OpcodeLoopRec::__run(vm, ctx, curr_p);
@@ -122,6 +127,11 @@ pub fn dispatch_op_inline(vm: &mut VM, op: RawOpcode, ctx: &mut Context, curr_p:
       return OpcodeWait::__run(vm, ctx, curr_p);
     },
 
+    OPCODE_WAIT_TIMEOUT => {
+      assert_arity(OPCODE_WAIT_TIMEOUT, OpcodeWaitTimeout::ARITY);
+      return OpcodeWaitTimeout::__run(vm, ctx, curr_p);
+    },
+
     OPCODE_IS_LT => {
       assert_arity(OPCODE_IS_LT, OpcodeIsLt::ARITY);
       return OpcodeIsLt::__run(vm, ctx, curr_p);
@@ -282,6 +292,51 @@ pub fn dispatch_op_inline(vm: &mut VM, op: RawOpcode, ctx: &mut Context, curr_p:
       return OpcodeBsPutBinary::__run(vm, ctx, curr_p);
     },
 
+    OPCODE_FCLEARERROR => {
+      assert_arity(OPCODE_FCLEARERROR, OpcodeFclearerror::ARITY);
+      return OpcodeFclearerror::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_FCHECKERROR => {
+      assert_arity(OPCODE_FCHECKERROR, OpcodeFcheckerror::ARITY);
+      return OpcodeFcheckerror::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_FMOVE => {
+      assert_arity(OPCODE_FMOVE, OpcodeFmove::ARITY);
+      return OpcodeFmove::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_FCONV => {
+      assert_arity(OPCODE_FCONV, OpcodeFconv::ARITY);
+      return OpcodeFconv::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_FADD => {
+      assert_arity(OPCODE_FADD, OpcodeFadd::ARITY);
+      return OpcodeFadd::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_FSUB => {
+      assert_arity(OPCODE_FSUB, OpcodeFsub::ARITY);
+      return OpcodeFsub::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_FMUL => {
+      assert_arity(OPCODE_FMUL, OpcodeFmul::ARITY);
+      return OpcodeFmul::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_FDIV => {
+      assert_arity(OPCODE_FDIV, OpcodeFdiv::ARITY);
+      return OpcodeFdiv::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_FNEGATE => {
+      assert_arity(OPCODE_FNEGATE, OpcodeFnegate::ARITY);
+      return OpcodeFnegate::__run(vm, ctx, curr_p);
+    },
+
     OPCODE_MAKE_FUN2 => {
       assert_arity(OPCODE_MAKE_FUN2, OpcodeMakeFun2::ARITY);
       return OpcodeMakeFun2::__run(vm, ctx, curr_p);
@@ -357,6 +412,16 @@ pub fn dispatch_op_inline(vm: &mut VM, op: RawOpcode, ctx: &mut Context, curr_p:
       return OpcodeTrim::__run(vm, ctx, curr_p);
     },
 
+    OPCODE_RECV_MARK => {
+      assert_arity(OPCODE_RECV_MARK, OpcodeRecvMark::ARITY);
+      return OpcodeRecvMark::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_RECV_SET => {
+      assert_arity(OPCODE_RECV_SET, OpcodeRecvSet::ARITY);
+      return OpcodeRecvSet::__run(vm, ctx, curr_p);
+    },
+
     OPCODE_GC_BIF3 => {
       assert_arity(OPCODE_GC_BIF3, OpcodeGcBif3::ARITY);
       return OpcodeGcBif3::__run(vm, ctx, curr_p);
@@ -387,6 +452,26 @@ pub fn dispatch_op_inline(vm: &mut VM, op: RawOpcode, ctx: &mut Context, curr_p:
       return OpcodeBsStartMatch3::__run(vm, ctx, curr_p);
     },
 
+    OPCODE_RECV_MARKER_BIND => {
+      assert_arity(OPCODE_RECV_MARKER_BIND, OpcodeRecvMarkerBind::ARITY);
+      return OpcodeRecvMarkerBind::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_RECV_MARKER_CLEAR => {
+      assert_arity(OPCODE_RECV_MARKER_CLEAR, OpcodeRecvMarkerClear::ARITY);
+      return OpcodeRecvMarkerClear::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_RECV_MARKER_RESERVE => {
+      assert_arity(OPCODE_RECV_MARKER_RESERVE, OpcodeRecvMarkerReserve::ARITY);
+      return OpcodeRecvMarkerReserve::__run(vm, ctx, curr_p);
+    },
+
+    OPCODE_RECV_MARKER_USE => {
+      assert_arity(OPCODE_RECV_MARKER_USE, OpcodeRecvMarkerUse::ARITY);
+      return OpcodeRecvMarkerUse::__run(vm, ctx, curr_p);
+    },
+
     other => unknown_opcode(other, ctx),
   }
   Ok(DispatchResult::Yield(YieldType::EndOfTheQueue))
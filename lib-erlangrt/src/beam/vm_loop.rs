@@ -4,9 +4,12 @@ use crate::{
     gen_op,
     vm_dispatch::dispatch_op_inline,
   },
-  emulator::{disasm, scheduler::SliceResult, vm::VM},
+  defs::Word,
+  emulator::{code::opcode::RawOpcode, disasm, scheduler::SliceResult, vm::VM},
   fail::{RtErr, RtResult},
+  term::value::Term,
 };
+use colored::Colorize;
 
 // fn module() -> &'static str { "vm_loop: " }
 
@@ -16,17 +19,31 @@ impl VM {
   /// Reduce the reduction (instruction) count and once it reaches zero, return.
   /// Call dispatch again to schedule another process.
   ///
-  /// Returns: `false` if VM found no process to run, `true` if the process has
-  /// used its time slice and wants to run another.
+  /// Returns: `false` if there are no more processes in the VM, `true` if the
+  /// process has used its time slice and wants to run another, or if this
+  /// scheduler had nothing to run and waited for work.
   pub fn dispatch(&mut self) -> RtResult<bool> {
-    // let scheduler = self.get_scheduler_p();
-    let curr_p = match self.scheduler.next_process(&mut self.processes) {
-      None => return Ok(false),
-      Some(next_pid) => {
-        let next_ptr = self.processes.unsafe_lookup_pid_mut(next_pid);
-        unsafe { &mut (*next_ptr) }
+    let next = self.scheduler.next_process(&self.processes);
+    if self.scheduler.has_crash_reports() {
+      self.log_crash_reports();
+    }
+    let curr_handle = match next {
+      None => {
+        if self.processes.count() == 0 {
+          println!("All processes finished, this is the end.");
+          return Ok(false);
+        }
+        self.scheduler.wait_for_work();
+        return Ok(true);
       }
+      Some(next_pid) => match self.processes.get(next_pid) {
+        Some(handle) => handle,
+        None => panic!("vm: Scheduled process {} is not registered", next_pid),
+      },
     };
+    // The handle keeps the process alive while it runs, the scheduler has
+    // marked it as running so no other thread will change it
+    let curr_p = unsafe { &mut (*curr_handle.as_ptr()) };
 
     // Ugly borrowing the context from the process, but we guarantee that the
     // borrow will not outlive the owning process or we pay the harsh price
@@ -34,9 +51,8 @@ impl VM {
     let ctx_p = curr_p.get_context_p();
     let mut ctx = unsafe { &mut (*ctx_p) };
     ctx.swap_in(); // tell the context, that it is active now
-                   // curr_p.heap.print_stack();
-
-    let cs = self.get_code_server_p();
+    curr_p.hibernated = false;
+    // curr_p.heap.print_stack();
 
     // Fetch some opcodes, Execute some opcodes
     //
@@ -44,12 +60,13 @@ impl VM {
       if cfg!(feature = "trace_opcode_execution") {
         print!("   ↳ ");
         unsafe {
-          disasm::disasm_op(ctx.ip.get_pointer(), &(*cs));
+          disasm::disasm_op(ctx.ip.get_pointer(), &self.code_server());
         }
         //        curr_p.heap.stack_dump();
       }
 
       // Take next opcode
+      let op_ip = ctx.ip.get_pointer();
       let op = ctx.fetch_opcode();
       debug_assert!(
         op <= gen_op::OPCODE_MAX,
@@ -68,11 +85,23 @@ impl VM {
         other => other?,
       };
 
+      if cfg!(feature = "verify_heap") {
+        let live = unsafe { live_operand(op, op_ip) };
+        if let Err(e) = curr_p.verify_heap(live) {
+          println!("{} {}", "Heap verification failed after opcode:".red(), e);
+          unsafe {
+            disasm::disasm_op(op_ip, &self.code_server());
+          }
+          panic!("vm: Heap of {} is damaged: {}", curr_p.pid, e);
+        }
+      }
+
       match disp_result {
         DispatchResult::Yield(yt) => {
           curr_p.timeslice_result = match yt {
             YieldType::EndOfTheQueue => SliceResult::Yield,
-            YieldType::InfiniteWait => SliceResult::InfiniteWait,
+            YieldType::InfiniteWait | YieldType::Hibernate => SliceResult::InfiniteWait,
+            YieldType::TimedWait => SliceResult::TimedWait,
           };
           return Ok(true);
         }
@@ -86,12 +115,6 @@ impl VM {
         }
       }
 
-      // TODO: this seems to not work?
-      if self.processes.count() == 0 {
-        println!("All processes finished, this is the end.");
-        return Ok(false);
-      }
-
       if ctx.reductions <= 0 {
         // curr_p.heap.print_stack();
         // Out of reductions, just give up and let another one run
@@ -101,3 +124,21 @@ impl VM {
     } // end loop
   }
 }
+
+/// For opcodes which may run the GC, read their `live` operand: registers
+/// `0..live` survived the GC and must hold valid terms after the opcode. For
+/// other opcodes the count of valid registers is not known.
+unsafe fn live_operand(op: RawOpcode, op_ip: *const Word) -> usize {
+  let index = match op {
+    gen_op::OPCODE_ALLOCATE
+    | gen_op::OPCODE_ALLOCATE_ZERO
+    | gen_op::OPCODE_TEST_HEAP
+    | gen_op::OPCODE_GC_BIF1
+    | gen_op::OPCODE_GC_BIF2
+    | gen_op::OPCODE_GC_BIF3 => 1,
+    gen_op::OPCODE_ALLOCATE_HEAP | gen_op::OPCODE_ALLOCATE_HEAP_ZERO => 2,
+    gen_op::OPCODE_BS_INIT2 => 3,
+    _ => return 0,
+  };
+  Term::from_raw(*op_ip.add(1 + index)).get_small_unsigned()
+}
@@ -1,13 +1,28 @@
 use crate::{
   defs,
   emulator::heap::heap_trait::THeap,
-  fail::RtResult,
+  fail::{self, RtResult},
   term::{
-    boxed::{self, endianness::Endianness},
+    boxed::{
+      self,
+      bignum::{sign::Sign, Digit},
+      endianness::Endianness,
+    },
     value::Term,
   },
 };
-use core::ptr;
+use core::{cmp::Ordering, ptr};
+
+/// Largest bignum size in digits, a larger result is a `system_limit` error
+/// (same as `BIG_ARITY_MAX` in OTP).
+pub const MAX_DIGITS: usize = (1 << 19) - 1;
+
+/// Twice as wide as a `Digit`, holds a product of two digits.
+#[cfg(target_pointer_width = "64")]
+type DoubleDigit = u128;
+
+#[cfg(target_pointer_width = "32")]
+type DoubleDigit = u64;
 
 /// Helper, creates a new big integer on heap with val.
 pub fn from_isize(hp: &mut THeap, val: isize) -> RtResult<Term> {
@@ -15,19 +30,575 @@ pub fn from_isize(hp: &mut THeap, val: isize) -> RtResult<Term> {
   Ok(Term::make_boxed(p))
 }
 
-/// Multiply two big ints
-pub fn mul(_hp: &mut THeap, a: Term, b: Term) -> RtResult<Term> {
-  debug_assert!(a.is_big_int());
-  debug_assert!(b.is_big_int());
-  unimplemented!("mul big a, b")
-}
-
+/// Read a small or a big integer, if it fits an isize.
 #[allow(dead_code)]
 pub fn isize_from(val: Term) -> Option<isize> {
   if val.is_small() {
     return Some(val.get_small_signed());
   }
-  unimplemented!("isize from big")
+  BigInt::from_term(val)?.to_isize()
+}
+
+/// An integer taken out of a term, to do arithmetic with it: the sign and
+/// the magnitude, where the least significant digit goes first. There are no
+/// leading zero digits, and zero has no digits and is not negative.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+  pub negative: bool,
+  pub digits: Vec<Digit>,
+}
+
+impl BigInt {
+  pub fn from_isize(val: isize) -> Self {
+    let (sign, magnitude) = match val {
+      // Sign::split can not negate it
+      isize::MIN => (Sign::Negative, isize::MIN as usize),
+      _ => Sign::split(val),
+    };
+    Self {
+      negative: sign == Sign::Negative,
+      digits: if magnitude == 0 { Vec::new() } else { vec![magnitude] },
+    }
+  }
+
+  /// Read a small integer or a bignum.
+  /// Returns: `None` if the term is not an integer.
+  pub fn from_term(t: Term) -> Option<Self> {
+    if t.is_small() {
+      return Some(Self::from_isize(t.get_small_signed()));
+    }
+    if !t.is_big_int() {
+      return None;
+    }
+    let p = t.get_box_ptr::<boxed::Bignum>();
+    let result = unsafe {
+      Self {
+        negative: (*p).is_negative(),
+        digits: (*p).get_digits().to_vec(),
+      }
+    };
+    Some(result.normalize())
+  }
+
+  /// Store the value on the heap, if it fits a small integer the result is
+  /// a small, otherwise a bignum.
+  pub fn to_term(&self, hp: &mut THeap) -> RtResult<Term> {
+    if self.digits.len() > MAX_DIGITS {
+      return fail::create::system_limit();
+    }
+    if let Some(val) = self.to_isize() {
+      if Term::small_fits(val) {
+        return Ok(Term::make_small_signed(val));
+      }
+    }
+    let sign = if self.negative { Sign::Negative } else { Sign::Positive };
+    let p = unsafe { boxed::Bignum::create_into(hp, sign, &self.digits)? };
+    Ok(Term::make_boxed(p))
+  }
+
+  /// Returns: the value, if it fits an isize.
+  pub fn to_isize(&self) -> Option<isize> {
+    match self.digits.len() {
+      0 => Some(0),
+      1 if !self.negative && self.digits[0] <= isize::MAX as usize => {
+        Some(self.digits[0] as isize)
+      }
+      // A magnitude of `isize::MAX + 1` is isize::MIN
+      1 if self.negative && self.digits[0] <= isize::MIN as usize => {
+        Some((self.digits[0] as isize).wrapping_neg())
+      }
+      _ => None,
+    }
+  }
+
+  /// The integer part of a finite float, rounded towards zero.
+  pub fn from_f64(val: f64) -> Self {
+    debug_assert!(val.is_finite());
+    let bits = val.to_bits();
+    let exp = ((bits >> 52) & 0x7ff) as isize;
+    if exp == 0 {
+      // Zero or subnormal, less than 1
+      return Self::from_isize(0);
+    }
+    let mut mantissa = (bits & ((1u64 << 52) - 1)) | (1u64 << 52);
+    let mut digits = Vec::new();
+    while mantissa != 0 {
+      digits.push(mantissa as Digit);
+      mantissa = mantissa.checked_shr(defs::WORD_BITS as u32).unwrap_or(0);
+    }
+    let magnitude = Self {
+      negative: false,
+      digits,
+    };
+    // The value is mantissa * 2^(exp - 1023 - 52)
+    let shift = exp - 1075;
+    let result = if shift >= 0 {
+      magnitude.shl(shift as usize)
+    } else {
+      magnitude.shr(-shift as usize)
+    };
+    if val < 0.0 {
+      result.neg()
+    } else {
+      result
+    }
+  }
+
+  /// Parse an optional sign and digits in `base` (2 to 36), letters may be
+  /// lower or upper case.
+  /// Returns: `None` if the text is empty or has a non-digit.
+  pub fn from_str_radix(text: &[u8], base: u32) -> Option<Self> {
+    debug_assert!((2..=36).contains(&base));
+    let (negative, text) = match text.first() {
+      Some(b'-') => (true, &text[1..]),
+      Some(b'+') => (false, &text[1..]),
+      _ => (false, text),
+    };
+    if text.is_empty() {
+      return None;
+    }
+    // Collect as many digits as fit a small to multiply by base^n once
+    let chunk_len = chunk_digits(base);
+    let mut result = Self::from_isize(0);
+    for chunk in text.chunks(chunk_len) {
+      let mut val = 0isize;
+      for c in chunk {
+        val = val * base as isize + (*c as char).to_digit(base)? as isize;
+      }
+      let scale = Self::from_isize((base as isize).pow(chunk.len() as u32));
+      result = result.mul(&scale).add(&Self::from_isize(val));
+    }
+    Some(if negative { result.neg() } else { result })
+  }
+
+  /// Digits in `base` (2 to 36) with upper case letters, and a minus sign if
+  /// negative.
+  pub fn to_str_radix(&self, base: u32) -> String {
+    debug_assert!((2..=36).contains(&base));
+    if self.is_zero() {
+      return "0".to_string();
+    }
+    let chunk_len = chunk_digits(base);
+    let divisor = Self::from_isize((base as isize).pow(chunk_len as u32));
+    // Chunks of digits, the least significant first
+    let mut chunks = Vec::new();
+    let mut rest = self.abs();
+    while !rest.is_zero() {
+      let (q, r) = rest.div_rem(&divisor).unwrap();
+      chunks.push(r.to_isize().unwrap() as u32);
+      rest = q;
+    }
+    let mut result = String::new();
+    if self.negative {
+      result.push('-');
+    }
+    for (i, chunk) in chunks.iter().rev().enumerate() {
+      let mut text = Vec::with_capacity(chunk_len);
+      let mut val = *chunk;
+      while val != 0 {
+        text.push(core::char::from_digit(val % base, base).unwrap());
+        val /= base;
+      }
+      // Pad all chunks but the first with zeros
+      if i > 0 {
+        text.resize(chunk_len, '0');
+      }
+      result.extend(text.iter().rev().map(|c| c.to_ascii_uppercase()));
+    }
+    result
+  }
+
+  /// Nearest float, may be infinite if the value is too large.
+  pub fn to_f64(&self) -> f64 {
+    let base = (defs::WORD_BITS as f64).exp2();
+    let magnitude = self.digits.iter().rev().fold(0.0, |acc, d| acc * base + *d as f64);
+    if self.negative {
+      -magnitude
+    } else {
+      magnitude
+    }
+  }
+
+  #[inline]
+  pub fn is_zero(&self) -> bool {
+    self.digits.is_empty()
+  }
+
+  /// Remove leading zero digits, a zero is never negative.
+  pub fn normalize(mut self) -> Self {
+    while self.digits.last() == Some(&0) {
+      self.digits.pop();
+    }
+    if self.digits.is_empty() {
+      self.negative = false;
+    }
+    self
+  }
+
+  pub fn neg(&self) -> Self {
+    Self {
+      negative: !self.negative && !self.is_zero(),
+      digits: self.digits.clone(),
+    }
+  }
+
+  pub fn add(&self, other: &Self) -> Self {
+    if self.negative == other.negative {
+      let digits = add_digits(&self.digits, &other.digits);
+      return Self { negative: self.negative, digits }.normalize();
+    }
+    // Signs differ, subtract the smaller magnitude from the larger
+    let result = match cmp_digits(&self.digits, &other.digits) {
+      Ordering::Less => Self {
+        negative: other.negative,
+        digits: sub_digits(&other.digits, &self.digits),
+      },
+      _ => Self {
+        negative: self.negative,
+        digits: sub_digits(&self.digits, &other.digits),
+      },
+    };
+    result.normalize()
+  }
+
+  pub fn sub(&self, other: &Self) -> Self {
+    self.add(&other.neg())
+  }
+
+  pub fn mul(&self, other: &Self) -> Self {
+    Self {
+      negative: self.negative != other.negative,
+      digits: mul_digits(&self.digits, &other.digits),
+    }
+    .normalize()
+  }
+
+  pub fn abs(&self) -> Self {
+    Self {
+      negative: false,
+      digits: self.digits.clone(),
+    }
+  }
+
+  /// Division truncated towards zero, the remainder has the sign of `self`
+  /// (same as `div` and `rem` in Erlang).
+  /// Returns: `None` if `other` is zero.
+  pub fn div_rem(&self, other: &Self) -> Option<(Self, Self)> {
+    if other.is_zero() {
+      return None;
+    }
+    let (q, r) = div_rem_digits(&self.digits, &other.digits);
+    let quotient = Self {
+      negative: self.negative != other.negative,
+      digits: q,
+    };
+    let remainder = Self {
+      negative: self.negative,
+      digits: r,
+    };
+    Some((quotient.normalize(), remainder.normalize()))
+  }
+
+  /// Shift left by `n` bits, that is multiply by `2^n`.
+  pub fn shl(&self, n: usize) -> Self {
+    let mut digits = vec![0 as Digit; n / defs::WORD_BITS];
+    digits.extend(shl_digits(&self.digits, n % defs::WORD_BITS));
+    Self {
+      negative: self.negative,
+      digits,
+    }
+    .normalize()
+  }
+
+  /// Arithmetic shift right by `n` bits, which rounds towards negative
+  /// infinity like in two's complement.
+  pub fn shr(&self, n: usize) -> Self {
+    if !self.negative {
+      return Self {
+        negative: false,
+        digits: shr_digits(&self.digits, n),
+      }
+      .normalize();
+    }
+    // -x >> n == -(((x - 1) >> n) + 1)
+    let t = shr_digits(&sub_digits(&self.digits, &[1]), n);
+    Self {
+      negative: true,
+      digits: add_digits(&t, &[1]),
+    }
+    .normalize()
+  }
+
+  pub fn bitand(&self, other: &Self) -> Self {
+    self.bitwise(other, |x, y| x & y)
+  }
+
+  pub fn bitor(&self, other: &Self) -> Self {
+    self.bitwise(other, |x, y| x | y)
+  }
+
+  pub fn bitxor(&self, other: &Self) -> Self {
+    self.bitwise(other, |x, y| x ^ y)
+  }
+
+  /// Bitwise not in two's complement, same as `-x - 1`.
+  pub fn bitnot(&self) -> Self {
+    self.neg().sub(&Self::from_isize(1))
+  }
+
+  /// Apply `f` to the digits of both numbers in two's complement, with one
+  /// more digit than needed so that the sign bit is not lost.
+  fn bitwise<F>(&self, other: &Self, f: F) -> Self
+  where
+    F: Fn(Digit, Digit) -> Digit,
+  {
+    let len = core::cmp::max(self.digits.len(), other.digits.len()) + 1;
+    let a = self.to_twos_complement(len);
+    let b = other.to_twos_complement(len);
+    let digits = a.iter().zip(b.iter()).map(|(x, y)| f(*x, *y)).collect();
+    Self::from_twos_complement(digits)
+  }
+
+  fn to_twos_complement(&self, len: usize) -> Vec<Digit> {
+    if !self.negative {
+      let mut digits = self.digits.clone();
+      digits.resize(len, 0);
+      return digits;
+    }
+    // -x == !(x - 1)
+    let mut digits = sub_digits(&self.digits, &[1]);
+    digits.resize(len, 0);
+    for d in digits.iter_mut() {
+      *d = !*d;
+    }
+    digits
+  }
+
+  fn from_twos_complement(mut digits: Vec<Digit>) -> Self {
+    let negative = digits.last().is_some_and(|d| (*d as isize) < 0);
+    if !negative {
+      return Self { negative, digits }.normalize();
+    }
+    for d in digits.iter_mut() {
+      *d = !*d;
+    }
+    Self {
+      negative,
+      digits: add_digits(&digits, &[1]),
+    }
+    .normalize()
+  }
+}
+
+/// Integers are ordered by the sign first, then by the magnitude.
+impl Ord for BigInt {
+  fn cmp(&self, other: &Self) -> Ordering {
+    match (self.negative, other.negative) {
+      (false, true) => Ordering::Greater,
+      (true, false) => Ordering::Less,
+      (false, false) => cmp_digits(&self.digits, &other.digits),
+      (true, true) => cmp_digits(&other.digits, &self.digits),
+    }
+  }
+}
+
+impl PartialOrd for BigInt {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+/// How many digits in `base` always fit an i32, and so an isize.
+fn chunk_digits(base: u32) -> usize {
+  let mut n = 0;
+  let mut limit = i32::MAX as u32 / base;
+  while limit > 0 {
+    limit /= base;
+    n += 1;
+  }
+  n
+}
+
+/// Compare two magnitudes without leading zeros.
+fn cmp_digits(a: &[Digit], b: &[Digit]) -> Ordering {
+  if a.len() != b.len() {
+    return a.len().cmp(&b.len());
+  }
+  for (x, y) in a.iter().rev().zip(b.iter().rev()) {
+    if x != y {
+      return x.cmp(y);
+    }
+  }
+  Ordering::Equal
+}
+
+fn add_digits(a: &[Digit], b: &[Digit]) -> Vec<Digit> {
+  let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+  let mut result = Vec::with_capacity(long.len() + 1);
+  let mut carry = false;
+  for (i, x) in long.iter().enumerate() {
+    let y = if i < short.len() { short[i] } else { 0 };
+    let (sum1, overflow1) = x.overflowing_add(y);
+    let (sum2, overflow2) = sum1.overflowing_add(carry as Digit);
+    result.push(sum2);
+    carry = overflow1 || overflow2;
+  }
+  if carry {
+    result.push(1);
+  }
+  result
+}
+
+/// Subtract magnitude `b` from the magnitude `a`, which is not less than `b`.
+fn sub_digits(a: &[Digit], b: &[Digit]) -> Vec<Digit> {
+  debug_assert!(cmp_digits(a, b) != Ordering::Less);
+  let mut result = Vec::with_capacity(a.len());
+  let mut borrow = false;
+  for (i, x) in a.iter().enumerate() {
+    let y = if i < b.len() { b[i] } else { 0 };
+    let (diff1, overflow1) = x.overflowing_sub(y);
+    let (diff2, overflow2) = diff1.overflowing_sub(borrow as Digit);
+    result.push(diff2);
+    borrow = overflow1 || overflow2;
+  }
+  debug_assert!(!borrow);
+  result
+}
+
+/// Schoolbook multiplication of two magnitudes.
+fn mul_digits(a: &[Digit], b: &[Digit]) -> Vec<Digit> {
+  if a.is_empty() || b.is_empty() {
+    return Vec::new();
+  }
+  let mut result = vec![0 as Digit; a.len() + b.len()];
+  for (i, x) in a.iter().enumerate() {
+    let mut carry: DoubleDigit = 0;
+    for (j, y) in b.iter().enumerate() {
+      let product = *x as DoubleDigit * *y as DoubleDigit;
+      let t = result[i + j] as DoubleDigit + product + carry;
+      result[i + j] = t as Digit;
+      carry = t >> defs::WORD_BITS;
+    }
+    result[i + b.len()] = carry as Digit;
+  }
+  result
+}
+
+/// Shift a magnitude left by `n` bits, less than a digit. The result has one
+/// more digit, which may be zero.
+fn shl_digits(a: &[Digit], n: usize) -> Vec<Digit> {
+  debug_assert!(n < defs::WORD_BITS);
+  let mut result = Vec::with_capacity(a.len() + 1);
+  let mut carry: Digit = 0;
+  for d in a {
+    result.push((d << n) | carry);
+    carry = if n == 0 { 0 } else { d >> (defs::WORD_BITS - n) };
+  }
+  result.push(carry);
+  result
+}
+
+/// Shift a magnitude right by `n` bits, the bits shifted out are lost.
+fn shr_digits(a: &[Digit], n: usize) -> Vec<Digit> {
+  let skip = n / defs::WORD_BITS;
+  if skip >= a.len() {
+    return Vec::new();
+  }
+  let bits = n % defs::WORD_BITS;
+  let a = &a[skip..];
+  let mut result = Vec::with_capacity(a.len());
+  for i in 0..a.len() {
+    let high = if bits == 0 || i + 1 == a.len() {
+      0
+    } else {
+      a[i + 1] << (defs::WORD_BITS - bits)
+    };
+    result.push((a[i] >> bits) | high);
+  }
+  result
+}
+
+/// Divide magnitude `a` by a non-zero magnitude `b`, both without leading
+/// zeros. This is the algorithm D from Knuth's TAOCP vol.2, 4.3.1.
+/// Returns: the quotient and the remainder.
+fn div_rem_digits(a: &[Digit], b: &[Digit]) -> (Vec<Digit>, Vec<Digit>) {
+  debug_assert!(!b.is_empty());
+  if cmp_digits(a, b) == Ordering::Less {
+    return (Vec::new(), a.to_vec());
+  }
+  let word_bits = defs::WORD_BITS;
+  if b.len() == 1 {
+    let divisor = b[0] as DoubleDigit;
+    let mut q = vec![0 as Digit; a.len()];
+    let mut rem: DoubleDigit = 0;
+    for i in (0..a.len()).rev() {
+      let cur = (rem << word_bits) | a[i] as DoubleDigit;
+      q[i] = (cur / divisor) as Digit;
+      rem = cur % divisor;
+    }
+    return (q, vec![rem as Digit]);
+  }
+
+  // Normalize so that the top digit of the divisor has its high bit set,
+  // then the estimate of each quotient digit is off by at most 2
+  let shift = b[b.len() - 1].leading_zeros() as usize;
+  let mut v = shl_digits(b, shift);
+  v.pop();
+  let mut u = shl_digits(a, shift);
+  let n = v.len();
+  let m = u.len() - n;
+  let base: DoubleDigit = 1 << word_bits;
+  let v_top = v[n - 1] as DoubleDigit;
+  let v_next = v[n - 2] as DoubleDigit;
+  let mut q = vec![0 as Digit; m];
+
+  for j in (0..m).rev() {
+    let num = ((u[j + n] as DoubleDigit) << word_bits) | u[j + n - 1] as DoubleDigit;
+    let mut qhat = num / v_top;
+    let mut rhat = num % v_top;
+    loop {
+      let next = (rhat << word_bits) | u[j + n - 2] as DoubleDigit;
+      if qhat < base && qhat * v_next <= next {
+        break;
+      }
+      qhat -= 1;
+      rhat += v_top;
+      if rhat >= base {
+        break;
+      }
+    }
+
+    // Multiply the divisor by qhat and subtract from the current digits of u
+    let mut carry: DoubleDigit = 0;
+    let mut borrow = false;
+    for i in 0..n {
+      let p = qhat * v[i] as DoubleDigit + carry;
+      carry = p >> word_bits;
+      let (d1, o1) = u[i + j].overflowing_sub(p as Digit);
+      let (d2, o2) = d1.overflowing_sub(borrow as Digit);
+      u[i + j] = d2;
+      borrow = o1 || o2;
+    }
+    let (d1, o1) = u[j + n].overflowing_sub(carry as Digit);
+    let (d2, o2) = d1.overflowing_sub(borrow as Digit);
+    u[j + n] = d2;
+
+    if o1 || o2 {
+      // The estimate was one too large, add the divisor back
+      qhat -= 1;
+      let mut carry = false;
+      for i in 0..n {
+        let (s1, c1) = u[i + j].overflowing_add(v[i]);
+        let (s2, c2) = s1.overflowing_add(carry as Digit);
+        u[i + j] = s2;
+        carry = c1 || c2;
+      }
+      u[j + n] = u[j + n].wrapping_add(carry as Digit);
+    }
+    q[j] = qhat as Digit;
+  }
+
+  (q, shr_digits(&u[..n], shift))
 }
 
 /// From array of bytes create limb array for bignum.
@@ -46,10 +617,10 @@ pub fn make_limbs_from_bytes(input_endian: Endianness, b: Vec<u8>) -> Vec<usize>
     ptr::copy_nonoverlapping(
       b.as_ptr(),
       dst.as_mut_ptr() as *mut u8,
-      b.len() & !(defs::WORD_BITS - 1),
+      b.len() & !(defs::WORD_BYTES - 1),
     );
     // Bytes which did not form a new full usize
-    let remaining_bytes = b.len() & (defs::WORD_BITS - 1);
+    let remaining_bytes = b.len() & (defs::WORD_BYTES - 1);
     if remaining_bytes > 0 && dst.len() > 0 {
       let index = dst.len() - 1;
       dst[index] = 0;
@@ -62,3 +633,64 @@ pub fn make_limbs_from_bytes(input_endian: Endianness, b: Vec<u8>) -> Vec<usize>
   }
   dst
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn make(digits: &[Digit], negative: bool) -> BigInt {
+    BigInt {
+      negative,
+      digits: digits.to_vec(),
+    }
+    .normalize()
+  }
+
+  #[test]
+  fn test_div_rem_identity() {
+    let max = Digit::max_value();
+    let samples = [
+      make(&[max, max, max, 1], false),
+      make(&[0, 0, 1 << (defs::WORD_BITS - 1)], true),
+      make(&[1, max - 1, 3], false),
+      make(&[max, 0, max], true),
+      make(&[0, 1], false),
+      make(&[12345], true),
+    ];
+    for a in samples.iter() {
+      for b in samples.iter() {
+        let (q, r) = a.div_rem(b).unwrap();
+        assert_eq!(q.mul(b).add(&r), *a);
+        assert_eq!(cmp_digits(&r.digits, &b.digits), Ordering::Less);
+        assert!(r.is_zero() || r.negative == a.negative);
+      }
+    }
+    assert!(samples[0].div_rem(&make(&[], false)).is_none());
+  }
+
+  #[test]
+  fn test_from_f64() {
+    assert_eq!(BigInt::from_f64(-7.9), BigInt::from_isize(-7));
+    assert_eq!(BigInt::from_f64(0.25), BigInt::from_isize(0));
+    let big = BigInt::from_f64(-1.5e300);
+    assert!(big.negative && big.digits.len() > 10);
+    assert_eq!(big.to_f64(), -1.5e300);
+  }
+
+  #[test]
+  fn test_radix_roundtrip() {
+    let max = Digit::max_value();
+    let big = make(&[max, 12345, max - 7], true);
+    for base in 2..=36 {
+      let text = big.to_str_radix(base);
+      assert_eq!(BigInt::from_str_radix(text.as_bytes(), base), Some(big.clone()));
+    }
+    assert_eq!(BigInt::from_isize(-255).to_str_radix(16), "-FF");
+    assert_eq!(BigInt::from_isize(0).to_str_radix(2), "0");
+    let parsed = BigInt::from_str_radix(b"+zz", 36);
+    assert_eq!(parsed, Some(BigInt::from_isize(1295)));
+    for bad in [&b""[..], b"-", b"12a", b" 1", b"1_0"].iter() {
+      assert_eq!(BigInt::from_str_radix(bad, 10), None);
+    }
+  }
+}
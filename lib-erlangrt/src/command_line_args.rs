@@ -28,6 +28,8 @@ pub struct ErlStartArgs {
   /// Which modules:functions to start (option -s m f arg1,...)
   pub start: Vec<Vec<String>>,
   pub search_path: Vec<String>,
+  /// How many scheduler threads to run (option +S N), default is one per CPU
+  pub schedulers: Option<usize>,
 
   /// Small heap only for storing command line available globally
   arg_heap: Heap,
@@ -43,6 +45,7 @@ impl ErlStartArgs {
       node: NodeName::Short("nonode@nohost".to_string()),
       start: Vec::new(),
       search_path: vec![],
+      schedulers: None,
       arg_heap: Heap::new(Designation::ProgramArgumentsHeap),
       args_term: Term::non_value(),
     }
@@ -61,13 +64,28 @@ impl ErlStartArgs {
   {
     loop {
       if let Some(s) = iter.next() {
-        self.add_arg1(s.as_ref())
+        if Self::takes_parameter(s) {
+          match iter.next() {
+            Some(param) => self.add_arg2(s.as_ref(), param.as_ref()),
+            None => self.add_arg1(s.as_ref()),
+          }
+        } else {
+          self.add_arg1(s.as_ref())
+        }
       } else {
         break;
       }
     }
   }
 
+  /// Whether an option is followed by a parameter on the command line
+  fn takes_parameter(a: &str) -> bool {
+    match a {
+      "-sname" | "-name" | "+S" => true,
+      _ => false,
+    }
+  }
+
   /// Parses and adds one argument with no parameter
   pub fn add_arg1(&mut self, a1: &str) {
     self.parse_arg(&[a1]);
@@ -89,6 +107,14 @@ impl ErlStartArgs {
       "-name" => {
         self.node = NodeName::Full(args[1].to_string());
       }
+      "+S" if args.len() > 1 => {
+        // +S Schedulers[:SchedulersOnline], only the first number is used
+        let n = args[1].split(':').next().unwrap_or("");
+        match n.parse::<usize>() {
+          Ok(n) if n > 0 => self.schedulers = Some(n),
+          _ => println!("Bad number of schedulers for +S: {}", args[1]),
+        }
+      }
       other => self.other_args.push(String::from(other)),
     }
   }
@@ -0,0 +1,111 @@
+//! Addition, subtraction, negation and `abs` for numbers. Small integers are
+//! handled inline, if either operand is a float the operation is done on
+//! floats, otherwise the operands are converted to `big::BigInt` and the result
+//! is demoted to a small if it fits.
+use crate::{
+  big::{self, BigInt},
+  emulator::{arith::float, heap::heap_trait::THeap},
+  fail::{self, RtResult},
+  term::value::*,
+};
+
+pub fn add(hp: &mut THeap, x: Term, y: Term) -> RtResult<Term> {
+  if x.is_small() && y.is_small() {
+    // The sum of two smalls always fits isize, because smalls use fewer bits
+    return small_or_big(hp, x.get_small_signed() + y.get_small_signed());
+  }
+  if x.is_float() || y.is_float() {
+    return float::apply2(hp, x, y, |a, b| a + b);
+  }
+  match (BigInt::from_term(x), BigInt::from_term(y)) {
+    (Some(a), Some(b)) => a.add(&b).to_term(hp),
+    _ => fail::create::badarith(),
+  }
+}
+
+pub fn subtract(hp: &mut THeap, x: Term, y: Term) -> RtResult<Term> {
+  if x.is_small() && y.is_small() {
+    return small_or_big(hp, x.get_small_signed() - y.get_small_signed());
+  }
+  if x.is_float() || y.is_float() {
+    return float::apply2(hp, x, y, |a, b| a - b);
+  }
+  match (BigInt::from_term(x), BigInt::from_term(y)) {
+    (Some(a), Some(b)) => a.sub(&b).to_term(hp),
+    _ => fail::create::badarith(),
+  }
+}
+
+pub fn negate(hp: &mut THeap, x: Term) -> RtResult<Term> {
+  if x.is_small() {
+    // Negated smallest small is one larger than the largest small
+    return small_or_big(hp, -x.get_small_signed());
+  }
+  if x.is_float() {
+    let val = unsafe { x.get_float_unchecked() };
+    return Term::make_float(hp, -val);
+  }
+  match BigInt::from_term(x) {
+    Some(a) => a.neg().to_term(hp),
+    None => fail::create::badarith(),
+  }
+}
+
+/// Absolute value of a number, the error for other terms is `badarg` as for
+/// the `abs` BIF.
+pub fn abs(hp: &mut THeap, x: Term) -> RtResult<Term> {
+  if x.is_small() {
+    return small_or_big(hp, x.get_small_signed().abs());
+  }
+  if x.is_float() {
+    let val = unsafe { x.get_float_unchecked() };
+    return Term::make_float(hp, val.abs());
+  }
+  match BigInt::from_term(x) {
+    Some(a) => a.abs().to_term(hp),
+    None => fail::create::badarg(),
+  }
+}
+
+/// A result of an operation on two smalls, which may be too large for a small.
+#[inline]
+pub fn small_or_big(hp: &mut THeap, val: isize) -> RtResult<Term> {
+  if Term::small_fits(val) {
+    return Ok(Term::make_small_signed(val));
+  }
+  big::from_isize(hp, val)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::emulator::{
+    arith::multiplication,
+    heap::{flat_heap::FlatHeap, Designation},
+  };
+
+  #[test]
+  fn test_add_subtract_mixed() {
+    let mut heap = FlatHeap::new(Designation::ProcessHeap);
+    let largest = Term::make_small_signed(LARGEST_SMALL);
+    let one = Term::small_1();
+
+    // Overflow promotes to a bignum, the way back demotes to a small
+    let big = add(&mut heap, largest, one).unwrap();
+    assert!(big.is_big_int());
+    assert_eq!(subtract(&mut heap, big, one).unwrap(), largest);
+    assert_eq!(subtract(&mut heap, big, big).unwrap(), Term::small_0());
+
+    // Carry into the next digit and back
+    let big2 = multiplication::multiply(&mut heap, big, big).unwrap();
+    let sum = add(&mut heap, big2, big2).unwrap();
+    let neg_big2 = negate(&mut heap, big2).unwrap();
+    assert_eq!(
+      BigInt::from_term(add(&mut heap, sum, neg_big2).unwrap()),
+      BigInt::from_term(big2)
+    );
+    let smallest = Term::make_small_signed(SMALLEST_SMALL);
+    assert!(negate(&mut heap, smallest).unwrap().is_big_int());
+    assert!(add(&mut heap, largest, Term::nil()).is_err());
+  }
+}
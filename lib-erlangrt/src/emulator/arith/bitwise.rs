@@ -0,0 +1,146 @@
+//! Bitwise operators `band`, `bor`, `bxor`, `bnot`, `bsl` and `bsr`. Negative
+//! integers behave as if they were stored in two's complement with an
+//! infinite number of sign bits, same as in OTP.
+use crate::{
+  big::{self, BigInt},
+  defs,
+  emulator::heap::heap_trait::THeap,
+  fail::{self, RtResult},
+  term::value::*,
+};
+
+pub fn band(hp: &mut THeap, x: Term, y: Term) -> RtResult<Term> {
+  if x.is_small() && y.is_small() {
+    return Ok(Term::make_small_signed(x.get_small_signed() & y.get_small_signed()));
+  }
+  bitwise_big(hp, x, y, BigInt::bitand)
+}
+
+pub fn bor(hp: &mut THeap, x: Term, y: Term) -> RtResult<Term> {
+  if x.is_small() && y.is_small() {
+    return Ok(Term::make_small_signed(x.get_small_signed() | y.get_small_signed()));
+  }
+  bitwise_big(hp, x, y, BigInt::bitor)
+}
+
+pub fn bxor(hp: &mut THeap, x: Term, y: Term) -> RtResult<Term> {
+  if x.is_small() && y.is_small() {
+    return Ok(Term::make_small_signed(x.get_small_signed() ^ y.get_small_signed()));
+  }
+  bitwise_big(hp, x, y, BigInt::bitxor)
+}
+
+pub fn bnot(hp: &mut THeap, x: Term) -> RtResult<Term> {
+  if x.is_small() {
+    return Ok(Term::make_small_signed(!x.get_small_signed()));
+  }
+  match BigInt::from_term(x) {
+    Some(a) => a.bitnot().to_term(hp),
+    None => fail::create::badarith(),
+  }
+}
+
+fn bitwise_big<F>(hp: &mut THeap, x: Term, y: Term, f: F) -> RtResult<Term>
+where
+  F: Fn(&BigInt, &BigInt) -> BigInt,
+{
+  match (BigInt::from_term(x), BigInt::from_term(y)) {
+    (Some(a), Some(b)) => f(&a, &b).to_term(hp),
+    _ => fail::create::badarith(),
+  }
+}
+
+pub fn bsl(hp: &mut THeap, x: Term, y: Term) -> RtResult<Term> {
+  shift(hp, x, y, true)
+}
+
+pub fn bsr(hp: &mut THeap, x: Term, y: Term) -> RtResult<Term> {
+  shift(hp, x, y, false)
+}
+
+/// Shift `x` left by `y` bits, or right if `left` is false. A negative `y`
+/// shifts in the other direction.
+fn shift(hp: &mut THeap, x: Term, y: Term, left: bool) -> RtResult<Term> {
+  let a = match BigInt::from_term(x) {
+    Some(a) => a,
+    None => return fail::create::badarith(),
+  };
+  if !y.is_integer() {
+    return fail::create::badarith();
+  }
+  // Shift amount as a left shift, `None` if it does not fit an isize
+  let n = big::isize_from(y).and_then(|n| if left { Some(n) } else { n.checked_neg() });
+  let n = match n {
+    Some(n) => n,
+    None => {
+      // Shifting by a bignum: left fills the memory, right leaves the sign
+      let y_negative = BigInt::from_term(y).unwrap().negative;
+      if left != y_negative && !a.is_zero() {
+        return fail::create::system_limit();
+      }
+      return Ok(Term::make_small_signed(if a.negative { -1 } else { 0 }));
+    }
+  };
+
+  if n >= 0 {
+    let n = n as usize;
+    if x.is_small() && n < defs::WORD_BITS {
+      let val = x.get_small_signed();
+      let shifted = val << n;
+      if shifted >> n == val && Term::small_fits(shifted) {
+        return Ok(Term::make_small_signed(shifted));
+      }
+    }
+    if a.is_zero() {
+      return Ok(Term::small_0());
+    }
+    if n / defs::WORD_BITS > big::MAX_DIGITS {
+      return fail::create::system_limit();
+    }
+    a.shl(n).to_term(hp)
+  } else {
+    let n = n.unsigned_abs();
+    if x.is_small() {
+      let bits = core::cmp::min(n, defs::WORD_BITS - 1);
+      return Ok(Term::make_small_signed(x.get_small_signed() >> bits));
+    }
+    a.shr(n).to_term(hp)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::emulator::heap::{flat_heap::FlatHeap, Designation};
+
+  fn small(val: isize) -> Term {
+    Term::make_small_signed(val)
+  }
+
+  #[test]
+  fn test_bitwise_twos_complement() {
+    let mut heap = FlatHeap::new(Designation::ProcessHeap);
+    let hp = &mut heap;
+    // -2^100 and 2^100 - 1
+    let minus_big = bsl(hp, small(-1), small(100)).unwrap();
+    let mask = bnot(hp, minus_big).unwrap();
+    assert!(mask.is_big_int());
+
+    assert!(band(hp, minus_big, small(-1)).unwrap().is_big_int());
+    assert_eq!(band(hp, minus_big, small(12345)).unwrap(), small(0));
+    let masked = band(hp, mask, small(-8)).unwrap();
+    assert!(masked.is_big_int());
+    assert_eq!(band(hp, masked, small(7)).unwrap(), small(0));
+    assert_eq!(bor(hp, minus_big, mask).unwrap(), small(-1));
+    assert_eq!(bxor(hp, minus_big, mask).unwrap(), small(-1));
+    assert_eq!(bsr(hp, minus_big, small(100)).unwrap(), small(-1));
+    assert_eq!(bsr(hp, minus_big, small(98)).unwrap(), small(-4));
+    assert_eq!(bsr(hp, mask, small(98)).unwrap(), small(3));
+    assert_eq!(bsl(hp, mask, small(-99)).unwrap(), small(1));
+    assert_eq!(bsr(hp, small(-5), small(1)).unwrap(), small(-3));
+    assert_eq!(bsr(hp, small(-5), small(1000)).unwrap(), small(-1));
+    assert_eq!(bsl(hp, small(1), minus_big).unwrap(), small(0));
+    assert!(bsl(hp, small(1), mask).is_err());
+    assert!(band(hp, small(1), Term::nil()).is_err());
+  }
+}
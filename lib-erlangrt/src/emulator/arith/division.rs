@@ -0,0 +1,89 @@
+//! Integer division `div` and remainder `rem`. Both truncate towards zero,
+//! the remainder has the sign of the dividend. Division by zero is `badarith`.
+use crate::{
+  big::BigInt,
+  emulator::{arith::addition::small_or_big, heap::heap_trait::THeap},
+  fail::{self, RtResult},
+  term::value::*,
+};
+
+pub fn div(hp: &mut THeap, x: Term, y: Term) -> RtResult<Term> {
+  if x.is_small() && y.is_small() {
+    let divisor = y.get_small_signed();
+    if divisor == 0 {
+      return fail::create::badarith();
+    }
+    // The smallest small divided by -1 does not fit a small
+    return small_or_big(hp, x.get_small_signed() / divisor);
+  }
+  match div_rem_big(x, y) {
+    Some((q, _r)) => q.to_term(hp),
+    None => fail::create::badarith(),
+  }
+}
+
+pub fn rem(hp: &mut THeap, x: Term, y: Term) -> RtResult<Term> {
+  if x.is_small() && y.is_small() {
+    let divisor = y.get_small_signed();
+    if divisor == 0 {
+      return fail::create::badarith();
+    }
+    return Ok(Term::make_small_signed(x.get_small_signed() % divisor));
+  }
+  match div_rem_big(x, y) {
+    Some((_q, r)) => r.to_term(hp),
+    None => fail::create::badarith(),
+  }
+}
+
+/// Returns: `None` if an argument is not an integer, or if `y` is zero.
+fn div_rem_big(x: Term, y: Term) -> Option<(BigInt, BigInt)> {
+  BigInt::from_term(x)?.div_rem(&BigInt::from_term(y)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::emulator::{
+    arith::{
+      addition::{add, negate},
+      multiplication::multiply,
+    },
+    heap::flat_heap::FlatHeap,
+  };
+
+  fn pow10(hp: &mut THeap, n: usize) -> Term {
+    let mut x = Term::small_1();
+    for _ in 0..n {
+      x = multiply(hp, x, Term::make_small_signed(10)).unwrap();
+    }
+    x
+  }
+
+  #[test]
+  fn test_div_rem_big() {
+    let mut heap = FlatHeap::new_process_heap(4000);
+    let hp = &mut heap;
+    let three = Term::make_small_signed(3);
+    let seven = Term::make_small_signed(7);
+    // Two digits long divisor, and x = 3 * 10^40 + 7
+    let d = pow10(hp, 20);
+    let x = pow10(hp, 40);
+    let x = multiply(hp, x, three).unwrap();
+    let x = add(hp, x, seven).unwrap();
+    let minus_x = negate(hp, x).unwrap();
+    let q = multiply(hp, three, d).unwrap();
+    let minus_q = negate(hp, q).unwrap();
+
+    assert_eq!(BigInt::from_term(div(hp, x, d).unwrap()), BigInt::from_term(q));
+    assert_eq!(rem(hp, x, d).unwrap(), seven);
+    let minus_x_div_d = div(hp, minus_x, d).unwrap();
+    assert_eq!(BigInt::from_term(minus_x_div_d), BigInt::from_term(minus_q));
+    assert_eq!(rem(hp, minus_x, d).unwrap(), Term::make_small_signed(-7));
+    let d39 = pow10(hp, 39);
+    assert_eq!(div(hp, x, d39).unwrap(), Term::make_small_signed(30));
+
+    assert!(div(hp, x, Term::small_0()).is_err());
+    assert!(rem(hp, Term::small_1(), Term::nil()).is_err());
+  }
+}
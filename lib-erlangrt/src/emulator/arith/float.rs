@@ -0,0 +1,77 @@
+//! Float arithmetic and mixing integers with floats. An integer operand is
+//! converted to a float if the other operand is a float. A result which is
+//! infinite or not a number is a `badarith` error, same as in OTP.
+use crate::{
+  big::BigInt,
+  emulator::heap::heap_trait::THeap,
+  fail::{self, RtResult},
+  term::value::*,
+};
+
+/// Convert a number to a float.
+/// Returns: `None` if the term is not a number.
+pub fn to_f64(t: Term) -> Option<f64> {
+  if t.is_small() {
+    return Some(t.get_small_signed() as f64);
+  }
+  if t.is_float() {
+    return Some(unsafe { t.get_float_unchecked() });
+  }
+  BigInt::from_term(t).map(|big| big.to_f64())
+}
+
+/// Store the result of a float operation, or fail if it is not finite.
+pub fn float_result(hp: &mut THeap, val: f64) -> RtResult<Term> {
+  if !val.is_finite() {
+    return fail::create::badarith();
+  }
+  Term::make_float(hp, val)
+}
+
+/// Apply `f` to the numbers `x` and `y` converted to floats.
+pub fn apply2<F>(hp: &mut THeap, x: Term, y: Term, f: F) -> RtResult<Term>
+where
+  F: Fn(f64, f64) -> f64,
+{
+  match (to_f64(x), to_f64(y)) {
+    (Some(a), Some(b)) => float_result(hp, f(a, b)),
+    _ => fail::create::badarith(),
+  }
+}
+
+/// The `/` operator, the result is always a float.
+pub fn divide(hp: &mut THeap, x: Term, y: Term) -> RtResult<Term> {
+  match (to_f64(x), to_f64(y)) {
+    (Some(a), Some(b)) if b != 0.0 => float_result(hp, a / b),
+    _ => fail::create::badarith(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::emulator::{
+    arith::{addition, multiplication},
+    heap::{flat_heap::FlatHeap, Designation},
+  };
+
+  #[test]
+  fn test_mixed_int_float() {
+    let mut heap = FlatHeap::new(Designation::ProcessHeap);
+    let hp = &mut heap;
+    let half = Term::make_float(hp, 0.5).unwrap();
+    let three = Term::make_small_signed(3);
+
+    let sum = addition::add(hp, three, half).unwrap();
+    assert_eq!(sum.get_float().unwrap(), 3.5);
+    let product = multiplication::multiply(hp, half, three).unwrap();
+    assert_eq!(product.get_float().unwrap(), 1.5);
+    let two = Term::make_small_signed(2);
+    assert_eq!(divide(hp, three, two).unwrap().get_float().unwrap(), 1.5);
+    assert!(divide(hp, three, Term::small_0()).is_err());
+
+    let huge = Term::make_float(hp, 1e308).unwrap();
+    assert!(multiplication::multiply(hp, huge, huge).is_err());
+    assert!(addition::add(hp, half, Term::nil()).is_err());
+  }
+}
@@ -1 +1,5 @@
+pub mod addition;
+pub mod bitwise;
+pub mod division;
+pub mod float;
 pub mod multiplication;
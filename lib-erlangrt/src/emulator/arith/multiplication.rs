@@ -1,4 +1,9 @@
-use crate::{big, emulator::heap::heap_trait::THeap, fail::RtResult, term::value::*};
+use crate::{
+  big::BigInt,
+  emulator::{arith::float, heap::heap_trait::THeap},
+  fail::{self, RtResult},
+  term::value::*,
+};
 
 #[allow(dead_code)]
 fn module() -> &'static str {
@@ -18,12 +23,15 @@ pub fn multiply(hp: &mut THeap, x: Term, y: Term) -> RtResult<Term> {
       }
       let result = multiply_two_small(hp, x.get_small_signed(), y.get_small_signed())?;
       return Ok(result);
-    } else {
-      // TODO: See Erlang OTP erl_arith.c function erts_mixed_times
-      unimplemented!("{}b={} other than small", module(), y)
     }
   }
-  unimplemented!("{}a={} other than small", module(), x)
+  if x.is_float() || y.is_float() {
+    return float::apply2(hp, x, y, |a, b| a * b);
+  }
+  match (BigInt::from_term(x), BigInt::from_term(y)) {
+    (Some(a), Some(b)) => a.mul(&b).to_term(hp),
+    _ => fail::create::badarith(),
+  }
 }
 
 /// Optimistic multiplication which will possibly fit the small int without
@@ -55,9 +63,6 @@ pub fn multiply_two_small(hp: &mut THeap, x: isize, y: isize) -> RtResult<Term>
     None => {}
   }
 
-  // Pessimistic case, the result did not fit, so proceed with 2 big ints
-  // creating a third big int.
-  let big_x = big::from_isize(hp, x)?;
-  let big_y = big::from_isize(hp, y)?;
-  big::mul(hp, big_x, big_y)
+  // Pessimistic case, the result did not fit, so multiply as big integers
+  BigInt::from_isize(x).mul(&BigInt::from_isize(y)).to_term(hp)
 }
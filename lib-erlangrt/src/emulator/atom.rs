@@ -130,6 +130,17 @@ pub fn from_str(val: &str) -> Term {
   Term::make_atom(index)
 }
 
+/// How many atoms are there in the atom table.
+pub fn count() -> usize {
+  ATOMS.atoms_by_index.lock().unwrap().len()
+}
+
+/// Copy of all atom names, in the order of their indices.
+pub fn get_all_names() -> Vec<String> {
+  let atoms_r = ATOMS.atoms_by_index.lock().unwrap();
+  atoms_r.iter().map(|a| a.name.clone()).collect()
+}
+
 pub fn to_str(a: Term) -> RtResult<String> {
   assert!(a.is_atom());
   let p = lookup(a);
@@ -0,0 +1,94 @@
+//! Literal areas are heaps where loaded modules keep their constant terms
+//! (see `Module::lit_heap`). Literals are never garbage collected and never
+//! change, so terms pointing into a literal area are not copied when sent to
+//! another process or passed to spawn, the copy shares them instead.
+//!
+//! A global registry of literal area address ranges is kept here, so that
+//! `copy_term` can recognise literals without access to the code server. The
+//! code server keeps a literal area alive while any process may refer to it,
+//! this is checked when the module is purged.
+use crate::{
+  defs::Word,
+  emulator::heap::gc::FromSpaceRange,
+  term::value::{PrimaryTag, Term},
+};
+use std::sync::RwLock;
+
+/// Address ranges `[begin, end)` of all registered literal areas. Stored as
+/// integers because raw pointers can not be shared between threads.
+type AreaList = Vec<(usize, usize)>;
+
+lazy_static! {
+  static ref LITERAL_AREAS: RwLock<AreaList> = RwLock::new(Vec::new());
+}
+
+/// Register memory range of a module literal heap. Empty ranges are ignored.
+pub fn register(range: FromSpaceRange) {
+  if range.0 == range.1 {
+    return;
+  }
+  let mut areas = LITERAL_AREAS.write().unwrap();
+  areas.push((range.0 as usize, range.1 as usize));
+}
+
+/// Forget a literal area, called before its memory is freed.
+pub fn unregister(range: FromSpaceRange) {
+  let mut areas = LITERAL_AREAS.write().unwrap();
+  areas.retain(|(begin, _)| *begin != range.0 as usize);
+}
+
+/// Check whether `p` points into any registered literal area. Takes the lock,
+/// use `LiteralAreas` to check many pointers.
+pub fn is_literal_ptr(p: *const Word) -> bool {
+  let areas = LITERAL_AREAS.read().unwrap();
+  area_list_contains(&areas, p)
+}
+
+#[inline]
+fn area_list_contains(areas: &AreaList, p: *const Word) -> bool {
+  let p = p as usize;
+  areas.iter().any(|(begin, end)| p >= *begin && p < *end)
+}
+
+/// A copy of the literal area ranges, taken once for a whole heap walk or
+/// a term copy, so that the lock is not taken for every visited cell.
+pub struct LiteralAreas {
+  areas: AreaList,
+}
+
+impl LiteralAreas {
+  pub fn snapshot() -> Self {
+    Self {
+      areas: LITERAL_AREAS.read().unwrap().clone(),
+    }
+  }
+
+  #[inline]
+  pub fn contains(&self, p: *const Word) -> bool {
+    area_list_contains(&self.areas, p)
+  }
+}
+
+/// Check whether `t` is a list or a boxed term, which points into a literal
+/// area. Such terms do not need to be copied.
+#[inline]
+pub fn is_literal(t: Term) -> bool {
+  match t.get_term_tag() {
+    PrimaryTag::CONS_PTR | PrimaryTag::BOX_PTR => {
+      is_literal_ptr(t.get_box_ptr_unchecked::<Word>())
+    }
+    _ => false,
+  }
+}
+
+/// Check whether `t` is a list or a boxed term, which points into `range`.
+#[inline]
+pub fn term_points_into(t: Term, range: FromSpaceRange) -> bool {
+  match t.get_term_tag() {
+    PrimaryTag::CONS_PTR | PrimaryTag::BOX_PTR => {
+      let p = t.get_box_ptr_unchecked::<Word>();
+      p >= range.0 && p < range.1
+    }
+    _ => false,
+  }
+}
@@ -7,6 +7,7 @@ use crate::{
   emulator::{
     atom,
     code::{pointer::VersionedCodePtr, CodePtr},
+    heap::{gc::FromSpaceRange, Designation, Heap},
     mfa::ModFunArity,
     module::{Module, VersionedModuleName},
   },
@@ -16,9 +17,12 @@ use crate::{
 };
 use std::{
   collections::BTreeMap,
+  mem,
   path::{Path, PathBuf},
 };
 
+pub mod literal_area;
+
 fn module() -> &'static str {
   "code_srv: "
 }
@@ -46,6 +50,10 @@ pub struct CodeServer {
   mods: BTreeMap<Term, ModuleGenerations>,
   search_path: Vec<String>,
   mod_version: usize,
+  /// Literal heaps of purged modules, which might be still referenced from
+  /// process heaps. Freed by `release_unused_literals` when nobody refers to
+  /// them anymore.
+  purged_literals: Vec<Heap>,
 
   pub native_functions: NativeFunRegistry,
 }
@@ -55,6 +63,7 @@ impl CodeServer {
     CodeServer {
       mod_version: 1,
       mods: BTreeMap::new(),
+      purged_literals: Vec::new(),
       search_path: args.search_path.clone(),
       native_functions: NativeFunRegistry::new(),
     }
@@ -126,10 +135,24 @@ impl CodeServer {
   }
 
   /// Notify the code server about the fact that a new module is ready to be
-  /// added to the codebase.
+  /// added to the codebase. The current version of the module, if any,
+  /// becomes old, and the previous old version is purged, its literals are
+  /// freed by the next `release_unused_literals`.
   pub fn module_loaded(&mut self, mod_ptr: Box<Module>) {
     let name = mod_ptr.versioned_name.module;
     let v = mod_ptr.versioned_name.version;
+    literal_area::register(mod_ptr.lit_heap.get_used_range());
+
+    if self.mods.contains_key(&name) {
+      self.purge_module(name);
+      let mg = self.mods.get_mut(&name).unwrap();
+      let prev_modp = mem::replace(&mut mg.curr_modp, mod_ptr);
+      mg.old_modp = Some(prev_modp);
+      mg.old_version = mg.curr_version;
+      mg.curr_version = v;
+      return;
+    }
+
     let mg = ModuleGenerations {
       curr_modp: mod_ptr,
       curr_version: v,
@@ -139,6 +162,49 @@ impl CodeServer {
     self.mods.insert(name, mg);
   }
 
+  /// Remove the old version of module `m`. Its literal heap is not freed
+  /// immediately, because processes may still refer to the literals, instead
+  /// it waits for `release_unused_literals`.
+  /// Returns: false if there was no old version to purge.
+  pub fn purge_module(&mut self, m: Term) -> bool {
+    let old_modp = match self.mods.get_mut(&m) {
+      Some(mg) => mg.old_modp.take(),
+      None => None,
+    };
+    match old_modp {
+      Some(mut modp) => {
+        let empty = Heap::new(Designation::TransientDestructible);
+        let lit_heap = mem::replace(&mut modp.lit_heap, empty);
+        self.purged_literals.push(lit_heap);
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Whether literal heaps of purged modules are waiting to be freed.
+  #[inline]
+  pub fn has_purged_literals(&self) -> bool {
+    !self.purged_literals.is_empty()
+  }
+
+  /// Free literal heaps of purged modules for which `is_referenced` returns
+  /// false. Nothing may run meanwhile, which could copy the literals around
+  /// (see `VM::release_unused_literals`).
+  pub fn release_unused_literals<F>(&mut self, is_referenced: F)
+  where
+    F: Fn(FromSpaceRange) -> bool,
+  {
+    self.purged_literals.retain(|lit_heap| {
+      let range = lit_heap.get_used_range();
+      if is_referenced(range) {
+        return true;
+      }
+      literal_area::unregister(range);
+      false
+    });
+  }
+
   /// Lookup, which will attempt to load a missing module if lookup fails
   /// on the first attempt.
   pub fn lookup_beam_code_and_load(&mut self, mfarity: &ModFunArity) -> RtResult<CodePtr> {
@@ -193,6 +259,22 @@ impl CodeServer {
     None
   }
 
+  /// For each loaded module return its name, and code sizes in bytes of the
+  /// current and old versions (0 if there is no old version).
+  pub fn get_module_sizes(&self) -> Vec<(Term, usize, usize)> {
+    self
+      .mods
+      .iter()
+      .map(|(name, mg)| {
+        let old_size = match &mg.old_modp {
+          Some(modp) => modp.get_code_size(),
+          None => 0,
+        };
+        (*name, mg.curr_modp.get_code_size(), old_size)
+      })
+      .collect()
+  }
+
   pub fn next_module_version(&mut self, _m: Term) -> usize {
     let ver = self.mod_version;
     self.mod_version += 1;
@@ -200,6 +282,20 @@ impl CodeServer {
   }
 }
 
+impl Drop for CodeServer {
+  /// Literal areas of all modules, which are freed with the code server, are
+  /// forgotten, including the current and old versions of loaded modules.
+  fn drop(&mut self) {
+    let loaded = self.mods.values().flat_map(|mg| {
+      let old = mg.old_modp.as_ref().map(|m| &m.lit_heap);
+      Some(&mg.curr_modp.lit_heap).into_iter().chain(old)
+    });
+    for lit_heap in self.purged_literals.iter().chain(loaded) {
+      literal_area::unregister(lit_heap.get_used_range());
+    }
+  }
+}
+
 /// Iterate through the search path list and try to find a file
 fn first_that_exists(search_path: &[String], filename: &str) -> Option<PathBuf> {
   for s in search_path {
@@ -0,0 +1,472 @@
+//! Writes `erl_crash.dump` when the VM stops on a fatal error or a panic. The
+//! format follows OTP (see `erl_crash_dump.c` and `erl_process_dump.c`), so
+//! the file can be opened with the standard `crashdump_viewer`.
+//!
+//! Heap of each process is dumped as seen from its roots (stack, live
+//! registers and messages), each object on its own line `<addr>:<term>`,
+//! where the terms are encoded as:
+//! * `I<int>` small integer, `A<len hex>:<text>` atom, `N` nil, `P<pid>`;
+//! * `H<addr>` reference to another heap object;
+//! * `l<head>|<tail>` cons cell, `t<arity hex>:<elements,...>` tuple;
+//! * `F<len hex>:<text>` float, `B<int>` bignum,
+//!   `Yh<size hex>:<hex bytes>` binary;
+//! * `p<0.0>` any other object.
+use crate::{
+  defs::{Word, WORD_BYTES},
+  emulator::{
+    atom,
+    code::CodePtr,
+    code_srv::CodeServer,
+    process::Process,
+    scheduler,
+    vm::VM,
+  },
+  term::{
+    boxed::{self, BoxHeader},
+    value::{PrimaryTag, SpecialConst, SpecialTag, Term},
+  },
+};
+use core::ptr;
+use std::{
+  collections::HashSet,
+  env,
+  fs::File,
+  io::{self, BufWriter, Write},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Crash dump format version, as produced by OTP 22.
+const DUMP_VERSION: &str = "0.5";
+
+/// Default file name, can be changed with `ERL_CRASH_DUMP` environment variable.
+const DEFAULT_DUMP_FILE: &str = "erl_crash.dump";
+
+/// Write the crash dump for the `vm`, `slogan` is the reason for the crash.
+/// Returns: the file name where the dump has been written.
+pub fn write_crash_dump(vm: &VM, slogan: &str) -> io::Result<String> {
+  let filename =
+    env::var("ERL_CRASH_DUMP").unwrap_or_else(|_| DEFAULT_DUMP_FILE.to_string());
+  println!("Crash dump is being written to: {}...", filename);
+
+  // Other schedulers are stopping, the processes are inspected as they are
+  let mut handles = vm.processes.get_all();
+  handles.sort_by_key(|h| h.pid().raw());
+  let processes: Vec<&Process> =
+    handles.iter().map(|h| unsafe { &(*h.as_ptr()) }).collect();
+  let cs = vm.code_server_after_crash();
+
+  let mut out = BufWriter::new(File::create(&filename)?);
+  dump_preamble(&mut out, vm, slogan)?;
+  for id in 0..vm.scheduler.count() {
+    dump_scheduler(&mut out, vm, id)?;
+  }
+  dump_memory(&mut out, &processes)?;
+  for (h, p) in handles.iter().zip(processes.iter()) {
+    let queue = h.lock_run_state().queue;
+    dump_process(&mut out, vm, &cs, p, queue)?;
+  }
+
+  dump_modules(&mut out, &cs)?;
+  dump_atoms(&mut out)?;
+  writeln!(out, "=end")?;
+  out.flush()?;
+
+  println!("done");
+  Ok(filename)
+}
+
+fn dump_preamble(out: &mut Write, vm: &VM, slogan: &str) -> io::Result<()> {
+  writeln!(out, "=erl_crash_dump:{}", DUMP_VERSION)?;
+  writeln!(out, "{}", format_date(SystemTime::now()))?;
+  writeln!(out, "Slogan: {}", slogan)?;
+  writeln!(
+    out,
+    "System version: ErlangRT {} (compat OTP 22)",
+    env!("CARGO_PKG_VERSION")
+  )?;
+  writeln!(out, "Taints: ")?;
+  writeln!(out, "Atoms: {}", atom::count())?;
+  writeln!(out, "Calling Thread: scheduler:{}", vm.scheduler.get_id() + 1)
+}
+
+/// Scheduler `id` is written as `id + 1`, OTP numbers them from 1.
+fn dump_scheduler(out: &mut Write, vm: &VM, id: usize) -> io::Result<()> {
+  let sched = &vm.scheduler;
+  writeln!(out, "=scheduler:{}", id + 1)?;
+  writeln!(out, "Scheduler Sleep Info Flags: ")?;
+  writeln!(out, "Scheduler Sleep Info Aux Work: ")?;
+  writeln!(out, "Current Port: ")?;
+  let high = sched.queue_len(id, scheduler::Queue::High);
+  let normal = sched.queue_len(id, scheduler::Queue::Normal);
+  let low = sched.queue_len(id, scheduler::Queue::Low);
+  let max = *[high, normal, low].iter().max().unwrap();
+  writeln!(out, "Run Queue Max Length: {}", max)?;
+  writeln!(out, "Run Queue High Length: {}", high)?;
+  writeln!(out, "Run Queue Normal Length: {}", normal)?;
+  writeln!(out, "Run Queue Low Length: {}", low)?;
+  writeln!(out, "Run Queue Port Length: 0")?;
+  writeln!(out, "Run Queue Flags: ")?;
+  match sched.get_current_of(id) {
+    Some(pid) => writeln!(out, "Current Process: {}", format_pid(pid)),
+    None => writeln!(out, "Current Process: "),
+  }
+}
+
+fn dump_memory(out: &mut Write, processes: &[&Process]) -> io::Result<()> {
+  let processes_words: usize = processes.iter().map(|p| p.get_heap_size()).sum();
+  let processes_used: usize = processes
+    .iter()
+    .map(|p| p.get_flat_heap().get_heap_used_words() + p.get_heap().stack_depth())
+    .sum();
+  writeln!(out, "=memory")?;
+  writeln!(out, "total: {}", processes_words * WORD_BYTES)?;
+  writeln!(out, "processes: {}", processes_words * WORD_BYTES)?;
+  writeln!(out, "processes_used: {}", processes_used * WORD_BYTES)
+}
+
+fn process_state(vm: &VM, p: &Process, queue: scheduler::Queue) -> &'static str {
+  let sched = &vm.scheduler;
+  if (0..sched.count()).any(|id| sched.get_current_of(id) == Some(p.pid)) {
+    return "Running";
+  }
+  match queue {
+    scheduler::Queue::High | scheduler::Queue::Normal | scheduler::Queue::Low => {
+      "Scheduled"
+    }
+    scheduler::Queue::TimedWait | scheduler::Queue::InfiniteWait => "Waiting",
+    scheduler::Queue::None => "Exiting",
+  }
+}
+
+fn dump_process(
+  out: &mut Write,
+  vm: &VM,
+  cs: &CodeServer,
+  p: &Process,
+  queue: scheduler::Queue,
+) -> io::Result<()> {
+  let pid = format_pid(p.pid);
+  let heap = p.get_flat_heap();
+  let capacity = heap.get_heap_max_capacity();
+  let used = heap.get_heap_used_words() + p.get_heap().stack_depth();
+  let messages = p.mailbox.get_messages();
+
+  writeln!(out, "=proc:{}", pid)?;
+  writeln!(out, "State: {}", process_state(vm, p, queue))?;
+  if let Some(name) = vm.processes.find_name_of(p.pid) {
+    writeln!(out, "Name: {}", atom::to_str(name).unwrap_or_default())?;
+  }
+  if let Some(mfa) = cs.code_reverse_lookup(p.context.ip) {
+    writeln!(out, "Current call: {}", mfa)?;
+  }
+  writeln!(out, "Spawned by: []")?;
+  writeln!(out, "Message queue length: {}", messages.len())?;
+  writeln!(out, "Number of heap fragments: {}", p.mailbox.get_fragment_count())?;
+  writeln!(out, "Heap fragment data: {}", p.mailbox.get_fragment_words())?;
+  let links: Vec<String> = p.links.iter().map(|l| format_pid(*l)).collect();
+  writeln!(out, "Link list: [{}]", links.join(", "))?;
+  writeln!(out, "Stack+heap: {}", capacity)?;
+  writeln!(out, "OldHeap: 0")?;
+  writeln!(out, "Heap unused: {}", capacity - used)?;
+  writeln!(out, "OldHeap unused: 0")?;
+  writeln!(out, "Memory: {}", capacity * WORD_BYTES)?;
+  writeln!(
+    out,
+    "Program counter: {:p} ({})",
+    p.context.ip.get_pointer(),
+    format_code_location(cs, p.context.ip)
+  )?;
+  writeln!(
+    out,
+    "CP: {:p} ({})",
+    p.context.cp.get_pointer(),
+    format_code_location(cs, p.context.cp)
+  )?;
+  writeln!(out, "arity = {}", p.context.live)?;
+
+  let stack = heap.get_stack_slice();
+  writeln!(out, "=proc_stack:{}", pid)?;
+  dump_stack(out, cs, stack)?;
+
+  let mut dumper = HeapDumper::new();
+  writeln!(out, "=proc_heap:{}", pid)?;
+  dumper.dump_reachable(out, stack)?;
+  dumper.dump_reachable(out, p.context.live_registers(p.context.live))?;
+  dumper.dump_reachable(out, &messages)?;
+
+  writeln!(out, "=proc_messages:{}", pid)?;
+  for m in messages.iter() {
+    write_element(out, *m)?;
+    writeln!(out, ":N")?;
+  }
+  Ok(())
+}
+
+/// Stack is printed top to bottom, return addresses separate the frames and
+/// Y registers are numbered from 0 in each frame.
+fn dump_stack(out: &mut Write, cs: &CodeServer, stack: &[Term]) -> io::Result<()> {
+  let mut yreg = 0;
+  for (i, t) in stack.iter().enumerate() {
+    if t.is_cp() {
+      let cp = CodePtr::from_cp(*t);
+      writeln!(
+        out,
+        "{:p}:SReturn addr {:p} ({})",
+        &stack[i] as *const Term,
+        cp.get_pointer(),
+        format_code_location(cs, cp)
+      )?;
+      yreg = 0;
+    } else {
+      write!(out, "y{}:", yreg)?;
+      write_element(out, *t)?;
+      writeln!(out)?;
+      yreg += 1;
+    }
+  }
+  Ok(())
+}
+
+fn dump_modules(out: &mut Write, cs: &CodeServer) -> io::Result<()> {
+  let modules = cs.get_module_sizes();
+  let current: usize = modules.iter().map(|(_, curr, _)| *curr).sum();
+  let old: usize = modules.iter().map(|(_, _, old)| *old).sum();
+  writeln!(out, "=loaded_modules")?;
+  writeln!(out, "Current code: {}", current)?;
+  writeln!(out, "Old code: {}", old)?;
+  for (name, curr_size, old_size) in modules {
+    writeln!(out, "=mod:{}", atom::to_str(name).unwrap_or_default())?;
+    writeln!(out, "Current size: {}", curr_size)?;
+    if old_size != 0 {
+      writeln!(out, "Old size: {}", old_size)?;
+    }
+  }
+  Ok(())
+}
+
+fn dump_atoms(out: &mut Write) -> io::Result<()> {
+  writeln!(out, "=atoms")?;
+  // Newest atoms go first, like OTP does it
+  for name in atom::get_all_names().iter().rev() {
+    writeln!(out, "{}", name)?;
+  }
+  Ok(())
+}
+
+/// Walks heap objects reachable from the given roots and prints each once.
+struct HeapDumper {
+  visited: HashSet<*const Word>,
+}
+
+impl HeapDumper {
+  fn new() -> Self {
+    Self {
+      visited: HashSet::new(),
+    }
+  }
+
+  fn dump_reachable(&mut self, out: &mut Write, roots: &[Term]) -> io::Result<()> {
+    let mut pending: Vec<Term> = roots.iter().rev().cloned().collect();
+    while let Some(t) = pending.pop() {
+      let p = match t.get_term_tag() {
+        PrimaryTag::CONS_PTR => t.get_cons_ptr() as *const Word,
+        PrimaryTag::BOX_PTR if !t.is_non_value() && !t.is_cp() => {
+          t.get_box_ptr::<Word>()
+        }
+        _ => continue,
+      };
+      if !self.visited.insert(p) {
+        continue;
+      }
+      write!(out, "{:X}:", p as usize)?;
+      unsafe {
+        if t.is_cons() {
+          self.dump_cons(out, p, &mut pending)?;
+        } else {
+          self.dump_boxed(out, t, &mut pending)?;
+        }
+      }
+      writeln!(out)?;
+    }
+    Ok(())
+  }
+
+  unsafe fn dump_cons(
+    &self,
+    out: &mut Write,
+    p: *const Word,
+    pending: &mut Vec<Term>,
+  ) -> io::Result<()> {
+    let head = Term::from_raw(ptr::read(p));
+    let tail = Term::from_raw(ptr::read(p.add(1)));
+    write!(out, "l")?;
+    write_element(out, head)?;
+    write!(out, "|")?;
+    write_element(out, tail)?;
+    pending.push(tail);
+    pending.push(head);
+    Ok(())
+  }
+
+  unsafe fn dump_boxed(
+    &self,
+    out: &mut Write,
+    t: Term,
+    pending: &mut Vec<Term>,
+  ) -> io::Result<()> {
+    let header_p = t.get_box_ptr::<BoxHeader>();
+    let box_type = (*(*header_p).get_trait_ptr()).get_type();
+    if box_type == boxed::BOXTYPETAG_TUPLE {
+      let tuple_p = t.get_tuple_ptr();
+      let arity = (*tuple_p).get_arity();
+      write!(out, "t{:X}:", arity)?;
+      for i in 0..arity {
+        if i > 0 {
+          write!(out, ",")?;
+        }
+        let element = (*tuple_p).get_element(i);
+        write_element(out, element)?;
+        pending.push(element);
+      }
+      Ok(())
+    } else if box_type == boxed::BOXTYPETAG_FLOAT {
+      let text = format!("{:e}", t.get_float_unchecked());
+      write!(out, "F{:X}:{}", text.len(), text)
+    } else if box_type == boxed::BOXTYPETAG_BIGINTEGER {
+      write!(out, "B{}", t)
+    } else if box_type == boxed::BOXTYPETAG_BINARY {
+      write_binary(out, t)
+    } else {
+      write!(out, "p<0.0>")
+    }
+  }
+}
+
+/// Print a term which is an element of another term or a root: pointers are
+/// printed as references to heap objects, immediate values are printed inline.
+fn write_element(out: &mut Write, t: Term) -> io::Result<()> {
+  match t.get_term_tag() {
+    PrimaryTag::CONS_PTR => write!(out, "H{:X}", t.get_cons_ptr() as usize),
+    PrimaryTag::BOX_PTR if t.is_non_value() => write!(out, "N"),
+    PrimaryTag::BOX_PTR => write!(out, "H{:X}", t.get_box_ptr::<Word>() as usize),
+    PrimaryTag::SMALL_INT => write!(out, "I{}", t.get_small_signed()),
+    PrimaryTag::ATOM => {
+      let name = atom::to_str(t).unwrap_or_default();
+      write!(out, "A{:X}:{}", name.len(), name)
+    }
+    PrimaryTag::LOCAL_PID => write!(out, "P{}", format_pid(t)),
+    PrimaryTag::LOCAL_PORT => write!(out, "p<0.{}>", t.get_term_val_without_tag()),
+    PrimaryTag::SPECIAL if t.get_special_tag() == SpecialTag::CONST => {
+      match t.get_special_value() {
+        v if v == SpecialConst::EMPTY_TUPLE.0 => write!(out, "t0:"),
+        v if v == SpecialConst::EMPTY_BINARY.0 => write!(out, "Yh0:"),
+        _ => write!(out, "N"),
+      }
+    }
+    _ => write!(out, "N"),
+  }
+}
+
+unsafe fn write_binary(out: &mut Write, t: Term) -> io::Result<()> {
+  use crate::defs::data_reader::TDataReader;
+  let bin_trait = boxed::Binary::get_trait_from_term(t);
+  match (*bin_trait).get_byte_reader() {
+    Some(reader) => {
+      let size = reader.get_bit_size().get_byte_size_rounded_up().bytes();
+      write!(out, "Yh{:X}:", size)?;
+      for i in 0..size {
+        write!(out, "{:02X}", reader.read(i))?;
+      }
+      Ok(())
+    }
+    // Unaligned binary slices are not printed
+    None => write!(out, "Yh0:"),
+  }
+}
+
+fn format_pid(pid: Term) -> String {
+  format!("<0.{}.0>", pid.get_term_val_without_tag())
+}
+
+fn format_code_location(cs: &CodeServer, ip: CodePtr) -> String {
+  if ip.is_null() {
+    return "<terminate process normally>".to_string();
+  }
+  match cs.code_reverse_lookup(ip) {
+    Some(mfa) => format!("{}", mfa),
+    None => "unknown function".to_string(),
+  }
+}
+
+/// Format current time (UTC) like C `ctime` does: `Sun Oct 18 12:34:56 2026`.
+fn format_date(now: SystemTime) -> String {
+  const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+  const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+  ];
+  let secs = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+  let days = (secs / 86400) as i64;
+  let (year, month, day) = civil_from_days(days);
+  let time_of_day = secs % 86400;
+  format!(
+    "{} {} {:2} {:02}:{:02}:{:02} {}",
+    WEEKDAYS[(days % 7) as usize],
+    MONTHS[(month - 1) as usize],
+    day,
+    time_of_day / 3600,
+    time_of_day % 3600 / 60,
+    time_of_day % 60,
+    year
+  )
+}
+
+/// Convert days since 1970-01-01 to (year, month, day), using the algorithm
+/// from H. Hinnant's "chrono-Compatible Low-Level Date Algorithms".
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+  let z = days + 719_468;
+  let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+  let doe = z - era * 146_097;
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+  (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    emulator::heap::{Designation, Heap},
+    term::term_builder::{tuple_builder::tuple2, ListBuilder},
+  };
+
+  #[test]
+  fn test_dump_heap_terms() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    unsafe {
+      let mut lb = ListBuilder::new().unwrap();
+      lb.append(Term::make_small_unsigned(1), &mut hp).unwrap();
+      let lst = lb.make_term();
+      let t = tuple2(&mut hp, lst, atom::from_str("foo")).unwrap();
+
+      let mut out = Vec::<u8>::new();
+      HeapDumper::new().dump_reachable(&mut out, &[t, lst]).unwrap();
+      let tuple_addr = t.get_box_ptr::<Word>() as usize;
+      let cons_addr = lst.get_cons_ptr() as usize;
+      let expected = format!(
+        "{:X}:t2:H{:X},A3:foo\n{:X}:lI1|N\n",
+        tuple_addr, cons_addr, cons_addr
+      );
+      assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+  }
+
+  #[test]
+  fn test_civil_from_days() {
+    assert_eq!(civil_from_days(0), (1970, 1, 1));
+    assert_eq!(civil_from_days(20_744), (2026, 10, 18));
+  }
+}
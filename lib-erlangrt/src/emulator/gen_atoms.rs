@@ -1,6 +1,6 @@
 //! Generated by `codegen/create_gen_atoms.py`
 //! Creates array of predefined atoms
-//! Config used: OTP22
+//! Config used: OTP24
 #![allow(dead_code)]
 
 use crate::term::value::*;
@@ -9,69 +9,159 @@ use crate::term::value::*;
 pub const SYM_PLUS: Term = Term::make_atom(0);
 pub const SYM_MINUS: Term = Term::make_atom(1);
 pub const SYM_EQ_EQ: Term = Term::make_atom(2);
-pub const ALL: Term = Term::make_atom(3);
-pub const APPLY: Term = Term::make_atom(4);
-pub const BADARG: Term = Term::make_atom(5);
-pub const BADARITH: Term = Term::make_atom(6);
-pub const BADARITY: Term = Term::make_atom(7);
-pub const BADFUN: Term = Term::make_atom(8);
-pub const BADMATCH: Term = Term::make_atom(9);
-pub const CASE_CLAUSE: Term = Term::make_atom(10);
-pub const ERLANG: Term = Term::make_atom(11);
-pub const ERROR: Term = Term::make_atom(12);
-pub const ERTS_INTERNAL: Term = Term::make_atom(13);
-pub const EXIT: Term = Term::make_atom(14);
-pub const FALSE: Term = Term::make_atom(15);
-pub const FUNCTION_CLAUSE: Term = Term::make_atom(16);
-pub const HIGH: Term = Term::make_atom(17);
-pub const IF_CLAUSE: Term = Term::make_atom(18);
-pub const INIT: Term = Term::make_atom(19);
-pub const KILL: Term = Term::make_atom(20);
-pub const KILLED: Term = Term::make_atom(21);
-pub const LOW: Term = Term::make_atom(22);
-pub const NIF_ERROR: Term = Term::make_atom(23);
-pub const NOCATCH: Term = Term::make_atom(24);
-pub const NORMAL: Term = Term::make_atom(25);
-pub const OK: Term = Term::make_atom(26);
-pub const SYSTEM_LIMIT: Term = Term::make_atom(27);
-pub const THROW: Term = Term::make_atom(28);
-pub const TRAP_EXIT: Term = Term::make_atom(29);
-pub const TRUE: Term = Term::make_atom(30);
-pub const UNDEF: Term = Term::make_atom(31);
-pub const UNDEFINED: Term = Term::make_atom(32);
+pub const DOWN_UPPER: Term = Term::make_atom(3);
+pub const EXIT_UPPER: Term = Term::make_atom(4);
+pub const ABS: Term = Term::make_atom(5);
+pub const ALL: Term = Term::make_atom(6);
+pub const APPLY: Term = Term::make_atom(7);
+pub const ASYNC: Term = Term::make_atom(8);
+pub const BADARG: Term = Term::make_atom(9);
+pub const BADARITH: Term = Term::make_atom(10);
+pub const BADARITY: Term = Term::make_atom(11);
+pub const BADFUN: Term = Term::make_atom(12);
+pub const BADMATCH: Term = Term::make_atom(13);
+pub const CANCEL_TIMER: Term = Term::make_atom(14);
+pub const CASE_CLAUSE: Term = Term::make_atom(15);
+pub const COMPACT: Term = Term::make_atom(16);
+pub const CURRENT_FUNCTION: Term = Term::make_atom(17);
+pub const DECIMALS: Term = Term::make_atom(18);
+pub const DICTIONARY: Term = Term::make_atom(19);
+pub const ERLANG: Term = Term::make_atom(20);
+pub const ERROR: Term = Term::make_atom(21);
+pub const ERROR_LOGGER: Term = Term::make_atom(22);
+pub const ERTS_INTERNAL: Term = Term::make_atom(23);
+pub const EXIT: Term = Term::make_atom(24);
+pub const FALSE: Term = Term::make_atom(25);
+pub const FLUSH: Term = Term::make_atom(26);
+pub const FULLSWEEP_AFTER: Term = Term::make_atom(27);
+pub const FUNCTION_CLAUSE: Term = Term::make_atom(28);
+pub const HEAP_SIZE: Term = Term::make_atom(29);
+pub const HIBERNATE: Term = Term::make_atom(30);
+pub const HIGH: Term = Term::make_atom(31);
+pub const IF_CLAUSE: Term = Term::make_atom(32);
+pub const INFINITY: Term = Term::make_atom(33);
+pub const INFO: Term = Term::make_atom(34);
+pub const INIT: Term = Term::make_atom(35);
+pub const KILL: Term = Term::make_atom(36);
+pub const KILLED: Term = Term::make_atom(37);
+pub const LINK: Term = Term::make_atom(38);
+pub const LINKS: Term = Term::make_atom(39);
+pub const LOW: Term = Term::make_atom(40);
+pub const MAX_HEAP_SIZE: Term = Term::make_atom(41);
+pub const MESSAGE_QUEUE_DATA: Term = Term::make_atom(42);
+pub const MESSAGE_QUEUE_LEN: Term = Term::make_atom(43);
+pub const MESSAGES: Term = Term::make_atom(44);
+pub const MIN_BIN_VHEAP_SIZE: Term = Term::make_atom(45);
+pub const MIN_HEAP_SIZE: Term = Term::make_atom(46);
+pub const MONITOR: Term = Term::make_atom(47);
+pub const MONITORS: Term = Term::make_atom(48);
+pub const NIF_ERROR: Term = Term::make_atom(49);
+pub const NOCATCH: Term = Term::make_atom(50);
+pub const NONODE_NOHOST: Term = Term::make_atom(51);
+pub const NOPROC: Term = Term::make_atom(52);
+pub const NORMAL: Term = Term::make_atom(53);
+pub const OFF_HEAP: Term = Term::make_atom(54);
+pub const OK: Term = Term::make_atom(55);
+pub const ON_HEAP: Term = Term::make_atom(56);
+pub const PRIORITY: Term = Term::make_atom(57);
+pub const PROCESS: Term = Term::make_atom(58);
+pub const REDUCTIONS: Term = Term::make_atom(59);
+pub const REGISTERED_NAME: Term = Term::make_atom(60);
+pub const RUNNABLE: Term = Term::make_atom(61);
+pub const RUNNING: Term = Term::make_atom(62);
+pub const SCIENTIFIC: Term = Term::make_atom(63);
+pub const SHORT: Term = Term::make_atom(64);
+pub const SIZE: Term = Term::make_atom(65);
+pub const STACK_SIZE: Term = Term::make_atom(66);
+pub const STATUS: Term = Term::make_atom(67);
+pub const SYSTEM_LIMIT: Term = Term::make_atom(68);
+pub const THROW: Term = Term::make_atom(69);
+pub const TIMEOUT: Term = Term::make_atom(70);
+pub const TIMEOUT_VALUE: Term = Term::make_atom(71);
+pub const TOTAL_HEAP_SIZE: Term = Term::make_atom(72);
+pub const TRAP_EXIT: Term = Term::make_atom(73);
+pub const TRUE: Term = Term::make_atom(74);
+pub const UNDEF: Term = Term::make_atom(75);
+pub const UNDEFINED: Term = Term::make_atom(76);
+pub const WAITING: Term = Term::make_atom(77);
 
 pub static ATOM_INIT_NAMES: &'static [&'static str] = &[
   "+", // id=0
   "-", // id=1
   "==", // id=2
-  "all", // id=3
-  "apply", // id=4
-  "badarg", // id=5
-  "badarith", // id=6
-  "badarity", // id=7
-  "badfun", // id=8
-  "badmatch", // id=9
-  "case_clause", // id=10
-  "erlang", // id=11
-  "error", // id=12
-  "erts_internal", // id=13
-  "exit", // id=14
-  "false", // id=15
-  "function_clause", // id=16
-  "high", // id=17
-  "if_clause", // id=18
-  "init", // id=19
-  "kill", // id=20
-  "killed", // id=21
-  "low", // id=22
-  "nif_error", // id=23
-  "nocatch", // id=24
-  "normal", // id=25
-  "ok", // id=26
-  "system_limit", // id=27
-  "throw", // id=28
-  "trap_exit", // id=29
-  "true", // id=30
-  "undef", // id=31
-  "undefined", // id=32
+  "DOWN", // id=3
+  "EXIT", // id=4
+  "abs", // id=5
+  "all", // id=6
+  "apply", // id=7
+  "async", // id=8
+  "badarg", // id=9
+  "badarith", // id=10
+  "badarity", // id=11
+  "badfun", // id=12
+  "badmatch", // id=13
+  "cancel_timer", // id=14
+  "case_clause", // id=15
+  "compact", // id=16
+  "current_function", // id=17
+  "decimals", // id=18
+  "dictionary", // id=19
+  "erlang", // id=20
+  "error", // id=21
+  "error_logger", // id=22
+  "erts_internal", // id=23
+  "exit", // id=24
+  "false", // id=25
+  "flush", // id=26
+  "fullsweep_after", // id=27
+  "function_clause", // id=28
+  "heap_size", // id=29
+  "hibernate", // id=30
+  "high", // id=31
+  "if_clause", // id=32
+  "infinity", // id=33
+  "info", // id=34
+  "init", // id=35
+  "kill", // id=36
+  "killed", // id=37
+  "link", // id=38
+  "links", // id=39
+  "low", // id=40
+  "max_heap_size", // id=41
+  "message_queue_data", // id=42
+  "message_queue_len", // id=43
+  "messages", // id=44
+  "min_bin_vheap_size", // id=45
+  "min_heap_size", // id=46
+  "monitor", // id=47
+  "monitors", // id=48
+  "nif_error", // id=49
+  "nocatch", // id=50
+  "nonode@nohost", // id=51
+  "noproc", // id=52
+  "normal", // id=53
+  "off_heap", // id=54
+  "ok", // id=55
+  "on_heap", // id=56
+  "priority", // id=57
+  "process", // id=58
+  "reductions", // id=59
+  "registered_name", // id=60
+  "runnable", // id=61
+  "running", // id=62
+  "scientific", // id=63
+  "short", // id=64
+  "size", // id=65
+  "stack_size", // id=66
+  "status", // id=67
+  "system_limit", // id=68
+  "throw", // id=69
+  "timeout", // id=70
+  "timeout_value", // id=71
+  "total_heap_size", // id=72
+  "trap_exit", // id=73
+  "true", // id=74
+  "undef", // id=75
+  "undefined", // id=76
+  "waiting", // id=77
 ];
@@ -1,85 +1,342 @@
 //! The classic BEAM design approach is to copy terms to the new owning heap
 //! when an object changes its owner process.
-// TODO: Smarter approach with refcounted movable objects or use shared heap or something else
+//!
+//! Copying is done without recursion, in two steps: first `size_of_term` finds
+//! how much memory the copy will take, then the memory is allocated at once
+//! and the objects are copied breadth-first, similar to the copying GC (see
+//! `gc.rs`): the root object is copied, then the copied data is scanned and
+//! every term found there gets copied too, until the scan meets the end.
+//!
+//! With the "copy_shared" feature subterms which are referenced multiple times
+//! are copied only once and stay shared in the copy (OTP `copy_shared`
+//! semantics). Without it, every reference gets its own copy, which is faster
+//! for terms without sharing but may blow up the size of the copy.
+//!
+//! Terms pointing into a module literal area are not copied, the copy refers
+//! to the same literals (see `code_srv/literal_area.rs`).
 use crate::{
-  emulator::heap::THeap,
+  defs::{Word, WordSize},
+  emulator::{
+    code_srv::literal_area::{self, LiteralAreas},
+    heap::{Heap, THeap},
+  },
   fail::RtResult,
   term::{
-    boxed,
-    term_builder::{ListBuilder, TupleBuilder},
+    boxed::{
+      self,
+      binary::{binaryheap_bin::BinaryHeapBinary, refc_bin::ReferenceToBinary, BinaryType},
+      BoxHeader,
+    },
     value::{self, PrimaryTag, Term},
   },
 };
+use core::ptr;
+use std::collections::{HashMap, HashSet};
 
 /// Copies term to another heap.
 pub fn copy_to(term: Term, hp: &mut THeap) -> RtResult<Term> {
-  match term.get_term_tag() {
-    PrimaryTag::BOX_PTR => unsafe { copy_boxed_to(term, hp) },
-    PrimaryTag::HEADER => {
-      panic!("Attempt to copy header value");
+  if !is_pointer(term) {
+    return Ok(check_immediate(term));
+  }
+  if literal_area::is_literal(term) {
+    return Ok(term);
+  }
+  let size = size_of_term(term);
+  unsafe { copy_sized_to(term, size, hp) }
+}
+
+/// Copies term to a new heap fragment, which is created large enough for it.
+/// Returns: the copy and the fragment, or `None` if the term is an immediate
+/// value and needs no memory.
+pub fn copy_to_fragment(term: Term) -> RtResult<(Term, Option<Heap>)> {
+  if !is_pointer(term) {
+    return Ok((check_immediate(term), None));
+  }
+  if literal_area::is_literal(term) {
+    return Ok((term, None));
+  }
+  let size = size_of_term(term);
+  let mut fragment = Heap::new_fragment(size.words);
+  let copy = unsafe { copy_sized_to(term, size, &mut fragment)? };
+  Ok((copy, Some(fragment)))
+}
+
+/// Calculate how many words a copy of `term` will take on the heap. Literals
+/// are not copied and take no space.
+pub fn size_of_term(term: Term) -> WordSize {
+  let mut size = 0usize;
+  let mut visited = HashSet::<*const Word>::new();
+  let mut stack = vec![term];
+  let literals = LiteralAreas::snapshot();
+
+  while let Some(t) = stack.pop() {
+    match t.get_term_tag() {
+      PrimaryTag::CONS_PTR => {
+        let p = t.get_cons_ptr() as *const Word;
+        if literals.contains(p) || !first_visit(&mut visited, p) {
+          continue;
+        }
+        size += 2;
+        unsafe {
+          stack.push(Term::from_raw(ptr::read(p)));
+          stack.push(Term::from_raw(ptr::read(p.add(1))));
+        }
+      }
+      PrimaryTag::BOX_PTR => {
+        check_box_pointer(t);
+        let p = t.get_box_ptr_unchecked_mut::<BoxHeader>();
+        if literals.contains(p as *const Word)
+          || !first_visit(&mut visited, p as *const Word)
+        {
+          continue;
+        }
+        unsafe {
+          size += (*p).get_storage_size();
+          // Collect the contained terms, the values are not changed
+          let trait_p = (*p).get_trait_ptr_mut();
+          (*trait_p).inplace_map(&mut |inner| {
+            stack.push(inner);
+            inner
+          });
+        }
+      }
+      _ => {}
     }
-    PrimaryTag::CONS_PTR => unsafe { copy_cons_to(term, hp) },
+  }
+  WordSize::new(size)
+}
+
+/// Returns false if sharing is preserved and `p` has been visited before.
+#[inline]
+fn first_visit(visited: &mut HashSet<*const Word>, p: *const Word) -> bool {
+  !cfg!(feature = "copy_shared") || visited.insert(p)
+}
+
+#[inline]
+fn is_pointer(term: Term) -> bool {
+  term.is_cons() || term.is_boxed()
+}
+
+/// Terms which do not refer to memory are copied as is, but some of them can
+/// not appear in data which is being copied.
+fn check_immediate(term: Term) -> Term {
+  match term.get_term_tag() {
+    PrimaryTag::HEADER => panic!("Attempt to copy header value"),
     PrimaryTag::SMALL_INT
     | PrimaryTag::ATOM
     | PrimaryTag::LOCAL_PID
-    | PrimaryTag::LOCAL_PORT => Ok(term),
+    | PrimaryTag::LOCAL_PORT => term,
     PrimaryTag::SPECIAL => match term.get_special_tag() {
-      value::SpecialTag::CONST => Ok(term),
+      value::SpecialTag::CONST => term,
       _ => panic!("Attempt to copy a special value: {}", term),
     },
     t => panic!("Not sure how to copy term with {:?}", t),
   }
 }
 
-/// For each list element copy it to a new element in the destination heap.
-/// Also copy the tail element.
-/// Returns: `RtResult<copied_term>`
-unsafe fn copy_cons_to(lst: Term, hp: &mut THeap) -> RtResult<Term> {
-  let mut lb = ListBuilder::new()?;
+#[inline]
+fn check_box_pointer(term: Term) {
+  if term.is_non_value() || term.is_cp() {
+    panic!("Attempt to copy a special value: {}", term);
+  }
+}
+
+unsafe fn copy_sized_to(term: Term, size: WordSize, hp: &mut THeap) -> RtResult<Term> {
+  let dst = hp.alloc(size, false)?;
+  let mut copier = TermCopier::new(dst, size.words);
+  let result = copier.copy(term, hp);
+  copier.scan(hp);
+  debug_assert_eq!(copier.top, size.words, "size_of_term does not match the copy");
+  Ok(result)
+}
+
+/// State of a single term copy operation.
+struct TermCopier {
+  /// Preallocated memory where the copy goes.
+  dst: *mut Word,
+  /// Allocation top in `dst`, in words.
+  top: usize,
+  /// How many words were allocated.
+  limit: usize,
+  /// Source object address to its copy, used to preserve sharing.
+  copied: HashMap<*const Word, Term>,
+  /// Terms pointing there are shared, not copied.
+  literals: LiteralAreas,
+}
+
+impl TermCopier {
+  fn new(dst: *mut Word, limit: usize) -> Self {
+    Self {
+      dst,
+      top: 0,
+      limit,
+      copied: HashMap::new(),
+      literals: LiteralAreas::snapshot(),
+    }
+  }
+
+  /// Copy the object `t` refers to (without its contents, they are copied by
+  /// `scan`) and return the new term. Immediate values and literals are
+  /// returned as is.
+  unsafe fn copy(&mut self, t: Term, hp: &mut THeap) -> Term {
+    match t.get_term_tag() {
+      PrimaryTag::CONS_PTR => {
+        let p = t.get_cons_ptr() as *const Word;
+        if self.literals.contains(p) {
+          return t;
+        }
+        if let Some(copy) = self.find_copied(p) {
+          return copy;
+        }
+        let new_t = Term::make_cons(self.copy_words(p, 2));
+        self.remember_copied(p, new_t);
+        new_t
+      }
+      PrimaryTag::BOX_PTR => {
+        check_box_pointer(t);
+        let p = t.get_box_ptr::<Word>();
+        if self.literals.contains(p) {
+          return t;
+        }
+        if let Some(copy) = self.find_copied(p) {
+          return copy;
+        }
+        let size = (*(p as *const BoxHeader)).get_storage_size();
+        let new_t = Term::make_boxed(self.copy_words(p, size));
+        self.remember_copied(p, new_t);
+        Self::on_boxed_copied(new_t, hp);
+        new_t
+      }
+      _ => check_immediate(t),
+    }
+  }
 
-  let tail_el_result = value::cons::for_each(lst, |el| {
-    // Recurse into copy, for each list element
-    let copy_of_el = copy_to(el, hp)?;
-    lb.append(copy_of_el, hp)?;
-    Ok(())
-  })?;
+  #[inline]
+  fn find_copied(&self, p: *const Word) -> Option<Term> {
+    if !cfg!(feature = "copy_shared") {
+      return None;
+    }
+    self.copied.get(&p).cloned()
+  }
 
-  if let Some(tail_el) = tail_el_result {
-    return Ok(lb.make_term_with_tail(tail_el));
+  #[inline]
+  fn remember_copied(&mut self, p: *const Word, new_t: Term) {
+    if cfg!(feature = "copy_shared") {
+      self.copied.insert(p, new_t);
+    }
+  }
+
+  unsafe fn copy_words(&mut self, src: *const Word, n_words: usize) -> *mut Word {
+    assert!(
+      self.top + n_words <= self.limit,
+      "copy_term: destination overflow, top={} need={} limit={}",
+      self.top,
+      n_words,
+      self.limit
+    );
+    let dst = self.dst.add(self.top);
+    ptr::copy_nonoverlapping(src, dst, n_words);
+    self.top += n_words;
+    dst
+  }
+
+  /// A boxed object holding a resource outside of the heap needs its own
+  /// reference to it, so that both copies can be released separately.
+  unsafe fn on_boxed_copied(new_t: Term, hp: &mut THeap) {
+    let header_p = new_t.get_box_ptr::<BoxHeader>();
+    if (*(*header_p).get_trait_ptr()).get_type() != boxed::BOXTYPETAG_BINARY {
+      return;
+    }
+    let bin_trait = boxed::Binary::get_trait(header_p as *const boxed::Binary);
+    if let BinaryType::RefToBinaryHeap = (*bin_trait).get_type() {
+      let refbin_p = header_p as *const ReferenceToBinary;
+      BinaryHeapBinary::add_ref((*refbin_p).pointer);
+      hp.register_off_heap(new_t, (*refbin_p).size.get_words_rounded_up());
+    }
   }
 
-  Ok(lb.make_term())
+  /// Walk the copied data and copy every term found there. Boxed objects are
+  /// asked to update their contained terms via `TBoxed::inplace_map`, their
+  /// raw data is skipped.
+  unsafe fn scan(&mut self, hp: &mut THeap) {
+    let mut scan_pos = 0usize;
+    while scan_pos < self.top {
+      let p = self.dst.add(scan_pos);
+      let val = Term::from_raw(ptr::read(p));
+      if val.get_term_tag() == PrimaryTag::HEADER {
+        let header_p = p as *mut BoxHeader;
+        let trait_p = (*header_p).get_trait_ptr_mut();
+        (*trait_p).inplace_map(&mut |t| self.copy(t, hp));
+        scan_pos += (*header_p).get_storage_size();
+      } else {
+        ptr::write(p, self.copy(val, hp).raw());
+        scan_pos += 1;
+      }
+    }
+  }
 }
 
-unsafe fn copy_boxed_to(term: Term, hp: &mut THeap) -> RtResult<Term> {
-  let header_ptr = term.get_box_ptr::<boxed::BoxHeader>();
-  let trait_ptr = (*header_ptr).get_trait_ptr();
-  let box_type = (*trait_ptr).get_type();
-
-  match box_type {
-    boxed::BOXTYPETAG_TUPLE => {
-      let tuple_p = header_ptr as *const boxed::Tuple;
-      let arity = (*tuple_p).get_arity();
-      let tb = TupleBuilder::with_arity(arity, hp)?;
-      for i in 0..arity {
-        let element = (*tuple_p).get_element(i);
-        let copied = copy_to(element, hp)?;
-        tb.set_element(i, copied);
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    emulator::heap::Designation,
+    term::term_builder::{tuple_builder::tuple2, ListBuilder},
+  };
+
+  #[test]
+  fn test_copy_preserves_sharing() {
+    let mut src = Heap::new(Designation::ProcessHeap);
+    let mut dst = Heap::new(Designation::ProcessHeap);
+    unsafe {
+      let mut lb = ListBuilder::new().unwrap();
+      for i in 0..10 {
+        lb.append(Term::make_small_unsigned(i), &mut src).unwrap();
       }
-      return Ok(tb.make_term());
+      let lst = lb.make_term();
+      let t = tuple2(&mut src, lst, lst).unwrap();
+
+      let copy = copy_to(t, &mut dst).unwrap();
+      let tuple_p = copy.get_tuple_ptr();
+      assert_eq!((*tuple_p).get_element(0), (*tuple_p).get_element(1));
+      assert_eq!(size_of_term(copy).words, size_of_term(t).words);
+      assert_eq!(format!("{}", copy), format!("{}", t));
     }
-    boxed::BOXTYPETAG_BIGINTEGER => {}
-    boxed::BOXTYPETAG_EXTERNALPID => {}
-    boxed::BOXTYPETAG_EXTERNALREF => {}
-    boxed::BOXTYPETAG_EXTERNALPORT => {}
-    boxed::BOXTYPETAG_CLOSURE => {}
-    boxed::BOXTYPETAG_FLOAT => {}
-    boxed::BOXTYPETAG_IMPORT => {}
-    boxed::BOXTYPETAG_EXPORT => {}
-    boxed::BOXTYPETAG_MAP => {}
-    boxed::BOXTYPETAG_BINARY => {}
-    _other => {}
-  }
-
-  panic!("Don't know how to copy {}", term);
+  }
+
+  #[test]
+  fn test_copy_long_list() {
+    let n = 100_000;
+    let mut src = Heap::new_fragment(2 * n);
+    let mut dst = Heap::new_fragment(2 * n);
+    unsafe {
+      let mut lb = ListBuilder::new().unwrap();
+      for i in 0..n {
+        lb.append(Term::make_small_unsigned(i), &mut src).unwrap();
+      }
+      let copy = copy_to(lb.make_term(), &mut dst).unwrap();
+      assert_eq!(size_of_term(copy).words, 2 * n);
+    }
+  }
+
+  #[test]
+  fn test_copy_skips_literals() {
+    let mut lit_heap = Heap::new(Designation::ModuleLiterals);
+    let mut src = Heap::new(Designation::ProcessHeap);
+    let mut dst = Heap::new(Designation::ProcessHeap);
+    unsafe {
+      let mut lb = ListBuilder::new().unwrap();
+      for i in 0..10 {
+        lb.append(Term::make_small_unsigned(i), &mut lit_heap).unwrap();
+      }
+      let literal = lb.make_term();
+      literal_area::register(lit_heap.get_used_range());
+      let t = tuple2(&mut src, literal, Term::nil()).unwrap();
+
+      assert_eq!(size_of_term(literal).words, 0);
+      let copy = copy_to(t, &mut dst).unwrap();
+      assert_eq!((*copy.get_tuple_ptr()).get_element(0), literal);
+      literal_area::unregister(lit_heap.get_used_range());
+    }
+  }
 }
@@ -1,22 +1,31 @@
 use crate::{
   defs::{Word, WordSize},
-  emulator::heap::{catch::NextCatchResult, heap_trait::THeap, iter, Designation},
+  emulator::{
+    code_srv::literal_area,
+    heap::{
+      catch::NextCatchResult,
+      gc::{CopyingCollector, FromSpaceRange},
+      heap_size, heap_trait::THeap, iter,
+      Designation,
+    },
+  },
   fail::{RtErr, RtResult},
-  term::value::Term,
+  term::{
+    boxed::BoxHeader,
+    value::{PrimaryTag, Term},
+  },
 };
 use colored::Colorize;
-use core::{fmt, ptr};
+use core::{fmt, ptr, slice};
 
 /// Default heap size for constants (literals) when loading a module.
 const DEFAULT_LIT_HEAP: usize = 8192;
 
-/// Default heap size when spawning a process. (default: 300)
-const DEFAULT_PROC_HEAP: usize = 16384;
-const BINARY_HEAP_CAPACITY: usize = 65536; // 64k*8 = 512kb
-
 /// A heap structure which grows upwards with allocations. Cannot expand
 /// implicitly and will return error when capacity is exceeded. Organize a
-/// garbage collect call to get more memory TODO: gc on heap
+/// garbage collect call to get more memory (see `Process::ensure_heap`), the
+/// collector will also grow or shrink the heap depending on how much data
+/// survived (see `heap_size::capacity_after_gc`).
 pub struct FlatHeap {
   data: Vec<Word>,
   /// Heap top, begins at 0 and grows up towards the `stack_top`.
@@ -25,6 +34,15 @@ pub struct FlatHeap {
   stack_top: usize,
   /// Marks end of the stack and also end of the heap.
   capacity: usize,
+  /// The heap will not shrink below this capacity (`min_heap_size`).
+  min_capacity: usize,
+  /// Boxed terms on this heap which hold resources outside of it, with the
+  /// size of the resource in words. They are destroyed when the GC finds them
+  /// dead (see `THeap::register_off_heap`).
+  off_heap: Vec<(Term, usize)>,
+  /// Total size of the `off_heap` resources, this is the binary virtual heap
+  /// used by the `min_bin_vheap_size` policy.
+  off_heap_words: usize,
 }
 
 impl FlatHeap {}
@@ -45,13 +63,8 @@ impl THeap for FlatHeap {
     let pos = self.heap_top;
     let n_words = n.words;
     // Explicitly forbid expanding without a GC, fail if capacity is exceeded
-    if pos + n_words >= self.stack_top {
-      // return Err(Error::HeapIsFull);
-      panic!(
-        "Heap is full requested={} have={}",
-        n,
-        self.get_heap_available()
-      );
+    if pos + n_words > self.stack_top {
+      return Err(RtErr::HeapIsFull("heap::alloc"));
     }
 
     // Assume we can grow the data without reallocating
@@ -101,7 +114,13 @@ impl THeap for FlatHeap {
   }
 
   fn belongs_to_heap(&self, p: *const Word) -> bool {
-    p < self.get_heap_start_ptr() || p >= self.get_heap_top_ptr()
+    p >= self.get_heap_start_ptr() && p < self.get_heap_top_ptr()
+  }
+
+  fn register_off_heap(&mut self, t: Term, size: WordSize) {
+    debug_assert!(self.belongs_to_heap(t.get_box_ptr::<Word>()));
+    self.off_heap.push((t, size.words));
+    self.off_heap_words += size.words;
   }
 
   /// Set stack value (`index`th from stack top) to `val`.
@@ -257,22 +276,41 @@ impl THeap for FlatHeap {
 impl FlatHeap {
   fn get_size_for(d: Designation) -> usize {
     match d {
-      Designation::ProcessHeap => DEFAULT_PROC_HEAP,
+      Designation::ProcessHeap => {
+        heap_size::next_heap_size(heap_size::DEFAULT_MIN_HEAP_SIZE)
+      }
       Designation::ModuleLiterals => DEFAULT_LIT_HEAP,
-      Designation::BinaryHeap => BINARY_HEAP_CAPACITY,
       Designation::TransientDestructible => 1,
       Designation::ProgramArgumentsHeap => 512,
     }
   }
 
   pub fn new(designation: Designation) -> Self {
-    let capacity = Self::get_size_for(designation);
+    Self::with_capacity(Self::get_size_for(designation))
+  }
+
+  /// Create a process heap which will never shrink below `min_heap_size`
+  /// words. The capacity is rounded up to the next heap size step.
+  pub fn new_process_heap(min_heap_size: usize) -> Self {
+    Self::with_capacity(heap_size::next_heap_size(min_heap_size))
+  }
+
+  /// Create a heap fragment, which is a heap without GC and without a stack,
+  /// to hold a term outside of a process heap (see `copy_term::copy_to_fragment`).
+  pub fn new_fragment(capacity: usize) -> Self {
+    Self::with_capacity(capacity)
+  }
+
+  fn with_capacity(capacity: usize) -> Self {
     assert!(capacity > 0);
     let mut h = Self {
       data: Vec::with_capacity(capacity),
       heap_top: 0,
       stack_top: capacity,
       capacity,
+      min_capacity: capacity,
+      off_heap: Vec::new(),
+      off_heap_words: 0,
     };
     unsafe { h.data.set_len(capacity) };
     h
@@ -280,13 +318,30 @@ impl FlatHeap {
 
   /// How many words do we have before it will require GC/growth.
   #[inline]
-  fn get_heap_max_capacity(&self) -> usize {
-    self.data.capacity()
+  pub fn get_heap_max_capacity(&self) -> usize {
+    self.capacity
+  }
+
+  #[inline]
+  pub fn get_min_capacity(&self) -> usize {
+    self.min_capacity
+  }
+
+  /// Change the minimal heap size, takes effect on the next garbage collection.
+  pub fn set_min_capacity(&mut self, min_heap_size: usize) {
+    self.min_capacity = heap_size::next_heap_size(min_heap_size);
+  }
+
+  /// Words held outside of the heap by live off-heap objects, such as
+  /// binaries on the binary heap. Updated by the GC.
+  #[inline]
+  pub fn get_off_heap_words(&self) -> usize {
+    self.off_heap_words
   }
 
   /// Heap usage stat.
   #[inline]
-  fn get_heap_used_words(&self) -> usize {
+  pub fn get_heap_used_words(&self) -> usize {
     self.heap_top
   }
 
@@ -311,6 +366,13 @@ impl FlatHeap {
     unsafe { self.get_heap_start_ptr().add(self.heap_top) }
   }
 
+  /// Memory range occupied by the heap data, used when this heap is a
+  /// fragment to be merged into another heap by the GC.
+  #[inline]
+  pub fn get_used_range(&self) -> FromSpaceRange {
+    (self.get_heap_start_ptr(), self.get_heap_top_ptr())
+  }
+
   /// Stack start is same as end of everything, pointer to the first word after
   /// the allocated memory, used as limit when iterating the stack.
   #[inline]
@@ -333,9 +395,307 @@ impl FlatHeap {
     println!("Stack (s_top {}, s_end {})", self.stack_top, self.capacity)
   }
 
+  /// Run the copying garbage collector. Terms reachable from `roots` and from
+  /// the stack survive, the roots and the stack are updated to point to the
+  /// new locations. Live data from `fragments` is merged into the heap, after
+  /// the collection the fragments are no longer referenced and can be freed.
+  /// After the collection the heap may be grown or shrunk, so that `need`
+  /// words and some spare space are available.
+  pub fn garbage_collect(
+    &mut self,
+    need: WordSize,
+    roots: &mut [&mut [Term]],
+    fragments: &mut [&mut FlatHeap],
+  ) -> RtResult<()> {
+    if cfg!(feature = "trace_gc") {
+      print!("{} heap used {} -> ", "GC:".yellow(), self.heap_top);
+    }
+    // In the worst case everything survives, including the fragments
+    let fragments_size: usize = fragments.iter().map(|f| f.heap_top).sum();
+    let worst_case = self.heap_top + fragments_size + self.stack_depth();
+    let capacity = if worst_case > self.capacity {
+      heap_size::next_heap_size(worst_case)
+    } else {
+      self.capacity
+    };
+    self.collect_into(capacity, roots, fragments);
+
+    let used = self.heap_top + self.stack_depth() + need.words;
+    let new_capacity =
+      heap_size::capacity_after_gc(self.capacity, used, self.min_capacity);
+    if new_capacity != self.capacity {
+      // Resizing moves the heap and the stack again, which is done by another
+      // collection into the memory of the new size. It is cheap, because all
+      // garbage was already removed.
+      self.collect_into(new_capacity, roots, &mut []);
+    }
+
+    if cfg!(feature = "trace_gc") {
+      println!(
+        "{} words, capacity {}, available {}",
+        self.heap_top,
+        self.capacity,
+        self.get_heap_available()
+      );
+    }
+
+    if !self.heap_check_available(need) {
+      return Err(RtErr::HeapIsFull("heap::garbage_collect"));
+    }
+    Ok(())
+  }
+
+  /// Discard the stack and copy live data reachable from `roots` into a heap
+  /// which is just large enough to hold it, this is done for a hibernating
+  /// process. The `min_capacity` is ignored, the heap will grow back on the
+  /// next collection.
+  pub fn compact(&mut self, roots: &mut [&mut [Term]], fragments: &mut [&mut FlatHeap]) {
+    self.stack_top = self.capacity;
+    let fragments_size: usize = fragments.iter().map(|f| f.heap_top).sum();
+    self.collect_into(self.heap_top + fragments_size, roots, fragments);
+    let live = self.heap_top;
+    if live != self.capacity {
+      self.collect_into(live, roots, &mut []);
+    }
+  }
+
+  /// Copy live data into a new memory block of `new_capacity` words, the stack
+  /// is moved to the end of the new block.
+  fn collect_into(
+    &mut self,
+    new_capacity: usize,
+    roots: &mut [&mut [Term]],
+    fragments: &mut [&mut FlatHeap],
+  ) {
+    let depth = self.stack_depth();
+    assert!(depth <= new_capacity);
+    let new_stack_top = new_capacity - depth;
+
+    let mut new_data = Vec::<Word>::with_capacity(new_capacity);
+    unsafe { new_data.set_len(new_capacity) };
+
+    // The stack is moved as is, and then its values are updated as roots
+    new_data[new_stack_top..].copy_from_slice(&self.data[self.stack_top..]);
+
+    let fragment_ranges: Vec<FromSpaceRange> =
+      fragments.iter().map(|f| f.get_used_range()).collect();
+    unsafe {
+      let new_p = new_data.as_mut_ptr();
+      let mut gc = CopyingCollector::new(
+        self.get_heap_start_ptr(),
+        self.get_heap_top_ptr(),
+        &fragment_ranges,
+        new_p,
+        new_stack_top,
+      );
+      for root_slice in roots.iter_mut() {
+        gc.evacuate_roots(root_slice);
+      }
+      let stack = slice::from_raw_parts_mut(new_p.add(new_stack_top) as *mut Term, depth);
+      gc.evacuate_roots(stack);
+      gc.scan();
+      self.heap_top = gc.get_to_top();
+      // Old memory is still there, check which off-heap objects have moved
+      self.sweep_off_heap(fragments);
+    }
+    self.data = new_data;
+    self.stack_top = new_stack_top;
+    self.capacity = new_capacity;
+  }
+
+  /// After the collection, off-heap objects of this heap and of the merged
+  /// fragments have either been moved (the header word is replaced with the
+  /// new location) or they are garbage and are destroyed.
+  unsafe fn sweep_off_heap(&mut self, fragments: &mut [&mut FlatHeap]) {
+    let mut old_list = core::mem::take(&mut self.off_heap);
+    for f in fragments.iter_mut() {
+      old_list.append(&mut f.off_heap);
+      f.off_heap_words = 0;
+    }
+    self.off_heap_words = 0;
+    for (t, size) in old_list {
+      let header_p = t.get_box_ptr_unchecked_mut::<BoxHeader>();
+      let header_word = Term::from_raw(ptr::read(header_p as *const Word));
+      if header_word.get_term_tag() == PrimaryTag::HEADER {
+        (*(*header_p).get_trait_ptr_mut()).on_destroy();
+      } else {
+        self.off_heap.push((header_word, size));
+        self.off_heap_words += size;
+      }
+    }
+  }
+
+  /// Check whether any term on the heap or on the stack points into `range`,
+  /// used to find out whether a literal area is still referenced.
+  pub fn refers_to(&self, range: FromSpaceRange) -> bool {
+    unsafe {
+      let mut found = false;
+      let mut it = self.heap_iter();
+      while let Some(p) = it.next() {
+        let val = ptr::read(p);
+        if val.get_term_tag() == PrimaryTag::HEADER {
+          // Boxed objects know which of their words are terms
+          let header_p = p as *mut BoxHeader;
+          (*(*header_p).get_trait_ptr_mut()).inplace_map(&mut |t| {
+            found = found || literal_area::term_points_into(t, range);
+            t
+          });
+        } else {
+          found = literal_area::term_points_into(val, range);
+        }
+        if found {
+          return true;
+        }
+      }
+      self.get_stack_slice().iter().any(|t| literal_area::term_points_into(*t, range))
+    }
+  }
+
+  /// Stack contents from the stack top (Y0 is the first element after the CP)
+  /// to the end of the memory.
+  pub fn get_stack_slice(&self) -> &[Term] {
+    unsafe {
+      let stack_p = self.get_stack_top_ptr() as *const Term;
+      slice::from_raw_parts(stack_p, self.stack_depth())
+    }
+  }
+
   /// Check whether `y+1`-th element can be found in stack
   #[inline]
   pub fn stack_have_y(&self, y: Word) -> bool {
     self.capacity - self.stack_top >= y + 1
   }
 }
+
+impl Drop for FlatHeap {
+  fn drop(&mut self) {
+    for (t, _) in self.off_heap.iter() {
+      unsafe {
+        let header_p = t.get_box_ptr_unchecked_mut::<BoxHeader>();
+        (*(*header_p).get_trait_ptr_mut()).on_destroy();
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::term::term_builder::{tuple_builder::tuple2, ListBuilder};
+
+  #[test]
+  fn test_gc_keeps_live_data() {
+    let mut heap = FlatHeap::new(Designation::ProcessHeap);
+    unsafe {
+      let mut lb = ListBuilder::new().unwrap();
+      for i in 0..3 {
+        tuple2(&mut heap, Term::small_0(), Term::small_1()).unwrap(); // garbage
+        lb.append(Term::make_small_unsigned(i), &mut heap).unwrap();
+      }
+      let lst = lb.make_term();
+      let live = tuple2(&mut heap, lst, lst).unwrap();
+      let used_before = heap.get_heap_used_words();
+
+      let mut roots = [live];
+      heap
+        .garbage_collect(WordSize::new(0), &mut [&mut roots], &mut [])
+        .unwrap();
+
+      assert!(heap.get_heap_used_words() < used_before);
+      assert_eq!(format!("{}", roots[0]), "{[0, 1, 2], [0, 1, 2]}");
+      // Shared subterm must remain shared after the copy
+      let tuple_p = roots[0].get_tuple_ptr();
+      assert_eq!((*tuple_p).get_element(0), (*tuple_p).get_element(1));
+    }
+  }
+
+  #[test]
+  fn test_gc_grows_heap() {
+    let mut heap = FlatHeap::new_process_heap(0);
+    let capacity_before = heap.get_heap_max_capacity();
+    unsafe {
+      let mut lb = ListBuilder::new().unwrap();
+      for i in 0..4 {
+        lb.append(Term::make_small_unsigned(i), &mut heap).unwrap();
+      }
+      let mut roots = [lb.make_term()];
+      let need = WordSize::new(capacity_before);
+      heap.garbage_collect(need, &mut [&mut roots], &mut []).unwrap();
+
+      assert!(heap.get_heap_max_capacity() > capacity_before);
+      assert!(heap.heap_check_available(need));
+      assert_eq!(format!("{}", roots[0]), "[0, 1, 2, 3]");
+    }
+  }
+
+  #[test]
+  fn test_compact_drops_stack_and_garbage() {
+    let mut heap = FlatHeap::new_process_heap(1000);
+    let capacity_before = heap.get_heap_max_capacity();
+    tuple2(&mut heap, Term::small_0(), Term::small_0()).unwrap(); // garbage
+    let mut roots = [tuple2(&mut heap, Term::small_0(), Term::small_1()).unwrap()];
+    heap.stack_alloc_unchecked(WordSize::new(4), true);
+
+    heap.compact(&mut [&mut roots], &mut []);
+
+    assert_eq!(heap.stack_depth(), 0);
+    // Only the live tuple remains, and the heap has no free space left
+    assert_eq!(heap.get_heap_max_capacity(), heap.get_heap_used_words());
+    assert!(heap.get_heap_max_capacity() < capacity_before);
+    assert_eq!(format!("{}", roots[0]), "{0, 1}");
+  }
+
+  #[test]
+  fn test_gc_merges_fragment() {
+    let mut heap = FlatHeap::new(Designation::ProcessHeap);
+    let mut fragment = FlatHeap::new_fragment(16);
+    let msg = tuple2(&mut fragment, Term::small_0(), Term::small_1()).unwrap();
+
+    let mut roots = [msg];
+    heap
+      .garbage_collect(WordSize::new(0), &mut [&mut roots], &mut [&mut fragment])
+      .unwrap();
+    drop(fragment);
+
+    assert!(heap.belongs_to_heap(roots[0].get_box_ptr::<Word>()));
+    assert_eq!(format!("{}", roots[0]), "{0, 1}");
+  }
+
+  #[test]
+  fn test_gc_releases_dead_refbin() {
+    use crate::{
+      defs::BitSize,
+      emulator::heap::copy_term,
+      term::boxed::{
+        binary::{refc_bin::ReferenceToBinary, trait_interface::TBinary},
+        Binary,
+      },
+    };
+    let mut heap1 = FlatHeap::new(Designation::ProcessHeap);
+    let mut heap2 = FlatHeap::new(Designation::ProcessHeap);
+    unsafe {
+      let bin = Binary::create_into(BitSize::with_bytes(100), &mut heap1).unwrap();
+      let refbin_p = bin as *const ReferenceToBinary;
+      let storage = (*refbin_p).pointer;
+      let bin_term = (*bin).make_term().unwrap();
+      let mut roots = [copy_term::copy_to(bin_term, &mut heap2).unwrap()];
+      assert_eq!((*storage).get_refcount(), 2);
+      // The storage itself is only visible via the reference
+      assert!((*storage).make_term().is_err());
+      let bin_words = BitSize::with_bytes(100).get_words_rounded_up().words;
+      assert_eq!(heap1.get_off_heap_words(), bin_words);
+
+      // heap1 has no roots, its reference is garbage
+      heap1.garbage_collect(WordSize::new(0), &mut [], &mut []).unwrap();
+      assert_eq!((*storage).get_refcount(), 1);
+      assert_eq!(heap1.get_off_heap_words(), 0);
+
+      // heap2 reference survives and still points to the same storage
+      heap2.garbage_collect(WordSize::new(0), &mut [&mut roots], &mut []).unwrap();
+      let moved_p = roots[0].get_box_ptr::<ReferenceToBinary>();
+      assert_eq!((*moved_p).pointer, storage);
+      assert_eq!((*storage).get_refcount(), 1);
+      assert_eq!(heap2.get_off_heap_words(), bin_words);
+    }
+  }
+}
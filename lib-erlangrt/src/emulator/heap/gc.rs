@@ -0,0 +1,169 @@
+//! Copying garbage collector (Cheney's algorithm) for process heaps.
+//!
+//! Live data is evacuated from the old heap memory (from-space) into a new
+//! memory block (to-space). First all roots are evacuated, then the to-space
+//! is scanned from the start, and every term found there is evacuated too,
+//! until the scan position meets the allocation top of the to-space.
+//!
+//! An evacuated object leaves a forwarding marker in the from-space:
+//! * a boxed object has its header word replaced with a boxed term pointing to
+//!   the new location (a header word always has `PrimaryTag::HEADER`, so any
+//!   other tag found there means that the object has been moved);
+//! * a cons cell gets a `NON_VALUE` in its head and the new cons term in its
+//!   tail (`NON_VALUE` is never a valid list element).
+//!
+//! Besides the heap itself, the from-space can include heap fragments (such
+//! as message fragments, see `mailbox.rs`), their live data is merged into the
+//! new heap and the fragments can be freed after the collection.
+use crate::{
+  defs::Word,
+  term::{
+    boxed::BoxHeader,
+    value::{PrimaryTag, Term},
+  },
+};
+use core::ptr;
+
+fn module() -> &'static str {
+  "heap.gc: "
+}
+
+/// A memory range `[begin, end)` which is a part of the from-space.
+pub type FromSpaceRange = (*const Word, *const Word);
+
+/// State of a single garbage collection.
+pub struct CopyingCollector<'a> {
+  /// Old heap range `[from_begin, from_end)`, only pointers into this range
+  /// or into `fragments` are relocated. Other pointers (literals, binary heap)
+  /// are left as is.
+  from_begin: *const Word,
+  from_end: *const Word,
+  /// Heap fragments to be merged into the new heap.
+  fragments: &'a [FromSpaceRange],
+  /// New heap memory where the live data is copied to.
+  to_space: *mut Word,
+  /// Allocation top of the to-space, in words.
+  to_top: usize,
+  /// How many words can be used in the to-space.
+  to_limit: usize,
+}
+
+impl<'a> CopyingCollector<'a> {
+  pub fn new(
+    from_begin: *const Word,
+    from_end: *const Word,
+    fragments: &'a [FromSpaceRange],
+    to_space: *mut Word,
+    to_limit: usize,
+  ) -> Self {
+    Self {
+      from_begin,
+      from_end,
+      fragments,
+      to_space,
+      to_top: 0,
+      to_limit,
+    }
+  }
+
+  /// How many words have been copied to the to-space so far.
+  #[inline]
+  pub fn get_to_top(&self) -> usize {
+    self.to_top
+  }
+
+  #[inline]
+  fn belongs_to_from_space(&self, p: *const Word) -> bool {
+    (p >= self.from_begin && p < self.from_end)
+      || self.fragments.iter().any(|(begin, end)| p >= *begin && p < *end)
+  }
+
+  /// Evacuate each term of a root slice, updating the slice in place.
+  pub unsafe fn evacuate_roots(&mut self, roots: &mut [Term]) {
+    for root in roots.iter_mut() {
+      *root = self.evacuate(*root);
+    }
+  }
+
+  /// For a term which is a pointer into the from-space, copy the object it
+  /// refers to into the to-space (unless it was already copied) and return
+  /// the updated term. Other terms are returned unchanged.
+  pub unsafe fn evacuate(&mut self, t: Term) -> Term {
+    match t.get_term_tag() {
+      PrimaryTag::CONS_PTR => self.evacuate_cons(t),
+      PrimaryTag::BOX_PTR => self.evacuate_boxed(t),
+      _ => t,
+    }
+  }
+
+  unsafe fn evacuate_cons(&mut self, t: Term) -> Term {
+    let p = t.get_cons_ptr_mut() as *mut Word;
+    if !self.belongs_to_from_space(p) {
+      return t;
+    }
+    let head = Term::from_raw(ptr::read(p));
+    if head.is_non_value() {
+      // Already moved, the tail contains the new location
+      return Term::from_raw(ptr::read(p.add(1)));
+    }
+    let new_p = self.copy_words(p, 2);
+    let new_t = Term::make_cons(new_p);
+    ptr::write(p, Term::non_value().raw());
+    ptr::write(p.add(1), new_t.raw());
+    new_t
+  }
+
+  unsafe fn evacuate_boxed(&mut self, t: Term) -> Term {
+    // NON_VALUE and CP values will not pass the range check
+    let p = t.get_box_ptr_unchecked_mut::<Word>();
+    if !self.belongs_to_from_space(p) {
+      return t;
+    }
+    let header_word = ptr::read(p);
+    let header_term = Term::from_raw(header_word);
+    if header_term.get_term_tag() != PrimaryTag::HEADER {
+      // Already moved, the header word contains the new location
+      return header_term;
+    }
+    let size = BoxHeader::headerword_to_storage_size(header_word);
+    let new_p = self.copy_words(p, size);
+    let new_t = Term::make_boxed(new_p);
+    ptr::write(p, new_t.raw());
+    new_t
+  }
+
+  unsafe fn copy_words(&mut self, src: *const Word, n_words: usize) -> *mut Word {
+    assert!(
+      self.to_top + n_words <= self.to_limit,
+      "{}to-space overflow, top={} need={} limit={}",
+      module(),
+      self.to_top,
+      n_words,
+      self.to_limit
+    );
+    let dst = self.to_space.add(self.to_top);
+    ptr::copy_nonoverlapping(src, dst, n_words);
+    self.to_top += n_words;
+    dst
+  }
+
+  /// Walk the to-space and evacuate every term found there. Boxed objects are
+  /// asked to update their contained terms via `TBoxed::inplace_map`, their
+  /// raw data is skipped.
+  pub unsafe fn scan(&mut self) {
+    let mut scan_pos = 0usize;
+    while scan_pos < self.to_top {
+      let p = self.to_space.add(scan_pos);
+      let val = Term::from_raw(ptr::read(p));
+      if val.get_term_tag() == PrimaryTag::HEADER {
+        let header_p = p as *mut BoxHeader;
+        let trait_p = (*header_p).get_trait_ptr_mut();
+        (*trait_p).inplace_map(&mut |t| self.evacuate(t));
+        scan_pos += (*header_p).get_storage_size();
+      } else {
+        ptr::write(p, self.evacuate(val).raw());
+        scan_pos += 1;
+      }
+    }
+  }
+}
@@ -0,0 +1,93 @@
+//! Heap size policy: process heaps grow and shrink in steps taken from a
+//! Fibonacci-like sequence, same as OTP does it (see `erts_init_gc`).
+
+/// Default `min_heap_size` for a new process, in words. OTP starts with 233,
+/// but here not every allocating path can run the GC yet (`call_mfa`, `apply`
+/// and BIFs other than `gc_bif`), so a new process starts with the size which
+/// a heap had before heaps could grow (16384 words), rounded up to the next
+/// heap size.
+pub const DEFAULT_MIN_HEAP_SIZE: usize = 17731;
+
+/// Default `min_bin_vheap_size` for a new process, in words.
+pub const DEFAULT_MIN_BIN_VHEAP_SIZE: usize = 46422;
+
+/// Default `fullsweep_after` for a new process, same as in OTP.
+pub const DEFAULT_FULLSWEEP_AFTER: usize = 65535;
+
+/// After this size the heap grows slower, by 20% at a time.
+const SLOW_GROWTH_THRESHOLD: usize = 1_300_000;
+
+/// Heap is grown after GC, if more than 3/4 of it is still used.
+const GROW_USAGE_NUMERATOR: usize = 3;
+const GROW_USAGE_DENOMINATOR: usize = 4;
+
+/// Heap is shrunk after GC, if less than 1/4 of it is used.
+const SHRINK_USAGE_DENOMINATOR: usize = 4;
+
+/// Find the smallest heap size from the heap size sequence which fits `need`
+/// words. The sequence goes: 12, 38, 51, 90, 142, 233, 376, 610... where each
+/// is a sum of two previous plus one, and after `SLOW_GROWTH_THRESHOLD` each
+/// next size is 20% larger than the previous.
+pub fn next_heap_size(need: usize) -> usize {
+  let mut prev = 12usize;
+  let mut curr = 38usize;
+  if need <= prev {
+    return prev;
+  }
+  while curr < need {
+    let next = if curr < SLOW_GROWTH_THRESHOLD {
+      prev + curr + 1
+    } else {
+      curr + curr / 5
+    };
+    prev = curr;
+    curr = next;
+  }
+  curr
+}
+
+/// Having `used` words still occupied after a GC, decide which heap capacity
+/// the process should have: grow if the heap is nearly full, shrink if it is
+/// mostly empty, but never go below `min_capacity`.
+pub fn capacity_after_gc(capacity: usize, used: usize, min_capacity: usize) -> usize {
+  if used * GROW_USAGE_DENOMINATOR > capacity * GROW_USAGE_NUMERATOR {
+    return next_heap_size(used + used / 2);
+  }
+  if used * SHRINK_USAGE_DENOMINATOR < capacity {
+    let wanted = next_heap_size(core::cmp::max(used * 2, min_capacity));
+    if wanted < capacity {
+      return wanted;
+    }
+  }
+  capacity
+}
+
+/// Defines the `max_heap_size` process flag: the limit for the heap size and
+/// the action to take when the limit is reached.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxHeapSize {
+  /// Size limit in words, 0 means no limit.
+  pub size: usize,
+  /// Kill the process when the limit is reached.
+  pub kill: bool,
+  /// Log a report when the limit is reached.
+  pub error_logger: bool,
+}
+
+impl Default for MaxHeapSize {
+  fn default() -> Self {
+    Self {
+      size: 0,
+      kill: true,
+      error_logger: true,
+    }
+  }
+}
+
+impl MaxHeapSize {
+  /// Whether the limit is set and `heap_size` exceeds it.
+  #[inline]
+  pub fn is_exceeded_by(&self, heap_size: usize) -> bool {
+    self.size != 0 && heap_size > self.size
+  }
+}
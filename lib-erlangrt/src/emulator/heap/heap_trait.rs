@@ -43,6 +43,12 @@ pub trait THeap {
 
   unsafe fn heap_iter(&self) -> iter::HeapIterator;
   fn belongs_to_heap(&self, p: *const Word) -> bool;
+
+  /// Remember a boxed term which holds a resource outside of the heap (such
+  /// as a reference to a binary on the binary heap) of `size` words. When the
+  /// GC finds it dead or the heap is dropped, `TBoxed::on_destroy` is called
+  /// for it.
+  fn register_off_heap(&mut self, t: Term, size: WordSize);
   //  fn get_heap_start_ptr(&self) -> *const Word;
   //  fn get_heap_top_ptr(&self) -> *const Word;
   //  fn get_heap_begin_ptr_mut(&mut self) -> *mut Word;
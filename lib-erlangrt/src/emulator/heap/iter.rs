@@ -18,6 +18,11 @@ impl HeapIterator {
   }
 
   pub unsafe fn next(&mut self) -> Option<*const Term> {
+    if self.p >= self.end {
+      return None;
+    }
+    let current = self.p;
+
     // Peek inside *p to see if we're at a header, and if so - step over it
     // using header arity. Otherwise step by 1 cell
     let val = ptr::read(self.p);
@@ -25,14 +30,8 @@ impl HeapIterator {
       PrimaryTag::HEADER => boxed::BoxHeader::headerword_to_storage_size(val.raw()),
       _ => 1usize,
     };
+    self.p = self.p.add(size);
 
-    self.p.add(size);
-
-    let end = self.end;
-    if self.p >= end {
-      return None;
-    }
-
-    Some(self.p)
+    Some(current)
   }
 }
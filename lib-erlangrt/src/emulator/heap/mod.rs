@@ -2,8 +2,11 @@ pub mod catch;
 pub mod copy_term;
 pub mod dump;
 pub mod flat_heap;
+pub mod gc;
+pub mod heap_size;
 pub mod heap_trait;
 pub mod iter;
+pub mod verify;
 
 use crate::{
   defs::WordSize,
@@ -18,7 +21,6 @@ pub type Heap = FlatHeap;
 pub enum Designation {
   ProcessHeap,
   ModuleLiterals,
-  BinaryHeap,
   // Used to store command line args on startup
   ProgramArgumentsHeap,
   // Heap of smallest size to be destroyed after it is swapped with the real one
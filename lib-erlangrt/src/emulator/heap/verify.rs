@@ -0,0 +1,198 @@
+//! Heap integrity verifier, a debugging aid enabled with "verify_heap" feature.
+//! The VM loop runs it after each opcode (see `vm_loop.rs`), so that a broken
+//! heap is reported right where it happened, rather than much later as a
+//! garbage term or a segfault.
+//!
+//! The heap is walked object by object (see `heap::iter`), every boxed header
+//! is checked to be intact and every list or boxed pointer found in the heap,
+//! the stack, the live X registers or the mailbox must point into the same
+//! heap, a message fragment or a module literal area. References to binaries
+//! on the binary heap are checked to point to a live binary.
+use crate::{
+  defs::Word,
+  emulator::{
+    code_srv::literal_area::LiteralAreas,
+    heap::{flat_heap::FlatHeap, gc::FromSpaceRange, heap_trait::THeap},
+  },
+  term::{
+    boxed::{
+      self,
+      binary::{refc_bin::ReferenceToBinary, BinaryType},
+      BoxHeader,
+    },
+    value::{PrimaryTag, Term},
+  },
+};
+use core::ptr;
+
+pub type VerifyResult = Result<(), String>;
+
+/// Knows which memory ranges terms are allowed to point to.
+pub struct HeapVerifier {
+  ranges: Vec<FromSpaceRange>,
+  literals: LiteralAreas,
+}
+
+impl HeapVerifier {
+  pub fn new() -> Self {
+    Self {
+      ranges: Vec::new(),
+      literals: LiteralAreas::snapshot(),
+    }
+  }
+
+  /// Allow terms to point into the used part of `heap`.
+  pub fn add_heap(&mut self, heap: &FlatHeap) {
+    self.ranges.push(heap.get_used_range());
+  }
+
+  #[inline]
+  fn is_valid_pointer(&self, p: *const Word) -> bool {
+    self.ranges.iter().any(|(begin, end)| p >= *begin && p < *end)
+      || self.literals.contains(p)
+  }
+
+  /// Walk the heap objects and check the terms found there, then check the
+  /// stack.
+  pub fn verify_heap(&self, heap: &FlatHeap) -> VerifyResult {
+    let heap_top = heap.get_heap_top_ptr();
+    unsafe {
+      let mut it = heap.heap_iter();
+      while let Some(p) = it.next() {
+        let val = ptr::read(p);
+        if val.get_term_tag() != PrimaryTag::HEADER {
+          self.verify_term(val, "heap")?;
+          continue;
+        }
+        let header_p = p as *mut BoxHeader;
+        self.verify_header(header_p)?;
+        let size = (*header_p).get_storage_size();
+        if (p as *const Word).add(size) > heap_top {
+          return Err(format!(
+            "box at {:p} of size {} crosses the heap top {:p}",
+            p, size, heap_top
+          ));
+        }
+        let mut result = Ok(());
+        (*(*header_p).get_trait_ptr_mut()).inplace_map(&mut |t| {
+          if result.is_ok() {
+            result = self.verify_term(t, "box contents");
+          }
+          t
+        });
+        result?;
+      }
+    }
+    self.verify_terms(heap.get_stack_slice(), "stack")
+  }
+
+  pub fn verify_terms(&self, terms: &[Term], location: &str) -> VerifyResult {
+    for (i, t) in terms.iter().enumerate() {
+      if let Err(e) = self.verify_term(*t, location) {
+        return Err(format!("{}[{}]: {}", location, i, e));
+      }
+    }
+    Ok(())
+  }
+
+  /// Check a single term: a list or boxed pointer must point to a valid
+  /// memory range and to a valid object.
+  pub fn verify_term(&self, t: Term, location: &str) -> VerifyResult {
+    match t.get_term_tag() {
+      PrimaryTag::HEADER => Err(format!("header word 0x{:x} in {}", t.raw(), location)),
+      PrimaryTag::CONS_PTR => {
+        let p = t.get_box_ptr_unchecked::<Word>();
+        if !self.is_valid_pointer(p) {
+          return Err(format!("cons pointer {:p} in {} is out of heap", p, location));
+        }
+        let head = unsafe { Term::from_raw(ptr::read(p)) };
+        if head.get_term_tag() == PrimaryTag::HEADER {
+          return Err(format!("cons pointer {:p} in {} points to a box", p, location));
+        }
+        Ok(())
+      }
+      PrimaryTag::BOX_PTR => {
+        // Non-values and CP can be found on stack and in unused registers
+        if t.is_non_value() || t.is_cp() {
+          return Ok(());
+        }
+        let p = t.get_box_ptr_unchecked::<Word>();
+        if !self.is_valid_pointer(p) {
+          return Err(format!("box pointer {:p} in {} is out of heap", p, location));
+        }
+        let header_word = unsafe { Term::from_raw(ptr::read(p)) };
+        if header_word.get_term_tag() != PrimaryTag::HEADER {
+          return Err(format!(
+            "box pointer {:p} in {} points to a non-header 0x{:x}",
+            p,
+            location,
+            header_word.raw()
+          ));
+        }
+        unsafe { self.verify_header(p as *const BoxHeader) }
+      }
+      _ => Ok(()),
+    }
+  }
+
+  unsafe fn verify_header(&self, header_p: *const BoxHeader) -> VerifyResult {
+    if !(*header_p).is_valid() {
+      return Err(format!("box header at {:p} is damaged", header_p));
+    }
+    if (*header_p).get_storage_size() == 0 {
+      return Err(format!("box header at {:p} has zero size", header_p));
+    }
+    if (*(*header_p).get_trait_ptr()).get_type() == boxed::BOXTYPETAG_BINARY {
+      return self.verify_binary(header_p);
+    }
+    Ok(())
+  }
+
+  /// A reference to binary heap must point to a binary which is still alive.
+  unsafe fn verify_binary(&self, header_p: *const BoxHeader) -> VerifyResult {
+    let bin_trait = boxed::Binary::get_trait(header_p as *const boxed::Binary);
+    if let BinaryType::RefToBinaryHeap = (*bin_trait).get_type() {
+      let storage_p = (*(header_p as *const ReferenceToBinary)).pointer;
+      if storage_p.is_null() || (*storage_p).get_refcount() == 0 {
+        return Err(format!(
+          "refc binary at {:p} points to a freed binary {:p}",
+          header_p, storage_p
+        ));
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    emulator::heap::{Designation, Heap},
+    term::term_builder::{tuple_builder::tuple2, ListBuilder},
+  };
+
+  #[test]
+  fn test_verify_finds_foreign_pointer() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let mut other = Heap::new(Designation::ProcessHeap);
+    unsafe {
+      let mut lb = ListBuilder::new().unwrap();
+      lb.append(Term::make_small_unsigned(1), &mut hp).unwrap();
+      let lst = lb.make_term();
+      tuple2(&mut hp, lst, Term::nil()).unwrap();
+
+      let mut verifier = HeapVerifier::new();
+      verifier.add_heap(&hp);
+      assert!(verifier.verify_heap(&hp).is_ok());
+
+      // A tuple pointing to a list on another heap is an error
+      let mut lb = ListBuilder::new().unwrap();
+      lb.append(Term::make_small_unsigned(2), &mut other).unwrap();
+      tuple2(&mut hp, lb.make_term(), Term::nil()).unwrap();
+      let mut verifier = HeapVerifier::new();
+      verifier.add_heap(&hp);
+      assert!(verifier.verify_heap(&hp).is_err());
+    }
+  }
+}
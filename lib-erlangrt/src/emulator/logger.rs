@@ -0,0 +1,14 @@
+//! Reports from the runtime about events which are not errors of the calling
+//! code, such as a process killed for its heap size. The reports go to the
+//! standard error, separately from the program output.
+use colored::Colorize;
+
+/// Report about a process killed or warned by the runtime.
+pub fn error_report(text: &str) {
+  eprintln!("{} {}", "ERROR REPORT".red(), text);
+}
+
+/// Report about a process which has exited with an abnormal reason.
+pub fn crash_report(text: &str) {
+  eprintln!("{} {}", "CRASH REPORT".red(), text);
+}
@@ -1,86 +1,527 @@
-use crate::term::value::*;
+//! Process mailbox. Messages arrive copied into their own heap fragments, so
+//! the sender never touches the receiver's heap. The fragments are merged into
+//! the process heap during GC: for `MessageQueueLocation::OnHeap` this happens
+//! to all messages, for `OffHeap` only to messages which have been received.
+//!
+//! Senders running on other scheduler threads do not touch the inbox, instead
+//! they put messages to the locked signal queue, which the owning process
+//! moves to the inbox when it looks for messages. Signals (see `signal.rs`)
+//! also arrive through the signal queue and are handled by the scheduler. The
+//! signal queue is shared with the process handle (see `process_handle.rs`),
+//! so the senders do not need access to the process.
+//!
+//! Receive walks the inbox with the save pointer: `loop_rec` looks at the
+//! message under it, `loop_rec_end` moves it forward, `remove_message` takes
+//! the message out and resets it to the first message. A process which waits
+//! keeps its save pointer, so after a wake-up only new messages are matched.
+//! Received messages leave `NON_VALUE` holes, which are compacted away once
+//! they make up half of the inbox.
+//!
+//! The receive mark (`recv_mark` and `recv_set` opcodes) remembers the end of
+//! the queue before a new reference is created. A receive which matches on
+//! that reference starts at the mark, skipping older messages which can not
+//! contain the reference. OTP 24 `recv_marker_*` opcodes reserve the same mark
+//! and then bind it to the reference instead of the receive loop label.
+use crate::{
+  emulator::{
+    code_srv::literal_area,
+    heap::{
+      gc::FromSpaceRange,
+      verify::{HeapVerifier, VerifyResult},
+      Heap,
+    },
+    signal::Signal,
+    spawn_options::MessageQueueLocation,
+  },
+  term::{boxed::reference::RefId, value::*},
+};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Which receive the mark is set for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MarkKey {
+  /// The label of the receive loop (`recv_mark`)
+  Label(Term),
+  /// A marker from `recv_marker_reserve`, not bound to a reference yet
+  Reserved(usize),
+  /// The reference which the marker is bound to (`recv_marker_bind`)
+  Reference(RefId),
+}
+
+/// Contents of the signal queue.
+struct IncomingQueue {
+  /// Messages and their heap fragments, not yet seen by the process
+  messages: Vec<(Term, Option<Heap>)>,
+  /// Signals not yet handled by the scheduler
+  signals: Vec<Signal>,
+  /// The process has found no messages and is waiting, the sender who puts
+  /// the next message must wake it up
+  waiting: bool,
+  /// The receive timer has expired (see `wait_timeout` opcode)
+  timeout: bool,
+}
+
+/// Part of the mailbox which is accessed by senders, from any thread.
+pub struct SignalQueue {
+  incoming: Mutex<IncomingQueue>,
+}
+
+impl SignalQueue {
+  fn new() -> Self {
+    let incoming = IncomingQueue {
+      messages: Vec::new(),
+      signals: Vec::new(),
+      waiting: false,
+      timeout: false,
+    };
+    Self {
+      incoming: Mutex::new(incoming),
+    }
+  }
+
+  #[inline]
+  fn lock(&self) -> MutexGuard<'_, IncomingQueue> {
+    self.incoming.lock().unwrap()
+  }
+
+  /// Put a message into the queue.
+  /// Assumes: the message is already copied to `fragment`, or it is an
+  /// immediate value and the fragment is `None`.
+  /// Returns: true if the process was waiting for messages and the caller must
+  /// wake it up.
+  pub fn put(&self, message: Term, fragment: Option<Heap>) -> bool {
+    let mut incoming = self.lock();
+    incoming.messages.push((message, fragment));
+    let was_waiting = incoming.waiting;
+    incoming.waiting = false;
+    was_waiting
+  }
+
+  /// Put a signal into the queue.
+  /// Returns: true if the process was waiting and the caller must wake it up.
+  pub fn put_signal(&self, signal: Signal) -> bool {
+    let mut incoming = self.lock();
+    incoming.signals.push(signal);
+    let was_waiting = incoming.waiting;
+    incoming.waiting = false;
+    was_waiting
+  }
+
+  /// Mark the receive timeout as expired.
+  /// Returns: true if the process was waiting and the caller must wake it up.
+  pub fn put_timeout(&self) -> bool {
+    let mut incoming = self.lock();
+    incoming.timeout = true;
+    let was_waiting = incoming.waiting;
+    incoming.waiting = false;
+    was_waiting
+  }
+}
 
 pub struct ProcessMailbox {
+  incoming: Arc<SignalQueue>,
   inbox: Vec<Term>,
-  // TODO: Some structure on proc heap?
-  read_index: usize,
+  /// Heap fragment for each message in `inbox` (same index). `None` if the
+  /// message is an immediate value, or it has been merged into process heap.
+  fragments: Vec<Option<Heap>>,
+  /// Fragments of received messages, the process heap may refer to them until
+  /// the next GC merges them into the heap.
+  received_fragments: Vec<Heap>,
+  /// Index in `inbox` of the next message to be matched by `loop_rec`
+  save: usize,
+  /// How many received messages are left in `inbox` as `NON_VALUE`s
+  holes: usize,
+  /// Set by `recv_mark` or `recv_marker_reserve`: which receive the mark is
+  /// for and the `inbox` index where the messages arrived after the mark begin
+  mark: Option<(MarkKey, usize)>,
+  /// Last marker given out by `reserve_marker`
+  last_marker: usize,
+  /// Changed with `process_flag(message_queue_data, _)`
+  pub location: MessageQueueLocation,
 }
 
 impl ProcessMailbox {
-  pub fn new() -> Self {
+  pub fn new(location: MessageQueueLocation) -> Self {
     Self {
+      incoming: Arc::new(SignalQueue::new()),
       inbox: Vec::with_capacity(32),
-      read_index: 0,
+      fragments: Vec::with_capacity(32),
+      received_fragments: Vec::new(),
+      save: 0,
+      holes: 0,
+      mark: None,
+      last_marker: 0,
+      location,
     }
   }
 
-  #[inline]
+  /// Whether the inbox has messages which the current receive has not seen.
   pub fn have_unread_messages(&self) -> bool {
-    self.read_index < self.inbox.len()
+    self.inbox[self.save..].iter().any(|m| m.is_value())
+  }
+
+  /// The queue where other threads put messages and signals.
+  pub fn get_signal_queue(&self) -> Arc<SignalQueue> {
+    self.incoming.clone()
   }
 
-//  #[inline]
-//  pub fn is_empty(&self) -> bool {
-//    self.inbox.is_empty()
-//  }
+  /// Put a message into own mailbox, when the process is running. See
+  /// `ProcessHandle::deliver_message` for sending to other processes.
+  pub fn put(&self, message: Term, fragment: Option<Heap>) {
+    self.incoming.put(message, fragment);
+  }
+
+  /// Take the signals which have arrived, in the order they were sent.
+  pub fn take_signals(&self) -> Vec<Signal> {
+    let mut incoming = self.incoming.lock();
+    incoming.signals.drain(..).collect()
+  }
+
+  /// Keep a heap fragment alive until the next GC, because the process refers
+  /// to its data (for example the reason of an exit signal).
+  pub fn keep_fragment(&mut self, fragment: Heap) {
+    self.received_fragments.push(fragment);
+  }
+
+  /// Whether the receive timer has expired since the last `clear_timeout`.
+  pub fn is_timed_out(&self) -> bool {
+    self.incoming.lock().timeout
+  }
+
+  /// Forget the expired receive timeout, when the receive is done.
+  pub fn clear_timeout(&self) {
+    self.incoming.lock().timeout = false;
+  }
+
+  /// Move messages from the signal queue to the inbox, where the process
+  /// can see them.
+  pub fn fetch_incoming(&mut self) {
+    let mut incoming = self.incoming.lock();
+    for (message, fragment) in incoming.messages.drain(..) {
+      self.inbox.push(message);
+      self.fragments.push(fragment);
+    }
+  }
+
+  /// Called by the scheduler when the process wants to wait for messages.
+  /// If no new messages have arrived, marks the mailbox as waiting, so that
+  /// the next sender will wake the process up.
+  /// Returns: true if the process should wait, false if messages or signals
+  /// are there, or the receive timeout has expired.
+  pub fn begin_wait(&self) -> bool {
+    let mut incoming = self.incoming.lock();
+    if !incoming.messages.is_empty() || !incoming.signals.is_empty() || incoming.timeout
+    {
+      return false;
+    }
+    incoming.waiting = true;
+    true
+  }
+
+  /// Access all messages as a mutable slice, used by the GC to relocate them.
+  /// Already received messages are `NON_VALUE`s and will be ignored.
+  /// Also returns the fragments which the GC should merge into the heap.
+  pub fn get_roots_and_fragments_mut(&mut self) -> (&mut [Term], Vec<&mut Heap>) {
+    let mut fragments: Vec<&mut Heap> = self.received_fragments.iter_mut().collect();
+    if self.location == MessageQueueLocation::OnHeap {
+      fragments.extend(self.fragments.iter_mut().filter_map(|f| f.as_mut()));
+    }
+    (&mut self.inbox, fragments)
+  }
+
+  /// Called after GC, free the fragments returned by `get_roots_and_fragments_mut`
+  /// as their live data is now on the process heap.
+  pub fn drop_merged_fragments(&mut self) {
+    self.received_fragments.clear();
+    if self.location == MessageQueueLocation::OnHeap {
+      for f in self.fragments.iter_mut() {
+        *f = None;
+      }
+    }
+  }
+
+  /// Copy of the messages which are still in the queue (not received).
+  pub fn get_messages(&self) -> Vec<Term> {
+    let mut messages: Vec<Term> =
+      self.inbox.iter().filter(|m| m.is_value()).cloned().collect();
+    let incoming = self.incoming.lock();
+    messages.extend(incoming.messages.iter().map(|(m, _)| *m));
+    messages
+  }
+
+  /// How many messages are still in the queue (not received).
+  pub fn get_message_count(&self) -> usize {
+    let in_inbox = self.inbox.iter().filter(|m| m.is_value()).count();
+    in_inbox + self.incoming.lock().messages.len()
+  }
+
+  /// How many heap fragments are not yet merged into the process heap.
+  pub fn get_fragment_count(&self) -> usize {
+    self.fragments.iter().flatten().count() + self.received_fragments.len()
+  }
 
-  /// Copy a message and put into process mailbox.
-  /// Assumes: the message is already copied to receiving process heap.
-  pub fn put(&mut self, message: Term) {
-    self.inbox.push(message);
+  /// Total words used by the heap fragments.
+  pub fn get_fragment_words(&self) -> usize {
+    let all_fragments =
+      self.fragments.iter().flatten().chain(self.received_fragments.iter());
+    all_fragments.map(|f| f.get_heap_used_words()).sum()
   }
 
-  /// Read message at the current receive pointer.
+  /// Check whether any message or fragment data points into `range`.
+  pub fn refers_to(&self, range: FromSpaceRange) -> bool {
+    let incoming = self.incoming.lock();
+    self.inbox.iter().any(|m| literal_area::term_points_into(*m, range))
+      || self.fragments.iter().flatten().any(|f| f.refers_to(range))
+      || self.received_fragments.iter().any(|f| f.refers_to(range))
+      || incoming.messages.iter().any(|(m, fragment)| match fragment {
+        Some(f) => f.refers_to(range),
+        None => literal_area::term_points_into(*m, range),
+      })
+      || incoming.signals.iter().any(|s| match s {
+        Signal::Exit { fragment, .. } | Signal::Down { fragment, .. } => {
+          fragment.refers_to(range)
+        }
+        _ => false,
+      })
+  }
+
+  /// Check the messages and their fragments with the heap verifier, also
+  /// allow the process heap to refer to the fragments.
+  pub fn verify(&self, verifier: &mut HeapVerifier) -> VerifyResult {
+    let all_fragments = || {
+      self.fragments.iter().flatten().chain(self.received_fragments.iter())
+    };
+    for f in all_fragments() {
+      verifier.add_heap(f);
+    }
+    for f in all_fragments() {
+      verifier.verify_heap(f)?;
+    }
+    verifier.verify_terms(&self.inbox, "mailbox")
+  }
+
+  /// Read the message under the save pointer.
+  /// Returns: `None` if all messages have been looked at by this receive.
   pub fn get_current(&mut self) -> Option<Term> {
-    if self.inbox.is_empty() {
-      return None;
+    self.fetch_incoming();
+    while self.save < self.inbox.len() && self.inbox[self.save].is_non_value() {
+      self.save += 1;
     }
+    self.inbox.get(self.save).cloned()
+  }
 
-    let mri = self.read_index;
-    debug_assert!(mri < self.inbox.len());
+  /// Move the save pointer past the current message, which did not match.
+  pub fn skip_current(&mut self) {
+    if self.save < self.inbox.len() {
+      self.save += 1;
+    }
+  }
+
+  /// Start the next receive from the first message and forget the receive
+  /// mark. Done when a message is received, on receive timeout and when an
+  /// exception is caught.
+  pub fn reset_save(&mut self) {
+    self.save = 0;
+    self.mark = None;
+  }
+
+  /// Remember the end of the message queue for the receive `key` (the
+  /// `recv_mark` opcode).
+  pub fn set_mark(&mut self, key: MarkKey) {
+    // Messages in the signal queue have also arrived before the mark
+    self.fetch_incoming();
+    self.mark = Some((key, self.inbox.len()));
+  }
+
+  /// Move the save pointer to the receive mark, if it was set for the receive
+  /// `key` (the `recv_set` and `recv_marker_use` opcodes).
+  pub fn use_mark(&mut self, key: MarkKey) {
+    if let Some((mark_key, index)) = self.mark {
+      if mark_key == key && index > self.save {
+        self.save = index;
+      }
+    }
+  }
+
+  /// Set the mark for a reference which is not created yet (the
+  /// `recv_marker_reserve` opcode).
+  /// Returns: the marker to bind the reference to.
+  pub fn reserve_marker(&mut self) -> usize {
+    self.last_marker += 1;
+    self.set_mark(MarkKey::Reserved(self.last_marker));
+    self.last_marker
+  }
+
+  /// Bind the reserved `marker` to the new reference (the `recv_marker_bind`
+  /// opcode). Nothing happens if the mark has been replaced since.
+  pub fn bind_marker(&mut self, marker: usize, ref_id: RefId) {
+    if let Some((MarkKey::Reserved(reserved), index)) = self.mark {
+      if reserved == marker {
+        self.mark = Some((MarkKey::Reference(ref_id), index));
+      }
+    }
+  }
+
+  /// Forget the mark if it was set for the receive `key` (the
+  /// `recv_marker_clear` opcode).
+  pub fn clear_mark(&mut self, key: MarkKey) {
+    if let Some((mark_key, _)) = self.mark {
+      if mark_key == key {
+        self.mark = None;
+      }
+    }
+  }
 
-    let val = self.inbox[mri];
+  /// Replace the message at `index` with a hole and keep its fragment until
+  /// the next GC.
+  fn take_message(&mut self, index: usize) -> Term {
+    let val = self.inbox[index];
     debug_assert!(val.is_value());
-    Some(val)
+    self.inbox[index] = Term::non_value();
+    if let Some(fragment) = self.fragments[index].take() {
+      self.received_fragments.push(fragment);
+    }
+    self.holes += 1;
+    val
   }
 
-  // TODO: This is ugly, do proper mailbox algorithm impl here
-  pub fn step_over(&mut self) {
-    // Guard
-    if self.inbox.is_empty() {
-      self.read_index = 0;
+  /// Remove the holes left by received messages, if there are enough of them.
+  /// The save pointer and the mark are moved to where their messages are now.
+  fn compact(&mut self) {
+    if self.holes * 2 < self.inbox.len() {
       return;
     }
-
-    let mut mri = self.read_index;
-    // remember starting pos to know if we traversed all messages and all of
-    // them were nonvalues
-    let starting_pos = mri;
-    let max_mri = self.inbox.len();
-    // Increase mail receive index over nonvalues (received values) until
-    // we hit the end of the mailbox
-    while self.inbox[mri].is_non_value() {
-      mri += 1;
-      if mri == max_mri {
-        mri = 0;
+    let mut write = 0;
+    let mut new_save = None;
+    let mut new_mark = None;
+    for read in 0..self.inbox.len() {
+      if read == self.save {
+        new_save = Some(write);
+      }
+      if let Some((key, index)) = self.mark {
+        if read == index {
+          new_mark = Some((key, write));
+        }
       }
-      if mri == starting_pos {
-        // Done a full loop around mailbox and all values were non-values
-        self.inbox.clear();
-        self.read_index = 0;
-        return;
+      if self.inbox[read].is_value() {
+        self.inbox.swap(read, write);
+        self.fragments.swap(read, write);
+        write += 1;
       }
     }
-    self.read_index = mri;
+    self.inbox.truncate(write);
+    self.fragments.truncate(write);
+    self.save = new_save.unwrap_or(write);
+    self.mark = self.mark.map(|(key, _)| new_mark.unwrap_or((key, write)));
+    self.holes = 0;
   }
 
-  // Remove value from current mailbox position and return it, move pointer
-  // forward.
+  /// Remove the messages for which `pred` returns true, wherever they are in
+  /// the queue. Used by `demonitor(Ref, [flush])`.
+  pub fn remove_matching<F>(&mut self, pred: F)
+  where
+    F: Fn(Term) -> bool,
+  {
+    self.fetch_incoming();
+    for i in 0..self.inbox.len() {
+      let m = self.inbox[i];
+      if m.is_value() && pred(m) {
+        self.take_message(i);
+      }
+    }
+    self.compact();
+  }
+
+  /// Remove the message under the save pointer and return it, the next
+  /// receive starts from the first message.
   pub fn remove_current(&mut self) -> Term {
-    let mri = self.read_index;
-    let val = self.inbox[mri];
-    self.inbox[mri] = Term::non_value();
-    self.step_over();
+    let val = self.take_message(self.save);
+    self.reset_save();
+    self.compact();
     val
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn small(n: usize) -> Term {
+    Term::make_small_unsigned(n)
+  }
+
+  #[test]
+  fn test_save_pointer() {
+    let mut mailbox = ProcessMailbox::new(MessageQueueLocation::OnHeap);
+    for n in 0..3 {
+      mailbox.put(small(n), None);
+    }
+    // Receive the second message, the next receive starts from the first
+    assert_eq!(mailbox.get_current(), Some(small(0)));
+    mailbox.skip_current();
+    assert_eq!(mailbox.get_current(), Some(small(1)));
+    assert_eq!(mailbox.remove_current(), small(1));
+    assert_eq!(mailbox.get_current(), Some(small(0)));
+
+    // Nothing matched, only a new message is looked at after waiting
+    mailbox.skip_current();
+    assert_eq!(mailbox.get_current(), Some(small(2)));
+    mailbox.skip_current();
+    assert_eq!(mailbox.get_current(), None);
+    assert!(!mailbox.have_unread_messages());
+    mailbox.put(small(3), None);
+    assert_eq!(mailbox.get_current(), Some(small(3)));
+    assert_eq!(mailbox.remove_current(), small(3));
+    assert_eq!(mailbox.get_messages(), vec![small(0), small(2)]);
+  }
+
+  #[test]
+  fn test_receive_mark() {
+    let mut mailbox = ProcessMailbox::new(MessageQueueLocation::OnHeap);
+    // Stands for the code pointer of the receive loop
+    let label = MarkKey::Label(small(100));
+    for n in 0..4 {
+      mailbox.put(small(n), None);
+    }
+    mailbox.set_mark(label);
+    mailbox.put(small(4), None);
+    // Removing older messages compacts the inbox, the mark must follow
+    mailbox.remove_matching(|m| m.get_small_unsigned() < 3);
+    mailbox.use_mark(MarkKey::Label(small(101)));
+    assert_eq!(mailbox.get_current(), Some(small(3)));
+    mailbox.use_mark(label);
+    assert_eq!(mailbox.get_current(), Some(small(4)));
+    assert_eq!(mailbox.remove_current(), small(4));
+    // The mark is used once
+    mailbox.use_mark(label);
+    assert_eq!(mailbox.get_current(), Some(small(3)));
+  }
+
+  #[test]
+  fn test_receive_marker() {
+    let mut mailbox = ProcessMailbox::new(MessageQueueLocation::OnHeap);
+    let ref_id = [1, 0, 0];
+    mailbox.put(small(0), None);
+    let marker = mailbox.reserve_marker();
+    mailbox.put(small(1), None);
+    // Not bound yet, or bound to another reference
+    mailbox.use_mark(MarkKey::Reference(ref_id));
+    assert_eq!(mailbox.get_current(), Some(small(0)));
+    mailbox.bind_marker(marker + 1, ref_id);
+    mailbox.use_mark(MarkKey::Reference(ref_id));
+    assert_eq!(mailbox.get_current(), Some(small(0)));
+
+    mailbox.bind_marker(marker, ref_id);
+    mailbox.use_mark(MarkKey::Reference([2, 0, 0]));
+    assert_eq!(mailbox.get_current(), Some(small(0)));
+    mailbox.use_mark(MarkKey::Reference(ref_id));
+    assert_eq!(mailbox.get_current(), Some(small(1)));
+
+    // After a clear the receive starts from the first message again
+    mailbox.skip_current();
+    mailbox.clear_mark(MarkKey::Reference(ref_id));
+    mailbox.reset_save();
+    mailbox.use_mark(MarkKey::Reference(ref_id));
+    assert_eq!(mailbox.get_current(), Some(small(0)));
+  }
+}
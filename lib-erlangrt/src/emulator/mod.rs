@@ -2,19 +2,25 @@ pub mod arith;
 pub mod atom;
 pub mod code;
 pub mod code_srv;
+pub mod crash_dump;
 pub mod disasm;
 pub mod export;
 pub mod funarity;
 pub mod function;
 pub mod gen_atoms; // generated
 pub mod heap;
+pub mod logger;
 pub mod mailbox;
 pub mod mfa;
 pub mod module;
 pub mod process;
+pub mod process_dict;
 pub mod process_flags;
+pub mod process_handle;
 pub mod process_registry;
 pub mod runtime_ctx;
 pub mod scheduler;
+pub mod signal;
 pub mod spawn_options;
+pub mod timer_wheel;
 pub mod vm;
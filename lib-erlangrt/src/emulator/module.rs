@@ -60,6 +60,11 @@ impl Module {
     }
   }
 
+  /// Code size in bytes.
+  pub fn get_code_size(&self) -> usize {
+    self.code.len() * WORD_BYTES
+  }
+
   /// Get module name field
   pub fn name(&self) -> Term {
     self.versioned_name.module
@@ -2,23 +2,37 @@
 //! heap, stack, registers, and message queue.
 
 use crate::{
-  defs::exc_type::ExceptionType,
+  defs::{exc_type::ExceptionType, WordSize},
   emulator::{
+    code::CodePtr,
     code_srv::CodeServer,
-    heap::{copy_term, Designation, Heap},
+    gen_atoms,
+    heap::{
+      copy_term,
+      gc::FromSpaceRange,
+      heap_size::{self, MaxHeapSize},
+      heap_trait::THeap,
+      verify::{HeapVerifier, VerifyResult},
+      Heap,
+    },
+    logger,
     mailbox::ProcessMailbox,
     mfa::{ModFunArgs, ModFunArity},
-    process_flags::ProcessFlags,
-    process_registry::ProcessRegistry,
-    runtime_ctx,
-    scheduler::{self, Scheduler},
+    process_dict::ProcessDict,
+    process_flags::{self, ProcessFlags},
+    runtime_ctx::{self, current_binary::CurrentBinaryState},
+    scheduler,
+    signal::Signal,
     spawn_options::SpawnOptions,
+    timer_wheel::TimerId,
+  },
+  fail::{RtErr, RtResult},
+  term::{
+    boxed::{self, reference::RefId},
+    value::*,
   },
-  fail::RtResult,
-  term::value::*,
 };
-use core::ptr;
-use crate::emulator::heap::heap_trait::THeap;
+use std::collections::{HashMap, HashSet};
 
 //#[allow(dead_code)]
 //#[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -44,14 +58,6 @@ use crate::emulator::heap::heap_trait::THeap;
 pub struct Process {
   pub pid: Term,
 
-  // Scheduling and fail state
-  /// Scheduling priority (selects the runqueue when this process is scheduled)
-  pub prio: scheduler::Prio,
-
-  /// Current scheduler queue where this process is registered
-  pub current_queue: scheduler::Queue,
-  pub owned_by_scheduler: *mut Scheduler,
-
   // Execution Context, etc.
   /// Runtime context with registers, instruction pointer etc
   pub context: runtime_ctx::Context,
@@ -59,6 +65,18 @@ pub struct Process {
   // Memory
   heap: Heap,
   pub mailbox: ProcessMailbox,
+  /// Heap size limit, checked after each garbage collection
+  pub max_heap_size: MaxHeapSize,
+  /// Binary virtual heap does not shrink below this size, in words
+  min_bin_vheap_size: usize,
+  /// Binary virtual heap size: when binaries referenced from the heap but
+  /// stored outside of it grow over this size, the GC is run to release the
+  /// dead ones. Adjusted after each GC like the heap size, in words.
+  bin_vheap_size: usize,
+  /// Timer started by `wait_timeout` for the current `receive ... after`
+  pub receive_timer: Option<TimerId>,
+  /// Process dictionary, its keys and values are on the heap
+  pub dictionary: ProcessDict,
 
   // Error handling
   /// Record result of last scheduled timeslice for this process
@@ -69,50 +87,79 @@ pub struct Process {
   pub error: Option<(ExceptionType, Term)>,
   /// How many catch frames are there on stack
   pub num_catches: isize,
+  /// Linked processes, they receive an exit signal when this process exits.
+  /// Modified only by the owning scheduler, other processes send link and
+  /// unlink signals (see `signal.rs`).
+  pub links: HashSet<Term>,
+  /// Monitors created by this process with `erlang:monitor/2`
+  pub monitors: HashMap<RefId, MonitorPeer>,
+  /// Processes monitoring this process, they receive a 'DOWN' message when
+  /// this process exits
+  pub monitored_by: HashMap<RefId, MonitorPeer>,
 
   pub process_flags: ProcessFlags,
+  /// Set by `hibernate`, until the process runs again
+  pub hibernated: bool,
+}
+
+/// The other side of a monitor: the monitored process in `Process::monitors`
+/// or the watching process in `Process::monitored_by`. The `name` is set if
+/// the monitor was created for a registered name.
+#[derive(Debug, Clone, Copy)]
+pub struct MonitorPeer {
+  pub pid: Term,
+  pub name: Option<Term>,
 }
 
 impl Process {
   // Call this only from VM, the new process must be immediately registered
   // in proc registry for this VM
+  // Process must start with some code location `ip`
   pub fn new(
     pid: Term,
-    _parent_pid: Term,
-    mfarity: &ModFunArity,
+    parent_pid: Term,
+    ip: CodePtr,
     spawn_opts: &SpawnOptions,
-    code_server: &mut CodeServer,
-  ) -> RtResult<Process> {
+  ) -> Process {
     assert!(pid.is_local_pid());
-    assert!(_parent_pid.is_local_pid() || _parent_pid == Term::nil());
+    assert!(parent_pid.is_local_pid() || parent_pid == Term::nil());
 
-    // Process must start with some code location
-    match code_server.lookup_beam_code_and_load(mfarity) {
-      Ok(ip) => {
-        let p = Process {
-          pid,
-          process_flags: spawn_opts.process_flags,
-
-          // Scheduling
-          prio: spawn_opts.prio,
-          current_queue: scheduler::Queue::None,
-          timeslice_result: scheduler::SliceResult::None,
-          owned_by_scheduler: ptr::null_mut(),
-
-          // Memory
-          heap: Heap::new(Designation::ProcessHeap),
-          mailbox: ProcessMailbox::new(),
-
-          // Execution
-          context: runtime_ctx::Context::new(ip),
-
-          error: None,
-          num_catches: 0,
-        };
-        Ok(p)
-        // Ok(sync::Arc::new(sync::RwLock::new(p)))
-      }
-      Err(e) => Err(e),
+    let mut links = HashSet::new();
+    if spawn_opts.link {
+      assert!(parent_pid.is_local_pid(), "Can't link a new process to no parent");
+      links.insert(parent_pid);
+    }
+    let mut monitored_by = HashMap::new();
+    if let Some(ref_id) = spawn_opts.monitor {
+      assert!(parent_pid.is_local_pid(), "Can't monitor a new process from no parent");
+      monitored_by.insert(ref_id, MonitorPeer { pid: parent_pid, name: None });
+    }
+
+    Process {
+      pid,
+      process_flags: spawn_opts.process_flags,
+
+      // Scheduling
+      timeslice_result: scheduler::SliceResult::None,
+
+      // Memory
+      heap: Heap::new_process_heap(spawn_opts.min_heap_size),
+      mailbox: ProcessMailbox::new(spawn_opts.msg_queue),
+      max_heap_size: spawn_opts.max_heap_size,
+      min_bin_vheap_size: spawn_opts.min_bin_vheap_size,
+      bin_vheap_size: spawn_opts.min_bin_vheap_size,
+      receive_timer: None,
+      dictionary: ProcessDict::new(),
+
+      // Execution
+      context: runtime_ctx::Context::new(ip),
+
+      error: None,
+      num_catches: 0,
+      links,
+      monitors: HashMap::new(),
+      monitored_by,
+      hibernated: false,
     }
   }
 
@@ -126,15 +173,186 @@ impl Process {
     heap_ref as &mut THeap
   }
 
+  /// Read-only access to the heap for memory inspection tools, such as the
+  /// crash dump.
+  #[inline]
+  pub fn get_flat_heap(&self) -> &Heap {
+    &self.heap
+  }
+
+  /// Heap capacity (heap and stack together), in words.
+  #[inline]
+  pub fn get_heap_size(&self) -> usize {
+    self.heap.get_heap_max_capacity()
+  }
+
+  /// Current `min_heap_size` (rounded up to a heap size step), in words.
+  #[inline]
+  pub fn get_min_heap_size(&self) -> usize {
+    self.heap.get_min_capacity()
+  }
+
+  /// Change `min_heap_size`, takes effect on the next garbage collection.
+  #[inline]
+  pub fn set_min_heap_size(&mut self, min_heap_size: usize) {
+    self.heap.set_min_capacity(min_heap_size)
+  }
+
+  /// Current `min_bin_vheap_size`, in words.
+  #[inline]
+  pub fn get_min_bin_vheap_size(&self) -> usize {
+    self.min_bin_vheap_size
+  }
+
+  /// Change `min_bin_vheap_size`, the current binary virtual heap size grows
+  /// immediately if it is smaller.
+  pub fn set_min_bin_vheap_size(&mut self, size: usize) {
+    self.min_bin_vheap_size = size;
+    self.bin_vheap_size = core::cmp::max(self.bin_vheap_size, size);
+  }
+
+  /// Ensure that `need` words can be allocated on the heap, run the garbage
+  /// collector if they are not available, or if the binaries held by the heap
+  /// have grown over the binary virtual heap size.
+  /// Args: `live` - how many X registers are in use; `extra_roots` - other
+  /// values held by the caller which must survive and be updated by the GC.
+  pub fn ensure_heap(
+    &mut self,
+    ctx: &mut runtime_ctx::Context,
+    need: WordSize,
+    live: usize,
+    extra_roots: &mut [Term],
+  ) -> RtResult<()> {
+    if self.heap.heap_check_available(need)
+      && self.heap.get_off_heap_words() <= self.bin_vheap_size
+    {
+      return Ok(());
+    }
+    self.garbage_collect(ctx, need, live, extra_roots)
+  }
+
+  /// Collect garbage on the process heap. The root set consists of X registers
+  /// `0..live`, the stack, the mailbox, the process dictionary, the binary
+  /// being built (if any) and `extra_roots`. Message fragments are merged into
+  /// the heap. Returns an error if `need` words are still not available, or if
+  /// the heap has grown over `max_heap_size` and the process must die.
+  pub fn garbage_collect(
+    &mut self,
+    ctx: &mut runtime_ctx::Context,
+    need: WordSize,
+    live: usize,
+    extra_roots: &mut [Term],
+  ) -> RtResult<()> {
+    let mut current_bin = [ctx.current_bin.get_root()];
+    let result = {
+      let (messages, mut fragments) = self.mailbox.get_roots_and_fragments_mut();
+      let mut roots: [&mut [Term]; 5] = [
+        ctx.registers_slice_mut(0, live),
+        messages,
+        self.dictionary.get_roots_mut(),
+        &mut current_bin,
+        extra_roots,
+      ];
+      self.heap.garbage_collect(need, &mut roots, &mut fragments)
+    };
+    // The collection has happened even if the memory is still not enough
+    ctx.current_bin.set_root(current_bin[0]);
+    self.mailbox.drop_merged_fragments();
+    self.bin_vheap_size = heap_size::capacity_after_gc(
+      self.bin_vheap_size,
+      self.heap.get_off_heap_words(),
+      self.min_bin_vheap_size,
+    );
+    self.check_max_heap_size()?;
+    result
+  }
+
+  /// Having the heap grown by the GC, compare it against `max_heap_size` and
+  /// report and/or kill the process if the limit is exceeded. The kill is an
+  /// exception which cannot be caught.
+  fn check_max_heap_size(&self) -> RtResult<()> {
+    let heap_size = self.heap.get_heap_max_capacity();
+    if !self.max_heap_size.is_exceeded_by(heap_size) {
+      return Ok(());
+    }
+    if self.max_heap_size.error_logger {
+      logger::error_report(&format!(
+        "Process: {} Context: maximum heap size reached \
+         Max Heap Size: {} Total Heap Size: {} Kill: {}",
+        self.pid, self.max_heap_size.size, heap_size, self.max_heap_size.kill
+      ));
+    }
+    if self.max_heap_size.kill {
+      return Err(RtErr::Exception(ExceptionType::Panic, gen_atoms::KILLED));
+    }
+    Ok(())
+  }
+
   /// Copy args from mfargs-MFA-something into new process heap and set the
   /// registers to the arguments passed to spawn.
   pub fn set_spawn_args(&mut self, mfargs: &ModFunArgs) -> RtResult<()> {
-    let mut xindex = 0;
+    let mut args = Vec::new();
     mfargs.for_each_arg(|arg| -> RtResult<()> {
-      self.context.set_x(xindex, arg);
-      xindex += 1;
+      args.push(arg);
       Ok(())
-    })
+    })?;
+    let need = args.iter().map(|arg| copy_term::size_of_term(*arg).words).sum();
+    self.reserve_spawn_heap(WordSize::new(need))?;
+    for (xindex, arg) in args.iter().enumerate() {
+      let arg = copy_term::copy_to(*arg, self.get_heap_mut())?;
+      self.context.set_x(xindex, arg);
+    }
+    self.context.live = args.len();
+    Ok(())
+  }
+
+  /// Grow the heap of a new process, where nothing is live yet, so that the
+  /// spawn arguments of `need` words can be copied there.
+  fn reserve_spawn_heap(&mut self, need: WordSize) -> RtResult<()> {
+    if self.heap.heap_check_available(need) {
+      return Ok(());
+    }
+    self.heap.garbage_collect(need, &mut [], &mut [])
+  }
+
+  /// Copy a fun with no arguments to the process heap and load its frozen
+  /// values into the registers, for the process which starts by calling the
+  /// fun (see `VM::create_process_fun`).
+  pub fn set_spawn_fun(&mut self, fun: Term) -> RtResult<()> {
+    self.reserve_spawn_heap(copy_term::size_of_term(fun))?;
+    let fun = copy_term::copy_to(fun, self.get_heap_mut())?;
+    let frozen = unsafe { (*boxed::Closure::const_from_term(fun)?).get_frozen() };
+    self
+      .context
+      .registers_slice_mut(0, frozen.len())
+      .copy_from_slice(frozen);
+    self.context.live = frozen.len();
+    Ok(())
+  }
+
+  /// Put the process to sleep for `erlang:hibernate/3`. The stack and the
+  /// catches are discarded, `args` become the only live registers, and the
+  /// heap is compacted to contain only the live data. When a message arrives,
+  /// the process continues at `ip`. Returning from there with the empty stack
+  /// will end the process.
+  pub fn hibernate(&mut self, ip: CodePtr, args: &[Term]) {
+    let ctx = &mut self.context;
+    let regs = ctx.registers_slice_mut(0, args.len());
+    regs.copy_from_slice(args);
+    ctx.live = args.len();
+    ctx.ip = ip;
+    ctx.clear_cp();
+    ctx.current_bin = CurrentBinaryState::new();
+    self.num_catches = 0;
+    // Old messages are checked again by the receive after the wake up
+    self.mailbox.reset_save();
+    {
+      let (messages, mut fragments) = self.mailbox.get_roots_and_fragments_mut();
+      let mut roots: [&mut [Term]; 3] = [regs, messages, self.dictionary.get_roots_mut()];
+      self.heap.compact(&mut roots, &mut fragments);
+    }
+    self.mailbox.drop_merged_fragments();
+    self.hibernated = true;
   }
 
   /// Returns true if there was an error or exception during the last timeslice.
@@ -173,21 +391,112 @@ impl Process {
   //    self.error = ProcessError::None;
   //  }
 
-  /// Copy a message and put into process mailbox.
-  pub fn deliver_message(
+  /// Copy a message into a heap fragment and put into own mailbox. Messages
+  /// to other processes go through their handles, see
+  /// `ProcessHandle::deliver_message`.
+  pub fn deliver_message(&mut self, message: Term) -> RtResult<()> {
+    let (m1, fragment) = copy_term::copy_to_fragment(message)?;
+    self.mailbox.put(m1, fragment);
+    Ok(())
+  }
+
+  /// Act on the signals which have arrived, called by the scheduler before
+  /// the process runs. Exit signals are turned into `{'EXIT', From, Reason}`
+  /// messages if the process traps exits.
+  /// Returns: exit reason if an exit signal has killed the process.
+  pub fn handle_signals(&mut self) -> Option<Term> {
+    let mut exit_reason = None;
+    for signal in self.mailbox.take_signals() {
+      match signal {
+        Signal::Link { from } => {
+          self.links.insert(from);
+        }
+        Signal::Unlink { from } => {
+          self.links.remove(&from);
+        }
+        Signal::Exit {
+          from,
+          reason,
+          message,
+          fragment,
+          linked,
+        } => {
+          // The link might have been removed while the signal was on its way
+          if linked && !self.links.remove(&from) {
+            continue;
+          }
+          if exit_reason.is_none() {
+            exit_reason =
+              self.handle_exit_signal(from, reason, message, fragment, linked);
+          }
+        }
+        Signal::Monitor { from, ref_id, name } => {
+          self.monitored_by.insert(ref_id, MonitorPeer { pid: from, name });
+        }
+        Signal::Demonitor { ref_id } => {
+          self.monitored_by.remove(&ref_id);
+        }
+        Signal::Down {
+          ref_id,
+          message,
+          fragment,
+        } => {
+          // Ignore if the monitor has been removed with `demonitor`
+          if self.monitors.remove(&ref_id).is_some() {
+            self.mailbox.put(message, Some(fragment));
+          }
+        }
+      }
+    }
+    exit_reason
+  }
+
+  /// Act on an exit signal from `from`, see `Signal::Exit` for the other
+  /// arguments. A process trapping exits receives the message instead, unless
+  /// the reason is `kill`. Reason `normal` is ignored, except when the process
+  /// has sent the signal to itself with `exit(self(), normal)`.
+  /// Returns: exit reason if the signal kills the process.
+  pub fn handle_exit_signal(
     &mut self,
-    proc_reg: &mut ProcessRegistry,
+    from: Term,
+    reason: Term,
     message: Term,
-  ) -> RtResult<()> {
-    let m1 = copy_term::copy_to(message, &mut self.heap)?;
-    self.mailbox.put(m1);
-
-    // Notify our current scheduler that a new message has come to possibly wake
-    // up from infinite or timed wait.
-    unsafe {
-      (*self.owned_by_scheduler).notify_new_incoming_message(proc_reg, self);
+    fragment: Heap,
+    linked: bool,
+  ) -> Option<Term> {
+    if !linked && reason == gen_atoms::KILL {
+      // Untrappable kill
+      Some(gen_atoms::KILLED)
+    } else if self.process_flags.get(process_flags::TRAP_EXIT) {
+      self.mailbox.put(message, Some(fragment));
+      None
+    } else if reason != gen_atoms::NORMAL || from == self.pid {
+      self.mailbox.keep_fragment(fragment);
+      Some(reason)
+    } else {
+      None
     }
-    Ok(())
+  }
+
+  /// Check whether the process refers to memory `range` from its registers,
+  /// heap, stack, mailbox or dictionary. Used by the code server to find out
+  /// whether a literal area of a purged module can be freed.
+  pub fn refers_to(&self, range: FromSpaceRange) -> bool {
+    self.context.registers_refer_to(range)
+      || self.heap.refers_to(range)
+      || self.mailbox.refers_to(range)
+      || self.dictionary.refers_to(range)
+  }
+
+  /// Run the heap verifier on the process heap, stack, registers `0..live`,
+  /// the mailbox and the dictionary (see `heap/verify.rs`).
+  pub fn verify_heap(&self, live: usize) -> VerifyResult {
+    let mut verifier = HeapVerifier::new();
+    verifier.add_heap(&self.heap);
+    self.mailbox.verify(&mut verifier)?;
+    verifier.verify_heap(&self.heap)?;
+    verifier.verify_terms(self.dictionary.get_roots(), "dict")?;
+    verifier.verify_terms(self.context.live_registers(live), "x")
   }
 
   /// Ugly hack to mut-borrow the context without making borrow checker sad.
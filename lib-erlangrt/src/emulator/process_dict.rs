@@ -0,0 +1,216 @@
+//! Process dictionary: key-value storage private to a process, accessed with
+//! `put/2`, `get/0,1`, `get_keys/0,1` and `erase/0,1`. Keys and values live
+//! on the process heap and the dictionary is a root for the garbage collector.
+use crate::{
+  emulator::{code_srv::literal_area, heap::gc::FromSpaceRange},
+  term::{boxed, compare, value::Term},
+};
+use core::{
+  hash::{Hash, Hasher},
+  mem,
+};
+use std::collections::{hash_map::DefaultHasher, HashMap};
+
+pub struct ProcessDict {
+  /// Keys and values interleaved: `[k0, v0, k1, v1, ...]`, so that the whole
+  /// dictionary can be given to the GC as one slice of roots
+  entries: Vec<Term>,
+  /// Indexes of the keys in `entries` grouped by the hash of the key
+  index: HashMap<u64, Vec<usize>>,
+}
+
+/// Hash a term by its value, so that the terms equal with `=:=` have the same
+/// hash and a key keeps its hash when the GC moves it. Terms which are costly
+/// to walk (maps, funs) only hash their type, `compare::eq_exact` tells them
+/// apart.
+fn hash_term(t: Term) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  let mut stack = vec![t];
+  while let Some(t) = stack.pop() {
+    unsafe {
+      if t.is_cons() {
+        let p = t.get_cons_ptr();
+        stack.push((*p).tl());
+        stack.push((*p).hd());
+        "cons".hash(&mut hasher);
+      } else if t.is_tuple() {
+        let p = t.get_tuple_ptr();
+        let arity = (*p).get_arity();
+        (0..arity).for_each(|i| stack.push((*p).get_element(i)));
+        arity.hash(&mut hasher);
+      } else if t.is_float() {
+        // Adding 0.0 turns -0.0 into 0.0, they are equal
+        let val = t.get_float_unchecked() + 0.0;
+        val.to_bits().hash(&mut hasher);
+      } else if t.is_big_int() {
+        let p = t.get_box_ptr::<boxed::Bignum>();
+        (*p).is_negative().hash(&mut hasher);
+        (*p).get_digits().hash(&mut hasher);
+      } else if t.is_local_ref() {
+        boxed::Reference::get_id(t).hash(&mut hasher);
+      } else if !t.is_boxed() {
+        t.hash(&mut hasher);
+      } else {
+        "boxed".hash(&mut hasher);
+      }
+    }
+  }
+  hasher.finish()
+}
+
+impl ProcessDict {
+  pub fn new() -> Self {
+    Self {
+      entries: Vec::new(),
+      index: HashMap::new(),
+    }
+  }
+
+  /// Index of the key in `entries`, the value follows it. Keys are compared
+  /// with `=:=`, like in OTP.
+  fn find(&self, key: Term) -> Option<usize> {
+    let positions = self.index.get(&hash_term(key))?;
+    positions
+      .iter()
+      .cloned()
+      .find(|i| compare::eq_exact(self.entries[*i], key))
+  }
+
+  pub fn get(&self, key: Term) -> Option<Term> {
+    self.find(key).map(|i| self.entries[i + 1])
+  }
+
+  /// Store the value for the key.
+  /// Returns: the previous value, if the key existed.
+  pub fn put(&mut self, key: Term, value: Term) -> Option<Term> {
+    match self.find(key) {
+      Some(i) => Some(mem::replace(&mut self.entries[i + 1], value)),
+      None => {
+        let positions = self.index.entry(hash_term(key)).or_default();
+        positions.push(self.entries.len());
+        self.entries.push(key);
+        self.entries.push(value);
+        None
+      }
+    }
+  }
+
+  /// Remove the key, the last pair takes its place in `entries`.
+  /// Returns: the value, if the key existed.
+  pub fn erase(&mut self, key: Term) -> Option<Term> {
+    let i = self.find(key)?;
+    let value = self.entries[i + 1];
+    self.unindex(key, i);
+    let last = self.entries.len() - 2;
+    if i != last {
+      let last_key = self.entries[last];
+      self.unindex(last_key, last);
+      self.index.entry(hash_term(last_key)).or_default().push(i);
+    }
+    self.entries.swap_remove(i + 1);
+    self.entries.swap_remove(i);
+    Some(value)
+  }
+
+  /// Forget the position `i` of `key` in the index.
+  fn unindex(&mut self, key: Term, i: usize) {
+    let hash = hash_term(key);
+    if let Some(positions) = self.index.get_mut(&hash) {
+      positions.retain(|pos| *pos != i);
+      if positions.is_empty() {
+        self.index.remove(&hash);
+      }
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.entries.clear();
+    self.index.clear();
+  }
+
+  /// Iterate over `(key, value)` pairs, in no particular order.
+  pub fn iter(&self) -> impl Iterator<Item = (Term, Term)> + '_ {
+    self.entries.chunks(2).map(|kv| (kv[0], kv[1]))
+  }
+
+  /// Keys which have the value `value`.
+  pub fn keys_with_value(&self, value: Term) -> Vec<Term> {
+    self
+      .iter()
+      .filter(|(_k, v)| compare::eq_exact(*v, value))
+      .map(|(k, _v)| k)
+      .collect()
+  }
+
+  #[inline]
+  pub fn get_roots(&self) -> &[Term] {
+    &self.entries
+  }
+
+  /// Keys and values for the GC to update.
+  #[inline]
+  pub fn get_roots_mut(&mut self) -> &mut [Term] {
+    &mut self.entries
+  }
+
+  /// Check whether any key or value points into `range`.
+  pub fn refers_to(&self, range: FromSpaceRange) -> bool {
+    self
+      .entries
+      .iter()
+      .any(|t| literal_area::term_points_into(*t, range))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    emulator::{
+      gen_atoms,
+      heap::{Designation, Heap},
+    },
+    term::term_builder::tuple_builder::tuple2,
+  };
+
+  #[test]
+  fn test_put_get_erase() {
+    let mut dict = ProcessDict::new();
+    let one = Term::make_small_unsigned(1);
+    let two = Term::make_small_unsigned(2);
+    assert_eq!(dict.put(gen_atoms::OK, one), None);
+    assert_eq!(dict.put(gen_atoms::UNDEFINED, one), None);
+    assert_eq!(dict.put(gen_atoms::OK, two), Some(one));
+    assert_eq!(dict.get(gen_atoms::OK), Some(two));
+    assert_eq!(dict.keys_with_value(one), vec![gen_atoms::UNDEFINED]);
+
+    assert_eq!(dict.erase(gen_atoms::UNDEFINED), Some(one));
+    assert_eq!(dict.erase(gen_atoms::UNDEFINED), None);
+    assert_eq!(dict.get_roots(), &[gen_atoms::OK, two]);
+  }
+
+  #[test]
+  fn test_keys_compared_by_value() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let mut dict = ProcessDict::new();
+    let one = Term::make_small_unsigned(1);
+    let key = tuple2(&mut hp, gen_atoms::OK, one).unwrap();
+    let key_copy = tuple2(&mut hp, gen_atoms::OK, one).unwrap();
+    let float_one = Term::make_float(&mut hp, 1.0).unwrap();
+
+    assert_eq!(dict.put(key, gen_atoms::TRUE), None);
+    assert_eq!(dict.put(one, gen_atoms::FALSE), None);
+    assert_eq!(dict.put(gen_atoms::OK, one), None);
+    assert_eq!(dict.get(key_copy), Some(gen_atoms::TRUE));
+    // 1.0 is not exactly equal to 1
+    assert_eq!(dict.get(float_one), None);
+
+    // Erasing the first key moves the last pair in its place
+    assert_eq!(dict.erase(key_copy), Some(gen_atoms::TRUE));
+    assert_eq!(dict.get(key), None);
+    assert_eq!(dict.get(gen_atoms::OK), Some(one));
+    assert_eq!(dict.get(one), Some(gen_atoms::FALSE));
+    assert_eq!(dict.erase(one), Some(gen_atoms::FALSE));
+    assert_eq!(dict.get_roots(), &[gen_atoms::OK, one]);
+  }
+}
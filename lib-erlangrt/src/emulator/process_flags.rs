@@ -13,7 +13,7 @@ impl ProcessFlags {
   }
 
   #[inline]
-  pub fn get(&mut self, flag: ProcessFlag) -> bool {
+  pub fn get(&self, flag: ProcessFlag) -> bool {
     self.0 & flag.0 != 0
   }
 
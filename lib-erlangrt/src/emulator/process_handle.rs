@@ -0,0 +1,167 @@
+//! Shared ownership of a process. The process registry holds a handle for
+//! each process, and a thread which delivers a message or a signal, or which
+//! runs the process, takes its own handle from the registry. So the process
+//! memory stays valid while it is used, even if the process exits meanwhile.
+//!
+//! Other threads only use the thread-safe parts of the handle: the signal
+//! queue (incoming messages and signals, see `mailbox.rs`) and the run state,
+//! which tells where the process is in the scheduler. The process itself is
+//! used by the scheduler which has marked it as running, or by inspectors
+//! while it is not running (see `Scheduler::with_stopped_process`).
+use crate::{
+  emulator::{
+    heap::{copy_term, Heap},
+    mailbox::SignalQueue,
+    process::Process,
+    scheduler::{Prio, Queue, Scheduler},
+    signal::Signal,
+  },
+  fail::RtResult,
+  term::value::Term,
+};
+use core::cell::UnsafeCell;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// Scheduling state of a process, changed by the schedulers and by the
+/// threads which wake the process up.
+#[derive(Debug, Clone, Copy)]
+pub struct RunState {
+  /// Current scheduler queue where this process is registered
+  pub queue: Queue,
+  /// Index of the scheduler which runs this process, changes only when the
+  /// process is not queued and not waiting (see `Scheduler::steal_process`)
+  pub owner: usize,
+  /// Scheduling priority (selects the runqueue when this process is scheduled)
+  pub prio: Prio,
+  /// A scheduler is running the process
+  pub running: bool,
+  /// The running process waits in `with_stopped_process` for another process
+  pub inspecting: bool,
+  /// How many other processes are reading this process now, it does not run
+  /// until they are done
+  pub inspectors: usize,
+  /// The process has exited and is removed from the registry
+  pub exited: bool,
+}
+
+struct ProcessCell {
+  pid: Term,
+  signal_queue: Arc<SignalQueue>,
+  run_state: Mutex<RunState>,
+  /// Signalled when a process stops running, exits or when its inspectors
+  /// change, wakes up the threads waiting for it (see `wait_run_state`)
+  run_state_changed: Condvar,
+  process: UnsafeCell<Process>,
+}
+
+// The process is used by one thread at a time, as described by the run state,
+// other parts of the cell are locked.
+unsafe impl Send for ProcessCell {}
+unsafe impl Sync for ProcessCell {}
+
+#[derive(Clone)]
+pub struct ProcessHandle {
+  cell: Arc<ProcessCell>,
+}
+
+impl ProcessHandle {
+  pub fn new(proc: Process, prio: Prio) -> Self {
+    let run_state = RunState {
+      queue: Queue::None,
+      owner: 0,
+      prio,
+      running: false,
+      inspecting: false,
+      inspectors: 0,
+      exited: false,
+    };
+    let cell = ProcessCell {
+      pid: proc.pid,
+      signal_queue: proc.mailbox.get_signal_queue(),
+      run_state: Mutex::new(run_state),
+      run_state_changed: Condvar::new(),
+      process: UnsafeCell::new(proc),
+    };
+    Self {
+      cell: Arc::new(cell),
+    }
+  }
+
+  #[inline]
+  pub fn pid(&self) -> Term {
+    self.cell.pid
+  }
+
+  /// A panic while the run state is locked stops the VM, so the poisoned lock
+  /// is still good enough for writing the crash dump.
+  #[inline]
+  pub fn lock_run_state(&self) -> MutexGuard<'_, RunState> {
+    self.cell.run_state.lock().unwrap_or_else(|e| e.into_inner())
+  }
+
+  /// Release the locked run state and sleep until another thread calls
+  /// `notify_run_state`, then lock it again.
+  #[inline]
+  pub fn wait_run_state<'a>(
+    &self,
+    run: MutexGuard<'a, RunState>,
+  ) -> MutexGuard<'a, RunState> {
+    let changed = &self.cell.run_state_changed;
+    changed.wait(run).unwrap_or_else(|e| e.into_inner())
+  }
+
+  /// Wake up the threads which wait for the run state to change. Called after
+  /// `running`, `inspecting`, `inspectors` or `exited` have been changed.
+  #[inline]
+  pub fn notify_run_state(&self) {
+    self.cell.run_state_changed.notify_all()
+  }
+
+  /// Access the process. Only the scheduler which has set `running` in the
+  /// run state may change the process, other threads may only read it while
+  /// it is counted in `inspectors`.
+  /// The borrow must not outlive the handle.
+  #[inline]
+  pub fn as_ptr(&self) -> *mut Process {
+    self.cell.process.get()
+  }
+
+  /// Copy a message into a heap fragment and put it into the mailbox. The
+  /// heap of the receiving process is not touched, the receiver may be running
+  /// on another scheduler thread.
+  /// Arg: `scheduler` - scheduler of the sending thread, used to access the
+  ///   run queues of the receiver's scheduler.
+  pub fn deliver_message(&self, scheduler: &Scheduler, message: Term) -> RtResult<()> {
+    let (m1, fragment) = copy_term::copy_to_fragment(message)?;
+    self.deliver_copied_message(scheduler, m1, fragment);
+    Ok(())
+  }
+
+  /// Put a message which is already copied to `fragment` (or is an immediate
+  /// and has no fragment) into the mailbox, used by the timers.
+  pub fn deliver_copied_message(
+    &self,
+    scheduler: &Scheduler,
+    message: Term,
+    fragment: Option<Heap>,
+  ) {
+    // Wake up the receiver if it was in infinite or timed wait.
+    if self.cell.signal_queue.put(message, fragment) {
+      scheduler.wake_up(self);
+    }
+  }
+
+  /// Send a signal to the process.
+  pub fn send_signal(&self, scheduler: &Scheduler, signal: Signal) {
+    if self.cell.signal_queue.put_signal(signal) {
+      scheduler.wake_up(self);
+    }
+  }
+
+  /// The receive timer of the process has expired.
+  pub fn put_timeout(&self, scheduler: &Scheduler) {
+    if self.cell.signal_queue.put_timeout() {
+      scheduler.wake_up(self);
+    }
+  }
+}
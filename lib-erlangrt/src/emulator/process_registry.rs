@@ -1,82 +1,195 @@
-use crate::{emulator::process::Process, term::value::Term};
-use core::ptr;
-use std::collections::HashMap;
+use crate::{
+  emulator::{
+    gen_atoms,
+    heap::gc::FromSpaceRange,
+    process::Process,
+    process_handle::ProcessHandle,
+    scheduler::Prio,
+  },
+  term::value::Term,
+};
+use std::{collections::HashMap, sync::RwLock};
 
+/// Process table shared by all scheduler threads. The table holds a handle
+/// of each process (see `process_handle.rs`), other threads take their own
+/// handles from it.
 pub struct ProcessRegistry {
-  /// Dict of pids to process boxes
-  pid_to_proc: HashMap<Term, Process>,
-  name_to_pidport: HashMap<Term, Term>,
+  /// Dict of pids to processes
+  pid_to_proc: RwLock<HashMap<Term, ProcessHandle>>,
+  name_to_pidport: RwLock<HashMap<Term, Term>>,
 }
 
 impl ProcessRegistry {
   pub fn new() -> Self {
     Self {
-      pid_to_proc: HashMap::new(),
-      name_to_pidport: HashMap::new(),
+      pid_to_proc: RwLock::new(HashMap::new()),
+      name_to_pidport: RwLock::new(HashMap::new()),
     }
   }
 
-  /// Register a process `proc_` in the process table and also queue it for
-  /// execution. This is invoked by vm when a new process is spawned.
+  /// Register a process `proc_` in the process table. This is invoked by vm
+  /// when a new process is spawned.
   #[inline]
-  pub fn insert(&mut self, pid: Term, proc: Process) {
-    self.pid_to_proc.insert(pid, proc);
+  pub fn insert(&self, pid: Term, proc: Process, prio: Prio) -> ProcessHandle {
+    let handle = ProcessHandle::new(proc, prio);
+    self.pid_to_proc.write().unwrap().insert(pid, handle.clone());
+    handle
   }
 
+  /// Remove the process from the table. After this no new messages or
+  /// signals can arrive to the process, and the process is freed when the
+  /// last handle is dropped.
   #[inline]
-  pub fn remove(&mut self, pid: Term) {
-    self.pid_to_proc.remove(&pid);
+  pub fn remove(&self, pid: Term) -> Option<ProcessHandle> {
+    self.pid_to_proc.write().unwrap().remove(&pid)
   }
 
+  /// Find a process.
+  /// Returns: `None` if the process does not exist.
   #[inline]
-  pub fn count(&self) -> usize {
-    self.pid_to_proc.len()
+  pub fn get(&self, pid: Term) -> Option<ProcessHandle> {
+    assert!(pid.is_local_pid());
+    self.pid_to_proc.read().unwrap().get(&pid).cloned()
   }
 
-  /// Borrow a read-only process, if it exists. Return `None` if we are sorry.
   #[inline]
-  pub fn lookup_pid(&self, pid: Term) -> Option<&Process> {
-    assert!(pid.is_local_pid());
-    self.pid_to_proc.get(&pid)
+  pub fn count(&self) -> usize {
+    self.pid_to_proc.read().unwrap().len()
   }
 
-  /// Borrow a mutable process, if it exists. Return `None` if we are sorry.
   #[inline]
-  pub fn lookup_pid_mut(&mut self, pid: Term) -> Option<&mut Process> {
+  pub fn exists(&self, pid: Term) -> bool {
     assert!(pid.is_local_pid());
-    self.pid_to_proc.get_mut(&pid)
+    self.pid_to_proc.read().unwrap().contains_key(&pid)
   }
 
+  /// Handles of all processes (in no particular order).
+  pub fn get_all(&self) -> Vec<ProcessHandle> {
+    self.pid_to_proc.read().unwrap().values().cloned().collect()
+  }
 
-  /// Find a process and instead of borrowing return a pointer to it.
-  #[inline]
-  #[allow(dead_code)]
-  pub fn unsafe_lookup_pid(&self, pid: Term) -> *const Process {
-    assert!(pid.is_local_pid());
-    match self.pid_to_proc.get(&pid) {
-      Some(p) => p as *const Process,
-      None => ptr::null(),
+  /// Check whether any process refers to memory `range`. The processes are
+  /// read while they run, the other schedulers must be stopped (see
+  /// `Scheduler::stop_the_world`).
+  pub fn any_refers_to(&self, range: FromSpaceRange) -> bool {
+    let table = self.pid_to_proc.read().unwrap();
+    table.values().any(|h| unsafe { (*h.as_ptr()).refers_to(range) })
+  }
+
+  /// Query contents of the name-to-pid/port table
+  pub fn find_registered(&self, name: Term) -> Option<Term> {
+    self.name_to_pidport.read().unwrap().get(&name).cloned()
+  }
+
+  /// Reverse lookup in the name-to-pid/port table.
+  pub fn find_name_of(&self, pid_or_port: Term) -> Option<Term> {
+    self
+      .name_to_pidport
+      .read()
+      .unwrap()
+      .iter()
+      .find(|(_, v)| **v == pid_or_port)
+      .map(|(k, _)| *k)
+  }
+
+  /// All registered names.
+  pub fn registered_names(&self) -> Vec<Term> {
+    self.name_to_pidport.read().unwrap().keys().cloned().collect()
+  }
+
+  /// Remove the name from the name-to-pid/port table.
+  /// Returns: the pid or port which was registered with the name.
+  pub fn unregister_name(&self, name: Term) -> Option<Term> {
+    self.name_to_pidport.write().unwrap().remove(&name)
+  }
+
+  /// Remove the name of an exiting process. Must be called after the process
+  /// is removed from the process table, so that `register_name` can not give
+  /// it a new name.
+  /// Returns: the name, if the process had one.
+  pub fn unregister_pid(&self, pid: Term) -> Option<Term> {
+    let mut names = self.name_to_pidport.write().unwrap();
+    let name = names.iter().find(|(_, v)| **v == pid).map(|(k, _)| *k)?;
+    names.remove(&name);
+    Some(name)
+  }
+
+  /// Add contents of the name-to-pid/port table. Like in OTP, a name is
+  /// registered once, a process has at most one name, and the process must be
+  /// alive. The checks and the insert happen under the same lock.
+  /// Returns: false if any of the rules is broken.
+  pub fn register_name(&self, name: Term, pid_or_port: Term) -> bool {
+    let mut names = self.name_to_pidport.write().unwrap();
+    if name == gen_atoms::UNDEFINED
+      || names.contains_key(&name)
+      || names.values().any(|v| *v == pid_or_port)
+      || (pid_or_port.is_local_pid() && !self.exists(pid_or_port))
+    {
+      return false;
     }
+    names.insert(name, pid_or_port);
+    true
   }
+}
 
-  /// Find a process and instead of borrowing return a mutable pointer to it.
-  #[inline]
-  pub fn unsafe_lookup_pid_mut(&mut self, pid: Term) -> *mut Process {
-    assert!(pid.is_local_pid());
-    match self.pid_to_proc.get_mut(&pid) {
-      Some(p) => p as *mut Process,
-      None => ptr::null_mut(),
+/// Get the name from a registered name `Name` or from a tuple `{Name, Node}`
+/// where `Node` is the local node, which is how a registered process can be
+/// given to `send` or `monitor`.
+/// Returns: `None` for any other term.
+pub fn get_local_name(dest: Term) -> Option<Term> {
+  if dest.is_atom() {
+    return Some(dest);
+  }
+  if !dest.is_tuple() {
+    return None;
+  }
+  let tuple_p = dest.get_tuple_ptr();
+  unsafe {
+    if (*tuple_p).get_arity() != 2
+      || !(*tuple_p).get_element(0).is_atom()
+      || (*tuple_p).get_element(1) != gen_atoms::NONODE_NOHOST
+    {
+      return None;
     }
+    Some((*tuple_p).get_element(0))
   }
+}
 
-  /// Query contents of the name-to-pid/port table
-  pub fn find_registered(&self, name: Term) -> Option<Term> {
-    self.name_to_pidport.get(&name).cloned()
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::emulator::{code::CodePtr, spawn_options::SpawnOptions};
+
+  fn spawn(proc_reg: &ProcessRegistry, n: usize) -> Term {
+    let pid = Term::make_local_pid(n);
+    let p = Process::new(pid, Term::nil(), CodePtr::null(), &SpawnOptions::default());
+    proc_reg.insert(pid, p, Prio::Normal);
+    pid
   }
 
-  /// Add contents of the name-to-pid/port table, no check is made for whether
-  /// the value is new, will overwrite.
-  pub fn register_name(&mut self, name: Term, pid_or_port: Term) {
-    self.name_to_pidport.insert(name, pid_or_port);
+  #[test]
+  fn test_register_name_uniqueness() {
+    let proc_reg = ProcessRegistry::new();
+    let pid0 = spawn(&proc_reg, 0);
+    let pid1 = spawn(&proc_reg, 1);
+
+    assert!(!proc_reg.register_name(gen_atoms::UNDEFINED, pid0));
+    assert!(proc_reg.register_name(gen_atoms::INIT, pid0));
+    // Name is taken, and a process can only have one name
+    assert!(!proc_reg.register_name(gen_atoms::INIT, pid1));
+    assert!(!proc_reg.register_name(gen_atoms::ERROR, pid0));
+    // Process does not exist
+    assert!(!proc_reg.register_name(gen_atoms::ERROR, Term::make_local_pid(2)));
+    assert!(proc_reg.register_name(gen_atoms::ERROR, pid1));
+    assert_eq!(proc_reg.find_registered(gen_atoms::INIT), Some(pid0));
+
+    // Name of an exited process is free again
+    proc_reg.remove(pid0);
+    assert_eq!(proc_reg.unregister_pid(pid0), Some(gen_atoms::INIT));
+    assert_eq!(proc_reg.unregister_pid(pid0), None);
+    assert_eq!(proc_reg.find_registered(gen_atoms::INIT), None);
+    assert!(!proc_reg.register_name(gen_atoms::INIT, pid1));
+    assert_eq!(proc_reg.unregister_name(gen_atoms::ERROR), Some(pid1));
+    assert!(proc_reg.register_name(gen_atoms::INIT, pid1));
   }
 }
@@ -58,10 +58,7 @@ pub fn apply(
   // OR TODO: subscribe from all exports to the module and get invalidation notifications
   ctx.ip = match dst {
     Some(p) => p.ptr,
-    None => unsafe {
-      let cs = vm.get_code_server_p();
-      (*closure).update_location(&mut (*cs))?
-    },
+    None => unsafe { (*closure).update_location(&vm.code_server())? },
   };
   Ok(DispatchResult::Normal)
 }
@@ -40,7 +40,7 @@ pub fn apply(
     return fail::create::badarity();
   }
 
-  if vm.code_server.native_functions.mfa_exists(&mfa) {
+  if vm.code_server().native_functions.mfa_exists(&mfa) {
     return call_native_fun::find_and_call_native_fun(
       vm,
       ctx,
@@ -52,8 +52,7 @@ pub fn apply(
       false,
     );
   } else {
-    let code_server = vm.get_code_server_p();
-    match unsafe { (*code_server).lookup_beam_code_and_load(&mfa) } {
+    match vm.lookup_beam_code_and_load(&mfa) {
       Ok(ip) => {
         if save_cp {
           ctx.cp = ctx.ip
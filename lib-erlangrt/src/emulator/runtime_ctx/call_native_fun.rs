@@ -1,15 +1,22 @@
 use super::Context;
 use crate::{
-  beam::disp_result::DispatchResult,
+  beam::disp_result::{DispatchResult, YieldType},
+  defs::{exc_type::ExceptionType, WordSize},
   emulator::{code_srv::CodeServer, mfa::ModFunArity, process::Process, vm::VM},
   fail::{self, RtErr, RtResult},
   native_fun::NativeFn,
   term::{boxed::import, value::*},
 };
-use core::slice;
 
 // fn module() -> &'static str { "runtime_ctx.call_native_fun: " }
 
+/// How many times a `gc_bif` is retried after growing the heap, when the heap
+/// size is not limited by `max_heap_size`.
+const MAX_GC_ATTEMPTS: usize = 16;
+
+/// The largest arity of a registered native function (`erlang:spawn_opt/5`).
+const MAX_NATIVE_FUN_ARITY: usize = 5;
+
 // Call Bif generic facilities
 //
 
@@ -36,7 +43,9 @@ pub enum CallBifTarget {
 ///   `fail_label` - if not NIL, we suppress a possible exception and jump there;
 ///   `args` - the arguments;
 ///   `dst` - register where the result will go;
-///   `gc` if gc is allowed then `ctx.live` will be used as live.
+///   `gc` - only for `gc_bif*` opcodes: on a full heap the GC runs with
+///     `ctx.live` live registers and the call is repeated. Other native
+///     functions can have side effects and are called once.
 #[inline]
 pub fn find_and_call_native_fun(
   vm: &mut VM,
@@ -46,20 +55,20 @@ pub fn find_and_call_native_fun(
   target: CallBifTarget,
   args: &[Term],
   dst: Term,
-  _gc: bool,
+  gc: bool,
 ) -> RtResult<DispatchResult> {
   // Try resolve BIF destination, which can be defined by an import, mfarity
   // a pointer to import, or a pointer to native_fun function.
   // TODO: Maybe make this use codeserver generic lookup_mfa or extend it to support this
   let maybe_bif_fn = match target {
     CallBifTarget::ImportTerm(imp) => {
-      callbif_resolve_import(&vm.code_server, imp, args.len())?
+      callbif_resolve_import(&vm.code_server(), imp, args.len())?
     }
 
-    CallBifTarget::MFArity(mfa) => callbif_resolve_mfa(&vm.code_server, &mfa)?,
+    CallBifTarget::MFArity(mfa) => callbif_resolve_mfa(&vm.code_server(), &mfa)?,
 
     CallBifTarget::ImportPointer(imp_ptr) => {
-      if let Some(fn_ptr) = unsafe { (*imp_ptr).get_native_fn_ptr(&vm.code_server) } {
+      if let Some(fn_ptr) = unsafe { (*imp_ptr).get_native_fn_ptr(&vm.code_server()) } {
         BifResolutionResult::FnPointer(fn_ptr)
       } else {
         let bif_name = unsafe { format!("{}", (*imp_ptr).mfarity) };
@@ -73,7 +82,11 @@ pub fn find_and_call_native_fun(
   // Now having resolved the native_fun function, let's call it
   let bif_result = match maybe_bif_fn {
     BifResolutionResult::FnPointer(fn_ptr) => {
-      call_native_fun_fn(vm, ctx, curr_p, fn_ptr, args)
+      if gc {
+        call_native_fun_fn_gc(vm, ctx, curr_p, fn_ptr, args)
+      } else {
+        call_native_fun_fn(vm, ctx, curr_p, fn_ptr, args)
+      }
     }
 
     BifResolutionResult::BadfunError(badfun_val) => {
@@ -82,13 +95,19 @@ pub fn find_and_call_native_fun(
   };
 
   // Now having called the function let's see if there was some good result or
-  // an error occured
+  // an error occured. After `erlang:hibernate/3` the registers already hold
+  // the arguments for the wake up, the result is not stored.
+  if curr_p.hibernated {
+    bif_result?;
+    return Ok(DispatchResult::Yield(YieldType::Hibernate));
+  }
 
   // On error and if fail label is a CP, perform a goto
   // Assume that error is already written to `reason` in process
   match bif_result {
-    Err(RtErr::Exception(_, _)) => {
-      if fail_label.is_cp() {
+    Err(RtErr::Exception(exc_type, _)) => {
+      // Panic (such as kill on max_heap_size) cannot be caught by a fail label
+      if fail_label.is_cp() && exc_type != ExceptionType::Panic {
         ctx.jump(fail_label)
       }
       // Set exception via dispatchresult; pass through the error
@@ -174,22 +193,59 @@ pub fn call_native_fun_fn(
   func_pointer: NativeFn,
   args: &[Term],
 ) -> RtResult<Term> {
-  let n_args = args.len();
+  let loaded_args = load_native_fun_args(ctx, curr_p, args);
 
-  // Make a slice from the args. Bif arg count can go up to 3
-  assert!(args.len() < 4);
-  let mut loaded_args = [Term::nil(); 4];
+  // Apply the BIF call and return BifResult
+  (func_pointer)(vm, curr_p, &loaded_args[0..args.len()])
+}
 
-  {
-    let heap = curr_p.get_heap();
-    for i in 0..n_args {
-      loaded_args[i] = ctx.load(args[i], heap);
+/// Same as `call_native_fun_fn` but for `gc_bif*` opcodes: if the heap is full
+/// during the call, the GC is run with `ctx.live` registers and the loaded
+/// args as roots, and the call is repeated. Native functions used in `gc_bif`
+/// have no side effects, so it is safe to call them again.
+/// The native function does not tell how much memory it wanted, so the GC is
+/// asked to make the heap at least twice larger before each next attempt, at
+/// most `MAX_GC_ATTEMPTS` times, after that the result is `system_limit`.
+fn call_native_fun_fn_gc(
+  vm: &mut VM,
+  ctx: &mut Context,
+  curr_p: &mut Process,
+  func_pointer: NativeFn,
+  args: &[Term],
+) -> RtResult<Term> {
+  let n_args = args.len();
+  let mut loaded_args = load_native_fun_args(ctx, curr_p, args);
+
+  for _ in 0..MAX_GC_ATTEMPTS {
+    match (func_pointer)(vm, curr_p, &loaded_args[0..n_args]) {
+      Err(RtErr::HeapIsFull(_)) => {
+        let live = ctx.live;
+        let need = WordSize::new(curr_p.get_heap_size());
+        curr_p.garbage_collect(ctx, need, live, &mut loaded_args[0..n_args])?;
+      }
+      other => return other,
     }
   }
+  fail::create::system_limit()
+}
 
-  // Take n_args elements from args
-  let loaded_args1 = unsafe { slice::from_raw_parts(&loaded_args[0], n_args) };
-
-  // Apply the BIF call and return BifResult
-  (func_pointer)(vm, curr_p, loaded_args1)
+/// Resolve args which can be registers or stack cells into values.
+/// Bif arg count can go up to `MAX_NATIVE_FUN_ARITY`.
+fn load_native_fun_args(
+  ctx: &Context,
+  curr_p: &Process,
+  args: &[Term],
+) -> [Term; MAX_NATIVE_FUN_ARITY] {
+  assert!(
+    args.len() <= MAX_NATIVE_FUN_ARITY,
+    "native function arity {} is above the limit {}",
+    args.len(),
+    MAX_NATIVE_FUN_ARITY
+  );
+  let mut loaded_args = [Term::nil(); MAX_NATIVE_FUN_ARITY];
+  let heap = curr_p.get_heap();
+  for i in 0..args.len() {
+    loaded_args[i] = ctx.load(args[i], heap);
+  }
+  loaded_args
 }
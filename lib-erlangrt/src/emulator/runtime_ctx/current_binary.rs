@@ -28,6 +28,23 @@ impl CurrentBinaryState {
     self.offset = BitSize::with_bits(0);
   }
 
+  /// Present the destination binary as a term, so that the GC can relocate it.
+  /// Returns `NON_VALUE` if there is no binary being built.
+  pub fn get_root(&self) -> Term {
+    match self.dst {
+      Some(p) => Term::make_boxed(p as *const boxed::Binary),
+      None => Term::non_value(),
+    }
+  }
+
+  /// Update the destination binary after it was relocated by the GC, the
+  /// write offset is not changed.
+  pub fn set_root(&mut self, dst: Term) {
+    if self.dst.is_some() {
+      self.dst = Some(unsafe { boxed::Binary::get_trait_mut_from_term(dst) });
+    }
+  }
+
   #[inline]
   pub fn valid(&self) -> bool {
     self.dst.is_some()
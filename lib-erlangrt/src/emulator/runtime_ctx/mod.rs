@@ -5,12 +5,15 @@ use core::{fmt, ptr, slice};
 use colored::Colorize;
 
 use crate::{
-  beam::gen_op,
+  beam::{
+    disp_result::{DispatchResult, YieldType},
+    gen_op,
+  },
   defs::{Reductions, Word, MAX_FPREGS, MAX_XREGS},
   emulator::{
     code::{opcode, CodePtr},
-    code_srv::MFALookupResult,
-    heap::heap_trait::THeap,
+    code_srv::{literal_area, MFALookupResult},
+    heap::{gc::FromSpaceRange, heap_trait::THeap},
     process::Process,
     runtime_ctx::current_binary::CurrentBinaryState,
     vm::VM,
@@ -42,6 +45,8 @@ pub struct Context {
 
   /// A metric of CPU time spent on running the code, roughly equal to 1 function call
   pub reductions: isize,
+  /// Reductions spent in the previous time slices
+  reductions_done: usize,
 
   /// Current state of X registers.
   regs: [Term; MAX_XREGS],
@@ -75,7 +80,8 @@ impl Context {
       ip,
       regs: [Term::non_value(); MAX_XREGS],
       live: 0,
-      reductions: 0,
+      reductions: Reductions::DEFAULT,
+      reductions_done: 0,
       current_bin: CurrentBinaryState::new(),
     }
   }
@@ -131,9 +137,21 @@ impl Context {
   pub fn swap_in(&mut self) {
     // This amount is RESET every time process is about to be scheduled in, i.e.
     // there can be no "debt" of reductions, but the idea is nice.
+    self.reductions_done += self.get_slice_reductions();
     self.reductions = Reductions::DEFAULT;
   }
 
+  /// Reductions spent in the current (or the last) time slice.
+  #[inline]
+  fn get_slice_reductions(&self) -> usize {
+    (Reductions::DEFAULT - self.reductions).max(0) as usize
+  }
+
+  /// Reductions spent by the process since it was spawned.
+  pub fn get_reductions_total(&self) -> usize {
+    self.reductions_done + self.get_slice_reductions()
+  }
+
   #[inline]
   pub fn fetch_opcode(&mut self) -> opcode::RawOpcode {
     self.reductions -= Reductions::FETCH_OPCODE_COST;
@@ -195,6 +213,18 @@ impl Context {
     unsafe { slice::from_raw_parts_mut(self.regs.as_mut_ptr().add(offset), sz) }
   }
 
+  /// Registers `0..live`, the count is given by the caller because
+  /// `self.live` may be left over from an earlier call or allocation.
+  pub fn live_registers(&self, live: usize) -> &[Term] {
+    &self.regs[..live]
+  }
+
+  /// Check whether any X register points into `range`. Registers above `live`
+  /// may hold stale values, they are checked too to stay on the safe side.
+  pub fn registers_refer_to(&self, range: FromSpaceRange) -> bool {
+    self.regs.iter().any(|r| literal_area::term_points_into(*r, range))
+  }
+
   /// Fetch a word from code, assume it is either an `Term` or a source X, Y or
   /// FP register, then perform a load operation.
   #[inline]
@@ -329,7 +359,7 @@ impl Context {
     lr: &MFALookupResult,
     args: &[Term],
     save_cp: bool,
-  ) -> RtResult<()> {
+  ) -> RtResult<DispatchResult> {
     match lr {
       MFALookupResult::FoundBeamCode(code_p) => {
         if save_cp {
@@ -339,10 +369,13 @@ impl Context {
       }
       MFALookupResult::FoundBif(bif_fn) => {
         let x0 = call_native_fun::call_native_fun_fn(vm, self, curr_p, *bif_fn, args)?;
+        if curr_p.hibernated {
+          return Ok(DispatchResult::Yield(YieldType::Hibernate));
+        }
         self.set_x(0, x0);
       }
     }
-    Ok(())
+    Ok(DispatchResult::Normal)
   }
 
   #[allow(dead_code)]
@@ -1,13 +1,33 @@
 //! Code related to task scheduling and priorities.
+//!
+//! Every scheduler thread runs its own `Scheduler`. Run queues of all
+//! schedulers are shared between the threads (see `RunQueue`): another thread
+//! may queue a process which has received a message, and a scheduler which has
+//! nothing to run steals processes from the others.
+//!
+//! Timers are shared too, the scheduler which is switching processes turns
+//! the timer wheel (see `timer_wheel.rs`) and acts on the expired timers.
+//!
+//! Between two time slices a scheduler passes a safe point, where it can be
+//! parked while another scheduler has stopped the world (see `SafePoint`).
 use crate::{
   defs::{exc_type::ExceptionType, Word},
   emulator::{
-    gen_atoms, process::Process, process_flags, process_registry::ProcessRegistry,
+    gen_atoms,
+    process::{MonitorPeer, Process},
+    process_handle::{ProcessHandle, RunState},
+    process_registry::ProcessRegistry,
+    signal::Signal,
+    timer_wheel::{TimerAction, TimerWheel},
   },
   term::value::*,
 };
 use colored::Colorize;
-use std::collections::{HashMap, VecDeque};
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::{Arc, Condvar, Mutex, MutexGuard},
+  time::Duration,
+};
 
 fn module() -> &'static str {
   "scheduler: "
@@ -46,6 +66,8 @@ pub enum SliceResult {
   Yield,
   /// Process entered infinite wait during the last timeslice
   InfiniteWait,
+  /// Process entered wait with a timeout during the last timeslice
+  TimedWait,
   /// Process normally finished during the last timeslice
   Finished,
   /// Error, exit or throw occured during the last timeslice, error is stored
@@ -56,9 +78,11 @@ pub enum SliceResult {
 /// How many Normal processes can be scheduled before Low gets to run.
 const NORMAL_ADVANTAGE: Word = 8;
 
-/// Maintains run queues for different priorities and allows queuing processes,
-/// suspending processes, work balancing (TODO), etc.
-pub struct Scheduler {
+/// How long an idle scheduler sleeps before it tries to steal work again.
+const IDLE_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Run queues and wait sets of one scheduler.
+struct RunQueueState {
   // This is the naive implementation of run queues.
   // A better approach would be to build an intrusive double linked list through
   // every process in the queue (as done by the original ERTS).
@@ -69,12 +93,148 @@ pub struct Scheduler {
   timed_wait: HashMap<Term, ()>,
   /// Wait set for infinitely suspended processes (in endless receive)
   infinite_wait: HashMap<Term, ()>,
+  /// Process which the scheduler is running now
+  current: Option<Term>,
+}
+
+/// Run queues of one scheduler, shared with other threads. The owning
+/// scheduler locks it to pick the next process, other threads lock it to wake
+/// up a waiting process or to steal work.
+pub struct RunQueue {
+  state: Mutex<RunQueueState>,
+  /// Signalled when a process is queued, to wake up the idle scheduler
+  work_available: Condvar,
+}
+
+impl RunQueue {
+  fn new() -> Self {
+    let state = RunQueueState {
+      queue_low: VecDeque::new(),
+      queue_normal: VecDeque::new(),
+      queue_high: VecDeque::new(),
+      timed_wait: HashMap::new(),
+      infinite_wait: HashMap::new(),
+      current: None,
+    };
+    Self {
+      state: Mutex::new(state),
+      work_available: Condvar::new(),
+    }
+  }
+
+  /// A panic while the queue is locked stops the VM, so the poisoned lock is
+  /// still good enough for writing the crash dump.
+  #[inline]
+  fn lock(&self) -> MutexGuard<'_, RunQueueState> {
+    self.state.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+/// Run queues of all schedulers, indexed by the scheduler id.
+pub type RunQueues = Arc<Vec<RunQueue>>;
+
+/// Create run queues for `count` schedulers.
+pub fn create_run_queues(count: usize) -> RunQueues {
+  assert!(count > 0, "At least one scheduler is required");
+  Arc::new((0..count).map(|_| RunQueue::new()).collect())
+}
+
+/// Timer wheel shared by all schedulers.
+pub type SharedTimers = Arc<Mutex<TimerWheel>>;
+
+/// Lets one scheduler stop all others (see `Scheduler::stop_the_world`). The
+/// others park at their safe point, which they pass between two time slices,
+/// when they run no process and do not touch the timers.
+pub struct SafePoint {
+  state: Mutex<SafePointState>,
+  /// Signalled when a scheduler parks, leaves or when the world restarts
+  changed: Condvar,
+}
+
+struct SafePointState {
+  /// Schedulers which exist, all but the one stopping the world must park
+  schedulers: usize,
+  parked: usize,
+  stop_requested: bool,
+}
+
+impl SafePoint {
+  pub fn new() -> Self {
+    let state = SafePointState {
+      schedulers: 0,
+      parked: 0,
+      stop_requested: false,
+    };
+    Self {
+      state: Mutex::new(state),
+      changed: Condvar::new(),
+    }
+  }
+
+  #[inline]
+  fn lock(&self) -> MutexGuard<'_, SafePointState> {
+    self.state.lock().unwrap_or_else(|e| e.into_inner())
+  }
+
+  /// Called by a scheduler at its safe point, parks it while the world is
+  /// stopped.
+  fn pass(&self) {
+    let state = self.lock();
+    if state.stop_requested {
+      drop(self.park(state));
+    }
+  }
+
+  /// Wait while the world is stopped.
+  fn park<'a>(
+    &self,
+    mut state: MutexGuard<'a, SafePointState>,
+  ) -> MutexGuard<'a, SafePointState> {
+    state.parked += 1;
+    self.changed.notify_all();
+    while state.stop_requested {
+      state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+    }
+    state.parked -= 1;
+    state
+  }
+}
+
+/// The safe point shared by all schedulers.
+pub type SharedSafePoint = Arc<SafePoint>;
+
+/// A process which has exited with an abnormal reason. The report is logged
+/// by the VM, which can look up the function where the process has stopped
+/// (see `VM::log_crash_reports`).
+pub struct CrashReport {
+  pub pid: Term,
+  pub registered_name: Option<Term>,
+  /// Address of the code where the process has stopped, only compared with
+  /// the code ranges of the loaded modules
+  pub ip: usize,
+  /// Exit reason, formatted while the heap of the process still existed
+  pub reason: String,
+}
+
+/// Picks processes to run from the run queues of one scheduler thread, takes
+/// care of the process after its timeslice, and steals work from other
+/// schedulers when own queues are empty.
+pub struct Scheduler {
+  /// Index of this scheduler in `run_queues`
+  id: usize,
+  run_queues: RunQueues,
+  timers: SharedTimers,
+  safepoint: SharedSafePoint,
 
   /// A counter used to skip some schedulings for low processes
   advantage_count: Word,
 
   /// Currently selected process
   current: Option<Term>,
+
+  /// Processes terminated by this scheduler with an abnormal reason, not yet
+  /// reported
+  crash_reports: Vec<CrashReport>,
 }
 
 /// Hint from the logic finalizing timeslice result from a running process.
@@ -87,69 +247,135 @@ enum ScheduleHint {
 }
 
 impl Scheduler {
-  pub fn new() -> Self {
+  pub fn new(
+    id: usize,
+    run_queues: RunQueues,
+    timers: SharedTimers,
+    safepoint: SharedSafePoint,
+  ) -> Self {
+    assert!(id < run_queues.len());
+    safepoint.lock().schedulers += 1;
     Self {
-      queue_low: VecDeque::new(),
-      queue_normal: VecDeque::new(),
-      queue_high: VecDeque::new(),
-      timed_wait: HashMap::new(),
-      infinite_wait: HashMap::new(),
-
+      id,
+      run_queues,
+      timers,
+      safepoint,
       advantage_count: 0,
       current: None,
+      crash_reports: Vec::new(),
     }
   }
 
-  /// Queue a process by its pid.
-  pub fn enqueue(&mut self, proc_reg: &mut ProcessRegistry, pid: Term) {
-    self.enqueue_opt(proc_reg, pid, false);
+  /// Whether a process has exited with an abnormal reason since the reports
+  /// were taken last time.
+  #[inline]
+  pub fn has_crash_reports(&self) -> bool {
+    !self.crash_reports.is_empty()
   }
 
-  /// Queue a process by its pid.
-  /// Will `panic!` if the process doesn't exist or is already queued.
-  /// Arg: `skip_queue_check` allows skipping the current queue assertion for
-  ///   when you can guarantee that the process is not queued anywhere.
-  pub fn enqueue_opt(
-    &mut self,
-    proc_reg: &mut ProcessRegistry,
-    pid: Term,
-    skip_queue_check: bool,
-  ) {
-    assert!(pid.is_local_pid());
-
-    let prio = {
-      // Lookup the pid
-      let p = proc_reg.lookup_pid(pid).unwrap();
-      if !skip_queue_check {
-        assert_eq!(
-          p.current_queue,
-          Queue::None,
-          "Process must not be in any queue when queuing, now in {:?}",
-          p.current_queue
-        );
-      }
-      p.prio
-    };
+  /// Take the reports about processes which have crashed since the last call.
+  pub fn take_crash_reports(&mut self) -> Vec<CrashReport> {
+    self.crash_reports.drain(..).collect()
+  }
 
-    match prio {
-      Prio::Normal => self.queue_normal.push_back(pid),
-      Prio::Low => self.queue_low.push_back(pid),
-      Prio::High => self.queue_high.push_back(pid),
-    }
+  #[inline]
+  pub fn get_id(&self) -> usize {
+    self.id
   }
 
-  /// Queue a process by its pid into either timed_wait or infinite_wait queue.
+  /// Run queues of all schedulers, for creating another scheduler.
+  pub fn get_run_queues(&self) -> RunQueues {
+    self.run_queues.clone()
+  }
+
+  /// The timer wheel, for creating another scheduler.
+  pub fn get_shared_timers(&self) -> SharedTimers {
+    self.timers.clone()
+  }
+
+  /// The safe point, for creating another scheduler.
+  pub fn get_safepoint(&self) -> SharedSafePoint {
+    self.safepoint.clone()
+  }
+
+  /// Lock the timer wheel to start, cancel or read a timer.
   #[inline]
-  pub fn enqueue_wait(&mut self, infinite: bool, pid: Term) {
-    assert!(pid.is_local_pid());
+  pub fn timers(&self) -> MutexGuard<'_, TimerWheel> {
+    self.timers.lock().unwrap()
+  }
 
-    if infinite {
-      self.infinite_wait.insert(pid, ());
-    } else {
-      self.timed_wait.insert(pid, ());
+  /// How many schedulers are there in the VM.
+  #[inline]
+  pub fn count(&self) -> usize {
+    self.run_queues.len()
+  }
+
+  /// Currently selected process, if any.
+  #[inline]
+  pub fn get_current(&self) -> Option<Term> {
+    self.current
+  }
+
+  /// Process which is running now on the scheduler `id`, if any.
+  pub fn get_current_of(&self, id: usize) -> Option<Term> {
+    self.run_queues[id].lock().current
+  }
+
+  /// How many processes are registered in a queue of the scheduler `id`.
+  pub fn queue_len(&self, id: usize, queue: Queue) -> usize {
+    let state = self.run_queues[id].lock();
+    match queue {
+      Queue::None => 0,
+      Queue::High => state.queue_high.len(),
+      Queue::Normal => state.queue_normal.len(),
+      Queue::Low => state.queue_low.len(),
+      Queue::TimedWait => state.timed_wait.len(),
+      Queue::InfiniteWait => state.infinite_wait.len(),
     }
   }
 
+  #[inline]
+  fn own_queue(&self) -> &RunQueue {
+    &self.run_queues[self.id]
+  }
+
+  /// Queue a process on this scheduler, which becomes its owner.
+  /// Will `panic!` if the process is already queued.
+  pub fn enqueue(&self, handle: &ProcessHandle) {
+    let mut run = handle.lock_run_state();
+    run.owner = self.id;
+    run.running = false;
+    let run_queue = self.own_queue();
+    Self::push_to_run_queue(&mut run_queue.lock(), handle.pid(), &mut run);
+    run_queue.work_available.notify_one();
+    handle.notify_run_state();
+  }
+
+  /// Queue a process according to its priority, the run state of the process
+  /// and the run queue state must be locked, in that order.
+  fn push_to_run_queue(state: &mut RunQueueState, pid: Term, run: &mut RunState) {
+    assert_eq!(
+      run.queue,
+      Queue::None,
+      "Process must not be in any queue when queuing, now in {:?}",
+      run.queue
+    );
+    run.queue = match run.prio {
+      Prio::Normal => {
+        state.queue_normal.push_back(pid);
+        Queue::Normal
+      }
+      Prio::Low => {
+        state.queue_low.push_back(pid);
+        Queue::Low
+      }
+      Prio::High => {
+        state.queue_high.push_back(pid);
+        Queue::High
+      }
+    };
+  }
+
   #[inline]
   fn log_next_process(maybe_pid: Option<Term>) {
     if cfg!(feature = "trace_opcode_execution") {
@@ -168,9 +394,10 @@ impl Scheduler {
     }
   }
 
-  /// Get another process from the run queue for this scheduler.
-  /// Returns: `Option(pid)`
-  pub fn next_process(&mut self, proc_reg: &mut ProcessRegistry) -> Option<Term> {
+  /// Get another process from the run queue for this scheduler, or steal one
+  /// from another scheduler.
+  /// Returns: `Option(pid)`, `None` if there is nothing to run.
+  pub fn next_process(&mut self, proc_reg: &ProcessRegistry) -> Option<Term> {
     if let Some(prev_pid) = self.current {
       let hint = self.next_process_finalize_previous(proc_reg, prev_pid);
       if hint == ScheduleHint::ContinueSameProcess {
@@ -179,117 +406,215 @@ impl Scheduler {
       }
     }
 
+    // No process is running here, another scheduler may stop the world now
+    self.safepoint.pass();
+
     // Do necessities before taking another process
-    self.next_process_duties();
-
-    // Now try and find another process to run
-    loop {
-      // See if any are waiting in realtime (high) priority queue
-      if let Some(next_pid) = self.next_process_pick_from_the_queues() {
-        self.current = Some(next_pid);
-        break;
+    self.next_process_duties(proc_reg);
+
+    // Now try and find another process to run. Signals which have arrived to
+    // the process are handled first, and an exit signal may terminate it.
+    let next_pid = loop {
+      let pid = match self.next_process_pick_from_the_queues() {
+        Some(pid) => pid,
+        None => match self.steal_process(proc_reg) {
+          Some(pid) => pid,
+          None => break None,
+        },
+      };
+      let handle = match proc_reg.get(pid) {
+        Some(h) => h,
+        None => panic!("{}Queued process {} does not exist", module(), pid),
+      };
+      Self::start_running(&handle);
+      let exit_reason = unsafe { (*handle.as_ptr()).handle_signals() };
+      match exit_reason {
+        Some(reason) => {
+          self.terminate_process(proc_reg, pid, (ExceptionType::Exit, reason))
+        }
+        None => break Some(pid),
       }
-    }
+    };
+    self.current = next_pid;
+    self.own_queue().lock().current = next_pid;
 
     Self::log_next_process(self.current);
     self.current
   }
 
+  /// Mark a process taken out of a run queue as running, after other
+  /// processes have finished reading it. From now on, only this scheduler
+  /// uses the process.
+  fn start_running(handle: &ProcessHandle) {
+    Self::when_not_inspected(handle, |run| {
+      run.queue = Queue::None;
+      run.running = true;
+    });
+  }
+
+  /// Wait until no other process is reading the process (see
+  /// `with_stopped_process`), then change its run state with `f`.
+  fn when_not_inspected<F>(handle: &ProcessHandle, f: F)
+  where
+    F: FnOnce(&mut RunState),
+  {
+    let mut run = handle.lock_run_state();
+    while run.inspectors != 0 {
+      run = handle.wait_run_state(run);
+    }
+    f(&mut run);
+    handle.notify_run_state();
+  }
+
   /// Look through the queues and find some queue with highest priority where
   /// a process is waiting to be selected.
   /// Advantage counter allows running lower queues even if a higher is running.
   fn next_process_pick_from_the_queues(&mut self) -> Option<Term> {
-    if !self.queue_high.is_empty() {
-      return self.queue_high.pop_front();
+    let mut state = self.run_queues[self.id].lock();
+    if !state.queue_high.is_empty() {
+      return state.queue_high.pop_front();
     } else if self.advantage_count < NORMAL_ADVANTAGE {
-      if !self.queue_normal.is_empty() {
-        return self.queue_normal.pop_front();
-      } else if !self.queue_low.is_empty() {
-        return self.queue_low.pop_front();
+      if !state.queue_normal.is_empty() {
+        return state.queue_normal.pop_front();
+      } else if !state.queue_low.is_empty() {
+        return state.queue_low.pop_front();
       }
       self.advantage_count += 1;
     } else {
-      if !self.queue_low.is_empty() {
-        return self.queue_low.pop_front();
-      } else if !self.queue_normal.is_empty() {
-        return self.queue_normal.pop_front();
+      if !state.queue_low.is_empty() {
+        return state.queue_low.pop_front();
+      } else if !state.queue_normal.is_empty() {
+        return state.queue_normal.pop_front();
       }
       self.advantage_count = 0;
     };
     return None;
   }
 
+  /// Own queues are empty, visit other schedulers and take one process from
+  /// the tail of the first non-empty queue found, highest priority first. The
+  /// stolen process is now owned by this scheduler.
+  fn steal_process(&self, proc_reg: &ProcessRegistry) -> Option<Term> {
+    let n = self.run_queues.len();
+    for i in 1..n {
+      let victim = (self.id + i) % n;
+      let stolen = {
+        let mut state = self.run_queues[victim].lock();
+        match state.queue_high.pop_back() {
+          Some(pid) => Some(pid),
+          None => match state.queue_normal.pop_back() {
+            Some(pid) => Some(pid),
+            None => state.queue_low.pop_back(),
+          },
+        }
+      };
+      if let Some(pid) = stolen {
+        // A process taken out of a run queue is not waiting and can not be
+        // woken up by other threads, safe to change the owner
+        let handle = match proc_reg.get(pid) {
+          Some(h) => h,
+          None => panic!("{}Stolen process {} does not exist", module(), pid),
+        };
+        handle.lock_run_state().owner = self.id;
+        return Some(pid);
+      }
+    }
+    None
+  }
+
+  /// Nothing to run, sleep until a process is queued on this scheduler or
+  /// until it is time to try stealing again.
+  pub fn wait_for_work(&self) {
+    let run_queue = self.own_queue();
+    let state = run_queue.lock();
+    if state.queue_high.is_empty()
+      && state.queue_normal.is_empty()
+      && state.queue_low.is_empty()
+    {
+      let _ = run_queue.work_available.wait_timeout(state, IDLE_TIMEOUT);
+    }
+  }
+
   /// When time has come to select next running process, first we take a look
   /// at the previous process, what happened to it.
   #[inline]
   fn next_process_finalize_previous(
     &mut self,
-    proc_reg: &mut ProcessRegistry,
+    proc_reg: &ProcessRegistry,
     curr_pid: Term,
   ) -> ScheduleHint {
-    // Extract the last running process from the process registry
-    let curr_ptr = proc_reg.unsafe_lookup_pid_mut(curr_pid);
-    assert!(!curr_ptr.is_null());
-
-    // Unspeakable horrors are happening as we speak: (bypassing borrow checker)
-    let curr_proc = unsafe { &mut (*curr_ptr) };
+    // Extract the last running process from the process registry, it stays
+    // there until this scheduler terminates it
+    let handle = proc_reg.get(curr_pid).unwrap();
+    // This scheduler is still running the process
+    let curr_proc = unsafe { &mut (*handle.as_ptr()) };
 
+    let queue = handle.lock_run_state().queue;
     debug_assert_eq!(
-      curr_proc.current_queue,
+      queue,
       Queue::None,
       "Finalizing previous process which is not dequeued, now in {:?}",
-      curr_proc.current_queue
+      queue
     );
 
     match curr_proc.timeslice_result {
-      SliceResult::Yield => {
-        self.enqueue(proc_reg, curr_pid);
-        self.current = None
-      }
-
-      SliceResult::None => {
-        self.enqueue(proc_reg, curr_pid);
+      SliceResult::Yield | SliceResult::None => {
+        self.enqueue(&handle);
         self.current = None
       }
 
       SliceResult::Finished => {
         // Scheduler will terminate the process with EXIT:NORMAL
         let err = (ExceptionType::Exit, gen_atoms::NORMAL);
-        self.terminate_process(proc_reg, curr_pid, err)
+        self.terminate_process(proc_reg, curr_pid, err);
+        self.current = None
       }
 
       SliceResult::Exception => {
-        return self.handle_process_exception(proc_reg, curr_ptr, curr_pid);
+        return self.handle_process_exception(proc_reg, curr_proc, curr_pid);
       }
 
-      SliceResult::InfiniteWait => {
+      SliceResult::InfiniteWait | SliceResult::TimedWait => {
         // Check if there is anything that should wake it up right now, like
         // an incoming message or another signal?
-        // TODO: Respect already viewed messages in the mailbox
-        if !curr_proc.mailbox.have_unread_messages() {
-          self.enqueue_wait(true, curr_pid);
+        // The run state stays locked until the process is in the wait set, so
+        // that a sender which sees it waiting will find it there.
+        // Messages before the save pointer have been looked at and did not
+        // match, they will not wake the process up.
+        let mut run = handle.lock_run_state();
+        let mut state = self.run_queues[self.id].lock();
+        if !curr_proc.mailbox.have_unread_messages() && curr_proc.mailbox.begin_wait()
+        {
+          if curr_proc.timeslice_result == SliceResult::TimedWait {
+            state.timed_wait.insert(curr_pid, ());
+            run.queue = Queue::TimedWait;
+          } else {
+            state.infinite_wait.insert(curr_pid, ());
+            run.queue = Queue::InfiniteWait;
+          }
+        } else {
+          Self::push_to_run_queue(&mut state, curr_pid, &mut run);
         }
+        run.running = false;
+        handle.notify_run_state();
+        self.current = None
       }
     }
     ScheduleHint::TakeAnotherProcess
   }
-
   /// If exception happened, check whether a process is catching anything at
   /// this moment, otherwise proceed to terminate.
   fn handle_process_exception(
     &mut self,
-    proc_reg: &mut ProcessRegistry,
-    proc_p: *mut Process,
+    proc_reg: &ProcessRegistry,
+    proc: &mut Process,
     proc_pid: Term,
   ) -> ScheduleHint {
-    // Bypassing the borrow checker again
-    let proc = unsafe { &mut (*proc_p) };
-
     assert!(proc.is_failed());
     let p_error = proc.error.unwrap();
 
-    if proc.num_catches <= 0 {
-      // time to terminate, no catches
+    if proc.num_catches <= 0 || p_error.0 == ExceptionType::Panic {
+      // time to terminate, no catches or the exception cannot be caught
       self.terminate_process(proc_reg, proc_pid, p_error);
       self.current = None;
       return ScheduleHint::TakeAnotherProcess;
@@ -309,16 +634,12 @@ impl Scheduler {
         proc.context.jump_ptr(next_catch.loc);
         proc.context.clear_cp();
         proc.get_heap_mut().drop_stack_words(next_catch.stack_drop);
-
-        // TODO: Clear save mark on recv in process.mailbox
+        proc.mailbox.reset_save();
         return ScheduleHint::ContinueSameProcess;
       }
 
       None => {
         println!("Catch not found, terminating...");
-        if proc.process_flags.get(process_flags::TRAP_EXIT) {
-          panic!("todo: on terminate implement trap_exit");
-        }
         self.terminate_process(proc_reg, proc_pid, p_error);
         self.current = None;
       }
@@ -328,34 +649,82 @@ impl Scheduler {
 
   /// Things to do before scheduling another process for execution.
   #[inline]
-  fn next_process_duties(&self) {
-    // TODO: monotonic clock
-    // TODO: wait lists
+  fn next_process_duties(&self, proc_reg: &ProcessRegistry) {
+    self.fire_timers(proc_reg);
     // TODO: network checks
   }
 
-  /// Assuming that the error was not caught, begin process termination routine.
+  /// Turn the timer wheel to the current time and act on the expired timers.
+  /// If another scheduler is doing this right now, skip it. The expired
+  /// timers are taken out of the wheel first, so a process which cancels its
+  /// timer later is told that it has expired, then they fire after the wheel
+  /// is unlocked.
+  fn fire_timers(&self, proc_reg: &ProcessRegistry) {
+    let expired = {
+      let mut timers = match self.timers.try_lock() {
+        Ok(t) => t,
+        Err(_) => return,
+      };
+      let now = timers.monotonic_time();
+      timers.advance(now)
+    };
+    for timer in expired {
+      match timer.action {
+        TimerAction::Send {
+          dest,
+          message,
+          fragment,
+        } => {
+          let pid = if dest.is_atom() {
+            match proc_reg.find_registered(dest) {
+              Some(pid) => pid,
+              None => continue,
+            }
+          } else {
+            dest
+          };
+          if pid.is_local_pid() {
+            if let Some(handle) = proc_reg.get(pid) {
+              handle.deliver_copied_message(self, message, fragment);
+            }
+          }
+        }
+        TimerAction::ReceiveTimeout { pid } => {
+          if let Some(handle) = proc_reg.get(pid) {
+            handle.put_timeout(self);
+          }
+        }
+      }
+    }
+  }
+
+  /// Terminate the process with the reason `e`. All exits go here: a finished
+  /// process, an uncaught exception, an exit signal or `exit/2` to self.
+  /// The process loses its registered name and timers, linked processes get
+  /// exit signals and the monitoring processes get 'DOWN' messages. An exit
+  /// with reason other than `normal` is recorded for the crash report.
   pub fn terminate_process(
     &mut self,
-    proc_reg: &mut ProcessRegistry,
+    proc_reg: &ProcessRegistry,
     pid: Term,
     e: (ExceptionType, Term),
   ) {
-    // assert that process is not in any queue
-    {
-      let p = proc_reg.lookup_pid_mut(pid).unwrap();
-      assert_eq!(p.current_queue, Queue::None);
-    }
+    let handle = match proc_reg.get(pid) {
+      Some(h) => h,
+      None => return,
+    };
+    // The process is not in any queue, wait until the other processes have
+    // finished reading it, then no one will read it again
+    Self::when_not_inspected(&handle, |run| {
+      assert_eq!(run.queue, Queue::None);
+      run.running = false;
+      run.exited = true;
+    });
 
     // root process exits with halt()
     // assert!(p.get_registered_name() != atom::INIT);
 
-    // TODO: ets tables
-    // TODO: notify monitors
-    // TODO: cancel known timers who target this process
-    // TODO: notify links
-    // TODO: unregister name if registered
-    // TODO: if pending timers - become zombie and sit in pending timers queue
+    // TODO: ets tables, when they exist, are deleted or given to the heir here
     println!(
       "{}Terminating pid {} reason={}:{}",
       module(),
@@ -364,33 +733,507 @@ impl Scheduler {
       e.1 //, p.runtime_ctx.regs[0]
     );
 
-    self.timed_wait.remove(&pid);
-    self.infinite_wait.remove(&pid);
-    assert!(!self.queue_normal.contains(&pid));
-    assert!(!self.queue_low.contains(&pid));
-    assert!(!self.queue_high.contains(&pid));
+    self.timers().cancel_all_for(pid);
+    {
+      let mut state = self.own_queue().lock();
+      state.timed_wait.remove(&pid);
+      state.infinite_wait.remove(&pid);
+      assert!(!state.queue_normal.contains(&pid));
+      assert!(!state.queue_low.contains(&pid));
+      assert!(!state.queue_high.contains(&pid));
+    }
+
+    // After the process is removed from the table, no new signals can arrive
     proc_reg.remove(pid);
+    let proc = unsafe { &mut (*handle.as_ptr()) };
+    let registered_name = proc_reg.unregister_pid(pid);
+    if e.1 != gen_atoms::NORMAL {
+      self.crash_reports.push(CrashReport {
+        pid,
+        registered_name,
+        ip: proc.context.ip.get_pointer() as usize,
+        reason: format!("{}", e.1),
+      });
+    }
+    // Processes which have linked to us or started monitoring meanwhile, will
+    // get the exit signal or the 'DOWN' message
+    for signal in proc.mailbox.take_signals() {
+      match signal {
+        Signal::Link { from } => {
+          proc.links.insert(from);
+        }
+        Signal::Unlink { from } => {
+          proc.links.remove(&from);
+        }
+        Signal::Monitor { from, ref_id, name } => {
+          proc.monitored_by.insert(ref_id, MonitorPeer { pid: from, name });
+        }
+        Signal::Demonitor { ref_id } => {
+          proc.monitored_by.remove(&ref_id);
+        }
+        Signal::Exit { .. } | Signal::Down { .. } => {}
+      }
+    }
+    self.notify_links(proc_reg, proc, e.1);
+    self.notify_monitors(proc_reg, proc, e.1);
   }
 
-  /// Called by `Process` when a new message is received. Checks whether the
-  /// process was placed in one of waiting sets and wakes it up.
-  #[inline]
-  pub fn notify_new_incoming_message(
-    &mut self,
-    proc_reg: &mut ProcessRegistry,
-    proc: &mut Process,
-  ) {
-    // Remove from whatever wait set
-    match proc.current_queue {
-      Queue::InfiniteWait => {
-        self.infinite_wait.remove(&proc.pid);
-        self.enqueue_opt(proc_reg, proc.pid, true);
+  /// Send 'DOWN' messages with `reason` to the processes monitoring `proc`,
+  /// and remove the monitors which `proc` has created.
+  fn notify_monitors(&self, proc_reg: &ProcessRegistry, proc: &Process, reason: Term) {
+    for (ref_id, watcher) in proc.monitored_by.iter() {
+      if watcher.pid == proc.pid {
+        continue;
+      }
+      match Signal::new_down(proc.pid, *ref_id, watcher.name, reason) {
+        Ok(signal) => {
+          self.send_signal(proc_reg, watcher.pid, signal);
+        }
+        Err(err) => {
+          println!("{}Can't send 'DOWN' to {}: {:?}", module(), watcher.pid, err)
+        }
+      }
+    }
+    for (ref_id, target) in proc.monitors.iter() {
+      if target.pid == proc.pid {
+        continue;
+      }
+      let signal = Signal::Demonitor { ref_id: *ref_id };
+      self.send_signal(proc_reg, target.pid, signal);
+    }
+  }
+
+  /// Send exit signals with `reason` to the processes linked to `proc`.
+  fn notify_links(&self, proc_reg: &ProcessRegistry, proc: &Process, reason: Term) {
+    for linked_pid in proc.links.iter() {
+      match Signal::new_exit(proc.pid, reason, true) {
+        Ok(signal) => {
+          self.send_signal(proc_reg, *linked_pid, signal);
+        }
+        Err(err) => {
+          println!("{}Can't send exit signal to {}: {:?}", module(), linked_pid, err)
+        }
+      }
+    }
+  }
+
+  /// Stop all other schedulers at their safe points and run `f`, meanwhile no
+  /// other process runs and the timer wheel does not turn, so that all
+  /// processes can be read. The current process of this scheduler counts as
+  /// stopped while it waits, processes waiting for it to stop may read it.
+  /// If another scheduler is stopping the world, this one parks first.
+  pub fn stop_the_world<T, F>(&self, proc_reg: &ProcessRegistry, f: F) -> T
+  where
+    F: FnOnce() -> T,
+  {
+    let curr_handle = self.current.and_then(|pid| proc_reg.get(pid));
+    if let Some(h) = &curr_handle {
+      h.lock_run_state().running = false;
+      h.notify_run_state();
+    }
+
+    let mut state = self.safepoint.lock();
+    while state.stop_requested {
+      state = self.safepoint.park(state);
+    }
+    state.stop_requested = true;
+    // Idle schedulers come to their safe point sooner
+    for run_queue in self.run_queues.iter() {
+      run_queue.work_available.notify_one();
+    }
+    while state.parked + 1 < state.schedulers {
+      state = self.safepoint.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+    }
+    drop(state);
+
+    let result = f();
+
+    self.safepoint.lock().stop_requested = false;
+    self.safepoint.changed.notify_all();
+    // Continue running when nobody is reading the current process
+    if let Some(h) = &curr_handle {
+      Self::when_not_inspected(h, |run| run.running = true);
+    }
+    result
+  }
+
+  /// Run `f` on another process while it is not running, so that its heap,
+  /// mailbox and other fields can be read by the `caller`. While `f` runs, the
+  /// process is counted as inspected in its run state and will not be started
+  /// by its scheduler. A process which is running on another scheduler is
+  /// waited for. If two processes wait for each other, the one with the
+  /// greater pid goes first, reading the other one while it waits.
+  /// Returns: `None` if the process does not exist.
+  pub fn with_stopped_process<T, F>(
+    &self,
+    proc_reg: &ProcessRegistry,
+    caller: Term,
+    pid: Term,
+    f: F,
+  ) -> Option<T>
+  where
+    F: FnOnce(&Process, &RunState) -> T,
+  {
+    assert_ne!(caller, pid, "A process can not wait for itself to stop");
+    let target = proc_reg.get(pid)?;
+    // Processes waiting for the caller to stop may read it meanwhile
+    let caller_handle = proc_reg.get(caller);
+    if let Some(h) = &caller_handle {
+      h.lock_run_state().inspecting = true;
+      h.notify_run_state();
+    }
+
+    let run_state = {
+      let mut run = target.lock_run_state();
+      loop {
+        if run.exited {
+          break None;
+        }
+        if !run.running || (run.inspecting && pid.raw() < caller.raw()) {
+          run.inspectors += 1;
+          break Some(*run);
+        }
+        run = target.wait_run_state(run);
+      }
+    };
+    let result = run_state.map(|run| {
+      let result = f(unsafe { &(*target.as_ptr()) }, &run);
+      target.lock_run_state().inspectors -= 1;
+      target.notify_run_state();
+      result
+    });
+
+    // The caller continues running when nobody is reading it
+    if let Some(h) = &caller_handle {
+      h.lock_run_state().inspecting = false;
+      Self::when_not_inspected(h, |_| {});
+    }
+    result
+  }
+
+  /// Called when a waiting process has received a message, possibly from
+  /// another scheduler thread. Removes the process from the wait set of its
+  /// owning scheduler and queues it there.
+  pub fn wake_up(&self, handle: &ProcessHandle) {
+    let mut run = handle.lock_run_state();
+    let run_queue = &self.run_queues[run.owner];
+    {
+      let mut state = run_queue.lock();
+      match run.queue {
+        Queue::InfiniteWait => {
+          state.infinite_wait.remove(&handle.pid());
+        }
+        Queue::TimedWait => {
+          state.timed_wait.remove(&handle.pid());
+        }
+        _other => return,
+      }
+      run.queue = Queue::None;
+      Self::push_to_run_queue(&mut state, handle.pid(), &mut run);
+    }
+    run_queue.work_available.notify_one();
+  }
+
+  /// Send `signal` to the process `pid`, which may be running on another
+  /// scheduler.
+  /// Returns: false if the process does not exist.
+  pub fn send_signal(
+    &self,
+    proc_reg: &ProcessRegistry,
+    pid: Term,
+    signal: Signal,
+  ) -> bool {
+    match proc_reg.get(pid) {
+      Some(handle) => {
+        handle.send_signal(self, signal);
+        true
       }
-      Queue::TimedWait => {
-        self.timed_wait.remove(&proc.pid);
-        self.enqueue_opt(proc_reg, proc.pid, true);
+      None => false,
+    }
+  }
+}
+
+impl Drop for Scheduler {
+  /// A scheduler which has stopped is not waited for at the safe point.
+  fn drop(&mut self) {
+    self.safepoint.lock().schedulers -= 1;
+    self.safepoint.changed.notify_all();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    beam::gen_op,
+    emulator::{
+      code::{opcode, CodePtr},
+      process_flags,
+      spawn_options::SpawnOptions,
+    },
+    term::{
+      boxed::{self, reference::next_ref_id},
+      term_builder::tuple_builder::tuple2,
+    },
+  };
+  use std::thread;
+
+  fn spawn(proc_reg: &ProcessRegistry, sched: &Scheduler, n: usize) -> Term {
+    let pid = Term::make_local_pid(n);
+    let p = Process::new(pid, Term::nil(), CodePtr::null(), &SpawnOptions::default());
+    let handle = proc_reg.insert(pid, p, Prio::Normal);
+    sched.enqueue(&handle);
+    pid
+  }
+
+  /// The process stays valid while it is registered.
+  fn process_p(proc_reg: &ProcessRegistry, pid: Term) -> *mut Process {
+    proc_reg.get(pid).unwrap().as_ptr()
+  }
+
+  #[test]
+  fn test_idle_scheduler_steals_work() {
+    let proc_reg = ProcessRegistry::new();
+    let run_queues = create_run_queues(2);
+    let timers = Arc::new(Mutex::new(TimerWheel::new()));
+    let safepoint = Arc::new(SafePoint::new());
+    let mut sched0 =
+      Scheduler::new(0, run_queues.clone(), timers.clone(), safepoint.clone());
+    let mut sched1 = Scheduler::new(1, run_queues, timers, safepoint);
+    let pid0 = spawn(&proc_reg, &sched0, 0);
+    let pid1 = spawn(&proc_reg, &sched0, 1);
+
+    assert_eq!(sched0.next_process(&proc_reg), Some(pid0));
+    // Second process is taken from the tail of scheduler 0 queue
+    assert_eq!(sched1.next_process(&proc_reg), Some(pid1));
+    assert_eq!(proc_reg.get(pid1).unwrap().lock_run_state().owner, 1);
+    assert_eq!(sched0.queue_len(0, Queue::Normal), 0);
+  }
+
+  #[test]
+  fn test_message_wakes_up_waiting_process() {
+    let proc_reg = ProcessRegistry::new();
+    let timers = Arc::new(Mutex::new(TimerWheel::new()));
+    let safepoint = Arc::new(SafePoint::new());
+    let mut sched = Scheduler::new(0, create_run_queues(1), timers, safepoint);
+    let pid = spawn(&proc_reg, &sched, 0);
+    assert_eq!(sched.next_process(&proc_reg), Some(pid));
+
+    // The process has found no messages and waits
+    let proc_p = process_p(&proc_reg, pid);
+    unsafe {
+      (*proc_p).timeslice_result = SliceResult::InfiniteWait;
+    }
+    assert_eq!(sched.next_process(&proc_reg), None);
+    assert_eq!(sched.queue_len(0, Queue::InfiniteWait), 1);
+
+    let other = Scheduler::new(
+      0,
+      sched.get_run_queues(),
+      sched.get_shared_timers(),
+      sched.get_safepoint(),
+    );
+    let handle = proc_reg.get(pid).unwrap();
+    handle.deliver_message(&other, Term::nil()).unwrap();
+    assert_eq!(sched.queue_len(0, Queue::InfiniteWait), 0);
+    assert_eq!(sched.next_process(&proc_reg), Some(pid));
+  }
+
+  #[test]
+  fn test_exit_signal_reaches_linked_processes() {
+    let proc_reg = ProcessRegistry::new();
+    let timers = Arc::new(Mutex::new(TimerWheel::new()));
+    let safepoint = Arc::new(SafePoint::new());
+    let mut sched = Scheduler::new(0, create_run_queues(1), timers, safepoint);
+    let pid0 = spawn(&proc_reg, &sched, 0);
+    let pid1 = spawn(&proc_reg, &sched, 1);
+    let pid2 = spawn(&proc_reg, &sched, 2);
+    unsafe {
+      let p0 = process_p(&proc_reg, pid0);
+      (*p0).links = [pid1, pid2].iter().cloned().collect();
+      (*process_p(&proc_reg, pid1)).links.insert(pid0);
+      let p2 = process_p(&proc_reg, pid2);
+      (*p2).links.insert(pid0);
+      (*p2).process_flags.read_and_set(process_flags::TRAP_EXIT, true);
+    }
+
+    // First process fails with an uncaught error
+    assert_eq!(sched.next_process(&proc_reg), Some(pid0));
+    unsafe {
+      let p0 = process_p(&proc_reg, pid0);
+      (*p0).set_exception(ExceptionType::Error, gen_atoms::BADARG);
+      (*p0).timeslice_result = SliceResult::Exception;
+    }
+
+    // The second process is killed by the exit signal, the third one traps
+    // exits and receives a message instead
+    assert_eq!(sched.next_process(&proc_reg), Some(pid2));
+    assert!(!proc_reg.exists(pid0));
+    assert!(!proc_reg.exists(pid1));
+    let p2 = process_p(&proc_reg, pid2);
+    let message = unsafe { (*p2).mailbox.get_current() }.unwrap();
+    assert!(message.is_tuple());
+    unsafe {
+      assert_eq!((*message.get_tuple_ptr()).get_element(0), gen_atoms::EXIT_UPPER);
+      assert_eq!((*message.get_tuple_ptr()).get_element(2), gen_atoms::BADARG);
+      assert!((*p2).links.is_empty());
+    }
+  }
+
+  #[test]
+  fn test_down_message_after_monitored_process_exits() {
+    let proc_reg = ProcessRegistry::new();
+    let timers = Arc::new(Mutex::new(TimerWheel::new()));
+    let safepoint = Arc::new(SafePoint::new());
+    let mut sched = Scheduler::new(0, create_run_queues(1), timers, safepoint);
+    let target = spawn(&proc_reg, &sched, 0);
+    let watcher0 = spawn(&proc_reg, &sched, 1);
+    let watcher1 = spawn(&proc_reg, &sched, 2);
+    let (ref0, ref1) = (next_ref_id(), next_ref_id());
+    unsafe {
+      let t = process_p(&proc_reg, target);
+      for (ref_id, pid) in [(ref0, watcher0), (ref1, watcher1)].iter() {
+        let peer = MonitorPeer { pid: *pid, name: None };
+        (*t).monitored_by.insert(*ref_id, peer);
       }
-      _other => {}
+      // The second watcher has called demonitor, but the signal did not
+      // reach the target before it exited
+      let peer = MonitorPeer { pid: target, name: None };
+      (*process_p(&proc_reg, watcher0)).monitors.insert(ref0, peer);
+    }
+
+    assert_eq!(sched.next_process(&proc_reg), Some(target));
+    unsafe {
+      let t = process_p(&proc_reg, target);
+      (*t).set_exception(ExceptionType::Exit, gen_atoms::KILLED);
+      (*t).timeslice_result = SliceResult::Exception;
+    }
+    assert_eq!(sched.next_process(&proc_reg), Some(watcher0));
+    unsafe {
+      let w0 = process_p(&proc_reg, watcher0);
+      let message = (*w0).mailbox.get_current().unwrap();
+      let tuple_p = message.get_tuple_ptr();
+      assert_eq!((*tuple_p).get_element(0), gen_atoms::DOWN_UPPER);
+      assert_eq!(boxed::Reference::get_id((*tuple_p).get_element(1)), Some(ref0));
+      assert_eq!((*tuple_p).get_element(3), target);
+      assert_eq!((*tuple_p).get_element(4), gen_atoms::KILLED);
+      assert!((*w0).monitors.is_empty());
     }
+
+    unsafe {
+      (*process_p(&proc_reg, watcher0)).timeslice_result = SliceResult::Yield;
+    }
+    assert_eq!(sched.next_process(&proc_reg), Some(watcher1));
+    let w1 = process_p(&proc_reg, watcher1);
+    assert!(unsafe { (*w1).mailbox.get_current() }.is_none());
+  }
+
+  #[test]
+  fn test_hibernated_process_wakes_up_on_message() {
+    let proc_reg = ProcessRegistry::new();
+    let timers = Arc::new(Mutex::new(TimerWheel::new()));
+    let safepoint = Arc::new(SafePoint::new());
+    let mut sched = Scheduler::new(0, create_run_queues(1), timers, safepoint);
+    let pid = spawn(&proc_reg, &sched, 0);
+    assert_eq!(sched.next_process(&proc_reg), Some(pid));
+
+    let code = [opcode::to_memory_word(gen_op::OPCODE_RETURN)];
+    let ip = CodePtr::from_ptr(&code[0]);
+    let one = Term::make_small_unsigned(1);
+    let p = process_p(&proc_reg, pid);
+    unsafe {
+      // Garbage, and a tuple which is given to the wake up function
+      tuple2((*p).get_heap_mut(), gen_atoms::OK, gen_atoms::OK).unwrap();
+      let arg = tuple2((*p).get_heap_mut(), gen_atoms::OK, one).unwrap();
+      (*p).hibernate(ip, &[arg]);
+
+      assert!((*p).hibernated);
+      assert_eq!((*p).context.ip, ip);
+      assert_eq!((*p).context.live, 1);
+      // Only the argument is left on the heap
+      assert_eq!((*p).get_heap_size(), boxed::Tuple::storage_size(2).words);
+      let x0 = (*p).context.get_x(0);
+      assert_eq!((*x0.get_tuple_ptr()).get_element(1), one);
+      // This is what the VM loop does after the call
+      (*p).timeslice_result = SliceResult::InfiniteWait;
+    }
+    assert_eq!(sched.next_process(&proc_reg), None);
+    assert_eq!(sched.queue_len(0, Queue::InfiniteWait), 1);
+
+    let handle = proc_reg.get(pid).unwrap();
+    handle.deliver_message(&sched, gen_atoms::OK).unwrap();
+    assert_eq!(sched.next_process(&proc_reg), Some(pid));
+    assert_eq!(unsafe { (*p).mailbox.get_current() }, Some(gen_atoms::OK));
+  }
+
+  #[test]
+  fn test_stop_the_world_waits_for_other_schedulers() {
+    let proc_reg = ProcessRegistry::new();
+    let timers = Arc::new(Mutex::new(TimerWheel::new()));
+    let safepoint = Arc::new(SafePoint::new());
+    let sched = Scheduler::new(0, create_run_queues(2), timers, safepoint);
+    let mut other = Scheduler::new(
+      1,
+      sched.get_run_queues(),
+      sched.get_shared_timers(),
+      sched.get_safepoint(),
+    );
+    let pid = spawn(&proc_reg, &other, 0);
+    assert_eq!(other.next_process(&proc_reg), Some(pid));
+    let reg = &proc_reg;
+    thread::scope(|s| {
+      let stopper = s.spawn(move || {
+        sched.stop_the_world(reg, || reg.get(pid).unwrap().lock_run_state().running)
+      });
+      // The other scheduler is running a process, the world is not stopped
+      thread::sleep(Duration::from_millis(10));
+      assert!(!stopper.is_finished());
+
+      // The other scheduler parks after the time slice, then continues
+      unsafe {
+        (*process_p(reg, pid)).timeslice_result = SliceResult::Yield;
+      }
+      assert_eq!(other.next_process(reg), Some(pid));
+      assert!(!stopper.join().unwrap());
+    });
+  }
+
+  #[test]
+  fn test_with_stopped_process_waits_for_running_process() {
+    let proc_reg = ProcessRegistry::new();
+    let timers = Arc::new(Mutex::new(TimerWheel::new()));
+    let safepoint = Arc::new(SafePoint::new());
+    let mut sched = Scheduler::new(0, create_run_queues(2), timers, safepoint);
+    let target = spawn(&proc_reg, &sched, 0);
+    let caller = spawn(&proc_reg, &sched, 1);
+    // A queued process is read right away
+    let queue =
+      sched.with_stopped_process(&proc_reg, caller, target, |_p, run| run.queue);
+    assert_eq!(queue, Some(Queue::Normal));
+
+    assert_eq!(sched.next_process(&proc_reg), Some(target));
+    let other = Scheduler::new(
+      1,
+      sched.get_run_queues(),
+      sched.get_shared_timers(),
+      sched.get_safepoint(),
+    );
+    let reg = &proc_reg;
+    thread::scope(|s| {
+      let inspector = s.spawn(move || {
+        other.with_stopped_process(reg, caller, target, |p, run| (p.pid, run.queue))
+      });
+      // The target is still running, the inspector waits
+      thread::sleep(Duration::from_millis(10));
+      assert!(!inspector.is_finished());
+
+      unsafe {
+        (*process_p(reg, target)).timeslice_result = SliceResult::InfiniteWait;
+      }
+      assert_eq!(sched.next_process(reg), Some(caller));
+      let result = inspector.join().unwrap();
+      assert_eq!(result, Some((target, Queue::InfiniteWait)));
+    });
+    assert_eq!(reg.get(target).unwrap().lock_run_state().inspectors, 0);
   }
 }
@@ -0,0 +1,116 @@
+//! Signals between processes, other than messages. Like messages, signals
+//! travel through the locked incoming queue of the receiver's mailbox (see
+//! `mailbox.rs`), so the link set of a process is only modified by the
+//! scheduler which owns the process, and the signals from one sender arrive
+//! in the order they were sent.
+use crate::{
+  emulator::{
+    gen_atoms,
+    heap::{copy_term, heap_trait::THeap, Heap},
+  },
+  fail::RtResult,
+  term::{
+    boxed::{self, reference::RefId},
+    term_builder::tuple_builder::{tuple2, tuple3, TupleBuilder},
+    value::Term,
+  },
+};
+
+pub enum Signal {
+  /// Process `from` has created a link to the receiver
+  Link { from: Term },
+  /// Process `from` has removed its link to the receiver
+  Unlink { from: Term },
+  /// Exit signal from process `from`. The `reason` and the message
+  /// `{'EXIT', From, Reason}`, which a process trapping exits receives
+  /// instead, are both stored in `fragment`.
+  Exit {
+    from: Term,
+    reason: Term,
+    message: Term,
+    fragment: Heap,
+    /// The signal was sent because a linked process has exited
+    linked: bool,
+  },
+  /// Process `from` monitors the receiver, `name` is set if the monitor was
+  /// created for a registered name
+  Monitor {
+    from: Term,
+    ref_id: RefId,
+    name: Option<Term>,
+  },
+  /// The monitor `ref_id` was removed by the process which created it
+  Demonitor { ref_id: RefId },
+  /// The process monitored with `ref_id` has exited, `message` is the
+  /// `{'DOWN', Ref, process, Item, Reason}` stored in `fragment`
+  Down {
+    ref_id: RefId,
+    message: Term,
+    fragment: Heap,
+  },
+}
+
+impl Signal {
+  /// Create an exit signal, copying `reason` into a new heap fragment.
+  pub fn new_exit(from: Term, reason: Term, linked: bool) -> RtResult<Signal> {
+    let tuple_size = boxed::Tuple::storage_size(3);
+    let mut fragment =
+      Heap::new_fragment(copy_term::size_of_term(reason).words + tuple_size.words);
+    let reason = copy_term::copy_to(reason, &mut fragment)?;
+    let message = tuple3(&mut fragment, gen_atoms::EXIT_UPPER, from, reason)?;
+    Ok(Signal::Exit {
+      from,
+      reason,
+      message,
+      fragment,
+      linked,
+    })
+  }
+
+  /// Create a 'DOWN' signal for the monitor `ref_id`, copying `reason` into a
+  /// new heap fragment.
+  pub fn new_down(
+    from: Term,
+    ref_id: RefId,
+    name: Option<Term>,
+    reason: Term,
+  ) -> RtResult<Signal> {
+    let size = copy_term::size_of_term(reason).words
+      + boxed::Reference::storage_size().words
+      + boxed::Tuple::storage_size(5).words
+      + boxed::Tuple::storage_size(2).words;
+    let mut fragment = Heap::new_fragment(size);
+    let reason = copy_term::copy_to(reason, &mut fragment)?;
+    let ref_term = boxed::Reference::create_into(&mut fragment, ref_id)?;
+    let message = make_down_message(&mut fragment, ref_term, from, name, reason)?;
+    Ok(Signal::Down {
+      ref_id,
+      message,
+      fragment,
+    })
+  }
+}
+
+/// Build `{'DOWN', Ref, process, Item, Reason}` where `Item` is the pid, or
+/// `{Name, Node}` if the monitor was created for a registered name.
+pub fn make_down_message(
+  hp: &mut THeap,
+  ref_term: Term,
+  pid: Term,
+  name: Option<Term>,
+  reason: Term,
+) -> RtResult<Term> {
+  let item = match name {
+    Some(n) => tuple2(hp, n, gen_atoms::NONODE_NOHOST)?,
+    None => pid,
+  };
+  let tb = TupleBuilder::with_arity(5, hp)?;
+  unsafe {
+    tb.set_element(0, gen_atoms::DOWN_UPPER);
+    tb.set_element(1, ref_term);
+    tb.set_element(2, gen_atoms::PROCESS);
+    tb.set_element(3, item);
+    tb.set_element(4, reason);
+  }
+  Ok(tb.make_term())
+}
@@ -1,6 +1,13 @@
-use crate::emulator::{process_flags::ProcessFlags, scheduler::Prio};
+use crate::{
+  emulator::{
+    heap::heap_size::{self, MaxHeapSize},
+    process_flags::ProcessFlags,
+    scheduler::Prio,
+  },
+  term::boxed::reference::RefId,
+};
 
-#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum MessageQueueLocation {
   OnHeap,
   OffHeap,
@@ -11,6 +18,19 @@ pub struct SpawnOptions {
   pub prio: Prio,
   // TODO: Use bit flags?
   pub process_flags: ProcessFlags,
+  /// Heap will not shrink below this size, in words
+  pub min_heap_size: usize,
+  /// Binary virtual heap size, in words
+  pub min_bin_vheap_size: usize,
+  /// Heap size limit and the action to take when it is reached
+  pub max_heap_size: MaxHeapSize,
+  /// Link the new process to its parent
+  pub link: bool,
+  /// The parent monitors the new process with this monitor reference
+  pub monitor: Option<RefId>,
+  /// Accepted for compatibility: the collector is not generational, every
+  /// collection is a full sweep
+  pub fullsweep_after: usize,
 }
 
 impl SpawnOptions {
@@ -19,6 +39,12 @@ impl SpawnOptions {
       msg_queue: MessageQueueLocation::OnHeap,
       prio: Prio::Normal,
       process_flags: ProcessFlags::default(),
+      min_heap_size: heap_size::DEFAULT_MIN_HEAP_SIZE,
+      min_bin_vheap_size: heap_size::DEFAULT_MIN_BIN_VHEAP_SIZE,
+      max_heap_size: MaxHeapSize::default(),
+      link: false,
+      monitor: None,
+      fullsweep_after: heap_size::DEFAULT_FULLSWEEP_AFTER,
     }
   }
 }
@@ -0,0 +1,238 @@
+//! Timer wheel, driven by the monotonic clock of the VM. Timers are used by
+//! `erlang:send_after`, `erlang:start_timer` and `receive ... after`.
+//!
+//! Time is counted in milliseconds since the wheel was created. A timer is
+//! placed into the slot `expires % WHEEL_SIZE`, the wheel turns one slot per
+//! millisecond and a slot may contain timers for the later revolutions, these
+//! stay in the slot until their time comes. Cancelled timers are removed from
+//! the slots lazily.
+//!
+//! A timer is identified by a reference id, the BIFs return it to the caller
+//! as the timer reference.
+use crate::{
+  emulator::{
+    code_srv::literal_area,
+    heap::{gc::FromSpaceRange, Heap},
+  },
+  term::{boxed::reference::RefId, value::Term},
+};
+use std::{
+  collections::{HashMap, HashSet},
+  mem,
+  time::Instant,
+};
+
+/// Number of slots, one millisecond each.
+const WHEEL_SIZE: u64 = 1024;
+
+pub type TimerId = RefId;
+
+/// What happens when a timer expires.
+pub enum TimerAction {
+  /// Send a message to a pid or a registered name. The message is already
+  /// copied to `fragment`, or it is an immediate value and needs no fragment.
+  Send {
+    dest: Term,
+    message: Term,
+    fragment: Option<Heap>,
+  },
+  /// Wake up a process waiting in `receive ... after` (see `wait_timeout`)
+  ReceiveTimeout { pid: Term },
+}
+
+pub struct Timer {
+  pub id: TimerId,
+  /// Expiration time, in milliseconds of the monotonic clock
+  pub expires: u64,
+  pub action: TimerAction,
+}
+
+impl Timer {
+  /// The process which the timer targets, the timer is cancelled when this
+  /// process exits. Timers sending to a registered name have no target pid.
+  fn get_target_pid(&self) -> Option<Term> {
+    match self.action {
+      TimerAction::Send { dest, .. } if dest.is_pid() => Some(dest),
+      TimerAction::Send { .. } => None,
+      TimerAction::ReceiveTimeout { pid } => Some(pid),
+    }
+  }
+}
+
+pub struct TimerWheel {
+  /// Monotonic clock origin
+  start: Instant,
+  /// Time of the next slot to be visited by `advance`
+  now: u64,
+  slots: Vec<Vec<TimerId>>,
+  timers: HashMap<TimerId, Timer>,
+  /// Active timers of each target process, to cancel them when it exits
+  by_pid: HashMap<Term, HashSet<TimerId>>,
+}
+
+// Heap fragments of the delayed messages are only accessed under the lock
+// which protects the wheel (see `Scheduler::timers`).
+unsafe impl Send for TimerWheel {}
+
+impl TimerWheel {
+  pub fn new() -> Self {
+    Self {
+      start: Instant::now(),
+      now: 0,
+      slots: (0..WHEEL_SIZE).map(|_| Vec::new()).collect(),
+      timers: HashMap::new(),
+      by_pid: HashMap::new(),
+    }
+  }
+
+  /// Milliseconds passed since the wheel was created.
+  #[inline]
+  pub fn monotonic_time(&self) -> u64 {
+    self.start.elapsed().as_millis() as u64
+  }
+
+  /// Start a timer `id` (a new reference id) which expires at `expires`
+  /// milliseconds of the monotonic clock. A timer which is already in the past
+  /// expires on the next `advance`.
+  pub fn start(&mut self, id: TimerId, expires: u64, action: TimerAction) {
+    let slot = (expires.max(self.now) % WHEEL_SIZE) as usize;
+    self.slots[slot].push(id);
+    let timer = Timer {
+      id,
+      expires,
+      action,
+    };
+    if let Some(pid) = timer.get_target_pid() {
+      self.by_pid.entry(pid).or_default().insert(id);
+    }
+    self.timers.insert(id, timer);
+  }
+
+  /// Remove a timer which has not expired yet.
+  /// Returns: the timer, or `None` if it does not exist or has expired.
+  pub fn cancel(&mut self, id: TimerId) -> Option<Timer> {
+    let timer = self.timers.remove(&id)?;
+    if let Some(pid) = timer.get_target_pid() {
+      self.forget_pid_timer(pid, id);
+    }
+    Some(timer)
+  }
+
+  /// Cancel all timers which target the process `pid`, called when it exits.
+  pub fn cancel_all_for(&mut self, pid: Term) {
+    if let Some(ids) = self.by_pid.remove(&pid) {
+      for id in ids {
+        self.timers.remove(&id);
+      }
+    }
+  }
+
+  /// Milliseconds left until the timer expires.
+  /// Returns: `None` if the timer does not exist or has expired.
+  pub fn time_left(&self, id: TimerId) -> Option<u64> {
+    let timer = self.timers.get(&id)?;
+    Some(timer.expires.saturating_sub(self.monotonic_time()))
+  }
+
+  /// Check whether a message waiting in a timer points into `range`.
+  pub fn refers_to(&self, range: FromSpaceRange) -> bool {
+    self.timers.values().any(|timer| match &timer.action {
+      TimerAction::Send {
+        message, fragment, ..
+      } => match fragment {
+        Some(f) => f.refers_to(range),
+        None => literal_area::term_points_into(*message, range),
+      },
+      TimerAction::ReceiveTimeout { .. } => false,
+    })
+  }
+
+  fn forget_pid_timer(&mut self, pid: Term, id: TimerId) {
+    if let Some(ids) = self.by_pid.get_mut(&pid) {
+      ids.remove(&id);
+      if ids.is_empty() {
+        self.by_pid.remove(&pid);
+      }
+    }
+  }
+
+  /// Turn the wheel to the time `now` and take out the expired timers, in the
+  /// order of their expiration.
+  pub fn advance(&mut self, now: u64) -> Vec<Timer> {
+    if now < self.now {
+      return Vec::new();
+    }
+    // After a long pause every slot is visited once
+    let ticks = (now - self.now + 1).min(WHEEL_SIZE);
+    let mut expired_ids = Vec::new();
+    for tick in 0..ticks {
+      let slot = ((self.now + tick) % WHEEL_SIZE) as usize;
+      for id in mem::take(&mut self.slots[slot]) {
+        match self.timers.get(&id) {
+          // Cancelled, forget it
+          None => {}
+          Some(timer) if timer.expires <= now => expired_ids.push(id),
+          Some(_) => self.slots[slot].push(id),
+        }
+      }
+    }
+    self.now = now + 1;
+
+    let mut expired: Vec<Timer> =
+      expired_ids.into_iter().filter_map(|id| self.cancel(id)).collect();
+    // Timers expiring at the same time go in the order of creation
+    expired.sort_by_key(|t| (t.expires, t.id[2], t.id[1], t.id[0]));
+    expired
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{emulator::gen_atoms, term::boxed::reference::next_ref_id};
+
+  fn receive_timeout() -> TimerAction {
+    TimerAction::ReceiveTimeout {
+      pid: Term::make_local_pid(1),
+    }
+  }
+
+  #[test]
+  fn test_timers_expire_in_order() {
+    let mut wheel = TimerWheel::new();
+    let (late, early, cancelled) = (next_ref_id(), next_ref_id(), next_ref_id());
+    wheel.start(late, WHEEL_SIZE + 10, receive_timeout());
+    wheel.start(early, 10, receive_timeout());
+    wheel.start(cancelled, 5, receive_timeout());
+    assert!(wheel.cancel(cancelled).is_some());
+
+    assert!(wheel.advance(9).is_empty());
+    // The late timer shares the slot but expires on the next revolution
+    let expired: Vec<TimerId> = wheel.advance(10).iter().map(|t| t.id).collect();
+    assert_eq!(expired, vec![early]);
+
+    let expired: Vec<TimerId> =
+      wheel.advance(5 * WHEEL_SIZE).iter().map(|t| t.id).collect();
+    assert_eq!(expired, vec![late]);
+    assert_eq!(wheel.timers.len(), 0);
+  }
+
+  #[test]
+  fn test_cancel_timers_of_exited_process() {
+    let mut wheel = TimerWheel::new();
+    wheel.start(next_ref_id(), 100, receive_timeout());
+    let to_name = next_ref_id();
+    wheel.start(
+      to_name,
+      100,
+      TimerAction::Send {
+        dest: gen_atoms::UNDEFINED,
+        message: Term::nil(),
+        fragment: None,
+      },
+    );
+    wheel.cancel_all_for(Term::make_local_pid(1));
+    assert_eq!(wheel.timers.len(), 1);
+    assert!(wheel.time_left(to_name).is_some());
+  }
+}
@@ -5,60 +5,165 @@ use crate::{
   command_line_args::ErlStartArgs,
   defs::Word,
   emulator::{
+    code::CodePtr,
     code_srv::CodeServer,
-    mfa::ModFunArgs,
+    logger,
+    mfa::{ModFunArgs, ModFunArity},
     process::Process,
-    process_registry::ProcessRegistry,
-    scheduler::{Scheduler},
+    process_registry::{self, ProcessRegistry},
+    scheduler::{self, Prio, SafePoint, Scheduler},
     spawn_options::SpawnOptions,
+    timer_wheel::TimerWheel,
   },
-  fail::RtResult,
-  term::value::*,
+  fail::{self, RtResult},
+  term::{boxed, value::*},
 };
 use crate::emulator::process_flags;
-use crate::emulator::heap::{Heap, Designation};
+use std::{
+  sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+  },
+  thread,
+};
 
 /// VM environment, heaps, tables, processes all goes here.
 /// Atoms are a global API in `atom.rs`.
-/// Code server is a global API in `code_srv.rs`.
+///
+/// Every scheduler thread works with its own `VM` value, which has its own
+/// `Scheduler` and shares the code server, the process registry and the run
+/// queues with the other threads (see `new_scheduler_handle`). The shared
+/// parts are locked internally.
 pub struct VM {
   /// Pid counter increments every time a new process is spawned
-  pid_counter: Word,
+  pid_counter: Arc<AtomicUsize>,
 
   /// Contains all loaded modules and manages versions
-  pub code_server: CodeServer,
+  code_server: Arc<RwLock<CodeServer>>,
 
+  /// Set when the VM is stopping because of a fatal error in some scheduler
+  stopped: Arc<AtomicBool>,
+
+  /// Scheduler which is run by this thread
   pub scheduler: Scheduler,
-  pub processes: ProcessRegistry,
-  pub binary_heap: Heap,
+  pub processes: Arc<ProcessRegistry>,
 }
 
 impl VM {
-  /// Create a VM, multiple VMs can be created but atom table and code server
-  /// will be shared (global).
+  /// Create a VM with the number of schedulers given by `+S` option, or one
+  /// scheduler per CPU core. The created value is the handle for the first
+  /// scheduler, handles for other schedulers are created with
+  /// `new_scheduler_handle`.
+  /// Multiple VMs can be created but atom table will be shared (global).
   pub fn new(args: &mut ErlStartArgs) -> VM {
+    let n_schedulers = match args.schedulers {
+      Some(n) => n,
+      None => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+    };
+    let run_queues = scheduler::create_run_queues(n_schedulers);
+    let timers = Arc::new(Mutex::new(TimerWheel::new()));
+    let safepoint = Arc::new(SafePoint::new());
     VM {
-      code_server: CodeServer::new(args),
-      pid_counter: 0,
-      scheduler: Scheduler::new(),
-      processes: ProcessRegistry::new(),
-      binary_heap: Heap::new(Designation::BinaryHeap),
+      code_server: Arc::new(RwLock::new(CodeServer::new(args))),
+      pid_counter: Arc::new(AtomicUsize::new(0)),
+      stopped: Arc::new(AtomicBool::new(false)),
+      scheduler: Scheduler::new(0, run_queues, timers, safepoint),
+      processes: Arc::new(ProcessRegistry::new()),
     }
   }
 
-  /// Dirty trick to not have to dynamically borrow scheduler via
-  /// `RefCell<Box<>>` because schedulers live just as long as the VM itself.
-  #[allow(dead_code)]
-  pub fn get_scheduler_p(&self) -> *mut Scheduler {
-    let p = &self.scheduler as *const Scheduler;
-    p as *mut Scheduler
+  /// Create a VM handle for scheduler `id`, to be moved to its thread.
+  pub fn new_scheduler_handle(&self, id: usize) -> VM {
+    VM {
+      code_server: self.code_server.clone(),
+      pid_counter: self.pid_counter.clone(),
+      stopped: self.stopped.clone(),
+      scheduler: Scheduler::new(
+        id,
+        self.scheduler.get_run_queues(),
+        self.scheduler.get_shared_timers(),
+        self.scheduler.get_safepoint(),
+      ),
+      processes: self.processes.clone(),
+    }
   }
 
-  /// Dirty trick to not have to dynamically borrow code server via
-  /// `RefCell<Box<>>` because code server lives just as long as the VM itself.
-  pub fn get_code_server_p(&self) -> *mut CodeServer {
-    let p = &self.code_server as *const CodeServer;
-    p as *mut CodeServer
+  /// Lock the code server for reading, do not keep the guard while calling
+  /// something which may load code.
+  #[inline]
+  pub fn code_server(&self) -> RwLockReadGuard<'_, CodeServer> {
+    self.code_server.read().unwrap()
+  }
+
+  /// Read access for inspecting the code server after a crash, ignores the
+  /// lock poisoning left by a panic during code loading.
+  pub fn code_server_after_crash(&self) -> RwLockReadGuard<'_, CodeServer> {
+    self.code_server.read().unwrap_or_else(|e| e.into_inner())
+  }
+
+  /// Send `message` to `dest`, which is a pid, a registered name or a tuple
+  /// `{Name, Node}` for the local node. A message to a process which does not
+  /// exist is dropped, but a name which is not registered is a `badarg`.
+  pub fn send_message(&self, dest: Term, message: Term) -> RtResult<()> {
+    let pid = if dest.is_local_pid() {
+      dest
+    } else {
+      let name = match process_registry::get_local_name(dest) {
+        Some(name) => name,
+        None => return fail::create::badarg(),
+      };
+      match self.processes.find_registered(name) {
+        Some(pid) if pid.is_local_pid() => pid,
+        _ => return fail::create::badarg(),
+      }
+    };
+    // The receiver may be running on another scheduler, the message goes to
+    // its signal queue and the handle keeps it alive meanwhile
+    if let Some(handle) = self.processes.get(pid) {
+      handle.deliver_message(&self.scheduler, message)?;
+    }
+    Ok(())
+  }
+
+  /// Log the reports about processes which have exited with an abnormal
+  /// reason on this scheduler, with the function where each has stopped.
+  pub fn log_crash_reports(&mut self) {
+    let reports = self.scheduler.take_crash_reports();
+    let cs = self.code_server();
+    for report in reports {
+      let ip = CodePtr::from_ptr(report.ip as *const Word);
+      let current_function = match cs.code_reverse_lookup(ip) {
+        Some(mfa) => format!("{}", mfa),
+        None => String::from("undefined"),
+      };
+      let registered_name = match report.registered_name {
+        Some(name) => format!("{}", name),
+        None => String::from("[]"),
+      };
+      logger::crash_report(&format!(
+        "Process: {} Registered name: {} Current function: {} Exit reason: {}",
+        report.pid, registered_name, current_function, report.reason
+      ));
+    }
+  }
+
+  /// Lock the code server for modification, such as loading a module.
+  #[inline]
+  pub fn code_server_mut(&self) -> RwLockWriteGuard<'_, CodeServer> {
+    self.code_server.write().unwrap()
+  }
+
+  /// Find BEAM code for `mfarity`, the module is loaded if needed. Only the
+  /// loading takes the code server write lock. Loading a new version of a
+  /// module purges its old version, so the literal areas which are no longer
+  /// referenced are freed here too.
+  pub fn lookup_beam_code_and_load(&self, mfarity: &ModFunArity) -> RtResult<CodePtr> {
+    if let Ok(ip) = self.code_server().lookup_beam_code(mfarity) {
+      return Ok(ip);
+    }
+    let result = self.code_server_mut().lookup_beam_code_and_load(mfarity);
+    self.release_unused_literals();
+    result
   }
 
   /// Spawn a new process, create a new pid, register the process and jump to
@@ -70,18 +175,50 @@ impl VM {
     mfargs: &ModFunArgs,
     spawn_opts: &SpawnOptions,
   ) -> RtResult<Term> {
-    let pid_c = self.pid_counter;
-    self.pid_counter += 1;
+    let pid_c = self.pid_counter.fetch_add(1, Ordering::Relaxed);
 
     let pid = Term::make_local_pid(pid_c);
     let mfarity = mfargs.get_mfarity()?;
-    let cs = self.get_code_server_p();
-    let mut p0 = Process::new(pid, parent, &mfarity, spawn_opts, unsafe { &mut (*cs) })?;
+    let ip = self.lookup_beam_code_and_load(&mfarity)?;
+    let mut p0 = Process::new(pid, parent, ip, spawn_opts);
 
     // Error may happen here due to arg term copy error
     p0.set_spawn_args(&mfargs)?;
 
-    self.register_new_process(pid, p0);
+    self.register_new_process(pid, p0, spawn_opts.prio);
+    Ok(pid)
+  }
+
+  /// Spawn a new process which calls `fun` with no arguments. The fun and its
+  /// frozen values are copied into the new process heap.
+  pub fn create_process_fun(
+    &mut self,
+    parent: Term,
+    fun: Term,
+    spawn_opts: &SpawnOptions,
+  ) -> RtResult<Term> {
+    if !fun.is_fun_of_arity(0) {
+      return fail::create::badarg();
+    }
+    if fun.is_export() {
+      let mfa = unsafe { (*boxed::Export::const_from_term(fun)?).exp.mfa };
+      let mfargs = ModFunArgs::with_args_list(mfa.m, mfa.f, Term::nil());
+      return self.create_process(parent, &mfargs, spawn_opts);
+    }
+
+    let closure_p = unsafe { boxed::Closure::mut_from_term(fun)? };
+    let ip = unsafe {
+      match (*closure_p).dst.clone() {
+        Some(p) => p.ptr,
+        None => (*closure_p).update_location(&self.code_server())?,
+      }
+    };
+    let pid_c = self.pid_counter.fetch_add(1, Ordering::Relaxed);
+    let pid = Term::make_local_pid(pid_c);
+    let mut p0 = Process::new(pid, parent, ip, spawn_opts);
+    p0.set_spawn_fun(fun)?;
+
+    self.register_new_process(pid, p0, spawn_opts.prio);
     Ok(pid)
   }
 
@@ -93,22 +230,119 @@ impl VM {
   ) -> RtResult<Term> {
     let mfarity = mfargs.get_mfarity()?;
     // Fail if MFA not found, otherwise continue
-    let _ = self.code_server.lookup_mfa(&mfarity, false)?;
+    let _ = self.code_server_mut().lookup_mfa(&mfarity, false)?;
     spawn_opts.process_flags.set(process_flags::SYSTEM_PROCESS);
     self.create_process(parent, mfargs, &spawn_opts)
   }
 
-  pub fn register_new_process(&mut self, pid: Term, mut proc: Process) {
-    proc.owned_by_scheduler = (&mut self.scheduler) as *mut Scheduler;
-    self.processes.insert(pid, proc);
-    self.scheduler.enqueue(&mut self.processes, pid);
+  /// Register a new process and queue it on the scheduler of this thread.
+  pub fn register_new_process(&mut self, pid: Term, proc: Process, prio: Prio) {
+    let handle = self.processes.insert(pid, proc, prio);
+    self.scheduler.enqueue(&handle);
+  }
+
+  /// Remove the old version of module `m`, then free literal areas of purged
+  /// modules which are no longer referenced.
+  /// Returns: false if `m` had no old version.
+  pub fn purge_module(&mut self, m: Term) -> bool {
+    let purged = self.code_server_mut().purge_module(m);
+    self.release_unused_literals();
+    purged
+  }
+
+  /// Free literal areas of purged modules, which no process and no message
+  /// waiting in a timer refers to. The other schedulers are stopped, so that
+  /// the processes can be read and no literal is passed to another process
+  /// while they are checked. The code server must not be locked by the
+  /// caller.
+  fn release_unused_literals(&self) {
+    if !self.code_server().has_purged_literals() {
+      return;
+    }
+    self.scheduler.stop_the_world(&self.processes, || {
+      let timers = self.scheduler.timers();
+      self.code_server_mut().release_unused_literals(|range| {
+        timers.refers_to(range) || self.processes.any_refers_to(range)
+      });
+    });
+  }
+
+  /// Stop all schedulers after a fatal error, they will return from `tick`.
+  pub fn stop(&self) {
+    self.stopped.store(true, Ordering::SeqCst);
   }
 
   /// Run the VM loop (one time slice), call this repeatedly to run forever.
   /// Time slice ends when a current process yields or when reduction count
   /// reaches zero.
+  /// Returns: false when the VM has stopped or there are no more processes.
   #[inline]
   pub fn tick(&mut self) -> RtResult<bool> {
+    if self.stopped.load(Ordering::Relaxed) {
+      return Ok(false);
+    }
     self.dispatch()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    emulator::{
+      atom,
+      code_srv::literal_area,
+      gen_atoms,
+      heap::{Designation, Heap},
+      module::{Module, VersionedModuleName},
+      timer_wheel::TimerAction,
+    },
+    term::{boxed::reference::next_ref_id, term_builder::tuple_builder::tuple2},
+  };
+
+  /// Load a version of the module `m` with a tuple in its literal area.
+  /// Returns: the literal.
+  fn load_module_with_literal(vm: &VM, m: Term, version: usize) -> Term {
+    let mut module = Module::new(&VersionedModuleName::new(m, version));
+    module.lit_heap = Heap::new(Designation::ModuleLiterals);
+    let literal = tuple2(&mut module.lit_heap, gen_atoms::OK, m).unwrap();
+    vm.code_server_mut().module_loaded(Box::new(module));
+    literal
+  }
+
+  /// A purged literal area stays while a process register or a message in a
+  /// timer refers to it.
+  #[test]
+  fn test_purged_literals_are_freed_when_unreferenced() {
+    let mut args = ErlStartArgs::new(&Vec::new());
+    args.schedulers = Some(1);
+    let mut vm = VM::new(&mut args);
+    let pid = Term::make_local_pid(1);
+    let p = Process::new(pid, Term::nil(), CodePtr::null(), &SpawnOptions::default());
+    vm.register_new_process(pid, p, Prio::Normal);
+    assert_eq!(vm.scheduler.next_process(&vm.processes), Some(pid));
+    let curr_p = unsafe { &mut (*vm.processes.get(pid).unwrap().as_ptr()) };
+
+    let m = atom::from_str("purge_test");
+    let literal = load_module_with_literal(&vm, m, 1);
+    load_module_with_literal(&vm, m, 2);
+    curr_p.context.set_x(0, literal);
+    assert!(vm.purge_module(m));
+    assert!(literal_area::is_literal(literal));
+
+    curr_p.context.set_x(0, Term::nil());
+    let id = next_ref_id();
+    let action = TimerAction::Send {
+      dest: pid,
+      message: literal,
+      fragment: None,
+    };
+    vm.scheduler.timers().start(id, u64::MAX, action);
+    assert!(!vm.purge_module(m));
+    assert!(literal_area::is_literal(literal));
+
+    vm.scheduler.timers().cancel(id);
+    vm.purge_module(m);
+    assert!(!literal_area::is_literal(literal));
+  }
+}
@@ -34,6 +34,10 @@ pub fn badarg_val<T>(val: Term, hp: &mut THeap) -> RtResult<T> {
   generic_tuple2_fail(gen_atoms::BADARG, val, hp)
 }
 
+pub fn badarith<T>() -> RtResult<T> {
+  generic_fail(gen_atoms::BADARITH)
+}
+
 pub fn undef<T>() -> RtResult<T> {
   generic_fail(gen_atoms::UNDEF)
 }
@@ -50,3 +54,11 @@ pub fn badfun_val<T>(val: Term, hp: &mut THeap) -> RtResult<T> {
 pub fn system_limit<T>() -> RtResult<T> {
   generic_fail(gen_atoms::SYSTEM_LIMIT)
 }
+
+pub fn noproc<T>() -> RtResult<T> {
+  generic_fail(gen_atoms::NOPROC)
+}
+
+pub fn timeout_value<T>() -> RtResult<T> {
+  generic_fail(gen_atoms::TIMEOUT_VALUE)
+}
@@ -45,12 +45,15 @@ pub enum RtErr {
   BoxedIsNotAnImport,
   BoxedIsNotATuple,
   BoxedIsNotAMap,
+  BoxedIsNotAReference,
+  BoxedIsNotAFloat,
 
   //--- Binary ---
   CreatingZeroSizedBinary, // can't create 0-sized bin on heap, use immediate {} instead
   CreatingZeroSizedSlice,  // can't create 0-sized slice, use immediate {} instead
   CannotCopyIntoRefbin,    // To copy into binary, resolve ref into heapbin
   CannotCopyIntoBinSlice,  // Can not copy into binary slice, it is const
+  CannotMakeTermFromBinheap, // Binary heap binary is only visible via a refbin
   HeapBinTooSmall(usize, ByteSize), // want bytes, have bytes
   ProcBinTooSmall(usize, ByteSize), // want bytes, have bytes
   BinaryDestinationTooSmall, // bytes/bits will not fit the dst binary
@@ -1,12 +1,13 @@
 use crate::{
   command_line_args::ErlStartArgs,
   emulator::{
-    atom, mfa::ModFunArgs, spawn_options::SpawnOptions, vm::VM,
+    atom, crash_dump, mfa::ModFunArgs, spawn_options::SpawnOptions, vm::VM,
   },
   term::value::*,
 };
 use std::{
   io::{stdout, Write},
+  panic::{self, AssertUnwindSafe},
   thread, time,
 };
 
@@ -33,9 +34,67 @@ pub fn start_emulator(args: &mut ErlStartArgs) {
     .create_process(Term::nil(), &mfargs, &SpawnOptions::default())
     .unwrap();
 
-  println!("Process created. Entering main loop...");
-  while beam_vm.tick().unwrap() {
-    thread::sleep(time::Duration::from_millis(0));
+  // Scheduler 0 runs on this thread, others get their own threads
+  let n_schedulers = beam_vm.scheduler.count();
+  let mut scheduler_threads = Vec::with_capacity(n_schedulers - 1);
+  for id in 1..n_schedulers {
+    let vm = beam_vm.new_scheduler_handle(id);
+    let t = thread::Builder::new()
+      .name(format!("scheduler{}", id + 1))
+      .spawn(move || run_scheduler(vm))
+      .unwrap();
+    scheduler_threads.push(t);
   }
+
+  println!(
+    "Process created. Entering main loop with {} schedulers...",
+    n_schedulers
+  );
+  run_scheduler(beam_vm);
+  for t in scheduler_threads {
+    if let Err(payload) = t.join() {
+      panic::resume_unwind(payload);
+    }
+  }
+  stdout().flush().unwrap();
+}
+
+/// Run one scheduler until there are no processes left, or until the VM is
+/// stopped by a fatal error in any scheduler.
+fn run_scheduler(mut beam_vm: VM) {
+  loop {
+    // A panic while running Erlang code is caught to write the crash dump
+    // and then continues unwinding
+    match panic::catch_unwind(AssertUnwindSafe(|| beam_vm.tick())) {
+      Ok(Ok(true)) => thread::sleep(time::Duration::from_millis(0)),
+      Ok(Ok(false)) => break,
+      Ok(Err(e)) => {
+        let slogan = format!("{:?}", e);
+        beam_vm.stop();
+        emergency_crash_dump(&beam_vm, &slogan);
+        panic!("VM stopped: {}", slogan);
+      }
+      Err(payload) => {
+        let slogan = match payload.downcast_ref::<String>() {
+          Some(s) => s.clone(),
+          None => match payload.downcast_ref::<&str>() {
+            Some(s) => s.to_string(),
+            None => "Rust panic".to_string(),
+          },
+        };
+        beam_vm.stop();
+        emergency_crash_dump(&beam_vm, &slogan);
+        panic::resume_unwind(payload);
+      }
+    }
+  }
+}
+
+/// Write `erl_crash.dump`, the VM is stopping anyway so failure to write the
+/// dump is only reported.
+fn emergency_crash_dump(vm: &VM, slogan: &str) {
   stdout().flush().unwrap();
+  if let Err(e) = crash_dump::write_crash_dump(vm, slogan) {
+    println!("Failed to write the crash dump: {}", e);
+  }
 }
@@ -1,7 +1,10 @@
 use crate::{
-  big,
-  emulator::{arith::multiplication, process::Process, vm::VM},
-  fail::RtResult,
+  emulator::{
+    arith::{addition, bitwise, division, float, multiplication},
+    process::Process,
+    vm::VM,
+  },
+  fail::{self, RtResult},
   term::value::*,
 };
 
@@ -17,18 +20,7 @@ pub fn nativefun_minus_2(
   args: &[Term],
 ) -> RtResult<Term> {
   assert_eq!(args.len(), 2, "{}'-'/2 takes 2 args", module());
-  let a: Term = args[0];
-  let b: Term = args[1];
-  if a.is_small() {
-    if b.is_small() {
-      subtract_two_small(cur_proc, a, b)
-    } else {
-      // TODO: See Erlang OTP erl_arith.c function erts_mixed_minus
-      unimplemented!("{}subtract: b={} other than small", module(), b)
-    }
-  } else {
-    unimplemented!("{}subtract: a={} other than small", module(), a)
-  }
+  addition::subtract(cur_proc.get_heap_mut(), args[0], args[1])
 }
 
 /// Addition for 2 mixed terms.
@@ -37,46 +29,31 @@ pub fn nativefun_plus_2(
   cur_proc: &mut Process,
   args: &[Term],
 ) -> RtResult<Term> {
-  assert_eq!(args.len(), 2, "{}ubif_sminus_2_2 takes 2 args", module());
-  let a: Term = args[0];
-  let b: Term = args[1];
-  if a.is_small() {
-    if b.is_small() {
-      add_two_small(cur_proc, a, b)
-    } else {
-      // TODO: See Erlang OTP erl_arith.c function erts_mixed_plus
-      unimplemented!("{}subtract: b={} other than small", module(), b)
-    }
-  } else {
-    unimplemented!("{}subtract: a={} other than small", module(), a)
-  }
+  assert_eq!(args.len(), 2, "{}'+'/2 takes 2 args", module());
+  addition::add(cur_proc.get_heap_mut(), args[0], args[1])
 }
 
-/// So the check above has concluded that `a` and `b` are both small integers.
-/// Implement subtraction, possibly creating a big integer.
-fn subtract_two_small(cur_proc: &mut Process, a: Term, b: Term) -> RtResult<Term> {
-  // Both a and b are small, we've got an easy time
-  let iresult = a.get_small_signed() - b.get_small_signed();
-  // Even better: the result is also a small
-  if Term::small_fits(iresult) {
-    return Ok(Term::make_small_signed(iresult));
-  }
-
-  create_bigint(cur_proc, iresult)
+/// Unary minus.
+pub fn nativefun_minus_1(
+  _vm: &mut VM,
+  cur_proc: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_eq!(args.len(), 1, "{}'-'/1 takes 1 arg", module());
+  addition::negate(cur_proc.get_heap_mut(), args[0])
 }
 
-/// So the check above has concluded that `a` and `b` are both small integers.
-/// Implement addition, possibly creating a big integer.
-fn add_two_small(cur_proc: &mut Process, a: Term, b: Term) -> RtResult<Term> {
-  // Both a and b are small, we've got an easy time.
-  // The overflow in addition of two smalls will always fit Rust integer because
-  // small use less bits than a Rust integer.
-  let iresult = a.get_small_signed() + b.get_small_signed();
-  // Even better: the result is also a small
-  if Term::small_fits(iresult) {
-    return Ok(Term::make_small_signed(iresult));
+/// Unary plus, returns the number unchanged.
+pub fn nativefun_plus_1(
+  _vm: &mut VM,
+  _cur_proc: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_eq!(args.len(), 1, "{}'+'/1 takes 1 arg", module());
+  if !args[0].is_number() {
+    return fail::create::badarith();
   }
-  create_bigint(cur_proc, iresult)
+  Ok(args[0])
 }
 
 /// Multiplication for 2 mixed terms.
@@ -91,9 +68,98 @@ pub fn nativefun_multiply_2(
   return multiplication::multiply(cur_proc.get_heap_mut(), a, b);
 }
 
-// TODO: shorten, use only heap of the process, inline, move to a lib module in arith
-fn create_bigint(cur_proc: &mut Process, iresult: isize) -> RtResult<Term> {
-  // We're out of luck - the result is not a small, but we have BigInt!
-  let p = big::from_isize(cur_proc.get_heap_mut(), iresult)?;
-  Ok(p)
+/// Division of two numbers, the result is always a float.
+pub fn nativefun_divide_2(
+  _vm: &mut VM,
+  cur_proc: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_eq!(args.len(), 2, "{}'/'/2 takes 2 args", module());
+  float::divide(cur_proc.get_heap_mut(), args[0], args[1])
+}
+
+/// Absolute value of a number.
+pub fn nativefun_abs_1(
+  _vm: &mut VM,
+  cur_proc: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_eq!(args.len(), 1, "{}abs/1 takes 1 arg", module());
+  addition::abs(cur_proc.get_heap_mut(), args[0])
+}
+
+/// Integer division, truncates towards zero.
+pub fn nativefun_div_2(
+  _vm: &mut VM,
+  cur_proc: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_eq!(args.len(), 2, "{}'div'/2 takes 2 args", module());
+  division::div(cur_proc.get_heap_mut(), args[0], args[1])
+}
+
+/// Integer remainder, has the sign of the dividend.
+pub fn nativefun_rem_2(
+  _vm: &mut VM,
+  cur_proc: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_eq!(args.len(), 2, "{}'rem'/2 takes 2 args", module());
+  division::rem(cur_proc.get_heap_mut(), args[0], args[1])
+}
+
+pub fn nativefun_band_2(
+  _vm: &mut VM,
+  cur_proc: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_eq!(args.len(), 2, "{}'band'/2 takes 2 args", module());
+  bitwise::band(cur_proc.get_heap_mut(), args[0], args[1])
+}
+
+pub fn nativefun_bor_2(
+  _vm: &mut VM,
+  cur_proc: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_eq!(args.len(), 2, "{}'bor'/2 takes 2 args", module());
+  bitwise::bor(cur_proc.get_heap_mut(), args[0], args[1])
+}
+
+pub fn nativefun_bxor_2(
+  _vm: &mut VM,
+  cur_proc: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_eq!(args.len(), 2, "{}'bxor'/2 takes 2 args", module());
+  bitwise::bxor(cur_proc.get_heap_mut(), args[0], args[1])
+}
+
+pub fn nativefun_bnot_1(
+  _vm: &mut VM,
+  cur_proc: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_eq!(args.len(), 1, "{}'bnot'/1 takes 1 arg", module());
+  bitwise::bnot(cur_proc.get_heap_mut(), args[0])
+}
+
+/// Bit shift left, a negative shift amount shifts right.
+pub fn nativefun_bsl_2(
+  _vm: &mut VM,
+  cur_proc: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_eq!(args.len(), 2, "{}'bsl'/2 takes 2 args", module());
+  bitwise::bsl(cur_proc.get_heap_mut(), args[0], args[1])
+}
+
+/// Arithmetic bit shift right, a negative shift amount shifts left.
+pub fn nativefun_bsr_2(
+  _vm: &mut VM,
+  cur_proc: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_eq!(args.len(), 2, "{}'bsr'/2 takes 2 args", module());
+  bitwise::bsr(cur_proc.get_heap_mut(), args[0], args[1])
 }
@@ -2,7 +2,8 @@ use crate::{
   emulator::gen_atoms,
   native_fun::{
     erlang::{
-      arithmetic::*, compare::*, list::*, predicate::*, process::*, sys::*, tuple::*,
+      arithmetic::*, compare::*, list::*, monitor::*, predicate::*, process::*,
+      process_dict::*, process_info::*, spawn::*, sys::*, timer::*, tuple::*,
       type_conversions::*, binary::*,
     },
     fn_entry::NativeFnEntry,
@@ -13,9 +14,14 @@ use crate::{
 pub mod arithmetic;
 pub mod compare;
 pub mod list;
+pub mod monitor;
 pub mod predicate;
 pub mod process;
+pub mod process_dict;
+pub mod process_info;
+pub mod spawn;
 pub mod sys;
+pub mod timer;
 pub mod tuple;
 pub mod type_conversions;
 pub mod binary;
@@ -23,10 +29,14 @@ pub mod binary;
 pub fn new() -> NativeModule {
   let mut m = NativeModule::new(gen_atoms::ERLANG);
   let fn_entries: Vec<NativeFnEntry> = vec![
+    NativeFnEntry::with_str("!", 2, NfErlangSend2::_f),
     NativeFnEntry::with_str("*", 2, nativefun_multiply_2),
+    NativeFnEntry::with_str("+", 1, nativefun_plus_1),
     NativeFnEntry::with_str("+", 2, nativefun_plus_2),
     NativeFnEntry::with_str("++", 2, NfErlangPlusPlus2::_f),
+    NativeFnEntry::with_str("-", 1, nativefun_minus_1),
     NativeFnEntry::with_str("-", 2, nativefun_minus_2),
+    NativeFnEntry::with_str("/", 2, nativefun_divide_2),
     NativeFnEntry::with_str("/=", 2, nativefun_notequal_2),
     NativeFnEntry::with_str("<", 2, nativefun_lessthan_2),
     NativeFnEntry::with_str("=/=", 2, nativefun_notequal_exact_2),
@@ -35,29 +45,100 @@ pub fn new() -> NativeModule {
     NativeFnEntry::with_str("==", 2, nativefun_equalequal_2),
     NativeFnEntry::with_str(">", 2, nativefun_greaterthan_2),
     NativeFnEntry::with_str(">=", 2, nativefun_greaterequal_2),
+    NativeFnEntry::with_str("abs", 1, nativefun_abs_1),
     NativeFnEntry::with_str("atom_to_list", 1, NfErlangA2List2::_f),
+    NativeFnEntry::with_str("band", 2, nativefun_band_2),
+    NativeFnEntry::with_str("binary_to_float", 1, NfErlangBin2Float1::_f),
+    NativeFnEntry::with_str("binary_to_integer", 1, NfErlangBin2Int1::_f),
+    NativeFnEntry::with_str("binary_to_integer", 2, NfErlangBin2Int2::_f),
+    NativeFnEntry::with_str("bnot", 1, nativefun_bnot_1),
+    NativeFnEntry::with_str("bor", 2, nativefun_bor_2),
+    NativeFnEntry::with_str("bsl", 2, nativefun_bsl_2),
+    NativeFnEntry::with_str("bsr", 2, nativefun_bsr_2),
+    NativeFnEntry::with_str("bxor", 2, nativefun_bxor_2),
+    NativeFnEntry::with_str("cancel_timer", 1, NfErlangCancelTimer1::_f),
+    NativeFnEntry::with_str("cancel_timer", 2, NfErlangCancelTimer2::_f),
+    NativeFnEntry::with_str("ceil", 1, NfErlangCeil1::_f),
+    NativeFnEntry::with_str("demonitor", 1, NfErlangDemonitor1::_f),
+    NativeFnEntry::with_str("demonitor", 2, NfErlangDemonitor2::_f),
+    NativeFnEntry::with_str("div", 2, nativefun_div_2),
+    NativeFnEntry::with_str("erase", 0, NfErlangErase0::_f),
+    NativeFnEntry::with_str("erase", 1, NfErlangErase1::_f),
     NativeFnEntry::with_str("error", 1, NfErlangError1::_f),
     NativeFnEntry::with_str("error", 2, NfErlangError2::_f),
+    NativeFnEntry::with_str("exit", 1, NfErlangExit1::_f),
+    NativeFnEntry::with_str("exit", 2, NfErlangExit2::_f),
+    NativeFnEntry::with_str("float", 1, NfErlangFloat1::_f),
+    NativeFnEntry::with_str("float_to_binary", 1, NfErlangFloat2Bin1::_f),
+    NativeFnEntry::with_str("float_to_binary", 2, NfErlangFloat2Bin2::_f),
+    NativeFnEntry::with_str("float_to_list", 1, NfErlangFloat2List1::_f),
+    NativeFnEntry::with_str("float_to_list", 2, NfErlangFloat2List2::_f),
+    NativeFnEntry::with_str("floor", 1, NfErlangFloor1::_f),
+    NativeFnEntry::with_str("get", 0, NfErlangGet0::_f),
+    NativeFnEntry::with_str("get", 1, NfErlangGet1::_f),
+    NativeFnEntry::with_str("get_keys", 0, NfErlangGetKeys0::_f),
+    NativeFnEntry::with_str("get_keys", 1, NfErlangGetKeys1::_f),
     NativeFnEntry::with_str("hd", 1, NfErlangHd1::_f),
-    NativeFnEntry::with_str("integer_to_list", 1, NfErlangInt2List2::_f),
+    NativeFnEntry::with_str("hibernate", 3, NfErlangHibernate3::_f),
+    NativeFnEntry::with_str("integer_to_binary", 1, NfErlangInt2Bin1::_f),
+    NativeFnEntry::with_str("integer_to_binary", 2, NfErlangInt2Bin2::_f),
+    NativeFnEntry::with_str("integer_to_list", 1, NfErlangInt2List1::_f),
+    NativeFnEntry::with_str("integer_to_list", 2, NfErlangInt2List2::_f),
     NativeFnEntry::with_str("is_boolean", 1, nativefun_is_boolean_1),
     NativeFnEntry::with_str("is_process_alive", 1, NfErlangIsPAlive1::_f),
+    NativeFnEntry::with_str("is_reference", 1, nativefun_is_reference_1),
     NativeFnEntry::with_str("length", 1, NfErlangLength1::_f),
+    NativeFnEntry::with_str("link", 1, NfErlangLink1::_f),
     NativeFnEntry::with_str("list_to_binary", 1, NfErlangL2b1::_f),
+    NativeFnEntry::with_str("list_to_float", 1, NfErlangList2Float1::_f),
+    NativeFnEntry::with_str("list_to_integer", 1, NfErlangList2Int1::_f),
+    NativeFnEntry::with_str("list_to_integer", 2, NfErlangList2Int2::_f),
     NativeFnEntry::with_str("load_nif", 2, NfErlangLoadNif2::_f),
     NativeFnEntry::with_str("make_fun", 3, nativefun_make_fun_3),
+    NativeFnEntry::with_str("make_ref", 0, NfErlangMakeRef0::_f),
+    NativeFnEntry::with_str("monitor", 2, NfErlangMonitor2::_f),
     NativeFnEntry::with_str("nif_error", 1, NfErlangNifError1::_f),
     NativeFnEntry::with_str("nif_error", 2, NfErlangNifError2::_f),
+    NativeFnEntry::with_str("process_info", 1, NfErlangProcessInfo1::_f),
+    NativeFnEntry::with_str("process_info", 2, NfErlangProcessInfo2::_f),
     NativeFnEntry::with_str("process_flag", 2, NfErlangProcFlag2::_f),
     NativeFnEntry::with_str("process_flag", 3, NfErlangProcFlag3::_f),
+    NativeFnEntry::with_str("purge_module", 1, NfErlangPurgeModule1::_f),
+    NativeFnEntry::with_str("put", 2, NfErlangPut2::_f),
+    NativeFnEntry::with_str("read_timer", 1, NfErlangReadTimer1::_f),
     NativeFnEntry::with_str("register", 2, NfErlangRegister2::_f),
     NativeFnEntry::with_str("registered", 0, NfErlangRegistered0::_f),
+    NativeFnEntry::with_str("rem", 2, nativefun_rem_2),
+    NativeFnEntry::with_str("round", 1, NfErlangRound1::_f),
     NativeFnEntry::with_str("self", 0, NfErlangSelf0::_f),
+    NativeFnEntry::with_str("send", 2, NfErlangSend2::_f),
+    NativeFnEntry::with_str("send_after", 3, NfErlangSendAfter3::_f),
+    NativeFnEntry::with_str("send_after", 4, NfErlangSendAfter4::_f),
     NativeFnEntry::with_str("size", 1, NfErlangSize1::_f),
     NativeFnEntry::with_str("bit_size", 1, NfErlangBitSize1::_f),
     NativeFnEntry::with_str("byte_size", 1, NfErlangByteSize1::_f),
+    NativeFnEntry::with_str("spawn", 1, NfErlangSpawn1::_f),
+    NativeFnEntry::with_str("spawn", 2, NfErlangSpawn2::_f),
     NativeFnEntry::with_str("spawn", 3, NfErlangSpawn3::_f),
+    NativeFnEntry::with_str("spawn", 4, NfErlangSpawn4::_f),
+    NativeFnEntry::with_str("spawn_link", 1, NfErlangSpawnLink1::_f),
+    NativeFnEntry::with_str("spawn_link", 2, NfErlangSpawnLink2::_f),
+    NativeFnEntry::with_str("spawn_link", 3, NfErlangSpawnLink3::_f),
+    NativeFnEntry::with_str("spawn_link", 4, NfErlangSpawnLink4::_f),
+    NativeFnEntry::with_str("spawn_monitor", 1, NfErlangSpawnMonitor1::_f),
+    NativeFnEntry::with_str("spawn_monitor", 3, NfErlangSpawnMonitor3::_f),
+    NativeFnEntry::with_str("spawn_opt", 2, NfErlangSpawnOpt2::_f),
+    NativeFnEntry::with_str("spawn_opt", 3, NfErlangSpawnOpt3::_f),
+    NativeFnEntry::with_str("spawn_opt", 4, NfErlangSpawnOpt4::_f),
+    NativeFnEntry::with_str("spawn_opt", 5, NfErlangSpawnOpt5::_f),
+    NativeFnEntry::with_str("start_timer", 3, NfErlangStartTimer3::_f),
+    NativeFnEntry::with_str("start_timer", 4, NfErlangStartTimer4::_f),
+    NativeFnEntry::with_str("term_to_binary", 1, NfErlangT2b1::_f),
     NativeFnEntry::with_str("tl", 1, NfErlangTl1::_f),
+    NativeFnEntry::with_str("trunc", 1, NfErlangTrunc1::_f),
+    NativeFnEntry::with_str("unlink", 1, NfErlangUnlink1::_f),
+    NativeFnEntry::with_str("unregister", 1, NfErlangUnregister1::_f),
+    NativeFnEntry::with_str("whereis", 1, NfErlangWhereis1::_f),
   ];
   m.init_with(fn_entries.iter());
   m
@@ -0,0 +1,177 @@
+//! Process monitors: `monitor/2`, `demonitor/1,2`. The monitored process
+//! keeps the list of its watchers and sends each of them a 'DOWN' message
+//! when it exits (see `Scheduler::terminate_process`).
+use crate::{
+  emulator::{
+    gen_atoms,
+    process::{MonitorPeer, Process},
+    process_registry,
+    signal::{self, Signal},
+    vm::VM,
+  },
+  fail::{self, RtResult},
+  term::{
+    boxed::{
+      self,
+      reference::{self, RefId},
+    },
+    value::{cons, *},
+  },
+};
+
+/// Find the process to monitor. `item` is a pid, a registered name or a tuple
+/// `{Name, Node}` for the local node.
+/// Returns: the pid if the process exists, and the name if it was given.
+fn resolve_monitor_target(
+  vm: &VM,
+  item: Term,
+) -> RtResult<(Option<Term>, Option<Term>)> {
+  if item.is_local_pid() {
+    return Ok((Some(item), None));
+  }
+  let name = match process_registry::get_local_name(item) {
+    Some(name) => name,
+    None => return fail::create::badarg(),
+  };
+  let pid = vm.processes.find_registered(name).filter(|p| p.is_local_pid());
+  Ok((pid, Some(name)))
+}
+
+// Start monitoring a process, when it exits the caller receives
+// `{'DOWN', Ref, process, Item, Reason}`. For a process which does not exist
+// the message arrives immediately with reason `noproc`.
+// Spec: erlang:monitor(process, Item) -> Ref
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:monitor/2", struct_name: NfErlangMonitor2, arity: 2,
+  invoke: { monitor_2(vm, curr_p, kind, item) },
+  args: atom(kind), term(item),
+);
+
+pub fn monitor_2(
+  vm: &mut VM,
+  curr_p: &mut Process,
+  kind: Term,
+  item: Term,
+) -> RtResult<Term> {
+  if kind != gen_atoms::PROCESS {
+    return fail::create::badarg();
+  }
+  let (pid, name) = resolve_monitor_target(vm, item)?;
+  let ref_id = reference::next_ref_id();
+  let ref_term = boxed::Reference::create_into(curr_p.get_heap_mut(), ref_id)?;
+
+  if let Some(pid) = pid {
+    // Monitoring self is allowed, but it will never trigger
+    if pid == curr_p.pid {
+      let peer = MonitorPeer { pid, name };
+      curr_p.monitors.insert(ref_id, peer);
+      curr_p.monitored_by.insert(ref_id, peer);
+      return Ok(ref_term);
+    }
+    let signal = Signal::Monitor {
+      from: curr_p.pid,
+      ref_id,
+      name,
+    };
+    if vm.scheduler.send_signal(&vm.processes, pid, signal) {
+      curr_p.monitors.insert(ref_id, MonitorPeer { pid, name });
+      return Ok(ref_term);
+    }
+  }
+
+  // No such process
+  let hp = curr_p.get_heap_mut();
+  let message = signal::make_down_message(hp, ref_term, item, name, gen_atoms::NOPROC)?;
+  curr_p.deliver_message(message)?;
+  Ok(ref_term)
+}
+
+// Spec: erlang:demonitor(Ref) -> true
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:demonitor/1", struct_name: NfErlangDemonitor1, arity: 1,
+  invoke: {
+    demonitor(vm, curr_p, get_ref_id(monitor_ref)?);
+    Ok(gen_atoms::TRUE)
+  },
+  args: term(monitor_ref),
+);
+
+// Removes the monitor. With `flush` option also removes the 'DOWN' message for
+// this monitor from the message queue. With `info` option returns whether the
+// monitor was found and removed, false means that the 'DOWN' message has been
+// delivered (or flushed).
+// Spec: erlang:demonitor(Ref, [flush | info]) -> boolean()
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:demonitor/2", struct_name: NfErlangDemonitor2, arity: 2,
+  invoke: { demonitor_2(vm, curr_p, monitor_ref, opts) },
+  args: term(monitor_ref), list(opts),
+);
+
+pub fn demonitor_2(
+  vm: &mut VM,
+  curr_p: &mut Process,
+  monitor_ref: Term,
+  opts: Term,
+) -> RtResult<Term> {
+  let ref_id = get_ref_id(monitor_ref)?;
+  let mut flush = false;
+  let mut info = false;
+  let tail = cons::for_each(opts, |opt| {
+    match opt {
+      gen_atoms::FLUSH => flush = true,
+      gen_atoms::INFO => info = true,
+      _ => return fail::create::badarg(),
+    }
+    Ok(())
+  })?;
+  if let Some(t) = tail {
+    if t != Term::nil() {
+      return fail::create::badarg();
+    }
+  }
+
+  let removed = demonitor(vm, curr_p, ref_id);
+  if flush {
+    curr_p.mailbox.remove_matching(|m| is_down_message_for(m, ref_id));
+  }
+  if info {
+    return Ok(Term::make_bool(removed));
+  }
+  Ok(gen_atoms::TRUE)
+}
+
+fn get_ref_id(monitor_ref: Term) -> RtResult<RefId> {
+  match boxed::Reference::get_id(monitor_ref) {
+    Some(id) => Ok(id),
+    None => fail::create::badarg(),
+  }
+}
+
+/// Remove the monitor and tell the monitored process to forget it.
+/// Returns: false if there was no such monitor.
+fn demonitor(vm: &mut VM, curr_p: &mut Process, ref_id: RefId) -> bool {
+  let target = match curr_p.monitors.remove(&ref_id) {
+    Some(t) => t,
+    None => return false,
+  };
+  if target.pid == curr_p.pid {
+    curr_p.monitored_by.remove(&ref_id);
+  } else {
+    let signal = Signal::Demonitor { ref_id };
+    vm.scheduler.send_signal(&vm.processes, target.pid, signal);
+  }
+  true
+}
+
+/// Check whether `m` is a 'DOWN' message for the monitor `ref_id`.
+fn is_down_message_for(m: Term, ref_id: RefId) -> bool {
+  if !m.is_tuple() {
+    return false;
+  }
+  let tuple_p = m.get_tuple_ptr();
+  unsafe {
+    (*tuple_p).get_arity() == 5
+      && (*tuple_p).get_element(0) == gen_atoms::DOWN_UPPER
+      && boxed::Reference::get_id((*tuple_p).get_element(1)) == Some(ref_id)
+  }
+}
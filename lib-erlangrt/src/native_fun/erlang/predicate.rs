@@ -19,3 +19,13 @@ pub fn nativefun_is_boolean_1(
   assert_arity("erlang:is_boolean", 1, args);
   Ok(Term::make_bool(args[0].is_bool()))
 }
+
+/// Return `true` if the value is a local or an external reference
+pub fn nativefun_is_reference_1(
+  _vm: &mut VM,
+  _curr_p: &mut Process,
+  args: &[Term],
+) -> RtResult<Term> {
+  assert_arity("erlang:is_reference", 1, args);
+  Ok(Term::make_bool(args[0].is_ref()))
+}
@@ -2,15 +2,21 @@ use crate::{
   defs::exc_type::ExceptionType,
   emulator::{
     gen_atoms,
-    mfa::{ModFunArity, ModFunArgs},
+    heap::{heap_size::MaxHeapSize, heap_trait::THeap},
+    mfa::{ModFunArgs, ModFunArity},
     process::Process,
     process_flags,
-    spawn_options::SpawnOptions,
+    signal::Signal,
+    spawn_options::MessageQueueLocation,
     vm::VM,
   },
   fail::{self, RtErr, RtResult},
   native_fun::assert_arity,
-  term::{boxed, value::*},
+  term::{
+    boxed,
+    term_builder::{list_builder::build_list_from_slice, tuple_builder::tuple3},
+    value::*,
+  },
 };
 
 #[allow(dead_code)]
@@ -43,22 +49,131 @@ pub fn nativefun_make_fun_3(
   Ok(expt)
 }
 
-// Creates a new process specified by `module:function/arity` with `args`
-// (args are passed as list), `arity` is length of args list.
-// Spec: erlang:spawn(mod, fun, args:list)
-define_nativefun!(vm, _proc, _args,
-  name: "erlang:spawn/3", struct_name: NfErlangSpawn3, arity: 3,
+// Creates a link to another process. If the process does not exist, the
+// caller gets `{'EXIT', Pid, noproc}` if it traps exits, otherwise a `noproc`
+// error.
+// Spec: erlang:link(pid) -> true
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:link/1", struct_name: NfErlangLink1, arity: 1,
+  invoke: { link_1(vm, curr_p, pid) },
+  args: pid(pid),
+);
+
+pub fn link_1(vm: &mut VM, curr_p: &mut Process, pid: Term) -> RtResult<Term> {
+  if !pid.is_local_pid() {
+    return fail::create::badarg();
+  }
+  if pid == curr_p.pid || curr_p.links.contains(&pid) {
+    return Ok(gen_atoms::TRUE);
+  }
+  let signal = Signal::Link { from: curr_p.pid };
+  if vm.scheduler.send_signal(&vm.processes, pid, signal) {
+    curr_p.links.insert(pid);
+    return Ok(gen_atoms::TRUE);
+  }
+
+  if !curr_p.process_flags.get(process_flags::TRAP_EXIT) {
+    return fail::create::noproc();
+  }
+  let hp = curr_p.get_heap_mut();
+  let message = tuple3(hp, gen_atoms::EXIT_UPPER, pid, gen_atoms::NOPROC)?;
+  curr_p.deliver_message(message)?;
+  Ok(gen_atoms::TRUE)
+}
+
+// Removes a link to another process, if there was one.
+// Spec: erlang:unlink(pid) -> true
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:unlink/1", struct_name: NfErlangUnlink1, arity: 1,
+  invoke: {
+    if curr_p.links.remove(&pid) {
+      let signal = Signal::Unlink { from: curr_p.pid };
+      vm.scheduler.send_signal(&vm.processes, pid, signal);
+    }
+    Ok(gen_atoms::TRUE)
+  },
+  args: pid(pid),
+);
+
+// Stop the calling process with an exception of class `exit`, which can be
+// caught.
+// Spec: erlang:exit(Reason) -> no_return()
+define_nativefun!(_vm, _proc, _args,
+  name: "erlang:exit/1", struct_name: NfErlangExit1, arity: 1,
+  invoke: { Err(RtErr::Exception(ExceptionType::Exit, reason)) },
+  args: term(reason),
+);
+
+// Send an exit signal to a process, which is not linked to the caller. With
+// reason `kill` the process is killed even if it traps exits.
+// Spec: erlang:exit(Pid, Reason) -> true
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:exit/2", struct_name: NfErlangExit2, arity: 2,
+  invoke: { exit_2(vm, curr_p, pid, reason) },
+  args: pid(pid), term(reason),
+);
+
+pub fn exit_2(
+  vm: &mut VM,
+  curr_p: &mut Process,
+  pid: Term,
+  reason: Term,
+) -> RtResult<Term> {
+  if !pid.is_local_pid() {
+    return fail::create::badarg();
+  }
+  let signal = Signal::new_exit(curr_p.pid, reason, false)?;
+  if pid != curr_p.pid {
+    vm.scheduler.send_signal(&vm.processes, pid, signal);
+    return Ok(gen_atoms::TRUE);
+  }
+
+  // The signal to self takes effect immediately, and the exit can not be
+  // caught, same as for an exit signal from another process
+  if let Signal::Exit {
+    from,
+    reason,
+    message,
+    fragment,
+    linked,
+  } = signal
+  {
+    if let Some(exit_reason) =
+      curr_p.handle_exit_signal(from, reason, message, fragment, linked)
+    {
+      return Err(RtErr::Exception(ExceptionType::Panic, exit_reason));
+    }
+  }
+  Ok(gen_atoms::TRUE)
+}
+
+// Put the calling process into a wait state with a minimal heap, when a
+// message arrives it continues with `apply(M, F, Args)` and an empty stack.
+// The process is compacted here (see `Process::hibernate`), the call site
+// then yields with `YieldType::Hibernate` instead of storing the result.
+// Spec: erlang:hibernate(Module, Function, Args) -> no_return()
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:hibernate/3", struct_name: NfErlangHibernate3, arity: 3,
   invoke: {
     let mfargs = ModFunArgs::with_args_list(m, f, args);
-    let spawn_opts = SpawnOptions::default();
-    vm.create_process(Term::nil(), &mfargs, &spawn_opts)
+    let mut call_args = Vec::new();
+    mfargs.for_each_arg(|arg| {
+      call_args.push(arg);
+      Ok(())
+    })?;
+    let ip = match vm.lookup_beam_code_and_load(&mfargs.get_mfarity()?) {
+      Ok(ip) => ip,
+      Err(_) => return fail::create::undef(),
+    };
+    curr_p.hibernate(ip, &call_args);
+    Ok(gen_atoms::OK)
   },
   args: atom(m), atom(f), list(args),
 );
 
 define_nativefun!(vm, _proc, args,
   name: "erlang:is_process_alive/1", struct_name: NfErlangIsPAlive1, arity: 1,
-  invoke: { Ok(Term::make_bool(vm.processes.lookup_pid(pid).is_some())) },
+  invoke: { Ok(Term::make_bool(vm.processes.exists(pid))) },
   args: pid(pid),
 );
 
@@ -73,54 +188,319 @@ define_nativefun!(vm, _proc, _args,
 
 pub fn register_2(vm: &mut VM, name: Term, pid_or_port: Term) -> RtResult<Term> {
   // The define_nativefun! macro will check that the arguments are atom and pid/port
-  // but here we additionally check if the name is not `undefined` and does not exist
-  if name == gen_atoms::UNDEFINED || vm.processes.find_registered(name).is_some() {
+  // but here we additionally check the rules of `ProcessRegistry::register_name`
+  if !vm.processes.register_name(name, pid_or_port) {
     return fail::create::badarg();
   }
-  vm.processes.register_name(name, pid_or_port);
   Ok(gen_atoms::TRUE)
 }
 
-define_nativefun!(_vm, _proc, _args,
+// Spec: erlang:unregister(RegName) -> true
+define_nativefun!(vm, _proc, _args,
+  name: "erlang:unregister/1", struct_name: NfErlangUnregister1, arity: 1,
+  invoke: {
+    match vm.processes.unregister_name(name) {
+      Some(_) => Ok(gen_atoms::TRUE),
+      None => fail::create::badarg(),
+    }
+  },
+  args: atom(name),
+);
+
+// Spec: erlang:whereis(RegName) -> pid() | port() | undefined
+define_nativefun!(vm, _proc, _args,
+  name: "erlang:whereis/1", struct_name: NfErlangWhereis1, arity: 1,
+  invoke: { Ok(vm.processes.find_registered(name).unwrap_or(gen_atoms::UNDEFINED)) },
+  args: atom(name),
+);
+
+// Spec: erlang:registered() -> [RegName]
+define_nativefun!(vm, curr_p, _args,
   name: "erlang:registered/0", struct_name: NfErlangRegistered0, arity: 0,
-  invoke: { panic!("not implemented") },
+  invoke: {
+    let names = vm.processes.registered_names();
+    unsafe { build_list_from_slice(&names, curr_p.get_heap_mut()) }
+  },
   args:
 );
 
+// Send a message to a pid or a registered name, same as the `!` operator.
+// Spec: erlang:send(Dest, Msg) -> Msg
+define_nativefun!(vm, _proc, _args,
+  name: "erlang:send/2", struct_name: NfErlangSend2, arity: 2,
+  invoke: {
+    vm.send_message(dest, message)?;
+    Ok(message)
+  },
+  args: term(dest), term(message),
+);
+
 define_nativefun!(_vm, proc, args,
   name: "erlang:process_flag/2", struct_name: NfErlangProcFlag2, arity: 2,
   invoke: { do_erlang_process_flag(proc, flag, value) },
-  args: atom(flag), bool(value),
+  args: atom(flag), term(value),
 );
 
-// Set a supported process flag for some other process.
-define_nativefun!(vm, _proc, args,
+// Set a supported process flag for some other process. Flags of another
+// process can not be changed, it may be running on another scheduler (OTP only
+// allows `save_calls` there, which is not supported), so only the calling
+// process itself is accepted.
+define_nativefun!(_vm, curr_p, args,
   name: "erlang:process_flag/3", struct_name: NfErlangProcFlag3, arity: 3,
-  invoke: { process_flag_3(vm, pid, flag, value) },
-  args: pid(pid), atom(flag), bool(value),
+  invoke: { process_flag_3(curr_p, pid, flag, value) },
+  args: pid(pid), atom(flag), term(value),
 );
 
 pub fn process_flag_3(
-  vm: &mut VM,
+  curr_p: &mut Process,
   pid: Term,
   flag: Term,
-  value: bool,
+  value: Term,
 ) -> RtResult<Term> {
-  let proc_p = vm.processes.unsafe_lookup_pid_mut(pid);
-  if proc_p.is_null() {
+  if pid != curr_p.pid {
     return fail::create::badarg();
   }
-  let p = unsafe { &mut (*proc_p) };
-  do_erlang_process_flag(p, flag, value)
+  do_erlang_process_flag(curr_p, flag, value)
 }
 
 #[inline]
-fn do_erlang_process_flag(p: &mut Process, flag: Term, value: bool) -> RtResult<Term> {
+fn do_erlang_process_flag(p: &mut Process, flag: Term, value: Term) -> RtResult<Term> {
   match flag {
-    gen_atoms::TRAP_EXIT => Ok(Term::make_bool(
-      p.process_flags
-        .read_and_set(process_flags::TRAP_EXIT, value),
-    )),
+    gen_atoms::TRAP_EXIT => {
+      let old_value = p
+        .process_flags
+        .read_and_set(process_flags::TRAP_EXIT, get_bool_arg(value)?);
+      Ok(Term::make_bool(old_value))
+    }
+    gen_atoms::MIN_HEAP_SIZE => {
+      let size = get_heap_size_arg(value)?;
+      let old_value = p.get_min_heap_size();
+      p.set_min_heap_size(size);
+      Ok(Term::make_small_unsigned(old_value))
+    }
+    gen_atoms::MIN_BIN_VHEAP_SIZE => {
+      let size = get_heap_size_arg(value)?;
+      let old_value = p.get_min_bin_vheap_size();
+      p.set_min_bin_vheap_size(size);
+      Ok(Term::make_small_unsigned(old_value))
+    }
+    gen_atoms::MAX_HEAP_SIZE => {
+      let max_heap_size = parse_max_heap_size(value, p.max_heap_size)?;
+      if max_heap_size.size != 0 && max_heap_size.size < p.get_min_heap_size() {
+        return fail::create::badarg();
+      }
+      let old_value = make_max_heap_size_map(p.max_heap_size, p.get_heap_mut())?;
+      p.max_heap_size = max_heap_size;
+      Ok(old_value)
+    }
+    gen_atoms::MESSAGE_QUEUE_DATA => {
+      let location = get_message_queue_data_arg(value)?;
+      let old_value = match p.mailbox.location {
+        MessageQueueLocation::OnHeap => gen_atoms::ON_HEAP,
+        MessageQueueLocation::OffHeap => gen_atoms::OFF_HEAP,
+      };
+      p.mailbox.location = location;
+      Ok(old_value)
+    }
     _ => fail::create::badarg_val(flag, p.get_heap_mut()),
   }
 }
+
+fn get_bool_arg(value: Term) -> RtResult<bool> {
+  if !value.is_bool() {
+    return fail::create::badarg();
+  }
+  Ok(value == gen_atoms::TRUE)
+}
+
+/// A heap size in words must be a non-negative small integer.
+pub fn get_heap_size_arg(value: Term) -> RtResult<usize> {
+  if !value.is_small() || value.get_small_signed() < 0 {
+    return fail::create::badarg();
+  }
+  Ok(value.get_small_unsigned())
+}
+
+/// Parse `max_heap_size` flag value which is either a size, or a map
+/// `#{size => Size, kill => Bool, error_logger => Bool}`, where the missing
+/// keys take their values from `current`.
+pub fn parse_max_heap_size(value: Term, current: MaxHeapSize) -> RtResult<MaxHeapSize> {
+  if value.is_small() {
+    return Ok(MaxHeapSize {
+      size: get_heap_size_arg(value)?,
+      ..current
+    });
+  }
+  if !value.is_map() {
+    return fail::create::badarg();
+  }
+  let map_p = value.get_box_ptr_safe::<boxed::Map>()?;
+  let mut result = current;
+  // Count known keys, to reject maps which have anything else in them
+  let mut n_known = 0usize;
+  unsafe {
+    if let Some(size) = boxed::Map::get(map_p, gen_atoms::SIZE)? {
+      result.size = get_heap_size_arg(size)?;
+      n_known += 1;
+    }
+    if let Some(kill) = boxed::Map::get(map_p, gen_atoms::KILL)? {
+      result.kill = get_bool_arg(kill)?;
+      n_known += 1;
+    }
+    if let Some(error_logger) = boxed::Map::get(map_p, gen_atoms::ERROR_LOGGER)? {
+      result.error_logger = get_bool_arg(error_logger)?;
+      n_known += 1;
+    }
+    if n_known != (*map_p).get_count() {
+      return fail::create::badarg();
+    }
+  }
+  Ok(result)
+}
+
+/// Value of `message_queue_data` flag is `on_heap` or `off_heap`.
+pub fn get_message_queue_data_arg(value: Term) -> RtResult<MessageQueueLocation> {
+  match value {
+    gen_atoms::ON_HEAP => Ok(MessageQueueLocation::OnHeap),
+    gen_atoms::OFF_HEAP => Ok(MessageQueueLocation::OffHeap),
+    _ => fail::create::badarg(),
+  }
+}
+
+/// Build `#{size => Size, kill => Bool, error_logger => Bool}` on the heap.
+fn make_max_heap_size_map(mhs: MaxHeapSize, hp: &mut THeap) -> RtResult<Term> {
+  let map_p = boxed::Map::create_into(hp, 3)?;
+  unsafe {
+    boxed::Map::add(map_p, gen_atoms::ERROR_LOGGER, Term::make_bool(mhs.error_logger))?;
+    boxed::Map::add(map_p, gen_atoms::KILL, Term::make_bool(mhs.kill))?;
+    boxed::Map::add(map_p, gen_atoms::SIZE, Term::make_small_unsigned(mhs.size))?;
+  }
+  Ok(Term::make_boxed(map_p))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    command_line_args::ErlStartArgs,
+    emulator::{
+      atom,
+      code::CodePtr,
+      process::MonitorPeer,
+      runtime_ctx::{
+        call_native_fun::{find_and_call_native_fun, CallBifTarget},
+        Context,
+      },
+      scheduler::{Prio, SliceResult},
+      spawn_options::SpawnOptions,
+    },
+    term::{boxed::reference::next_ref_id, term_builder::tuple_builder::tuple2},
+  };
+
+  fn spawn(vm: &mut VM, n: usize) -> Term {
+    let pid = Term::make_local_pid(n);
+    let p = Process::new(pid, Term::nil(), CodePtr::null(), &SpawnOptions::default());
+    vm.register_new_process(pid, p, Prio::Normal);
+    pid
+  }
+
+  /// The process stays valid while it is registered.
+  fn process_p(vm: &VM, pid: Term) -> *mut Process {
+    vm.processes.get(pid).unwrap().as_ptr()
+  }
+
+  /// Call `erlang:exit/1` the way `call_ext` does, then let the exception end
+  /// the time slice like the VM loop does.
+  fn call_exit_1(vm: &mut VM, curr_p: &mut Process, reason: Term) {
+    let mut ctx = Context::new(CodePtr::null());
+    let mfa = ModFunArity::new(gen_atoms::ERLANG, gen_atoms::EXIT, 1);
+    let target = CallBifTarget::MFArity(mfa);
+    let dst = Term::make_register_x(0);
+    let fail = Term::nil();
+    let args = [reason];
+    match find_and_call_native_fun(vm, &mut ctx, curr_p, fail, target, &args, dst, false)
+    {
+      Err(RtErr::Exception(exc_type, exc_reason)) => {
+        curr_p.set_exception(exc_type, exc_reason);
+        curr_p.timeslice_result = SliceResult::Exception;
+      }
+      _ => panic!("exit/1 must raise an exception"),
+    }
+  }
+
+  /// A registered process calls `exit(Reason)`, it loses its name, is
+  /// reported as crashed, the linked process trapping exits receives
+  /// `{'EXIT', Pid, Reason}` and the monitoring process receives `'DOWN'`.
+  #[test]
+  fn test_abnormal_exit_unregisters_and_reports() {
+    let mut args = ErlStartArgs::new(&Vec::new());
+    args.schedulers = Some(1);
+    let mut vm = VM::new(&mut args);
+    let pid0 = spawn(&mut vm, 0);
+    let linked = spawn(&mut vm, 1);
+    let watcher = spawn(&mut vm, 2);
+    assert!(vm.processes.register_name(gen_atoms::INIT, pid0));
+    let ref_id = next_ref_id();
+    unsafe {
+      let p0 = process_p(&vm, pid0);
+      (*p0).links.insert(linked);
+      let peer = MonitorPeer { pid: watcher, name: None };
+      (*p0).monitored_by.insert(ref_id, peer);
+      let l = process_p(&vm, linked);
+      (*l).links.insert(pid0);
+      (*l).process_flags.set(process_flags::TRAP_EXIT);
+      let peer = MonitorPeer { pid: pid0, name: None };
+      (*process_p(&vm, watcher)).monitors.insert(ref_id, peer);
+    }
+
+    assert_eq!(vm.scheduler.next_process(&vm.processes), Some(pid0));
+    let p0 = unsafe { &mut (*process_p(&vm, pid0)) };
+    let crash = atom::from_str("crash");
+    let reason = tuple2(p0.get_heap_mut(), gen_atoms::ERROR, crash).unwrap();
+    call_exit_1(&mut vm, p0, reason);
+
+    assert_eq!(vm.scheduler.next_process(&vm.processes), Some(linked));
+    assert!(!vm.processes.exists(pid0));
+    assert_eq!(vm.processes.find_registered(gen_atoms::INIT), None);
+    let reports = vm.scheduler.take_crash_reports();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].pid, pid0);
+    assert_eq!(reports[0].registered_name, Some(gen_atoms::INIT));
+
+    // The reason is copied to the receivers, compare it by elements
+    let is_reason = |t: Term| unsafe {
+      let tuple_p = t.get_tuple_ptr();
+      (*tuple_p).get_arity() == 2
+        && (*tuple_p).get_element(0) == gen_atoms::ERROR
+        && (*tuple_p).get_element(1) == crash
+    };
+    unsafe {
+      let l = process_p(&vm, linked);
+      let message = (*l).mailbox.get_current().unwrap();
+      let tuple_p = message.get_tuple_ptr();
+      assert_eq!((*tuple_p).get_element(0), gen_atoms::EXIT_UPPER);
+      assert_eq!((*tuple_p).get_element(1), pid0);
+      assert!(is_reason((*tuple_p).get_element(2)));
+      assert!((*l).links.is_empty());
+      (*l).timeslice_result = SliceResult::Yield;
+    }
+
+    assert_eq!(vm.scheduler.next_process(&vm.processes), Some(watcher));
+    unsafe {
+      let w = process_p(&vm, watcher);
+      let message = (*w).mailbox.get_current().unwrap();
+      let tuple_p = message.get_tuple_ptr();
+      assert_eq!((*tuple_p).get_element(0), gen_atoms::DOWN_UPPER);
+      assert_eq!(boxed::Reference::get_id((*tuple_p).get_element(1)), Some(ref_id));
+      assert_eq!((*tuple_p).get_element(2), gen_atoms::PROCESS);
+      assert_eq!((*tuple_p).get_element(3), pid0);
+      assert!(is_reason((*tuple_p).get_element(4)));
+      assert!((*w).monitors.is_empty());
+
+      // Normal exit is not reported
+      (*w).timeslice_result = SliceResult::Finished;
+    }
+    assert_eq!(vm.scheduler.next_process(&vm.processes), Some(linked));
+    assert!(vm.scheduler.take_crash_reports().is_empty());
+  }
+}
@@ -0,0 +1,89 @@
+//! Process dictionary BIFs: `put/2`, `get/0,1`, `get_keys/0,1`, `erase/0,1`.
+//! The dictionary is stored in `Process::dictionary`.
+use crate::{
+  emulator::{gen_atoms, heap::heap_trait::THeap, process::Process},
+  fail::RtResult,
+  term::{
+    term_builder::{list_builder::build_list_from_slice, tuple_builder::tuple2},
+    value::Term,
+  },
+};
+
+/// Build a list of `{Key, Value}` tuples, the format of the dictionary in
+/// `get/0`, `erase/0` and `process_info(Pid, dictionary)`.
+pub fn pairs_to_list(hp: &mut THeap, pairs: &[(Term, Term)]) -> RtResult<Term> {
+  let mut items = Vec::with_capacity(pairs.len());
+  for (key, value) in pairs {
+    items.push(tuple2(hp, *key, *value)?);
+  }
+  unsafe { build_list_from_slice(&items, hp) }
+}
+
+fn dictionary_to_list(proc: &mut Process) -> RtResult<Term> {
+  let pairs: Vec<(Term, Term)> = proc.dictionary.iter().collect();
+  pairs_to_list(proc.get_heap_mut(), &pairs)
+}
+
+// Store a value in the process dictionary.
+// Spec: erlang:put(Key, Val) -> OldVal | undefined
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:put/2", struct_name: NfErlangPut2, arity: 2,
+  invoke: {
+    Ok(proc.dictionary.put(key, value).unwrap_or(gen_atoms::UNDEFINED))
+  },
+  args: term(key), term(value),
+);
+
+// Spec: erlang:get(Key) -> Val | undefined
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:get/1", struct_name: NfErlangGet1, arity: 1,
+  invoke: { Ok(proc.dictionary.get(key).unwrap_or(gen_atoms::UNDEFINED)) },
+  args: term(key),
+);
+
+// Spec: erlang:get() -> [{Key, Val}]
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:get/0", struct_name: NfErlangGet0, arity: 0,
+  invoke: { dictionary_to_list(proc) },
+  args:
+);
+
+// Spec: erlang:get_keys() -> [Key]
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:get_keys/0", struct_name: NfErlangGetKeys0, arity: 0,
+  invoke: {
+    let keys: Vec<Term> = proc.dictionary.iter().map(|(k, _v)| k).collect();
+    unsafe { build_list_from_slice(&keys, proc.get_heap_mut()) }
+  },
+  args:
+);
+
+// Keys which have the value `value`.
+// Spec: erlang:get_keys(Val) -> [Key]
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:get_keys/1", struct_name: NfErlangGetKeys1, arity: 1,
+  invoke: {
+    let keys = proc.dictionary.keys_with_value(value);
+    unsafe { build_list_from_slice(&keys, proc.get_heap_mut()) }
+  },
+  args: term(value),
+);
+
+// Spec: erlang:erase(Key) -> Val | undefined
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:erase/1", struct_name: NfErlangErase1, arity: 1,
+  invoke: { Ok(proc.dictionary.erase(key).unwrap_or(gen_atoms::UNDEFINED)) },
+  args: term(key),
+);
+
+// Remove everything from the dictionary and return its old contents.
+// Spec: erlang:erase() -> [{Key, Val}]
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:erase/0", struct_name: NfErlangErase0, arity: 0,
+  invoke: {
+    let result = dictionary_to_list(proc)?;
+    proc.dictionary.clear();
+    Ok(result)
+  },
+  args:
+);
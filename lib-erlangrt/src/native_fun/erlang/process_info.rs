@@ -0,0 +1,354 @@
+//! `process_info/1,2`: introspection of a process. Another process is read
+//! while it is not running (see `Scheduler::with_stopped_process`), and terms
+//! from its heap are copied to the heap of the caller.
+use crate::{
+  emulator::{
+    code::CodePtr,
+    gen_atoms,
+    heap::{copy_term, heap_trait::THeap},
+    process::{MonitorPeer, Process},
+    process_flags,
+    process_handle::RunState,
+    scheduler::{Prio, Queue},
+    vm::VM,
+  },
+  fail::{self, RtResult},
+  native_fun::erlang::process_dict::pairs_to_list,
+  term::{
+    term_builder::{
+      list_builder::build_list_from_slice,
+      tuple_builder::{tuple2, tuple3},
+    },
+    value::{cons, *},
+  },
+};
+
+/// Items returned by `process_info/1`, preceded by `registered_name` if the
+/// process has a name.
+const DEFAULT_ITEMS: [Term; 11] = [
+  gen_atoms::CURRENT_FUNCTION,
+  gen_atoms::STATUS,
+  gen_atoms::MESSAGE_QUEUE_LEN,
+  gen_atoms::LINKS,
+  gen_atoms::DICTIONARY,
+  gen_atoms::TRAP_EXIT,
+  gen_atoms::PRIORITY,
+  gen_atoms::TOTAL_HEAP_SIZE,
+  gen_atoms::HEAP_SIZE,
+  gen_atoms::STACK_SIZE,
+  gen_atoms::REDUCTIONS,
+];
+
+/// All items supported by `process_info/2`.
+const SUPPORTED_ITEMS: [Term; 14] = [
+  gen_atoms::CURRENT_FUNCTION,
+  gen_atoms::DICTIONARY,
+  gen_atoms::HEAP_SIZE,
+  gen_atoms::LINKS,
+  gen_atoms::MESSAGE_QUEUE_LEN,
+  gen_atoms::MESSAGES,
+  gen_atoms::MONITORS,
+  gen_atoms::PRIORITY,
+  gen_atoms::REDUCTIONS,
+  gen_atoms::REGISTERED_NAME,
+  gen_atoms::STACK_SIZE,
+  gen_atoms::STATUS,
+  gen_atoms::TOTAL_HEAP_SIZE,
+  gen_atoms::TRAP_EXIT,
+];
+
+/// Value of an item, collected from the process. Terms are already on the
+/// heap of the caller, the rest is turned into terms later when the inspected
+/// process may be running again.
+enum InfoValue {
+  Term(Term),
+  List(Vec<Term>),
+  Dictionary(Vec<(Term, Term)>),
+  Monitors(Vec<MonitorPeer>),
+  CurrentFunction(CodePtr),
+  /// Current function of a hibernating process, `{erlang, hibernate, 3}`
+  Hibernated,
+  RegisteredName,
+}
+
+/// Copy a term from the inspected process to the caller's heap `hp`, or
+/// leave it where it is when the process inspects itself (`hp` is `None`).
+fn copy_to_caller(t: Term, hp: &mut Option<&mut THeap>) -> RtResult<Term> {
+  match hp {
+    Some(hp) => copy_term::copy_to(t, &mut **hp),
+    None => Ok(t),
+  }
+}
+
+fn get_status(run: &RunState, is_self: bool) -> Term {
+  if is_self {
+    return gen_atoms::RUNNING;
+  }
+  match run.queue {
+    Queue::High | Queue::Normal | Queue::Low => gen_atoms::RUNNABLE,
+    Queue::TimedWait | Queue::InfiniteWait => gen_atoms::WAITING,
+    // Stopped while inspecting some other process
+    Queue::None => gen_atoms::RUNNING,
+  }
+}
+
+/// Read the values of `items` from the process `p` and its run state `run`.
+/// Must run while `p` is not running, unless it is the caller.
+fn collect(
+  p: &Process,
+  run: &RunState,
+  items: &[Term],
+  is_self: bool,
+  mut hp: Option<&mut THeap>,
+) -> RtResult<Vec<InfoValue>> {
+  let mut values = Vec::with_capacity(items.len());
+  for item in items {
+    let value = match *item {
+      gen_atoms::CURRENT_FUNCTION if p.hibernated => InfoValue::Hibernated,
+      gen_atoms::CURRENT_FUNCTION => InfoValue::CurrentFunction(p.context.ip),
+      gen_atoms::DICTIONARY => {
+        let mut pairs = Vec::new();
+        for (key, value) in p.dictionary.iter() {
+          let key = copy_to_caller(key, &mut hp)?;
+          pairs.push((key, copy_to_caller(value, &mut hp)?));
+        }
+        InfoValue::Dictionary(pairs)
+      }
+      gen_atoms::HEAP_SIZE => make_size(p.get_heap_size()),
+      gen_atoms::LINKS => InfoValue::List(p.links.iter().cloned().collect()),
+      gen_atoms::MESSAGE_QUEUE_LEN => make_size(p.mailbox.get_message_count()),
+      gen_atoms::MESSAGES => {
+        let mut messages = Vec::new();
+        for m in p.mailbox.get_messages() {
+          messages.push(copy_to_caller(m, &mut hp)?);
+        }
+        InfoValue::List(messages)
+      }
+      gen_atoms::MONITORS => InfoValue::Monitors(p.monitors.values().cloned().collect()),
+      gen_atoms::PRIORITY => InfoValue::Term(match run.prio {
+        Prio::Low => gen_atoms::LOW,
+        Prio::Normal => gen_atoms::NORMAL,
+        Prio::High => gen_atoms::HIGH,
+      }),
+      gen_atoms::REDUCTIONS => make_size(p.context.get_reductions_total()),
+      gen_atoms::REGISTERED_NAME => InfoValue::RegisteredName,
+      gen_atoms::STACK_SIZE => make_size(p.get_heap().stack_depth()),
+      gen_atoms::STATUS => InfoValue::Term(get_status(run, is_self)),
+      gen_atoms::TOTAL_HEAP_SIZE => {
+        make_size(p.get_heap_size() + p.mailbox.get_fragment_words())
+      }
+      gen_atoms::TRAP_EXIT => {
+        InfoValue::Term(Term::make_bool(p.process_flags.get(process_flags::TRAP_EXIT)))
+      }
+      _ => return fail::create::badarg(),
+    };
+    values.push(value);
+  }
+  Ok(values)
+}
+
+#[inline]
+fn make_size(n: usize) -> InfoValue {
+  InfoValue::Term(Term::make_small_unsigned(n))
+}
+
+/// Turn a collected value into a term on the caller's heap.
+fn build_value(
+  vm: &VM,
+  curr_p: &mut Process,
+  pid: Term,
+  value: InfoValue,
+) -> RtResult<Term> {
+  let hp = curr_p.get_heap_mut();
+  match value {
+    InfoValue::Term(t) => Ok(t),
+    InfoValue::List(items) => unsafe { build_list_from_slice(&items, hp) },
+    InfoValue::Dictionary(pairs) => pairs_to_list(hp, &pairs),
+    InfoValue::Monitors(peers) => {
+      let mut items = Vec::with_capacity(peers.len());
+      for peer in peers {
+        let target = match peer.name {
+          Some(name) => tuple2(hp, name, gen_atoms::NONODE_NOHOST)?,
+          None => peer.pid,
+        };
+        items.push(tuple2(hp, gen_atoms::PROCESS, target)?);
+      }
+      unsafe { build_list_from_slice(&items, hp) }
+    }
+    InfoValue::CurrentFunction(ip) => match vm.code_server().code_reverse_lookup(ip) {
+      Some(mfa) => {
+        let arity = Term::make_small_unsigned(mfa.arity);
+        tuple3(hp, mfa.m, mfa.f, arity)
+      }
+      None => Ok(gen_atoms::UNDEFINED),
+    },
+    InfoValue::Hibernated => {
+      tuple3(hp, gen_atoms::ERLANG, gen_atoms::HIBERNATE, Term::make_small_unsigned(3))
+    }
+    InfoValue::RegisteredName => {
+      Ok(vm.processes.find_name_of(pid).unwrap_or_else(Term::nil))
+    }
+  }
+}
+
+/// Build `{Item, Value}` tuples for the `items`.
+/// Returns: `None` if the process does not exist.
+fn process_info(
+  vm: &mut VM,
+  curr_p: &mut Process,
+  pid: Term,
+  items: &[Term],
+) -> RtResult<Option<Vec<Term>>> {
+  if items.iter().any(|item| !SUPPORTED_ITEMS.contains(item)) {
+    return fail::create::badarg();
+  }
+  let values = if pid == curr_p.pid {
+    // The caller is running and registered, only its priority is read
+    let run = match vm.processes.get(pid) {
+      Some(handle) => *handle.lock_run_state(),
+      None => panic!("process_info: Caller {} is not registered", pid),
+    };
+    collect(curr_p, &run, items, true, None)?
+  } else {
+    let caller = curr_p.pid;
+    let result =
+      vm.scheduler.with_stopped_process(&vm.processes, caller, pid, |p, run| {
+        collect(p, run, items, false, Some(curr_p.get_heap_mut()))
+      });
+    match result {
+      Some(values) => values?,
+      None => return Ok(None),
+    }
+  };
+
+  let mut result = Vec::with_capacity(items.len());
+  for (item, value) in items.iter().zip(values) {
+    let value = build_value(vm, curr_p, pid, value)?;
+    result.push(tuple2(curr_p.get_heap_mut(), *item, value)?);
+  }
+  Ok(Some(result))
+}
+
+// Spec: erlang:process_info(Pid) -> [{Item, Value}] | undefined
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:process_info/1", struct_name: NfErlangProcessInfo1, arity: 1,
+  invoke: { process_info_1(vm, curr_p, pid) },
+  args: pid(pid),
+);
+
+pub fn process_info_1(vm: &mut VM, curr_p: &mut Process, pid: Term) -> RtResult<Term> {
+  if !pid.is_local_pid() {
+    return fail::create::badarg();
+  }
+  let mut items = Vec::with_capacity(DEFAULT_ITEMS.len() + 1);
+  if vm.processes.find_name_of(pid).is_some() {
+    items.push(gen_atoms::REGISTERED_NAME);
+  }
+  items.extend_from_slice(&DEFAULT_ITEMS);
+  match process_info(vm, curr_p, pid, &items)? {
+    Some(tuples) => unsafe { build_list_from_slice(&tuples, curr_p.get_heap_mut()) },
+    None => Ok(gen_atoms::UNDEFINED),
+  }
+}
+
+// Spec: erlang:process_info(Pid, Item) -> {Item, Value} | undefined
+//       erlang:process_info(Pid, [Item]) -> [{Item, Value}] | undefined
+// A process which is not registered has `[]` as `registered_name`.
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:process_info/2", struct_name: NfErlangProcessInfo2, arity: 2,
+  invoke: { process_info_2(vm, curr_p, pid, item_or_list) },
+  args: pid(pid), term(item_or_list),
+);
+
+pub fn process_info_2(
+  vm: &mut VM,
+  curr_p: &mut Process,
+  pid: Term,
+  item_or_list: Term,
+) -> RtResult<Term> {
+  if !pid.is_local_pid() {
+    return fail::create::badarg();
+  }
+  if item_or_list.is_atom() {
+    return match process_info(vm, curr_p, pid, &[item_or_list])? {
+      Some(tuples) => Ok(tuples[0]),
+      None => Ok(gen_atoms::UNDEFINED),
+    };
+  }
+  if !item_or_list.is_list() {
+    return fail::create::badarg();
+  }
+  let mut items = Vec::new();
+  let tail = cons::for_each(item_or_list, |item| {
+    items.push(item);
+    Ok(())
+  })?;
+  if let Some(t) = tail {
+    if t != Term::nil() {
+      return fail::create::badarg();
+    }
+  }
+  match process_info(vm, curr_p, pid, &items)? {
+    Some(tuples) => unsafe { build_list_from_slice(&tuples, curr_p.get_heap_mut()) },
+    None => Ok(gen_atoms::UNDEFINED),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    command_line_args::ErlStartArgs,
+    emulator::{code::CodePtr, spawn_options::SpawnOptions},
+  };
+
+  fn spawn(vm: &mut VM, n: usize) -> Term {
+    let pid = Term::make_local_pid(n);
+    let p = Process::new(pid, Term::nil(), CodePtr::null(), &SpawnOptions::default());
+    vm.register_new_process(pid, p, Prio::Normal);
+    pid
+  }
+
+  /// Element `index` of a tuple.
+  fn element(t: Term, index: usize) -> Term {
+    unsafe { (*t.get_tuple_ptr()).get_element(index) }
+  }
+
+  #[test]
+  fn test_process_info_2() {
+    let mut args = ErlStartArgs::new(&Vec::new());
+    args.schedulers = Some(1);
+    let mut vm = VM::new(&mut args);
+    let caller = spawn(&mut vm, 0);
+    let target = spawn(&mut vm, 1);
+    assert_eq!(vm.scheduler.next_process(&vm.processes), Some(caller));
+    let handle = vm.processes.get(caller).unwrap();
+    let curr_p = unsafe { &mut (*handle.as_ptr()) };
+    let one = Term::make_small_unsigned(1);
+    unsafe {
+      let target_p = vm.processes.get(target).unwrap().as_ptr();
+      (*target_p).dictionary.put(gen_atoms::OK, one);
+    }
+
+    // The target is queued, its dictionary is copied to the caller
+    let items = [gen_atoms::STATUS, gen_atoms::DICTIONARY];
+    let list = unsafe { build_list_from_slice(&items, curr_p.get_heap_mut()) }.unwrap();
+    let info = process_info_2(&mut vm, curr_p, target, list).unwrap();
+    let mut values = Vec::new();
+    cons::for_each(info, |t| {
+      values.push(element(t, 1));
+      Ok(())
+    })
+    .unwrap();
+    assert_eq!(values[0], gen_atoms::RUNNABLE);
+    let pair = unsafe { (*values[1].get_cons_ptr()).hd() };
+    assert_eq!((element(pair, 0), element(pair, 1)), (gen_atoms::OK, one));
+
+    let status = process_info_2(&mut vm, curr_p, caller, gen_atoms::STATUS).unwrap();
+    assert_eq!(element(status, 1), gen_atoms::RUNNING);
+    let gone = Term::make_local_pid(2);
+    let info = process_info_2(&mut vm, curr_p, gone, gen_atoms::STATUS).unwrap();
+    assert_eq!(info, gen_atoms::UNDEFINED);
+    assert!(process_info_2(&mut vm, curr_p, target, gen_atoms::OK).is_err());
+  }
+}
@@ -0,0 +1,504 @@
+//! Process creation: `spawn`, `spawn_link`, `spawn_monitor` and `spawn_opt`.
+//! All variants parse their options into `SpawnOptions` and go through
+//! `spawn_with_options`. Variants which take a node accept only the local
+//! node, as there is no distribution.
+use crate::{
+  emulator::{
+    gen_atoms,
+    mfa::ModFunArgs,
+    process::{MonitorPeer, Process},
+    scheduler::Prio,
+    spawn_options::SpawnOptions,
+    vm::VM,
+  },
+  fail::{self, RtResult},
+  native_fun::erlang::process::{
+    get_heap_size_arg, get_message_queue_data_arg, parse_max_heap_size,
+  },
+  term::{
+    boxed::{self, reference},
+    term_builder::tuple_builder::tuple2,
+    value::{cons, *},
+  },
+};
+
+/// What the new process starts with.
+enum SpawnEntry {
+  /// Call `M:F(Args...)`, the arguments are a list
+  Apply(Term, Term, Term),
+  /// Call a fun with no arguments
+  Fun(Term),
+}
+
+/// Create the process, and link or monitor it from the caller if the options
+/// say so.
+/// Returns: the pid, or `{Pid, MonitorRef}` with the `monitor` option.
+fn spawn_with_options(
+  vm: &mut VM,
+  curr_p: &mut Process,
+  entry: SpawnEntry,
+  spawn_opts: &SpawnOptions,
+) -> RtResult<Term> {
+  let ref_term = match spawn_opts.monitor {
+    Some(ref_id) => Some(boxed::Reference::create_into(curr_p.get_heap_mut(), ref_id)?),
+    None => None,
+  };
+  let pid = match entry {
+    SpawnEntry::Apply(m, f, args) => {
+      let mfargs = ModFunArgs::with_args_list(m, f, args);
+      vm.create_process(curr_p.pid, &mfargs, spawn_opts)?
+    }
+    SpawnEntry::Fun(fun) => vm.create_process_fun(curr_p.pid, fun, spawn_opts)?,
+  };
+  // The new process knows about the link and the monitor from the start, see
+  // `Process::new`
+  if spawn_opts.link {
+    curr_p.links.insert(pid);
+  }
+  match (spawn_opts.monitor, ref_term) {
+    (Some(ref_id), Some(ref_term)) => {
+      curr_p.monitors.insert(ref_id, MonitorPeer { pid, name: None });
+      tuple2(curr_p.get_heap_mut(), pid, ref_term)
+    }
+    _ => Ok(pid),
+  }
+}
+
+/// Parse the option list of `spawn_opt`.
+pub fn parse_spawn_options(opts: Term) -> RtResult<SpawnOptions> {
+  let mut result = SpawnOptions::default();
+  let tail = cons::for_each(opts, |opt| {
+    match opt {
+      gen_atoms::LINK => result.link = true,
+      gen_atoms::MONITOR => result.monitor = Some(reference::next_ref_id()),
+      _ => {
+        let (key, value) = get_option_pair(opt)?;
+        match key {
+          gen_atoms::PRIORITY => result.prio = get_priority_arg(value)?,
+          gen_atoms::FULLSWEEP_AFTER => {
+            result.fullsweep_after = get_heap_size_arg(value)?
+          }
+          gen_atoms::MIN_HEAP_SIZE => result.min_heap_size = get_heap_size_arg(value)?,
+          gen_atoms::MIN_BIN_VHEAP_SIZE => {
+            result.min_bin_vheap_size = get_heap_size_arg(value)?
+          }
+          gen_atoms::MAX_HEAP_SIZE => {
+            result.max_heap_size = parse_max_heap_size(value, result.max_heap_size)?
+          }
+          gen_atoms::MESSAGE_QUEUE_DATA => {
+            result.msg_queue = get_message_queue_data_arg(value)?
+          }
+          _ => return fail::create::badarg(),
+        }
+      }
+    }
+    Ok(())
+  })?;
+  if let Some(t) = tail {
+    if t != Term::nil() {
+      return fail::create::badarg();
+    }
+  }
+  let max_size = result.max_heap_size.size;
+  if max_size != 0 && max_size < result.min_heap_size {
+    return fail::create::badarg();
+  }
+  Ok(result)
+}
+
+/// An option other than `link` and `monitor` is a tuple `{Key, Value}`.
+fn get_option_pair(opt: Term) -> RtResult<(Term, Term)> {
+  if !opt.is_tuple() {
+    return fail::create::badarg();
+  }
+  let tuple_p = opt.get_tuple_ptr();
+  unsafe {
+    if (*tuple_p).get_arity() != 2 {
+      return fail::create::badarg();
+    }
+    Ok(((*tuple_p).get_element(0), (*tuple_p).get_element(1)))
+  }
+}
+
+/// Priority is `low`, `normal` or `high`, the `max` priority is reserved for
+/// the system and not supported.
+fn get_priority_arg(value: Term) -> RtResult<Prio> {
+  match value {
+    gen_atoms::LOW => Ok(Prio::Low),
+    gen_atoms::NORMAL => Ok(Prio::Normal),
+    gen_atoms::HIGH => Ok(Prio::High),
+    _ => fail::create::badarg(),
+  }
+}
+
+/// Only the local node can be given to the spawn variants which take a node.
+fn check_local_node(node: Term) -> RtResult<()> {
+  if node != gen_atoms::NONODE_NOHOST {
+    return fail::create::badarg();
+  }
+  Ok(())
+}
+
+fn link_options() -> SpawnOptions {
+  let mut spawn_opts = SpawnOptions::default();
+  spawn_opts.link = true;
+  spawn_opts
+}
+
+fn monitor_options() -> SpawnOptions {
+  let mut spawn_opts = SpawnOptions::default();
+  spawn_opts.monitor = Some(reference::next_ref_id());
+  spawn_opts
+}
+
+// Creates a new process which calls `fun` with no arguments.
+// Spec: erlang:spawn(Fun) -> pid()
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn/1", struct_name: NfErlangSpawn1, arity: 1,
+  invoke: {
+    spawn_with_options(vm, curr_p, SpawnEntry::Fun(fun), &SpawnOptions::default())
+  },
+  args: term(fun),
+);
+
+// Spec: erlang:spawn(Node, Fun) -> pid()
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn/2", struct_name: NfErlangSpawn2, arity: 2,
+  invoke: {
+    check_local_node(node)?;
+    spawn_with_options(vm, curr_p, SpawnEntry::Fun(fun), &SpawnOptions::default())
+  },
+  args: atom(node), term(fun),
+);
+
+// Creates a new process specified by `module:function/arity` with `args`
+// (args are passed as list), `arity` is length of args list.
+// Spec: erlang:spawn(Module, Function, Args) -> pid()
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn/3", struct_name: NfErlangSpawn3, arity: 3,
+  invoke: {
+    let entry = SpawnEntry::Apply(m, f, args);
+    spawn_with_options(vm, curr_p, entry, &SpawnOptions::default())
+  },
+  args: atom(m), atom(f), list(args),
+);
+
+// Spec: erlang:spawn(Node, Module, Function, Args) -> pid()
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn/4", struct_name: NfErlangSpawn4, arity: 4,
+  invoke: {
+    check_local_node(node)?;
+    let entry = SpawnEntry::Apply(m, f, args);
+    spawn_with_options(vm, curr_p, entry, &SpawnOptions::default())
+  },
+  args: atom(node), atom(m), atom(f), list(args),
+);
+
+// Creates a new process which calls `fun` with no arguments, and links it to
+// the caller.
+// Spec: erlang:spawn_link(Fun) -> pid()
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn_link/1", struct_name: NfErlangSpawnLink1, arity: 1,
+  invoke: { spawn_with_options(vm, curr_p, SpawnEntry::Fun(fun), &link_options()) },
+  args: term(fun),
+);
+
+// Spec: erlang:spawn_link(Node, Fun) -> pid()
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn_link/2", struct_name: NfErlangSpawnLink2, arity: 2,
+  invoke: {
+    check_local_node(node)?;
+    spawn_with_options(vm, curr_p, SpawnEntry::Fun(fun), &link_options())
+  },
+  args: atom(node), term(fun),
+);
+
+// Creates a new process like `spawn/3` and links it to the caller.
+// Spec: erlang:spawn_link(Module, Function, Args) -> pid()
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn_link/3", struct_name: NfErlangSpawnLink3, arity: 3,
+  invoke: {
+    let entry = SpawnEntry::Apply(m, f, args);
+    spawn_with_options(vm, curr_p, entry, &link_options())
+  },
+  args: atom(m), atom(f), list(args),
+);
+
+// Spec: erlang:spawn_link(Node, Module, Function, Args) -> pid()
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn_link/4", struct_name: NfErlangSpawnLink4, arity: 4,
+  invoke: {
+    check_local_node(node)?;
+    let entry = SpawnEntry::Apply(m, f, args);
+    spawn_with_options(vm, curr_p, entry, &link_options())
+  },
+  args: atom(node), atom(m), atom(f), list(args),
+);
+
+// Creates a new process which calls `fun` with no arguments, and monitors it.
+// Spec: erlang:spawn_monitor(Fun) -> {pid(), reference()}
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn_monitor/1", struct_name: NfErlangSpawnMonitor1, arity: 1,
+  invoke: { spawn_with_options(vm, curr_p, SpawnEntry::Fun(fun), &monitor_options()) },
+  args: term(fun),
+);
+
+// Creates a new process like `spawn/3` and monitors it.
+// Spec: erlang:spawn_monitor(Module, Function, Args) -> {pid(), reference()}
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn_monitor/3", struct_name: NfErlangSpawnMonitor3, arity: 3,
+  invoke: {
+    let entry = SpawnEntry::Apply(m, f, args);
+    spawn_with_options(vm, curr_p, entry, &monitor_options())
+  },
+  args: atom(m), atom(f), list(args),
+);
+
+// Creates a new process which calls `fun` with no arguments, with the options
+// `link`, `monitor`, `{priority, P}`, `{fullsweep_after, N}`,
+// `{min_heap_size, N}`, `{min_bin_vheap_size, N}`, `{max_heap_size, S}` and
+// `{message_queue_data, L}`.
+// Spec: erlang:spawn_opt(Fun, Options) -> pid() | {pid(), reference()}
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn_opt/2", struct_name: NfErlangSpawnOpt2, arity: 2,
+  invoke: {
+    let spawn_opts = parse_spawn_options(opts)?;
+    spawn_with_options(vm, curr_p, SpawnEntry::Fun(fun), &spawn_opts)
+  },
+  args: term(fun), list(opts),
+);
+
+// Spec: erlang:spawn_opt(Node, Fun, Options) -> pid() | {pid(), reference()}
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn_opt/3", struct_name: NfErlangSpawnOpt3, arity: 3,
+  invoke: {
+    check_local_node(node)?;
+    let spawn_opts = parse_spawn_options(opts)?;
+    spawn_with_options(vm, curr_p, SpawnEntry::Fun(fun), &spawn_opts)
+  },
+  args: atom(node), term(fun), list(opts),
+);
+
+// Creates a new process like `spawn/3` with the options, see `spawn_opt/2`.
+// Spec: erlang:spawn_opt(Module, Function, Args, Options)
+//   -> pid() | {pid(), reference()}
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn_opt/4", struct_name: NfErlangSpawnOpt4, arity: 4,
+  invoke: {
+    let spawn_opts = parse_spawn_options(opts)?;
+    spawn_with_options(vm, curr_p, SpawnEntry::Apply(m, f, args), &spawn_opts)
+  },
+  args: atom(m), atom(f), list(args), list(opts),
+);
+
+// Spec: erlang:spawn_opt(Node, Module, Function, Args, Options)
+//   -> pid() | {pid(), reference()}
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:spawn_opt/5", struct_name: NfErlangSpawnOpt5, arity: 5,
+  invoke: {
+    check_local_node(node)?;
+    let spawn_opts = parse_spawn_options(opts)?;
+    spawn_with_options(vm, curr_p, SpawnEntry::Apply(m, f, args), &spawn_opts)
+  },
+  args: atom(node), atom(m), atom(f), list(args), list(opts),
+);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    beam::gen_op,
+    command_line_args::ErlStartArgs,
+    defs::exc_type::ExceptionType,
+    emulator::{
+      atom,
+      code::{opcode, CodePtr, VersionedCodePtr},
+      funarity::FunArity,
+      function::FunEntry,
+      heap::{heap_trait::THeap, Heap},
+      mfa::ModFunArity,
+      module::{Module, VersionedModuleName},
+      runtime_ctx::{
+        call_native_fun::{find_and_call_native_fun, CallBifTarget},
+        Context,
+      },
+      scheduler::SliceResult,
+      spawn_options::MessageQueueLocation,
+    },
+    term::term_builder::list_builder::build_list_from_slice,
+  };
+
+  /// A VM with one scheduler, running a process which will call spawn. Pids
+  /// of the spawned processes are counted from 0.
+  fn vm_with_caller() -> (VM, Term) {
+    let mut args = ErlStartArgs::new(&Vec::new());
+    args.schedulers = Some(1);
+    let mut vm = VM::new(&mut args);
+    let caller = Term::make_local_pid(1000);
+    let p = Process::new(caller, Term::nil(), CodePtr::null(), &SpawnOptions::default());
+    vm.register_new_process(caller, p, Prio::Normal);
+    assert_eq!(vm.scheduler.next_process(&vm.processes), Some(caller));
+    (vm, caller)
+  }
+
+  /// A fun with no arguments which already knows its code location, so that
+  /// no module has to be loaded to spawn it. The spawned process never runs.
+  fn make_fun(hp: &mut THeap) -> Term {
+    let mfa = ModFunArity::new(gen_atoms::ERLANG, gen_atoms::OK, 0);
+    unsafe {
+      let fun = boxed::Closure::create_into(hp, &FunEntry::new(mfa, 0), &[]).unwrap();
+      let name = VersionedModuleName::new(gen_atoms::ERLANG, 1);
+      let closure_p = boxed::Closure::mut_from_term(fun).unwrap();
+      (*closure_p).dst = Some(VersionedCodePtr::new(name, CodePtr::null()));
+      fun
+    }
+  }
+
+  /// A module `spawn_test` with a function `ok/0`, so that spawning by MFA
+  /// finds the code without loading a file.
+  fn load_test_module(vm: &mut VM) -> Term {
+    let name = atom::from_str("spawn_test");
+    let mut m = Module::new(&VersionedModuleName::new(name, 1));
+    m.code.push(opcode::to_memory_word(gen_op::OPCODE_RETURN));
+    m.funs.insert(FunArity::new(gen_atoms::OK, 0), 0);
+    vm.code_server_mut().module_loaded(Box::new(m));
+    name
+  }
+
+  /// Call `erlang:Name/Arity` the way `call_ext` does, the result is in x0.
+  fn call_bif(
+    vm: &mut VM,
+    ctx: &mut Context,
+    curr_p: &mut Process,
+    name: &str,
+    args: &[Term],
+  ) -> RtResult<Term> {
+    let mfa = ModFunArity::new(gen_atoms::ERLANG, atom::from_str(name), args.len());
+    let target = CallBifTarget::MFArity(mfa);
+    let dst = Term::make_register_x(0);
+    find_and_call_native_fun(vm, ctx, curr_p, Term::nil(), target, args, dst, false)?;
+    Ok(ctx.get_x(0))
+  }
+
+  fn process_p(vm: &VM, pid: Term) -> *mut Process {
+    vm.processes.get(pid).unwrap().as_ptr()
+  }
+
+  /// Let the running process `pid` fail with `reason`, and run the next one.
+  fn crash(vm: &mut VM, pid: Term, reason: Term) -> Option<Term> {
+    unsafe {
+      let p = process_p(vm, pid);
+      (*p).set_exception(ExceptionType::Exit, reason);
+      (*p).timeslice_result = SliceResult::Exception;
+    }
+    vm.scheduler.next_process(&vm.processes)
+  }
+
+  #[test]
+  fn test_spawn_monitor_gets_down_message() {
+    let (mut vm, caller) = vm_with_caller();
+    let curr_p = unsafe { &mut (*process_p(&vm, caller)) };
+    let fun = make_fun(curr_p.get_heap_mut());
+    let result =
+      spawn_with_options(&mut vm, curr_p, SpawnEntry::Fun(fun), &monitor_options());
+    let result = result.unwrap();
+    assert!(result.is_tuple());
+    let (pid, ref_term) = unsafe {
+      let tuple_p = result.get_tuple_ptr();
+      ((*tuple_p).get_element(0), (*tuple_p).get_element(1))
+    };
+    assert!(pid.is_local_pid());
+    assert!(ref_term.is_local_ref());
+
+    // The caller yields, the new process runs and crashes
+    curr_p.timeslice_result = SliceResult::Yield;
+    assert_eq!(vm.scheduler.next_process(&vm.processes), Some(pid));
+    assert_eq!(crash(&mut vm, pid, gen_atoms::KILLED), Some(caller));
+    let message = curr_p.mailbox.get_current().unwrap();
+    unsafe {
+      let tuple_p = message.get_tuple_ptr();
+      assert_eq!((*tuple_p).get_element(0), gen_atoms::DOWN_UPPER);
+      let down_ref = (*tuple_p).get_element(1);
+      let ref_id = boxed::Reference::get_id(ref_term);
+      assert_eq!(boxed::Reference::get_id(down_ref), ref_id);
+      assert_eq!((*tuple_p).get_element(3), pid);
+      assert_eq!((*tuple_p).get_element(4), gen_atoms::KILLED);
+    }
+    assert!(curr_p.monitors.is_empty());
+  }
+
+  #[test]
+  fn test_spawn_opt_link_propagates_exit() {
+    let (mut vm, caller) = vm_with_caller();
+    let curr_p = unsafe { &mut (*process_p(&vm, caller)) };
+    let hp = curr_p.get_heap_mut();
+    let fun = make_fun(hp);
+    let opts = unsafe { build_list_from_slice(&[gen_atoms::LINK], hp) }.unwrap();
+    let spawn_opts = parse_spawn_options(opts).unwrap();
+    let pid = spawn_with_options(&mut vm, curr_p, SpawnEntry::Fun(fun), &spawn_opts);
+    let pid = pid.unwrap();
+    assert!(pid.is_local_pid());
+    assert!(curr_p.links.contains(&pid));
+
+    // The new process crashes, the linked caller does not trap exits and
+    // exits too
+    curr_p.timeslice_result = SliceResult::Yield;
+    assert_eq!(vm.scheduler.next_process(&vm.processes), Some(pid));
+    assert_eq!(crash(&mut vm, pid, gen_atoms::KILLED), None);
+    assert!(!vm.processes.exists(caller));
+    assert!(!vm.processes.exists(pid));
+  }
+
+  #[test]
+  fn test_spawn_4_and_5_through_native_fun_dispatch() {
+    let (mut vm, caller) = vm_with_caller();
+    let m = load_test_module(&mut vm);
+    let curr_p = unsafe { &mut (*process_p(&vm, caller)) };
+    let mut ctx = Context::new(CodePtr::null());
+    let node = gen_atoms::NONODE_NOHOST;
+
+    let args = [node, m, gen_atoms::OK, Term::nil()];
+    let pid = call_bif(&mut vm, &mut ctx, curr_p, "spawn", &args).unwrap();
+    assert!(pid.is_local_pid());
+    assert!(vm.processes.exists(pid));
+
+    let hp = curr_p.get_heap_mut();
+    let opts = unsafe { build_list_from_slice(&[gen_atoms::LINK], hp) }.unwrap();
+    let args = [node, m, gen_atoms::OK, Term::nil(), opts];
+    let pid = call_bif(&mut vm, &mut ctx, curr_p, "spawn_opt", &args).unwrap();
+    assert!(pid.is_local_pid());
+    assert!(curr_p.links.contains(&pid));
+
+    // Only the local node is accepted
+    let args = [gen_atoms::TRUE, m, gen_atoms::OK, Term::nil()];
+    assert!(call_bif(&mut vm, &mut ctx, curr_p, "spawn_link", &args).is_err());
+  }
+
+  #[test]
+  fn test_parse_spawn_options() {
+    let mut hp = Heap::new_fragment(64);
+    let opts = [
+      gen_atoms::MONITOR,
+      tuple2(&mut hp, gen_atoms::PRIORITY, gen_atoms::HIGH).unwrap(),
+      tuple2(&mut hp, gen_atoms::MESSAGE_QUEUE_DATA, gen_atoms::OFF_HEAP).unwrap(),
+      tuple2(&mut hp, gen_atoms::MIN_HEAP_SIZE, Term::make_small_unsigned(1000)).unwrap(),
+    ];
+    let list = unsafe { build_list_from_slice(&opts, &mut hp) }.unwrap();
+    let spawn_opts = parse_spawn_options(list).unwrap();
+    assert!(spawn_opts.monitor.is_some());
+    assert!(!spawn_opts.link);
+    assert_eq!(spawn_opts.prio as usize, Prio::High as usize);
+    assert_eq!(spawn_opts.msg_queue, MessageQueueLocation::OffHeap);
+    assert_eq!(spawn_opts.min_heap_size, 1000);
+
+    // Unknown option, and a known option with a bad value
+    let bad_opts = [
+      gen_atoms::TRUE,
+      tuple2(&mut hp, gen_atoms::PRIORITY, gen_atoms::UNDEFINED).unwrap(),
+    ];
+    for opt in bad_opts.iter() {
+      let list = unsafe { build_list_from_slice(&[*opt], &mut hp) }.unwrap();
+      assert!(parse_spawn_options(list).is_err());
+    }
+  }
+}
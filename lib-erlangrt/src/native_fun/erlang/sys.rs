@@ -1,8 +1,13 @@
 use crate::{
   defs::exc_type::ExceptionType,
   emulator::{gen_atoms, process::Process},
-  fail::{RtErr, RtResult},
-  term::{builders::make_badfun_n, term_builder::tuple_builder::tuple2, value::Term},
+  fail::{self, RtErr, RtResult},
+  term::{
+    boxed::{self, reference},
+    builders::make_badfun_n,
+    term_builder::tuple_builder::tuple2,
+    value::Term,
+  },
 };
 
 #[allow(dead_code)]
@@ -61,3 +66,26 @@ define_nativefun!(_vm, _proc, args,
   },
   args: list(path), term(load_info),
 );
+
+// Remove old code of a module. Literal areas of the purged code are freed
+// once no process refers to them.
+define_nativefun!(vm, _proc, args,
+  name: "erlang:purge_module/1", struct_name: NfErlangPurgeModule1, arity: 1,
+  invoke: {
+    if !vm.purge_module(m) {
+      return fail::create::badarg();
+    }
+    Ok(gen_atoms::TRUE)
+  },
+  args: atom(m),
+);
+
+// Create a new reference, unique for the lifetime of the node.
+// Spec: erlang:make_ref() -> reference()
+define_nativefun!(_vm, proc, _args,
+  name: "erlang:make_ref/0", struct_name: NfErlangMakeRef0, arity: 0,
+  invoke: {
+    boxed::Reference::create_into(proc.get_heap_mut(), reference::next_ref_id())
+  },
+  args:
+);
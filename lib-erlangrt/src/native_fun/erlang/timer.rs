@@ -0,0 +1,305 @@
+//! Timer BIFs: `send_after`, `start_timer`, `cancel_timer`, `read_timer`.
+//! Timers live in the timer wheel shared by all schedulers (see
+//! `emulator/timer_wheel.rs`), a timer reference is a local reference with
+//! the timer id.
+use crate::{
+  emulator::{
+    gen_atoms,
+    heap::copy_term,
+    process::Process,
+    timer_wheel::{TimerAction, TimerId},
+    vm::VM,
+  },
+  fail::{self, RtResult},
+  term::{
+    boxed::{self, reference},
+    term_builder::tuple_builder::tuple3,
+    value::{cons, *},
+  },
+};
+
+/// Options for the timer BIFs, given as a list of `{Name, Bool}`.
+struct TimerOptions {
+  abs: bool,
+  is_async: bool,
+  info: bool,
+}
+
+impl TimerOptions {
+  /// Parse the option list, only option names in `allowed` are accepted.
+  fn parse(opts: Term, allowed: &[Term]) -> RtResult<Self> {
+    let mut result = Self {
+      abs: false,
+      is_async: false,
+      info: true,
+    };
+    let tail = cons::for_each(opts, |opt| {
+      if !opt.is_tuple() {
+        return fail::create::badarg();
+      }
+      let tuple_p = opt.get_tuple_ptr();
+      let (name, value) = unsafe {
+        if (*tuple_p).get_arity() != 2 {
+          return fail::create::badarg();
+        }
+        ((*tuple_p).get_element(0), (*tuple_p).get_element(1))
+      };
+      if !allowed.contains(&name) || !value.is_bool() {
+        return fail::create::badarg();
+      }
+      let value = value == gen_atoms::TRUE;
+      match name {
+        gen_atoms::ABS => result.abs = value,
+        gen_atoms::ASYNC => result.is_async = value,
+        _ => result.info = value,
+      }
+      Ok(())
+    })?;
+    if let Some(t) = tail {
+      if t != Term::nil() {
+        return fail::create::badarg();
+      }
+    }
+    Ok(result)
+  }
+}
+
+fn get_timer_id(timer_ref: Term) -> RtResult<TimerId> {
+  match boxed::Reference::get_id(timer_ref) {
+    Some(id) => Ok(id),
+    None => fail::create::badarg(),
+  }
+}
+
+/// Start a timer which sends `msg` to `dest`, if `wrap_in_timeout` is true the
+/// message is `{timeout, TimerRef, Msg}`.
+/// Returns: the timer reference.
+fn start_send_timer(
+  vm: &mut VM,
+  curr_p: &mut Process,
+  time: Term,
+  dest: Term,
+  msg: Term,
+  opts: &TimerOptions,
+  wrap_in_timeout: bool,
+) -> RtResult<Term> {
+  if !time.is_small() || time.get_small_signed() < 0 {
+    return fail::create::badarg();
+  }
+  if !dest.is_local_pid() && !dest.is_atom() {
+    return fail::create::badarg();
+  }
+  let t = time.get_small_unsigned() as u64;
+
+  let id = reference::next_ref_id();
+  let timer_ref = boxed::Reference::create_into(curr_p.get_heap_mut(), id)?;
+  let message = if wrap_in_timeout {
+    tuple3(curr_p.get_heap_mut(), gen_atoms::TIMEOUT, timer_ref, msg)?
+  } else {
+    msg
+  };
+  // The message outlives the process heap, so it is copied to a fragment
+  let (message, fragment) = copy_term::copy_to_fragment(message)?;
+
+  let mut timers = vm.scheduler.timers();
+  let expires = if opts.abs { t } else { timers.monotonic_time() + t };
+  let action = TimerAction::Send {
+    dest,
+    message,
+    fragment,
+  };
+  timers.start(id, expires, action);
+  Ok(timer_ref)
+}
+
+// Spec: erlang:send_after(Time, Dest, Msg) -> TimerRef
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:send_after/3", struct_name: NfErlangSendAfter3, arity: 3,
+  invoke: {
+    let opts = TimerOptions::parse(Term::nil(), &[])?;
+    start_send_timer(vm, curr_p, time, dest, msg, &opts, false)
+  },
+  args: term(time), term(dest), term(msg),
+);
+
+// Spec: erlang:send_after(Time, Dest, Msg, [{abs, Bool}]) -> TimerRef
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:send_after/4", struct_name: NfErlangSendAfter4, arity: 4,
+  invoke: {
+    let opts = TimerOptions::parse(opts, &[gen_atoms::ABS])?;
+    start_send_timer(vm, curr_p, time, dest, msg, &opts, false)
+  },
+  args: term(time), term(dest), term(msg), list(opts),
+);
+
+// Spec: erlang:start_timer(Time, Dest, Msg) -> TimerRef
+// Sends `{timeout, TimerRef, Msg}` when the timer expires.
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:start_timer/3", struct_name: NfErlangStartTimer3, arity: 3,
+  invoke: {
+    let opts = TimerOptions::parse(Term::nil(), &[])?;
+    start_send_timer(vm, curr_p, time, dest, msg, &opts, true)
+  },
+  args: term(time), term(dest), term(msg),
+);
+
+// Spec: erlang:start_timer(Time, Dest, Msg, [{abs, Bool}]) -> TimerRef
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:start_timer/4", struct_name: NfErlangStartTimer4, arity: 4,
+  invoke: {
+    let opts = TimerOptions::parse(opts, &[gen_atoms::ABS])?;
+    start_send_timer(vm, curr_p, time, dest, msg, &opts, true)
+  },
+  args: term(time), term(dest), term(msg), list(opts),
+);
+
+/// Cancel the timer. The result is the time left in milliseconds or `false`
+/// if the timer was not found. With `{async, true}` the result is sent to the
+/// caller as `{cancel_timer, TimerRef, Result}`, with `{info, false}` no
+/// result is given.
+fn cancel_timer(
+  vm: &mut VM,
+  curr_p: &mut Process,
+  timer_ref: Term,
+  opts: &TimerOptions,
+) -> RtResult<Term> {
+  let id = get_timer_id(timer_ref)?;
+  let result = {
+    let mut timers = vm.scheduler.timers();
+    match timers.time_left(id) {
+      Some(left) => {
+        timers.cancel(id);
+        Term::make_small_unsigned(left as usize)
+      }
+      None => gen_atoms::FALSE,
+    }
+  };
+  if !opts.info {
+    return Ok(gen_atoms::OK);
+  }
+  if opts.is_async {
+    let hp = curr_p.get_heap_mut();
+    let reply = tuple3(hp, gen_atoms::CANCEL_TIMER, timer_ref, result)?;
+    curr_p.deliver_message(reply)?;
+    return Ok(gen_atoms::OK);
+  }
+  Ok(result)
+}
+
+// Spec: erlang:cancel_timer(TimerRef) -> Time | false
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:cancel_timer/1", struct_name: NfErlangCancelTimer1, arity: 1,
+  invoke: {
+    let opts = TimerOptions::parse(Term::nil(), &[])?;
+    cancel_timer(vm, curr_p, timer_ref, &opts)
+  },
+  args: term(timer_ref),
+);
+
+// Spec: erlang:cancel_timer(TimerRef, [{async, Bool} | {info, Bool}])
+//   -> Time | false | ok
+define_nativefun!(vm, curr_p, _args,
+  name: "erlang:cancel_timer/2", struct_name: NfErlangCancelTimer2, arity: 2,
+  invoke: {
+    let opts = TimerOptions::parse(opts, &[gen_atoms::ASYNC, gen_atoms::INFO])?;
+    cancel_timer(vm, curr_p, timer_ref, &opts)
+  },
+  args: term(timer_ref), list(opts),
+);
+
+// Spec: erlang:read_timer(TimerRef) -> Time | false
+define_nativefun!(vm, _proc, _args,
+  name: "erlang:read_timer/1", struct_name: NfErlangReadTimer1, arity: 1,
+  invoke: {
+    let id = get_timer_id(timer_ref)?;
+    match vm.scheduler.timers().time_left(id) {
+      Some(left) => Ok(Term::make_small_unsigned(left as usize)),
+      None => Ok(gen_atoms::FALSE),
+    }
+  },
+  args: term(timer_ref),
+);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    command_line_args::ErlStartArgs,
+    emulator::{
+      atom,
+      code::CodePtr,
+      mfa::ModFunArity,
+      runtime_ctx::{
+        call_native_fun::{find_and_call_native_fun, CallBifTarget},
+        Context,
+      },
+      scheduler::{Prio, SliceResult},
+      spawn_options::SpawnOptions,
+    },
+    term::term_builder::{list_builder::build_list_from_slice, tuple_builder::tuple2},
+  };
+  use std::{thread, time::Duration};
+
+  /// Call `erlang:Name/Arity` the way `call_ext` does, the result is in x0.
+  fn call_bif(
+    vm: &mut VM,
+    curr_p: &mut Process,
+    name: &str,
+    args: &[Term],
+  ) -> RtResult<Term> {
+    let mut ctx = Context::new(CodePtr::null());
+    let mfa = ModFunArity::new(gen_atoms::ERLANG, atom::from_str(name), args.len());
+    let target = CallBifTarget::MFArity(mfa);
+    let dst = Term::make_register_x(0);
+    let fail = Term::nil();
+    find_and_call_native_fun(vm, &mut ctx, curr_p, fail, target, args, dst, false)?;
+    Ok(ctx.get_x(0))
+  }
+
+  /// Start timers with `{abs, true}` at time 0, they expire when the wheel
+  /// turns next time and the messages arrive in the order of the timers.
+  #[test]
+  fn test_send_after_4_and_start_timer_4() {
+    let mut args = ErlStartArgs::new(&Vec::new());
+    args.schedulers = Some(1);
+    let mut vm = VM::new(&mut args);
+    let pid = Term::make_local_pid(1);
+    let p = Process::new(pid, Term::nil(), CodePtr::null(), &SpawnOptions::default());
+    vm.register_new_process(pid, p, Prio::Normal);
+    assert_eq!(vm.scheduler.next_process(&vm.processes), Some(pid));
+    let curr_p = unsafe { &mut (*vm.processes.get(pid).unwrap().as_ptr()) };
+
+    let hp = curr_p.get_heap_mut();
+    let abs = tuple2(hp, gen_atoms::ABS, gen_atoms::TRUE).unwrap();
+    let opts = unsafe { build_list_from_slice(&[abs], hp) }.unwrap();
+    let time = Term::small_0();
+    let args = [time, pid, gen_atoms::OK, opts];
+    let send_ref = call_bif(&mut vm, curr_p, "send_after", &args).unwrap();
+    assert!(send_ref.is_local_ref());
+    let args = [time, pid, gen_atoms::ERROR, opts];
+    let timer_ref = call_bif(&mut vm, curr_p, "start_timer", &args).unwrap();
+    assert!(timer_ref.is_local_ref());
+
+    // Only `abs` is a valid option
+    let hp = curr_p.get_heap_mut();
+    let bad_opt = tuple2(hp, gen_atoms::ASYNC, gen_atoms::TRUE).unwrap();
+    let bad_opts = unsafe { build_list_from_slice(&[bad_opt], hp) }.unwrap();
+    let args = [time, pid, gen_atoms::OK, bad_opts];
+    assert!(call_bif(&mut vm, curr_p, "send_after", &args).is_err());
+
+    // The wheel has already turned at this millisecond
+    thread::sleep(Duration::from_millis(2));
+    curr_p.timeslice_result = SliceResult::Yield;
+    assert_eq!(vm.scheduler.next_process(&vm.processes), Some(pid));
+    assert_eq!(curr_p.mailbox.get_current(), Some(gen_atoms::OK));
+    curr_p.mailbox.remove_current();
+    let timeout = curr_p.mailbox.get_current().unwrap();
+    unsafe {
+      let tuple_p = timeout.get_tuple_ptr();
+      assert_eq!((*tuple_p).get_element(0), gen_atoms::TIMEOUT);
+      let ref_id = boxed::Reference::get_id((*tuple_p).get_element(1));
+      assert_eq!(ref_id, boxed::Reference::get_id(timer_ref));
+      assert_eq!((*tuple_p).get_element(2), gen_atoms::ERROR);
+    }
+  }
+}
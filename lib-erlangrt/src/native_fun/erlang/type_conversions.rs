@@ -1,7 +1,14 @@
 use crate::{
-  emulator::{atom, process::Process},
+  big::BigInt,
+  defs::data_reader::TDataReader,
+  emulator::{arith::float, atom, gen_atoms, heap::heap_trait::THeap, process::Process},
   fail::{self, RtResult},
+  rt_util::{
+    ext_term_format,
+    float_format::{self, FloatFormat},
+  },
   term::{
+    boxed,
     value::{cons, Term},
     term_builder::BinaryBuilder,
   },
@@ -28,17 +35,118 @@ pub fn atom_to_list_1(proc: &mut Process, atom_val: Term) -> RtResult<Term> {
 
 // Converts an integer to Erlang string (list of integers)
 define_nativefun!(_vm, proc, args,
-  name: "erlang:integer_to_list/1", struct_name: NfErlangInt2List2, arity: 1,
-  invoke: { integer_to_list_1(proc, val) },
+  name: "erlang:integer_to_list/1", struct_name: NfErlangInt2List1, arity: 1,
+  invoke: { integer_to_list(proc, val, Term::make_small_unsigned(10)) },
   args: term(val),
 );
 
+// Spec: erlang:integer_to_list(Integer, Base) -> string()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:integer_to_list/2", struct_name: NfErlangInt2List2, arity: 2,
+  invoke: { integer_to_list(proc, val, base) },
+  args: term(val), term(base),
+);
+
 #[inline]
-pub fn integer_to_list_1(curr_p: &mut Process, val: Term) -> RtResult<Term> {
+pub fn integer_to_list(curr_p: &mut Process, val: Term, base: Term) -> RtResult<Term> {
+  let base = get_base(base)?;
   if !val.is_integer() {
     return fail::create::badarg();
   }
-  unsafe { cons::integer_to_list(val, curr_p.get_heap_mut()) }
+  unsafe { cons::integer_to_list(val, base, curr_p.get_heap_mut()) }
+}
+
+// Spec: erlang:integer_to_binary(Integer) -> binary()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:integer_to_binary/1", struct_name: NfErlangInt2Bin1, arity: 1,
+  invoke: { integer_to_binary(proc, val, Term::make_small_unsigned(10)) },
+  args: term(val),
+);
+
+// Spec: erlang:integer_to_binary(Integer, Base) -> binary()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:integer_to_binary/2", struct_name: NfErlangInt2Bin2, arity: 2,
+  invoke: { integer_to_binary(proc, val, base) },
+  args: term(val), term(base),
+);
+
+fn integer_to_binary(curr_p: &mut Process, val: Term, base: Term) -> RtResult<Term> {
+  let base = get_base(base)?;
+  match BigInt::from_term(val) {
+    Some(big) => {
+      let text = big.to_str_radix(base);
+      bytes_to_binary(curr_p.get_heap_mut(), text.as_bytes())
+    }
+    None => fail::create::badarg(),
+  }
+}
+
+// Spec: erlang:list_to_integer(String) -> integer()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:list_to_integer/1", struct_name: NfErlangList2Int1, arity: 1,
+  invoke: {
+    text_to_integer(proc, &list_to_bytes(list)?, Term::make_small_unsigned(10))
+  },
+  args: list(list),
+);
+
+// Spec: erlang:list_to_integer(String, Base) -> integer()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:list_to_integer/2", struct_name: NfErlangList2Int2, arity: 2,
+  invoke: { text_to_integer(proc, &list_to_bytes(list)?, base) },
+  args: list(list), term(base),
+);
+
+// Spec: erlang:binary_to_integer(Binary) -> integer()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:binary_to_integer/1", struct_name: NfErlangBin2Int1, arity: 1,
+  invoke: {
+    text_to_integer(proc, &binary_to_bytes(bin)?, Term::make_small_unsigned(10))
+  },
+  args: binary(bin),
+);
+
+// Spec: erlang:binary_to_integer(Binary, Base) -> integer()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:binary_to_integer/2", struct_name: NfErlangBin2Int2, arity: 2,
+  invoke: { text_to_integer(proc, &binary_to_bytes(bin)?, base) },
+  args: binary(bin), term(base),
+);
+
+fn text_to_integer(curr_p: &mut Process, text: &[u8], base: Term) -> RtResult<Term> {
+  let base = get_base(base)?;
+  if let Some(val) = small_from_decimal(text, base) {
+    return Ok(Term::make_small_signed(val));
+  }
+  match BigInt::from_str_radix(text, base) {
+    Some(big) => big.to_term(curr_p.get_heap_mut()),
+    None => fail::create::badarg(),
+  }
+}
+
+/// Parse base 10 text which fits a small integer without making a bignum.
+/// Returns: `None` for other bases, malformed or larger numbers.
+fn small_from_decimal(text: &[u8], base: u32) -> Option<isize> {
+  if base != 10 {
+    return None;
+  }
+  let val = std::str::from_utf8(text).ok()?.parse::<isize>().ok()?;
+  if Term::small_fits(val) {
+    Some(val)
+  } else {
+    None
+  }
+}
+
+/// Base for integer text conversions is from 2 to 36.
+fn get_base(base: Term) -> RtResult<u32> {
+  if !base.is_small() {
+    return fail::create::badarg();
+  }
+  match base.get_small_signed() {
+    b @ 2..=36 => Ok(b as u32),
+    _ => fail::create::badarg(),
+  }
 }
 
 // Returns list `list` reversed with `tail` appended (any term).
@@ -56,7 +164,7 @@ unsafe fn list_to_binary_1(proc: &mut Process, list: Term) -> RtResult<Term> {
   } else {
     let mut bb = BinaryBuilder::with_size(size, proc.get_heap_mut())?;
     list_to_binary_1_recursive(&mut bb, list)?;
-    Ok(bb.make_term())
+    bb.make_term()
   }
 }
 
@@ -78,3 +186,247 @@ unsafe fn list_to_binary_1_recursive(
   })?;
   Ok(list)
 }
+
+// Encode a term in the external term format.
+// Spec: erlang:term_to_binary(Term) -> binary()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:term_to_binary/1", struct_name: NfErlangT2b1, arity: 1,
+  invoke: {
+    let data = ext_term_format::encode(value)?;
+    unsafe {
+      let btrait = boxed::Binary::create_with_data(&data, proc.get_heap_mut())?;
+      (*btrait).make_term()
+    }
+  },
+  args: term(value),
+);
+
+/// Read a proper list of bytes, such as a string of latin-1 characters.
+fn list_to_bytes(list: Term) -> RtResult<Vec<u8>> {
+  let mut bytes = Vec::new();
+  let tail = cons::for_each(list, |elem| {
+    if !elem.is_small() || elem.get_small_unsigned() > 255 {
+      return fail::create::badarg();
+    }
+    bytes.push(elem.get_small_unsigned() as u8);
+    Ok(())
+  })?;
+  if let Some(t) = tail {
+    if t != Term::nil() {
+      return fail::create::badarg();
+    }
+  }
+  Ok(bytes)
+}
+
+/// Read the bytes of a binary, a bitstring is a `badarg`.
+fn binary_to_bytes(bin: Term) -> RtResult<Vec<u8>> {
+  if bin == Term::empty_binary() {
+    return Ok(Vec::new());
+  }
+  let bin_ptr = unsafe { boxed::Binary::get_trait_from_term(bin) };
+  let bit_size = unsafe { (*bin_ptr).get_bit_size() };
+  if bit_size.get_last_byte_bits() != 0 {
+    return fail::create::badarg();
+  }
+  match unsafe { (*bin_ptr).get_byte_reader() } {
+    Some(reader) => {
+      let n_bytes = bit_size.get_bytes_rounded_down();
+      Ok((0..n_bytes).map(|i| reader.read(i)).collect())
+    }
+    // TODO: Binary slices not aligned to a byte
+    None => fail::create::badarg(),
+  }
+}
+
+fn bytes_to_binary(hp: &mut THeap, data: &[u8]) -> RtResult<Term> {
+  if data.is_empty() {
+    return Ok(Term::empty_binary());
+  }
+  unsafe {
+    let btrait = boxed::Binary::create_with_data(data, hp)?;
+    (*btrait).make_term()
+  }
+}
+
+// Converts a number to a float.
+// Spec: erlang:float(Number) -> float()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:float/1", struct_name: NfErlangFloat1, arity: 1,
+  invoke: { float_1(proc, number) },
+  args: term(number),
+);
+
+#[inline]
+fn float_1(curr_p: &mut Process, number: Term) -> RtResult<Term> {
+  if number.is_float() {
+    return Ok(number);
+  }
+  match float::to_f64(number) {
+    // A bignum too large for a float is also a badarg
+    Some(val) if val.is_finite() => Term::make_float(curr_p.get_heap_mut(), val),
+    _ => fail::create::badarg(),
+  }
+}
+
+// Spec: erlang:trunc(Number) -> integer()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:trunc/1", struct_name: NfErlangTrunc1, arity: 1,
+  invoke: { float_to_integer(proc, number, f64::trunc) },
+  args: term(number),
+);
+
+// Rounds half away from zero: `round(2.5)` is 3.
+// Spec: erlang:round(Number) -> integer()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:round/1", struct_name: NfErlangRound1, arity: 1,
+  invoke: { float_to_integer(proc, number, f64::round) },
+  args: term(number),
+);
+
+// Spec: erlang:ceil(Number) -> integer()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:ceil/1", struct_name: NfErlangCeil1, arity: 1,
+  invoke: { float_to_integer(proc, number, f64::ceil) },
+  args: term(number),
+);
+
+// Spec: erlang:floor(Number) -> integer()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:floor/1", struct_name: NfErlangFloor1, arity: 1,
+  invoke: { float_to_integer(proc, number, f64::floor) },
+  args: term(number),
+);
+
+/// Round a float to an integer with `round_fn`, an integer is returned as is.
+fn float_to_integer(
+  curr_p: &mut Process,
+  number: Term,
+  round_fn: fn(f64) -> f64,
+) -> RtResult<Term> {
+  if number.is_integer() {
+    return Ok(number);
+  }
+  if !number.is_float() {
+    return fail::create::badarg();
+  }
+  let val = round_fn(unsafe { number.get_float_unchecked() });
+  BigInt::from_f64(val).to_term(curr_p.get_heap_mut())
+}
+
+/// Parse options of `float_to_list/2` and `float_to_binary/2`, the last of
+/// `{decimals, N}`, `{scientific, N}` and `short` wins.
+fn parse_float_format(opts: Term) -> RtResult<FloatFormat> {
+  let mut fmt = FloatFormat::default();
+  let mut compact = false;
+  let tail = cons::for_each(opts, |opt| {
+    match opt {
+      gen_atoms::COMPACT => compact = true,
+      gen_atoms::SHORT => fmt = FloatFormat::Short,
+      _ if opt.is_tuple() => {
+        let tuple_p = opt.get_tuple_ptr();
+        let (name, value) = unsafe {
+          if (*tuple_p).get_arity() != 2 {
+            return fail::create::badarg();
+          }
+          ((*tuple_p).get_element(0), (*tuple_p).get_element(1))
+        };
+        if !value.is_small() || value.get_small_signed() < 0 {
+          return fail::create::badarg();
+        }
+        let n = value.get_small_unsigned();
+        fmt = match name {
+          gen_atoms::DECIMALS if n <= float_format::MAX_DECIMALS => {
+            FloatFormat::Decimals(n, false)
+          }
+          gen_atoms::SCIENTIFIC if n <= float_format::MAX_SCIENTIFIC => {
+            FloatFormat::Scientific(n)
+          }
+          _ => return fail::create::badarg(),
+        };
+      }
+      _ => return fail::create::badarg(),
+    }
+    Ok(())
+  })?;
+  if let Some(t) = tail {
+    if t != Term::nil() {
+      return fail::create::badarg();
+    }
+  }
+  // Compact only applies to decimals
+  if let FloatFormat::Decimals(n, _) = fmt {
+    fmt = FloatFormat::Decimals(n, compact);
+  }
+  Ok(fmt)
+}
+
+fn float_to_string(val: Term, opts: Term) -> RtResult<String> {
+  if !val.is_float() {
+    return fail::create::badarg();
+  }
+  let fmt = parse_float_format(opts)?;
+  Ok(float_format::format(unsafe { val.get_float_unchecked() }, fmt))
+}
+
+// Spec: erlang:float_to_list(Float) -> string()
+// Same as `float_to_list(Float, [{scientific, 20}])`.
+define_nativefun!(_vm, proc, args,
+  name: "erlang:float_to_list/1", struct_name: NfErlangFloat2List1, arity: 1,
+  invoke: {
+    let s = float_to_string(val, Term::nil())?;
+    unsafe { cons::rust_str_to_list(&s, proc.get_heap_mut()) }
+  },
+  args: term(val),
+);
+
+// Spec: erlang:float_to_list(Float, Options) -> string()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:float_to_list/2", struct_name: NfErlangFloat2List2, arity: 2,
+  invoke: {
+    let s = float_to_string(val, opts)?;
+    unsafe { cons::rust_str_to_list(&s, proc.get_heap_mut()) }
+  },
+  args: term(val), list(opts),
+);
+
+// Spec: erlang:float_to_binary(Float) -> binary()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:float_to_binary/1", struct_name: NfErlangFloat2Bin1, arity: 1,
+  invoke: {
+    let s = float_to_string(val, Term::nil())?;
+    bytes_to_binary(proc.get_heap_mut(), s.as_bytes())
+  },
+  args: term(val),
+);
+
+// Spec: erlang:float_to_binary(Float, Options) -> binary()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:float_to_binary/2", struct_name: NfErlangFloat2Bin2, arity: 2,
+  invoke: {
+    let s = float_to_string(val, opts)?;
+    bytes_to_binary(proc.get_heap_mut(), s.as_bytes())
+  },
+  args: term(val), list(opts),
+);
+
+fn text_to_float(curr_p: &mut Process, text: &[u8]) -> RtResult<Term> {
+  match float_format::parse(text) {
+    Some(val) => Term::make_float(curr_p.get_heap_mut(), val),
+    None => fail::create::badarg(),
+  }
+}
+
+// Spec: erlang:list_to_float(String) -> float()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:list_to_float/1", struct_name: NfErlangList2Float1, arity: 1,
+  invoke: { text_to_float(proc, &list_to_bytes(list)?) },
+  args: list(list),
+);
+
+// Spec: erlang:binary_to_float(Binary) -> float()
+define_nativefun!(_vm, proc, args,
+  name: "erlang:binary_to_float/1", struct_name: NfErlangBin2Float1, arity: 1,
+  invoke: { text_to_float(proc, &binary_to_bytes(bin)?) },
+  args: binary(bin),
+);
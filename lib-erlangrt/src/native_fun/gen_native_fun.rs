@@ -1,6 +1,6 @@
 //! Generated by `codegen/create_gen_bif.py`
 //! Creates a lookup table of BIF functions
-//! Config used: OTP22 
+//! Config used: OTP24 
 #![allow(dead_code)]
 
 use crate::{native_fun, defs::Arity, term::value::*};
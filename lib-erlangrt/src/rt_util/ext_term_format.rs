@@ -1,10 +1,11 @@
 use super::bin_reader::BinaryReader;
 use crate::{
-  defs::{SWord, Word},
-  emulator::{atom, heap::heap_trait::THeap},
-  fail::{RtErr, RtResult},
+  big::{self, BigInt},
+  defs::{SWord, TDataReader, Word},
+  emulator::{atom, gen_atoms, heap::heap_trait::THeap, mfa::ModFunArity},
+  fail::{self, RtErr, RtResult},
   term::{
-    boxed::{self, bignum::sign::Sign},
+    boxed::{self, endianness::Endianness, reference::RefId},
     term_builder::{ListBuilder, TupleBuilder},
     value::Term,
   },
@@ -14,6 +15,8 @@ use crate::{
 #[allow(dead_code)]
 enum Tag {
   ETF = 131,
+  NewPid = 88,
+  NewerReference = 90,
   NewFloat = 70,
   BitBinary = 77,
   AtomCacheRef_ = 82,
@@ -70,7 +73,25 @@ pub fn decode_naked(r: &mut BinaryReader, hp: &mut THeap) -> RtResult<Term> {
 
     x if x == Tag::String as u8 => decode_string(r, hp),
 
-    x if x == Tag::AtomDeprecated as u8 => decode_atom_latin1(r, hp),
+    x if x == Tag::AtomDeprecated as u8 => {
+      let size = r.read_u16be() as Word;
+      decode_atom_latin1(r, size)
+    }
+
+    x if x == Tag::SmallAtomDeprecated as u8 => {
+      let size = r.read_u8() as Word;
+      decode_atom_latin1(r, size)
+    }
+
+    x if x == Tag::AtomUtf8 as u8 => {
+      let size = r.read_u16be() as Word;
+      decode_atom_utf8(r, size)
+    }
+
+    x if x == Tag::SmallAtomUtf8 as u8 => {
+      let size = r.read_u8() as Word;
+      decode_atom_utf8(r, size)
+    }
 
     x if x == Tag::SmallInteger as u8 => decode_u8(r, hp),
 
@@ -100,11 +121,28 @@ pub fn decode_naked(r: &mut BinaryReader, hp: &mut THeap) -> RtResult<Term> {
 
     x if x == Tag::Binary as u8 => decode_binary(r, hp),
 
+    x if x == Tag::NewFloat as u8 => {
+      let val = f64::from_bits(r.read_u64be());
+      Term::make_float(hp, val)
+    }
+
+    x if x == Tag::Float as u8 => decode_float_string(r, hp),
+
     x if x == Tag::Map as u8 => {
       let size = r.read_u32be() as Word;
       decode_map(r, size, hp)
     }
 
+    x if x == Tag::NewReference as u8 => decode_reference(r, hp, false),
+
+    x if x == Tag::NewerReference as u8 => decode_reference(r, hp, true),
+
+    x if x == Tag::Pid as u8 => decode_pid(r, hp, false),
+
+    x if x == Tag::NewPid as u8 => decode_pid(r, hp, true),
+
+    x if x == Tag::Export as u8 => decode_export(r, hp),
+
     _ => {
       let msg = format!(
         "Don't know how to decode ETF value tag 0x{:x} ({})",
@@ -115,16 +153,26 @@ pub fn decode_naked(r: &mut BinaryReader, hp: &mut THeap) -> RtResult<Term> {
   }
 }
 
-/// Given `size`, read digits for a bigint.
+/// Given `size`, read digits for a bigint. A value which fits a small integer
+/// is decoded as a small.
 fn decode_big(r: &mut BinaryReader, size: Word, hp: &mut THeap) -> RtResult<Term> {
-  let sign = if r.read_u8() == 0 {
-    Sign::Positive
-  } else {
-    Sign::Negative
-  };
+  let negative = r.read_u8() != 0;
   let digits = r.read_bytes(size)?;
-  // Determine storage size in words and create
-  unsafe { boxed::Bignum::create_le(hp, sign, digits) }
+  let value = BigInt {
+    negative,
+    digits: big::make_limbs_from_bytes(Endianness::Little, digits),
+  };
+  value.normalize().to_term(hp)
+}
+
+/// Old float format (tag 99): 31 bytes of text printed with `%.20e`, padded
+/// with zeros.
+fn decode_float_string(r: &mut BinaryReader, hp: &mut THeap) -> RtResult<Term> {
+  let text = r.read_str_latin1(31)?;
+  match text.trim_end_matches('\0').parse::<f64>() {
+    Ok(val) => Term::make_float(hp, val),
+    Err(_) => fail(format!("{}Bad float string {:?}", module(), text)),
+  }
 }
 
 fn decode_binary(r: &mut BinaryReader, hp: &mut THeap) -> RtResult<Term> {
@@ -136,7 +184,7 @@ fn decode_binary(r: &mut BinaryReader, hp: &mut THeap) -> RtResult<Term> {
   let data = r.read_bytes(n_bytes)?;
   unsafe {
     let btrait = boxed::Binary::create_with_data(&data, hp)?;
-    Ok((*btrait).make_term())
+    (*btrait).make_term()
   }
 }
 
@@ -161,6 +209,64 @@ fn decode_map(r: &mut BinaryReader, size: usize, hp: &mut THeap) -> RtResult<Ter
   Ok(Term::make_boxed(map_ptr))
 }
 
+/// Read a reference: 16-bit count of ids, node atom, creation (8-bit or 32-bit
+/// for `NewerReference`) and the 32-bit ids. Only local node references are
+/// supported.
+fn decode_reference(
+  r: &mut BinaryReader,
+  hp: &mut THeap,
+  newer: bool,
+) -> RtResult<Term> {
+  let n_ids = r.read_u16be() as usize;
+  let node = decode_naked(r, hp)?;
+  if newer {
+    r.read_u32be();
+  } else {
+    r.read_u8();
+  }
+  if node != gen_atoms::NONODE_NOHOST || n_ids == 0 || n_ids > 3 {
+    let msg = format!("{}Unsupported reference node={} ids={}", module(), node, n_ids);
+    return fail(msg);
+  }
+  let mut id: RefId = [0; 3];
+  for i in 0..n_ids {
+    id[i] = r.read_u32be();
+  }
+  id[0] &= 0x3ffff;
+  boxed::Reference::create_into(hp, id)
+}
+
+/// Read a pid: node atom, 32-bit id and serial, and creation (8-bit or 32-bit
+/// for `NewPid`). A pid on `nonode@nohost` is a local pid.
+fn decode_pid(r: &mut BinaryReader, hp: &mut THeap, new: bool) -> RtResult<Term> {
+  let node = decode_naked(r, hp)?;
+  let id = r.read_u32be() as Word;
+  let serial = r.read_u32be() as Word;
+  if new {
+    r.read_u32be();
+  } else {
+    r.read_u8();
+  }
+  let pindex = (serial << 32) | id;
+  if node == gen_atoms::NONODE_NOHOST {
+    Ok(Term::make_local_pid(pindex))
+  } else {
+    Term::make_remote_pid(hp, node, pindex)
+  }
+}
+
+/// Read an export `fun M:F/Arity`: two atoms and a small integer.
+fn decode_export(r: &mut BinaryReader, hp: &mut THeap) -> RtResult<Term> {
+  let m = decode_naked(r, hp)?;
+  let f = decode_naked(r, hp)?;
+  let arity = decode_naked(r, hp)?;
+  if !m.is_atom() || !f.is_atom() || !arity.is_small() {
+    return fail(format!("{}Bad export {}:{}/{}", module(), m, f, arity));
+  }
+  let mfa = ModFunArity::new(m, f, arity.get_small_unsigned());
+  unsafe { boxed::Export::create_into(hp, &mfa) }
+}
+
 fn decode_u8(r: &mut BinaryReader, _hp: &mut THeap) -> RtResult<Term> {
   let val = r.read_u8();
   Ok(Term::make_small_signed(val as SWord))
@@ -171,9 +277,13 @@ fn decode_s32(r: &mut BinaryReader, _hp: &mut THeap) -> RtResult<Term> {
   Ok(Term::make_small_signed(val as SWord))
 }
 
-fn decode_atom_latin1(r: &mut BinaryReader, _hp: &mut THeap) -> RtResult<Term> {
-  let sz = r.read_u16be();
-  let val = r.read_str_latin1(sz as Word).unwrap();
+fn decode_atom_latin1(r: &mut BinaryReader, size: Word) -> RtResult<Term> {
+  let val = r.read_str_latin1(size)?;
+  Ok(atom::from_str(&val))
+}
+
+fn decode_atom_utf8(r: &mut BinaryReader, size: Word) -> RtResult<Term> {
+  let val = r.read_str_utf8(size)?;
   Ok(atom::from_str(&val))
 }
 
@@ -216,3 +326,281 @@ fn decode_string(r: &mut BinaryReader, hp: &mut THeap) -> RtResult<Term> {
 
   Ok(lb.make_term())
 }
+
+/// Encode a term into external term format, with the ETF tag byte (131u8).
+pub fn encode(t: Term) -> RtResult<Vec<u8>> {
+  let mut out = vec![Tag::ETF as u8];
+  encode_naked(t, &mut out)?;
+  Ok(out)
+}
+
+/// Encode a term without the ETF tag byte, appending the bytes to `out`.
+/// Local funs (closures) can not be encoded and fail with `badarg`.
+pub fn encode_naked(t: Term, out: &mut Vec<u8>) -> RtResult<()> {
+  if t == Term::nil() {
+    out.push(Tag::Nil as u8);
+  } else if t.is_small() {
+    let val = t.get_small_signed();
+    if (0..=255).contains(&val) {
+      out.push(Tag::SmallInteger as u8);
+      out.push(val as u8);
+    } else if (i32::MIN as SWord..=i32::MAX as SWord).contains(&val) {
+      out.push(Tag::Integer as u8);
+      out.extend_from_slice(&(val as i32).to_be_bytes());
+    } else {
+      // Does not fit 32 bits, goes as a bignum of one word
+      let magnitude = val.unsigned_abs();
+      encode_big(val < 0, &[magnitude], out);
+    }
+  } else if t.is_big_int() {
+    let big_p = t.get_box_ptr::<boxed::Bignum>();
+    unsafe { encode_big((*big_p).is_negative(), (*big_p).get_digits(), out) };
+  } else if t.is_atom() {
+    encode_atom(t, out)?;
+  } else if t.is_local_pid() {
+    encode_pid(gen_atoms::NONODE_NOHOST, t.get_term_val_without_tag(), out)?;
+  } else if t.is_external_pid() {
+    let pid_p = t.get_box_ptr::<boxed::ExternalPid>();
+    unsafe { encode_pid((*pid_p).node, (*pid_p).id, out)? };
+  } else if t.is_map() {
+    encode_map(t, out)?;
+  } else if t.is_export() {
+    let mfa = unsafe { (*boxed::Export::const_from_term(t)?).exp.mfa };
+    out.push(Tag::Export as u8);
+    encode_atom(mfa.m, out)?;
+    encode_atom(mfa.f, out)?;
+    encode_naked(Term::make_small_unsigned(mfa.arity), out)?;
+  } else if t.is_cons() {
+    encode_list(t, out)?;
+  } else if t.is_tuple() {
+    let tuple_p = t.get_tuple_ptr();
+    let arity = unsafe { (*tuple_p).get_arity() };
+    if arity <= 255 {
+      out.push(Tag::SmallTuple as u8);
+      out.push(arity as u8);
+    } else {
+      out.push(Tag::LargeTuple as u8);
+      out.extend_from_slice(&(arity as u32).to_be_bytes());
+    }
+    for i in 0..arity {
+      encode_naked(unsafe { (*tuple_p).get_element(i) }, out)?;
+    }
+  } else if t == Term::empty_tuple() {
+    out.push(Tag::SmallTuple as u8);
+    out.push(0);
+  } else if t.is_binary() {
+    encode_binary(t, out)?;
+  } else if t.is_float() {
+    out.push(Tag::NewFloat as u8);
+    out.extend_from_slice(&t.get_float()?.to_bits().to_be_bytes());
+  } else if let Some(id) = boxed::Reference::get_id(t) {
+    encode_reference(id, out)?;
+  } else {
+    return fail::create::badarg();
+  }
+  Ok(())
+}
+
+/// Encode an atom as `SmallAtomUtf8` or `AtomUtf8` if the name is longer than
+/// 255 bytes.
+fn encode_atom(t: Term, out: &mut Vec<u8>) -> RtResult<()> {
+  let name = atom::to_str(t)?;
+  if name.len() <= 255 {
+    out.push(Tag::SmallAtomUtf8 as u8);
+    out.push(name.len() as u8);
+  } else {
+    out.push(Tag::AtomUtf8 as u8);
+    out.extend_from_slice(&(name.len() as u16).to_be_bytes());
+  }
+  out.extend_from_slice(name.as_bytes());
+  Ok(())
+}
+
+/// Encode an integer as `SmallBig` or `LargeBig`: byte count, sign and the
+/// magnitude bytes starting from the least significant. `digits` are the
+/// words of the magnitude, least significant first.
+fn encode_big(negative: bool, digits: &[Word], out: &mut Vec<u8>) {
+  let mut bytes: Vec<u8> = digits.iter().flat_map(|d| d.to_le_bytes().to_vec()).collect();
+  while bytes.len() > 1 && bytes[bytes.len() - 1] == 0 {
+    bytes.pop();
+  }
+  if bytes.len() <= 255 {
+    out.push(Tag::SmallBig as u8);
+    out.push(bytes.len() as u8);
+  } else {
+    out.push(Tag::LargeBig as u8);
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+  }
+  out.push(negative as u8);
+  out.extend_from_slice(&bytes);
+}
+
+/// Encode a pid as `NewPid`: the node, low and high 32 bits of the process
+/// index as id and serial, and creation 0.
+fn encode_pid(node: Term, pindex: Word, out: &mut Vec<u8>) -> RtResult<()> {
+  out.push(Tag::NewPid as u8);
+  encode_atom(node, out)?;
+  out.extend_from_slice(&(pindex as u32).to_be_bytes());
+  out.extend_from_slice(&((pindex as u64 >> 32) as u32).to_be_bytes());
+  out.extend_from_slice(&0u32.to_be_bytes());
+  Ok(())
+}
+
+/// Encode a map as tag 116 (Map): 32-bit pair count, then keys and values.
+fn encode_map(t: Term, out: &mut Vec<u8>) -> RtResult<()> {
+  let map_p = t.get_box_ptr::<boxed::Map>();
+  let count = unsafe { (*map_p).get_count() };
+  out.push(Tag::Map as u8);
+  out.extend_from_slice(&(count as u32).to_be_bytes());
+  for i in 0..count {
+    let (key, value) = unsafe { boxed::Map::get_pair(map_p, i) };
+    encode_naked(key, out)?;
+    encode_naked(value, out)?;
+  }
+  Ok(())
+}
+
+/// Encode a list as tag 108 (List): 32-bit length, elements and the tail.
+fn encode_list(t: Term, out: &mut Vec<u8>) -> RtResult<()> {
+  let count_pos = out.len();
+  out.push(Tag::List as u8);
+  out.extend_from_slice(&[0u8; 4]);
+  let mut n_elem = 0u32;
+  let mut curr = t;
+  while curr.is_cons() {
+    let cons_p = curr.get_cons_ptr();
+    unsafe {
+      encode_naked((*cons_p).hd(), out)?;
+      curr = (*cons_p).tl();
+    }
+    n_elem += 1;
+  }
+  encode_naked(curr, out)?;
+  out[count_pos + 1..count_pos + 5].copy_from_slice(&n_elem.to_be_bytes());
+  Ok(())
+}
+
+/// Encode a binary as tag 109 (Binary), or a bitstring as tag 77
+/// (BitBinary) with the count of bits used in the last byte.
+fn encode_binary(t: Term, out: &mut Vec<u8>) -> RtResult<()> {
+  if t == Term::empty_binary() {
+    out.push(Tag::Binary as u8);
+    out.extend_from_slice(&0u32.to_be_bytes());
+    return Ok(());
+  }
+  let bin_p = unsafe { boxed::Binary::get_trait_from_term(t) };
+  let size = unsafe { (*bin_p).get_bit_size() };
+  let n_bytes = size.get_byte_size_rounded_up().bytes();
+  if size.get_last_byte_bits() == 0 {
+    out.push(Tag::Binary as u8);
+    out.extend_from_slice(&(n_bytes as u32).to_be_bytes());
+  } else {
+    out.push(Tag::BitBinary as u8);
+    out.extend_from_slice(&(n_bytes as u32).to_be_bytes());
+    out.push(size.get_last_byte_bits() as u8);
+  }
+  // A sub-binary may start at any bit, read it with a bit reader then
+  match unsafe { (*bin_p).get_byte_reader() } {
+    Some(reader) => out.extend((0..n_bytes).map(|i| reader.read(i))),
+    None => {
+      let reader = unsafe { (*bin_p).get_bit_reader() };
+      out.extend((0..n_bytes).map(|i| reader.read(i)))
+    }
+  }
+  Ok(())
+}
+
+/// Encode a local reference as `NewReference` on node `nonode@nohost` with
+/// creation 0.
+fn encode_reference(id: RefId, out: &mut Vec<u8>) -> RtResult<()> {
+  out.push(Tag::NewReference as u8);
+  out.extend_from_slice(&(id.len() as u16).to_be_bytes());
+  encode_naked(gen_atoms::NONODE_NOHOST, out)?;
+  out.push(0);
+  for word in id.iter() {
+    out.extend_from_slice(&word.to_be_bytes());
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    emulator::heap::{Designation, Heap},
+    term::{boxed::reference, term_builder::tuple_builder::tuple2},
+  };
+
+  #[test]
+  fn test_reference_roundtrip() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let id = reference::next_ref_id();
+    let ref_term = boxed::Reference::create_into(&mut hp, id).unwrap();
+    let t = tuple2(&mut hp, ref_term, gen_atoms::OK).unwrap();
+
+    let encoded = encode(t).unwrap();
+    let mut r = BinaryReader::from_bytes(encoded);
+    let decoded = decode(&mut r, &mut hp).unwrap();
+
+    let tuple_p = decoded.get_tuple_ptr();
+    let decoded_ref = unsafe { (*tuple_p).get_element(0) };
+    assert_ne!(decoded_ref, ref_term);
+    assert_eq!(
+      boxed::Reference::get_id(decoded_ref),
+      boxed::Reference::get_id(ref_term)
+    );
+    assert_eq!(format!("{}", decoded), format!("{}", t));
+  }
+
+  #[test]
+  fn test_float_roundtrip() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let t = Term::make_float(&mut hp, -1.5e300).unwrap();
+    let mut r = BinaryReader::from_bytes(encode(t).unwrap());
+    assert_eq!(decode(&mut r, &mut hp).unwrap().get_float().unwrap(), -1.5e300);
+
+    // Float as text, the way old OTP versions have encoded it
+    let mut etf = vec![Tag::ETF as u8, Tag::Float as u8];
+    etf.extend_from_slice(b"2.50000000000000000000e+00");
+    etf.resize(33, 0);
+    let mut r = BinaryReader::from_bytes(etf);
+    assert_eq!(decode(&mut r, &mut hp).unwrap().get_float().unwrap(), 2.5);
+  }
+
+  #[test]
+  fn test_encode_pids_integers_and_maps() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let pid = Term::make_local_pid(5);
+    let large = Term::make_small_signed(-(1 << 40));
+    let map_p = boxed::Map::create_into(&mut hp, 1).unwrap();
+    unsafe { boxed::Map::add(map_p, gen_atoms::OK, pid).unwrap() };
+    let map = Term::make_boxed(map_p);
+    let t = tuple2(&mut hp, large, map).unwrap();
+
+    let encoded = encode(t).unwrap();
+    // Atoms go as SmallAtomUtf8, a pid as NewPid
+    let ok = [Tag::SmallAtomUtf8 as u8, 2, b'o', b'k'];
+    assert!(encoded.windows(ok.len()).any(|w| w == ok));
+    assert!(encoded.contains(&(Tag::NewPid as u8)));
+    let mut r = BinaryReader::from_bytes(encoded);
+    let decoded = decode(&mut r, &mut hp).unwrap();
+    let tuple_p = decoded.get_tuple_ptr();
+    unsafe {
+      assert_eq!((*tuple_p).get_element(0), large);
+      let map_p = (*tuple_p).get_element(1).get_box_ptr::<boxed::Map>();
+      assert_eq!(boxed::Map::get_pair(map_p, 0), (gen_atoms::OK, pid));
+    }
+
+    // One more than a word, as SmallBig
+    let value = BigInt {
+      negative: false,
+      digits: vec![0, 1],
+    };
+    let big = value.to_term(&mut hp).unwrap();
+    let encoded = encode(big).unwrap();
+    let n_bytes = crate::defs::WORD_BYTES as u8 + 1;
+    assert_eq!(&encoded[1..4], &[Tag::SmallBig as u8, n_bytes, 0]);
+    let mut r = BinaryReader::from_bytes(encoded);
+    assert_eq!(format!("{}", decode(&mut r, &mut hp).unwrap()), format!("{}", big));
+  }
+}
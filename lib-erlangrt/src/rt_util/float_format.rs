@@ -0,0 +1,209 @@
+//! Float to text and back, the way `float_to_list/2` and `list_to_float/1`
+//! in OTP do it.
+
+/// Largest decimals or scientific digits accepted by OTP.
+pub const MAX_DECIMALS: usize = 253;
+pub const MAX_SCIENTIFIC: usize = 249;
+
+/// Largest float with every integer below it exactly representable.
+const MAX_EXACT_INTEGER: f64 = (1u64 << 53) as f64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FloatFormat {
+  /// `{scientific, N}`, like `%.Ne` in C, the default is 20 digits
+  Scientific(usize),
+  /// `{decimals, N}` with optional `compact`, which drops trailing zeros
+  Decimals(usize, bool),
+  /// `short`, the fewest digits which parse back to the same float
+  Short,
+}
+
+impl Default for FloatFormat {
+  fn default() -> Self {
+    FloatFormat::Scientific(20)
+  }
+}
+
+/// Format a finite float.
+pub fn format(val: f64, fmt: FloatFormat) -> String {
+  debug_assert!(val.is_finite());
+  match fmt {
+    FloatFormat::Scientific(digits) => format_scientific(val, digits),
+    FloatFormat::Decimals(decimals, compact) => format_decimals(val, decimals, compact),
+    FloatFormat::Short => format_short(val),
+  }
+}
+
+/// Rust prints `1.5e-7`, C prints the exponent with a sign and at least two
+/// digits: `1.5e-07`.
+fn format_scientific(val: f64, digits: usize) -> String {
+  let s = format!("{:.*e}", digits, val);
+  let (mantissa, exp) = s.split_at(s.find('e').unwrap());
+  let exp: i32 = exp[1..].parse().unwrap();
+  let sign = if exp < 0 { '-' } else { '+' };
+  format!("{}e{}{:02}", mantissa, sign, exp.abs())
+}
+
+/// Numbers and precisions which fit an integer are rounded half away from
+/// zero after scaling by a power of 10, as in OTP, so `0.125` with 2 decimals
+/// is `0.13`. Other numbers use the exact decimal expansion.
+fn format_decimals(val: f64, decimals: usize, compact: bool) -> String {
+  let magnitude = val.abs();
+  let scale = 10f64.powi(decimals as i32);
+  let mut s = if decimals < 16 && magnitude * scale < MAX_EXACT_INTEGER {
+    let scale = scale as u64;
+    let scaled = (magnitude * scale as f64 + 0.5) as u64;
+    let int_part = scaled / scale;
+    if decimals == 0 {
+      format!("{}", int_part)
+    } else {
+      format!("{}.{:0width$}", int_part, scaled % scale, width = decimals)
+    }
+  } else {
+    format!("{:.*}", decimals, magnitude)
+  };
+  if compact && decimals > 0 {
+    // Keep one digit after the point
+    let trimmed = s.trim_end_matches('0').len().max(s.find('.').unwrap() + 2);
+    s.truncate(trimmed);
+  }
+  if val.is_sign_negative() && val != 0.0 {
+    s.insert(0, '-');
+  }
+  s
+}
+
+/// Shortest digits which parse back to the same float, placed the same way
+/// as `io_lib:format("~p")` does: with an exponent if that is shorter than
+/// padding the digits with zeros.
+fn format_short(val: f64) -> String {
+  if val == 0.0 {
+    return if val.is_sign_negative() { "-0.0" } else { "0.0" }.to_string();
+  }
+  // Rust prints the shortest digits in the form `d.ddde-X`
+  let s = format!("{:e}", val.abs());
+  let (mantissa, exp) = s.split_at(s.find('e').unwrap());
+  let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+  let exp: i32 = exp[1..].parse().unwrap();
+
+  // The value is `0.digits * 10^place`
+  let place = exp + 1;
+  let len = digits.len() as i32;
+  let exp_str = exp.to_string();
+  let exp_dot = if len == 1 { 2 } else { 1 };
+  let exp_cost = exp_str.len() as i32 + 1 + exp_dot;
+
+  let text = if place == 0 {
+    format!("0.{}", digits)
+  } else if place < 0 {
+    if 2 - place <= exp_cost {
+      format!("0.{}{}", "0".repeat(-place as usize), digits)
+    } else {
+      insert_exp(&digits, &exp_str)
+    }
+  } else if place >= len {
+    if place - len + 2 <= exp_cost {
+      format!("{}{}.0", digits, "0".repeat((place - len) as usize))
+    } else {
+      insert_exp(&digits, &exp_str)
+    }
+  } else {
+    let (int_part, frac_part) = digits.split_at(place as usize);
+    format!("{}.{}", int_part, frac_part)
+  };
+  if val < 0.0 {
+    format!("-{}", text)
+  } else {
+    text
+  }
+}
+
+fn insert_exp(digits: &str, exp_str: &str) -> String {
+  let (first, rest) = digits.split_at(1);
+  let rest = if rest.is_empty() { "0" } else { rest };
+  format!("{}.{}e{}", first, rest, exp_str)
+}
+
+/// Parse a float written as Erlang does: an optional sign, digits, a point,
+/// digits and an optional exponent, like `-1.5e10`.
+/// Returns: `None` if the text is not a float or is out of range.
+pub fn parse(text: &[u8]) -> Option<f64> {
+  let mut pos = 0;
+  let skip_digits = |pos: &mut usize| {
+    let start = *pos;
+    while *pos < text.len() && text[*pos].is_ascii_digit() {
+      *pos += 1;
+    }
+    *pos > start
+  };
+
+  if pos < text.len() && (text[pos] == b'-' || text[pos] == b'+') {
+    pos += 1;
+  }
+  if !skip_digits(&mut pos) || pos >= text.len() || text[pos] != b'.' {
+    return None;
+  }
+  pos += 1;
+  if !skip_digits(&mut pos) {
+    return None;
+  }
+  if pos < text.len() && (text[pos] == b'e' || text[pos] == b'E') {
+    pos += 1;
+    if pos < text.len() && (text[pos] == b'-' || text[pos] == b'+') {
+      pos += 1;
+    }
+    if !skip_digits(&mut pos) {
+      return None;
+    }
+  }
+  if pos != text.len() {
+    return None;
+  }
+  // Only ASCII was accepted above
+  let val: f64 = core::str::from_utf8(text).ok()?.parse().ok()?;
+  if val.is_finite() {
+    Some(val)
+  } else {
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_format() {
+    let sci = FloatFormat::default();
+    assert_eq!(format(7.12, sci), "7.12000000000000010658e+00");
+    assert_eq!(format(-1.0e-100, FloatFormat::Scientific(3)), "-1.000e-100");
+
+    assert_eq!(format(7.12, FloatFormat::Decimals(4, false)), "7.1200");
+    assert_eq!(format(7.12, FloatFormat::Decimals(4, true)), "7.12");
+    assert_eq!(format(7.0, FloatFormat::Decimals(4, true)), "7.0");
+    assert_eq!(format(0.125, FloatFormat::Decimals(2, false)), "0.13");
+    assert_eq!(format(-0.5, FloatFormat::Decimals(0, false)), "-1");
+    let large = format(1.0e20, FloatFormat::Decimals(1, false));
+    assert_eq!(large, "100000000000000000000.0");
+
+    let short = |v| format(v, FloatFormat::Short);
+    assert_eq!(short(7.12), "7.12");
+    assert_eq!(short(0.1 + 0.2), "0.30000000000000004");
+    assert_eq!(short(100.0), "100.0");
+    assert_eq!(short(1000.0), "1.0e3");
+    assert_eq!(short(0.0001), "0.0001");
+    assert_eq!(short(0.00001), "1.0e-5");
+    assert_eq!(short(-1.5e300), "-1.5e300");
+    assert_eq!(short(-0.0), "-0.0");
+  }
+
+  #[test]
+  fn test_parse() {
+    assert_eq!(parse(b"1.5"), Some(1.5));
+    assert_eq!(parse(b"-2.5E-3"), Some(-0.0025));
+    assert_eq!(parse(b"+1.0e+2"), Some(100.0));
+    for bad in [&b"1"[..], b"1.", b".5", b"1e5", b"1.0e", b" 1.0", b"1.0e999"].iter() {
+      assert_eq!(parse(bad), None, "{:?}", core::str::from_utf8(bad));
+    }
+  }
+}
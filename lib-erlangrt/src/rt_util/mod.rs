@@ -1,3 +1,4 @@
 pub mod bin_reader;
 pub mod ext_term_format;
+pub mod float_format;
 pub mod print;
@@ -1,19 +0,0 @@
-#![allow(dead_code)]
-
-use crate::term::value::*;
-
-pub fn add(_a: Term, _b: Term) -> Term {
-  // TODO: If a is small, check that b is small or big or float
-  // TODO: If a is big, check b is small or big or float
-  // TODO: If a is float, check b is small or big or float
-
-  Term::nil()
-}
-
-pub fn subtract(_a: Term, _b: Term) -> Term {
-  // TODO: If a is small, check that b is small or big or float
-  // TODO: If a is big, check b is small or big or float
-  // TODO: If a is float, check b is small or big or float
-
-  Term::nil()
-}
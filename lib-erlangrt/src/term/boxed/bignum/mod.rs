@@ -1,7 +1,6 @@
 use core::{mem::size_of, ptr};
 
 use crate::{
-  big,
   defs::{self, ByteSize, WordSize},
   fail::{RtErr, RtResult},
   term::{
@@ -16,10 +15,7 @@ use crate::{
 };
 
 use self::sign::*;
-use crate::{
-  emulator::heap::heap_trait::THeap,
-  term::boxed::{bignum, endianness::Endianness},
-};
+use crate::emulator::heap::heap_trait::THeap;
 
 pub mod endianness;
 pub mod sign;
@@ -48,9 +44,12 @@ impl TBoxed for Bignum {
 }
 
 impl Bignum {
-  const fn storage_size() -> WordSize {
-    // This impl stores bignum in dynamic heap with the num library
-    ByteSize::new(size_of::<Bignum>()).get_words_rounded_up()
+  /// Size of a bignum in memory with the header and `n_digits` limbs, one of
+  /// which is already included in the struct.
+  const fn storage_size(n_digits: usize) -> WordSize {
+    ByteSize::new(size_of::<Bignum>())
+      .get_words_rounded_up()
+      .add(n_digits - 1)
   }
 
   /// Create bignum for one isize
@@ -65,18 +64,6 @@ impl Bignum {
     unsafe { Self::create_into(hp, sign, limbs) }
   }
 
-  /// Given an array of bytes with little-endian order, create a bignum on the
-  /// provided heap, or fail.
-  pub unsafe fn create_le(
-    hp: &mut THeap,
-    sign: bignum::sign::Sign,
-    digits: Vec<u8>,
-  ) -> RtResult<Term> {
-    let limbs = big::make_limbs_from_bytes(Endianness::Little, digits);
-    let p = Self::create_into(hp, sign, &limbs)?;
-    Ok(Term::make_boxed(p))
-  }
-
   /// Consume bytes as either big- or little-endian stream, and build a big
   /// integer on heap.
   pub unsafe fn create_into(
@@ -84,7 +71,8 @@ impl Bignum {
     sign: Sign,
     limbs: &[Digit],
   ) -> RtResult<*mut Self> {
-    let n_words = Self::storage_size();
+    debug_assert!(!limbs.is_empty());
+    let n_words = Self::storage_size(limbs.len());
     let this = hp.alloc(n_words, false)? as *mut Self;
 
     ptr::write(
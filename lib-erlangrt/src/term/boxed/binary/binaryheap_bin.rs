@@ -1,5 +1,5 @@
 use crate::{
-  defs::{BitReader, BitSize, ByteReader, ByteSize, WordSize},
+  defs::{BitReader, BitSize, ByteReader, ByteSize, Word, WordSize},
   fail::{RtErr, RtResult},
   term::{
     boxed::{
@@ -9,12 +9,22 @@ use crate::{
     value::Term,
   },
 };
-use core::ptr;
+use core::{
+  ptr,
+  sync::atomic::{self, AtomicUsize, Ordering},
+};
+use std::alloc::{self, Layout};
 
-/// Defines operations with a binary on the binary heap
-/// Pointer to this can be directly casted from pointer to boxed::Binary
+/// Defines operations with a binary on the binary heap.
+/// The binary heap binary lives outside of process heaps, and is shared by
+/// the processes which refer to it via `ReferenceToBinary` objects on their
+/// heaps. The memory is freed when the last reference is released.
+/// Terms never point to this directly, always via a `ReferenceToBinary`.
+#[repr(C)]
 pub struct BinaryHeapBinary {
   pub bin_header: Binary,
+  /// How many `ReferenceToBinary` objects point here
+  refc: AtomicUsize,
   pub size: BitSize,
   pub data: usize, // first 8 (or 4) bytes of data begin here
 }
@@ -25,6 +35,51 @@ impl BinaryHeapBinary {
     // The size is `BinaryHeapBinary` in words rounded up + storage bytes rounded up
     header_size.get_words_rounded_up() + size.get_words_rounded_up()
   }
+
+  fn layout(storage_size: WordSize) -> Layout {
+    Layout::from_size_align(storage_size.bytes(), core::mem::align_of::<Word>())
+      .unwrap()
+  }
+
+  /// Allocate memory for a binary of `size` outside of any process heap. The
+  /// new binary has refcount of 1, owned by the caller.
+  pub unsafe fn create(size: BitSize) -> *mut Self {
+    let storage_sz = Self::storage_size(size);
+    let layout = Self::layout(storage_sz);
+    let this = alloc::alloc(layout) as *mut Self;
+    if this.is_null() {
+      alloc::handle_alloc_error(layout);
+    }
+    let new_self = Self {
+      bin_header: Binary::new(BinaryType::BinaryHeap, storage_sz),
+      refc: AtomicUsize::new(1),
+      size,
+      data: 0,
+    };
+    ptr::write(this, new_self);
+    this
+  }
+
+  /// Add one more reference, when another `ReferenceToBinary` is created.
+  #[inline]
+  pub unsafe fn add_ref(this: *mut Self) {
+    (*this).refc.fetch_add(1, Ordering::Relaxed);
+  }
+
+  /// Release one reference, free the memory if it was the last one.
+  pub unsafe fn release(this: *mut Self) {
+    if (*this).refc.fetch_sub(1, Ordering::Release) != 1 {
+      return;
+    }
+    atomic::fence(Ordering::Acquire);
+    let layout = Self::layout(Self::storage_size((*this).size));
+    alloc::dealloc(this as *mut u8, layout);
+  }
+
+  #[inline]
+  pub fn get_refcount(&self) -> usize {
+    self.refc.load(Ordering::Relaxed)
+  }
 }
 
 impl TBinary for BinaryHeapBinary {
@@ -79,8 +134,9 @@ impl TBinary for BinaryHeapBinary {
     Ok(())
   }
 
-  fn make_term(&self) -> Term {
-    Term::make_boxed((&self.bin_header) as *const Binary)
+  /// A binary heap binary can only be referred to via `ReferenceToBinary`.
+  fn make_term(&self) -> RtResult<Term> {
+    Err(RtErr::CannotMakeTermFromBinheap)
   }
 
   unsafe fn put_integer(
@@ -7,7 +7,8 @@ use crate::{
       self,
       binary::trait_interface::TBinary,
       boxtype::{self, BoxType},
-      trait_interface::TBoxed,
+      trait_interface::{InplaceMapFn, TBoxed},
+      Binary,
     },
     classify,
   },
@@ -16,7 +17,7 @@ use core::ptr;
 
 /// Binary match buffer is a part of `BinaryMatchState`
 struct MatchBuffer {
-  // TODO: Make sure this is detected when garbage collected
+  /// Relocated by the GC, see `BinaryMatchState::inplace_map`
   pub orig: *const TBinary,
   /// The window begins at bit offset 0 always, and `start_at` will advance
   /// forward as we are reading from the binary.
@@ -51,6 +52,11 @@ impl TBoxed for BinaryMatchState {
   fn get_type(&self) -> BoxType {
     boxtype::BOXTYPETAG_BINARY_MATCH_STATE
   }
+
+  fn inplace_map(&mut self, mapfn: &mut InplaceMapFn) {
+    let orig = self.match_buffer.orig;
+    self.match_buffer.orig = unsafe { Binary::inplace_map_trait_ptr(orig, mapfn) };
+  }
 }
 
 impl BinaryMatchState {
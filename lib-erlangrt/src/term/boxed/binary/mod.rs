@@ -2,7 +2,7 @@ use core::fmt;
 
 use crate::{
   defs::{self, data_reader::TDataReader, BitSize, ByteSize, WordSize},
-  emulator::{heap::heap_trait::THeap, vm::VM},
+  emulator::heap::heap_trait::THeap,
   fail::{RtErr, RtResult},
   term::{
     boxed::{
@@ -11,7 +11,7 @@ use crate::{
         refc_bin::ReferenceToBinary, slice::BinarySlice, trait_interface::TBinary,
       },
       boxtype::{self, BoxType},
-      trait_interface::TBoxed,
+      trait_interface::{InplaceMapFn, TBoxed},
       BoxHeader,
     },
     classify,
@@ -47,7 +47,10 @@ pub enum BinaryType {
 }
 
 /// Binary which stores everything in its allocated memory on process heap.
+/// Binary subtypes begin with this struct and are cast from a pointer to it,
+/// so they all use C layout to keep the fields in the declared order.
 #[allow(dead_code)]
+#[repr(C)]
 pub struct Binary {
   header: BoxHeader,
   /// Based on the bin_type, the pointer should be converted to one of binary
@@ -65,30 +68,42 @@ impl TBoxed for Binary {
   fn get_type(&self) -> BoxType {
     boxtype::BOXTYPETAG_BINARY
   }
+
+  fn inplace_map(&mut self, mapfn: &mut InplaceMapFn) {
+    // Only slices refer to other heap objects
+    if let BinaryType::Slice = self.bin_type {
+      let slice_p = self as *mut Binary as *mut BinarySlice;
+      unsafe {
+        (*slice_p).orig = Self::inplace_map_trait_ptr((*slice_p).orig, mapfn);
+      }
+    }
+  }
+
+  fn on_destroy(&mut self) {
+    // Only references to the binary heap are registered as off-heap objects
+    if let BinaryType::RefToBinaryHeap = self.bin_type {
+      let refbin_p = self as *mut Binary as *mut ReferenceToBinary;
+      unsafe { ReferenceToBinary::on_destroy(refbin_p) }
+    }
+  }
 }
 
 impl Binary {
-  /// For binary of given size ensure that the heap has enough space on it,
-  /// and if a large binary is to be created, also check the binary heap capacity.
-  pub fn ensure_memory_for_binary(
-    vm: &mut VM,
-    hp: &mut THeap,
-    size: BitSize,
-    extra_memory: WordSize,
-  ) -> RtResult<()> {
-    if size.get_byte_size_rounded_up().bytes() <= ProcessHeapBinary::ONHEAP_THRESHOLD {
-      return hp.allocate_intent_no_gc(ProcessHeapBinary::storage_size(size) + extra_memory);
+  /// For binary of given size return how much process heap will be used by
+  /// `create_into`. Large binaries only take space for a reference, and their
+  /// data goes to the binary heap.
+  pub fn storage_size_for_creation(size: BitSize) -> WordSize {
+    match Self::get_binary_type_for_creation(size) {
+      BinaryType::ProcessHeap => ProcessHeapBinary::storage_size(size),
+      _ => ReferenceToBinary::storage_size(),
     }
-    vm.binary_heap
-      .allocate_intent_no_gc(BinaryHeapBinary::storage_size(size))?;
-    return hp.allocate_intent_no_gc(ReferenceToBinary::storage_size() + extra_memory);
   }
 
   fn get_binary_type_for_creation(size: BitSize) -> BinaryType {
     if size.get_byte_size_rounded_up().bytes() <= ProcessHeapBinary::ONHEAP_THRESHOLD {
       return BinaryType::ProcessHeap;
     }
-    BinaryType::BinaryHeap
+    BinaryType::RefToBinaryHeap
   }
 
   fn new(bin_type: BinaryType, storage_size: WordSize) -> Binary {
@@ -112,8 +127,11 @@ impl Binary {
     let b_type = Self::get_binary_type_for_creation(size);
     match b_type {
       BinaryType::ProcessHeap => ProcessHeapBinary::create_into(size, hp),
-      BinaryType::BinaryHeap => unimplemented!("create binary on the binary heap"),
-      BinaryType::RefToBinaryHeap => unimplemented!("create ref to binary heap"),
+      BinaryType::RefToBinaryHeap => {
+        let storage = BinaryHeapBinary::create(size);
+        ReferenceToBinary::create_into(storage, hp)
+      }
+      BinaryType::BinaryHeap => panic!("Can't create binary heap storage here"),
       BinaryType::Slice => panic!("Can't create slice here"),
     }
   }
@@ -177,6 +195,17 @@ impl Binary {
     )
   }
 
+  /// Given a trait pointer to a binary, let `mapfn` update it as if it was a
+  /// boxed term (for example relocate it during GC), then rebuild the trait
+  /// pointer for the new location.
+  pub unsafe fn inplace_map_trait_ptr(
+    bin: *const TBinary,
+    mapfn: &mut InplaceMapFn,
+  ) -> *const TBinary {
+    let new_term = mapfn(Term::make_boxed(bin as *const Binary));
+    Self::get_trait(new_term.get_box_ptr_unchecked::<Binary>())
+  }
+
   /// Convert a VM term representation into a dynamic dispatch Rust trait
   pub unsafe fn get_trait_from_term(t: Term) -> *const TBinary {
     let bin_p = t.get_box_ptr::<Binary>();
@@ -15,6 +15,7 @@ use core::ptr;
 
 /// Defines operations with a binary on process heap.
 /// Pointer to this can be directly casted from pointer to boxed::Binary
+#[repr(C)]
 pub struct ProcessHeapBinary {
   pub bin_header: boxed::binary::Binary,
   pub size: BitSize,
@@ -73,8 +74,8 @@ impl TBinary for ProcessHeapBinary {
     Ok(())
   }
 
-  fn make_term(&self) -> Term {
-    Term::make_boxed((&self.bin_header) as *const Binary)
+  fn make_term(&self) -> RtResult<Term> {
+    Ok(Term::make_boxed((&self.bin_header) as *const Binary))
   }
 
   unsafe fn put_integer(
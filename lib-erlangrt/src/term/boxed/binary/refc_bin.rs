@@ -1,6 +1,7 @@
 use crate::{
-  defs::{BitReader, BitSize, ByteReader, ByteSize, Word, WordSize},
-  fail::{RtErr, RtResult},
+  defs::{BitReader, BitSize, ByteReader, ByteSize, WordSize},
+  emulator::heap::heap_trait::THeap,
+  fail::RtResult,
   term::{
     boxed::{
       binary::{binaryheap_bin::BinaryHeapBinary, trait_interface::TBinary, BinaryType},
@@ -9,13 +10,17 @@ use crate::{
     value::Term,
   },
 };
+use core::ptr;
 
 /// Defines operations with reference to binary.
 /// Pointer to this can be directly casted from pointer to boxed::Binary
+/// Lives on a process heap and holds one reference to a binary on the binary
+/// heap. The reference is released when the GC finds this object dead (it is
+/// registered with `THeap::register_off_heap` for that).
+#[repr(C)]
 pub struct ReferenceToBinary {
   pub bin_header: Binary,
   pub size: BitSize,
-  refc: Word,
   pub pointer: *mut BinaryHeapBinary,
 }
 
@@ -25,12 +30,36 @@ impl ReferenceToBinary {
     header_size.get_words_rounded_up()
   }
 
-  #[allow(dead_code)]
+  /// Create a reference on the heap `hp`, taking ownership of one reference
+  /// to `pointer` (either new, or added with `BinaryHeapBinary::add_ref`).
+  /// On failure the ownership is released.
+  pub unsafe fn create_into(
+    pointer: *mut BinaryHeapBinary,
+    hp: &mut THeap,
+  ) -> RtResult<*mut TBinary> {
+    let storage_sz = Self::storage_size();
+    let this = match hp.alloc(storage_sz, false) {
+      Ok(p) => p as *mut Self,
+      Err(e) => {
+        BinaryHeapBinary::release(pointer);
+        return Err(e);
+      }
+    };
+
+    let new_self = Self {
+      bin_header: Binary::new(BinaryType::RefToBinaryHeap, storage_sz),
+      size: (*pointer).size,
+      pointer,
+    };
+    ptr::write(this, new_self);
+    let term = Term::make_boxed((&(*this).bin_header) as *const Binary);
+    hp.register_off_heap(term, (*pointer).size.get_words_rounded_up());
+
+    Ok(this as *mut TBinary)
+  }
+
   pub unsafe fn on_destroy(this: *mut ReferenceToBinary) {
-    if (*this).refc > 0 {
-      (*this).refc -= 1;
-      return;
-    }
+    BinaryHeapBinary::release((*this).pointer);
   }
 }
 
@@ -48,28 +77,27 @@ impl TBinary for ReferenceToBinary {
   }
 
   fn get_byte_reader(&self) -> Option<ByteReader> {
-    unimplemented!()
+    unsafe { (*self.pointer).get_byte_reader() }
   }
 
   unsafe fn get_data_mut(&mut self) -> &mut [u8] {
-    unimplemented!()
+    (*self.pointer).get_data_mut()
   }
 
   unsafe fn get_data(&self) -> &[u8] {
-    unimplemented!()
+    (*self.pointer).get_data()
   }
 
   fn get_bit_reader(&self) -> BitReader {
-    unimplemented!()
+    unsafe { (*self.pointer).get_bit_reader() }
   }
 
-  fn store(&mut self, _data: &[u8]) -> RtResult<()> {
-    // TODO: Maybe should be possible? Assist with resolution into BinaryHeapBinary
-    return Err(RtErr::CannotCopyIntoRefbin);
+  fn store(&mut self, data: &[u8]) -> RtResult<()> {
+    unsafe { (*self.pointer).store(data) }
   }
 
-  fn make_term(&self) -> Term {
-    Term::make_boxed((&self.bin_header) as *const Binary)
+  fn make_term(&self) -> RtResult<Term> {
+    Ok(Term::make_boxed((&self.bin_header) as *const Binary))
   }
 
   unsafe fn put_integer(
@@ -79,7 +107,6 @@ impl TBinary for ReferenceToBinary {
     offset: BitSize,
     flags: crate::beam::opcodes::BsFlags,
   ) -> RtResult<()> {
-    let p = self.pointer as *mut TBinary;
-    (*p).put_integer(val, size, offset, flags)
+    (*self.pointer).put_integer(val, size, offset, flags)
   }
 }
@@ -13,11 +13,12 @@ use crate::{
 use core::ptr;
 
 /// Another type of binary. Refers to a slice in another binary.
+#[repr(C)]
 pub struct BinarySlice {
   pub bin_header: Binary,
   pub offset: BitSize,
   pub size: BitSize,
-  // TODO: Make sure this is recognized as a term during the GC
+  /// Relocated by the GC, see `Binary::inplace_map`
   pub orig: *const TBinary,
 }
 
@@ -108,8 +109,8 @@ impl TBinary for BinarySlice {
     return Err(RtErr::CannotCopyIntoBinSlice);
   }
 
-  fn make_term(&self) -> Term {
-    Term::make_boxed(&self.bin_header)
+  fn make_term(&self) -> RtResult<Term> {
+    Ok(Term::make_boxed(&self.bin_header))
   }
 
   unsafe fn put_integer(
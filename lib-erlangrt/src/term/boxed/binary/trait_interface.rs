@@ -30,7 +30,7 @@ pub trait TBinary {
   /// Write to the binary from position 0
   fn store(&mut self, data: &[u8]) -> RtResult<()>;
 
-  fn make_term(&self) -> Term;
+  fn make_term(&self) -> RtResult<Term>;
 
   // Writing support
   //
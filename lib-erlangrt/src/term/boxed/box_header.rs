@@ -78,6 +78,20 @@ impl BoxHeader {
   #[inline]
   pub const fn ensure_valid(&self) {}
 
+  /// Same check as `ensure_valid` but without panicking, used by the heap
+  /// verifier. Always true in release.
+  #[inline]
+  #[cfg(debug_assertions)]
+  pub fn is_valid(&self) -> bool {
+    self.guard_word == GUARD_WORD_VALUE
+  }
+
+  #[cfg(not(debug_assertions))]
+  #[inline]
+  pub const fn is_valid(&self) -> bool {
+    true
+  }
+
   #[inline]
   pub fn get_trait_ptr(&self) -> *const TBoxed {
     self.ensure_valid();
@@ -29,7 +29,7 @@ pub const BOXTYPETAG_MAP: BoxType = BoxType(100);
 pub const BOXTYPETAG_BINARY: BoxType = BoxType(110);
 pub const BOXTYPETAG_BINARY_MATCH_STATE: BoxType = BoxType(120);
 pub const BOXTYPETAG_JUMP_TABLE: BoxType = BoxType(130);
-// unused 13
+pub const BOXTYPETAG_LOCALREF: BoxType = BoxType(140);
 // unused 14
 // unused 15 => max 15 (1 << BOXTYPE_TAG_BITS)
 
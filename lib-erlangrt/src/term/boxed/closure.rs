@@ -11,7 +11,7 @@ use crate::{
   term::{
     boxed::{
       boxtype::{self, BoxType},
-      trait_interface::TBoxed,
+      trait_interface::{InplaceMapFn, TBoxed},
       BoxHeader, BOXTYPETAG_CLOSURE,
     },
     classify,
@@ -25,7 +25,9 @@ const fn module() -> &'static str {
 }
 
 /// Boxed `Closure` is placed on heap and referred via Term::p
+/// The box header must stay first, `repr(C)` keeps the fields in order.
 #[allow(dead_code)]
+#[repr(C)]
 pub struct Closure {
   pub header: BoxHeader,
 
@@ -45,13 +47,12 @@ impl TBoxed for Closure {
     boxtype::BOXTYPETAG_CLOSURE
   }
 
-  //  fn inplace_map(&mut self, mapfn: &InplaceMapFn) {
-  //    let this_p = self as *mut Closure;
-  //    let frozen = unsafe { (*this_p).get_frozen_mut() };
-  //    for i in 0..frozen.len() {
-  //      frozen[i] = mapfn(this_p as *mut BoxHeader, frozen[i]);
-  //    }
-  //  }
+  fn inplace_map(&mut self, mapfn: &mut InplaceMapFn) {
+    let frozen = unsafe { self.get_frozen_mut() };
+    for val in frozen.iter_mut() {
+      *val = mapfn(*val);
+    }
+  }
 }
 
 impl Closure {
@@ -63,9 +64,8 @@ impl Closure {
   }
 
   fn new(mfa: ModFunArity, nfrozen: usize) -> Self {
-    let storage_size = Self::storage_size(nfrozen) - WordSize::one();
     Self {
-      header: BoxHeader::new::<Self>(storage_size),
+      header: BoxHeader::new::<Self>(Self::storage_size(nfrozen)),
       mfa,
       dst: None,
       nfrozen: nfrozen as Arity,
@@ -119,7 +119,7 @@ impl Closure {
 
   /// Given a closure, find new value for the code pointer and update the
   /// closure. Return: the pointer.
-  pub unsafe fn update_location(&mut self, c_srv: &mut CodeServer) -> RtResult<CodePtr> {
+  pub unsafe fn update_location(&mut self, c_srv: &CodeServer) -> RtResult<CodePtr> {
     let new_dst = c_srv.lookup_beam_code_versioned(&self.mfa)?;
     let ptr = new_dst.ptr;
     self.dst = Some(new_dst);
@@ -14,7 +14,10 @@ use crate::{
 };
 use core::{mem::size_of, ptr};
 
+/// The box header must stay first, `repr(C)` keeps the compiler from moving
+/// the export fields before it.
 #[allow(dead_code)]
+#[repr(C)]
 pub struct Export {
   header: BoxHeader,
   pub exp: export::Export,
@@ -30,8 +30,7 @@ impl TBoxed for Float {
 }
 
 impl Float {
-  #[allow(dead_code)]
-  const fn storage_size() -> WordSize {
+  pub const fn storage_size() -> WordSize {
     ByteSize::new(core::mem::size_of::<Self>()).get_words_rounded_up()
   }
 
@@ -1,7 +1,7 @@
 use crate::{
   defs::{ByteSize, WordSize},
   emulator::{
-    code::pointer::CodePtr, code_srv::CodeServer, heap::heap_trait::THeap,
+    code_srv::CodeServer, heap::heap_trait::THeap,
     mfa::ModFunArity,
   },
   fail::{RtErr, RtResult},
@@ -85,12 +85,6 @@ impl Import {
     )
   }
 
-  /// Lookup a function, referred by this object and possibly attempt code
-  /// loading if the module was missing. Return a code pointer.
-  pub fn resolve(&self, code_server: &mut CodeServer) -> RtResult<CodePtr> {
-    code_server.lookup_beam_code_and_load(&self.mfarity)
-  }
-
   /// Assuming that this object refers to a native function, look it up and
   /// return the function pointer.
   pub fn get_native_fn_ptr(&self, code_srv: &CodeServer) -> Option<NativeFn> {
@@ -1,13 +1,13 @@
 use core::cmp::Ordering;
 
 use crate::{
-  defs::{Word, WordSize},
+  defs::{ByteSize, Word, WordSize},
   emulator::heap::heap_trait::THeap,
   fail::RtResult,
   term::{
     boxed::{
       boxtype::{self, BoxType},
-      trait_interface::TBoxed,
+      trait_interface::{InplaceMapFn, TBoxed},
       BoxHeader,
     },
     classify,
@@ -47,13 +47,31 @@ impl TBoxed for Map {
   fn get_type(&self) -> BoxType {
     boxtype::BOXTYPETAG_MAP
   }
+
+  fn inplace_map(&mut self, mapfn: &mut InplaceMapFn) {
+    let this = self as *mut Map;
+    unsafe {
+      let p = this.add(1) as *mut Term;
+      for i in 0..(2 * self.count) {
+        ptr::write(p.add(i), mapfn(ptr::read(p.add(i))));
+      }
+    }
+  }
 }
 
 impl Map {
   /// Size of a tuple in memory with the header word (used for allocations)
+  /// Size of the map struct in words, without the key/value pairs
+  #[inline]
+  fn self_size() -> WordSize {
+    ByteSize::new(core::mem::size_of::<Self>()).get_words_rounded_up()
+  }
+
+  /// Size of a map in memory with the header and the key/value pairs which
+  /// follow the struct in memory (used for allocations)
   #[inline]
   pub fn storage_size(num_pairs: Word) -> WordSize {
-    WordSize::new(2 * num_pairs) + BoxHeader::storage_size()
+    WordSize::new(2 * num_pairs) + Self::self_size()
   }
 
   /// Capacity is how many extra words been allocated
@@ -68,8 +86,9 @@ impl Map {
   }
 
   /// Returns allocated size used by this map on heap
+  /// How many key/value pairs can fit into the allocated memory
   pub fn get_capacity(&self) -> usize {
-    self.header.get_storage_size()
+    (self.header.get_storage_size() - Self::self_size().words) / 2
   }
 
   /// Returns actual element count, less or equal to the capacity
@@ -115,6 +134,13 @@ impl Map {
     Ok(())
   }
 
+  /// Key and value at `index`, the keys are stored in ascending order.
+  pub unsafe fn get_pair(this: *const Map, index: usize) -> (Term, Term) {
+    debug_assert!(index < (*this).get_count());
+    let p = this.add(1) as *const Term;
+    (ptr::read(p.add(index * 2)), ptr::read(p.add(index * 2 + 1)))
+  }
+
   /// Find key in map
   #[allow(dead_code)]
   pub unsafe fn get(this: *const Map, key: Term) -> RtResult<Option<Term>> {
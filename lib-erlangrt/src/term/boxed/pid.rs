@@ -37,9 +37,8 @@ impl ExternalPid {
   }
 
   fn new(node: Term, id: Word) -> ExternalPid {
-    let storage_size = ExternalPid::storage_size() - WordSize::one();
     ExternalPid {
-      header: BoxHeader::new::<ExternalPid>(storage_size),
+      header: BoxHeader::new::<ExternalPid>(ExternalPid::storage_size()),
       node,
       id,
     }
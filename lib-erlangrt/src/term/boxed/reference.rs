@@ -0,0 +1,83 @@
+use crate::{
+  defs::{ByteSize, WordSize},
+  emulator::heap::heap_trait::THeap,
+  fail::{RtErr, RtResult},
+  term::{
+    boxed::{
+      boxtype::{self, BoxType},
+      trait_interface::TBoxed,
+      BoxHeader, BOXTYPETAG_LOCALREF,
+    },
+    classify,
+    value::*,
+  },
+};
+use core::{mem::size_of, ptr};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Reference id, same layout as in OTP: 3 numbers of 32 bit, the first one
+/// using only the lower 18 bits.
+pub type RefId = [u32; 3];
+
+/// Counter for the unique reference ids, shared by all schedulers.
+static REF_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Create a new reference id, unique for the lifetime of this node.
+pub fn next_ref_id() -> RefId {
+  let n = REF_COUNTER.fetch_add(1, Ordering::Relaxed);
+  [(n & 0x3ffff) as u32, (n >> 18) as u32, (n >> 50) as u32]
+}
+
+/// Represents a local reference box on heap.
+#[allow(dead_code)]
+pub struct Reference {
+  header: BoxHeader,
+  pub id: RefId,
+}
+
+impl TBoxed for Reference {
+  fn get_class(&self) -> classify::TermClass {
+    classify::CLASS_REF
+  }
+
+  fn get_type(&self) -> BoxType {
+    boxtype::BOXTYPETAG_LOCALREF
+  }
+}
+
+impl Reference {
+  #[inline]
+  pub const fn storage_size() -> WordSize {
+    ByteSize::new(size_of::<Self>()).get_words_rounded_up()
+  }
+
+  fn new(id: RefId) -> Self {
+    Self {
+      header: BoxHeader::new::<Self>(Self::storage_size()),
+      id,
+    }
+  }
+
+  pub fn create_into(hp: &mut THeap, id: RefId) -> RtResult<Term> {
+    let this = hp.alloc(Self::storage_size(), false)? as *mut Self;
+    unsafe { ptr::write(this, Self::new(id)) }
+    Ok(Term::make_boxed(this))
+  }
+
+  pub unsafe fn const_from_term(t: Term) -> RtResult<*const Self> {
+    helper_get_const_from_boxed_term::<Self>(
+      t,
+      BOXTYPETAG_LOCALREF,
+      RtErr::BoxedIsNotAReference,
+    )
+  }
+
+  /// Extract the reference id from a term.
+  /// Returns: `None` if the term is not a local reference.
+  pub fn get_id(t: Term) -> Option<RefId> {
+    if !t.is_local_ref() {
+      return None;
+    }
+    unsafe { Self::const_from_term(t).ok().map(|r| (*r).id) }
+  }
+}
@@ -1,11 +1,20 @@
-use crate::term::{boxed::boxtype::BoxType, classify::TermClass};
+use crate::term::{boxed::boxtype::BoxType, classify::TermClass, value::Term};
 
-// pub type InplaceMapFn = FnMut(*mut boxed::BoxHeader, Term) -> Term;
+/// A function which is given every term stored inside a boxed value, and
+/// returns a new (possibly relocated) value to be written back.
+pub type InplaceMapFn<'a> = FnMut(Term) -> Term + 'a;
 
 pub trait TBoxed {
   fn get_class(&self) -> TermClass;
   fn get_type(&self) -> BoxType;
 
-  //  /// For all terms contained in this boxed, run a function and update the data.
-  //  fn inplace_map(&mut self, mapfn: &InplaceMapFn);
+  /// For all terms contained in this boxed, run a function and update the data.
+  /// Used by the garbage collector to relocate pointers. Boxes which contain
+  /// no terms (only raw data) can keep the default implementation.
+  fn inplace_map(&mut self, _mapfn: &mut InplaceMapFn) {}
+
+  /// Called for boxes registered with `THeap::register_off_heap` when they are
+  /// found to be garbage during GC, or when their heap is dropped. Used to
+  /// release resources held outside of the heap (such as refcounted binaries).
+  fn on_destroy(&mut self) {}
 }
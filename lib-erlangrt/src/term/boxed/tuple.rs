@@ -5,7 +5,7 @@ use crate::{
   term::{
     boxed::{
       boxtype::{self, BoxType},
-      trait_interface::TBoxed,
+      trait_interface::{InplaceMapFn, TBoxed},
       BoxHeader,
     },
     classify,
@@ -32,25 +32,22 @@ impl TBoxed for Tuple {
     boxtype::BOXTYPETAG_TUPLE
   }
 
-  //  fn inplace_map(&mut self, mapfn: &InplaceMapFn) {
-  //    let this_p = self as *mut Tuple;
-  //
-  //    unsafe {
-  //      let count = (*this_p).get_arity();
-  //      let data = &mut (*this_p).data0 as *mut Term;
-  //
-  //      for i in 0..count {
-  //        let val = ptr::read(data.add(i));
-  //        ptr::write(data.add(i), mapfn(this_p as *mut BoxHeader, val));
-  //      }
-  //    }
-  //  }
+  fn inplace_map(&mut self, mapfn: &mut InplaceMapFn) {
+    let count = self.get_arity();
+    let data = &mut self.data0 as *mut Term;
+    unsafe {
+      for i in 0..count {
+        let val = ptr::read(data.add(i));
+        ptr::write(data.add(i), mapfn(val));
+      }
+    }
+  }
 }
 
 impl Tuple {
   /// Size of a tuple in memory with the header word (used for allocations)
   #[inline]
-  const fn storage_size(arity: usize) -> WordSize {
+  pub const fn storage_size(arity: usize) -> WordSize {
     // Minus one because data0 in tuple already consumes one word
     let self_size = ByteSize::new(core::mem::size_of::<Self>()).get_words_rounded_up();
     WordSize::new(self_size.words + arity - 1)
@@ -1,8 +1,9 @@
 use core::cmp::Ordering;
 
 use crate::{
-  defs::TDataReader,
-  emulator::atom,
+  big::BigInt,
+  defs::{TDataReader, Word},
+  emulator::{atom, gen_atoms, mfa::ModFunArity},
   fail::RtResult,
   term::{
     boxed::{self, binary::trait_interface::TBinary},
@@ -21,6 +22,8 @@ enum ContinueCompare {
   AnyType { a: Term, b: Term },
   // Resume comparing Cons cells, we just reenter `eq_terms_cons`.
   Cons { a: Term, b: Term },
+  // Resume comparing tuple elements starting from `index`.
+  Tuple { a: Term, b: Term, index: usize },
 }
 
 #[allow(dead_code)]
@@ -47,6 +50,93 @@ pub fn cmp_terms(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
   cmp_terms_1(a, b, exact)
 }
 
+/// Exact equality (`=:=`) of two terms. Unlike `cmp_terms` this does not
+/// order the terms and never fails, terms of different types are not equal.
+/// Used where terms are looked up, such as the process dictionary keys.
+pub fn eq_exact(a: Term, b: Term) -> bool {
+  let mut stack = vec![(a, b)];
+  while let Some((a, b)) = stack.pop() {
+    if a == b {
+      continue;
+    }
+    unsafe {
+      if a.is_cons() && b.is_cons() {
+        let a_ptr = a.get_cons_ptr();
+        let b_ptr = b.get_cons_ptr();
+        stack.push(((*a_ptr).tl(), (*b_ptr).tl()));
+        stack.push(((*a_ptr).hd(), (*b_ptr).hd()));
+      } else if a.is_tuple() && b.is_tuple() {
+        let a_ptr = a.get_tuple_ptr();
+        let b_ptr = b.get_tuple_ptr();
+        let arity = (*a_ptr).get_arity();
+        if arity != (*b_ptr).get_arity() {
+          return false;
+        }
+        for i in 0..arity {
+          stack.push(((*a_ptr).get_element(i), (*b_ptr).get_element(i)));
+        }
+      } else if a.is_map() && b.is_map() {
+        let a_ptr = a.get_box_ptr::<boxed::Map>();
+        let b_ptr = b.get_box_ptr::<boxed::Map>();
+        let count = (*a_ptr).get_count();
+        if count != (*b_ptr).get_count() {
+          return false;
+        }
+        for i in 0..count {
+          let (a_key, a_value) = boxed::Map::get_pair(a_ptr, i);
+          let (b_key, b_value) = boxed::Map::get_pair(b_ptr, i);
+          stack.push((a_key, b_key));
+          stack.push((a_value, b_value));
+        }
+      } else if a.is_fun() && b.is_fun() && !a.is_export() && !b.is_export() {
+        let a_ptr = a.get_box_ptr::<boxed::Closure>();
+        let b_ptr = b.get_box_ptr::<boxed::Closure>();
+        if cmp_mfa(&(*a_ptr).mfa, &(*b_ptr).mfa) != Ordering::Equal
+          || (*a_ptr).nfrozen != (*b_ptr).nfrozen
+        {
+          return false;
+        }
+        let frozen = (*a_ptr).get_frozen().iter();
+        stack.extend(frozen.zip((*b_ptr).get_frozen()).map(|(x, y)| (*x, *y)));
+      } else if !eq_exact_leaf(a, b) {
+        return false;
+      }
+    }
+  }
+  true
+}
+
+/// Exact equality of two terms which are not containers.
+unsafe fn eq_exact_leaf(a: Term, b: Term) -> bool {
+  if a.is_float() && b.is_float() {
+    return a.get_float_unchecked() == b.get_float_unchecked();
+  }
+  if a.is_big_int() && b.is_big_int() {
+    let a_ptr = a.get_box_ptr::<boxed::Bignum>();
+    let b_ptr = b.get_box_ptr::<boxed::Bignum>();
+    return (*a_ptr).is_negative() == (*b_ptr).is_negative()
+      && (*a_ptr).get_digits() == (*b_ptr).get_digits();
+  }
+  if a.is_binary() && b.is_binary() {
+    if a == Term::empty_binary() || b == Term::empty_binary() {
+      return get_binary_bits(a) == get_binary_bits(b);
+    }
+    return matches!(cmp_binary(a, b), Ok(Ordering::Equal));
+  }
+  if a.is_export() && b.is_export() {
+    let a_ptr = a.get_box_ptr::<boxed::Export>();
+    let b_ptr = b.get_box_ptr::<boxed::Export>();
+    return cmp_mfa(&(*a_ptr).exp.mfa, &(*b_ptr).exp.mfa) == Ordering::Equal;
+  }
+  if a.is_local_ref() && b.is_local_ref() {
+    return cmp_local_refs(a, b) == Ordering::Equal;
+  }
+  if a.is_external_pid() && b.is_external_pid() {
+    return cmp_pids(a, b) == Ordering::Equal;
+  }
+  false
+}
+
 #[inline]
 fn cmp_terms_1(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
   // Comparison might want to recurse.
@@ -61,6 +151,9 @@ fn cmp_terms_1(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
     let eq_result = match op {
       ContinueCompare::AnyType { a: a1, b: b1 }
       | ContinueCompare::Cons { a: a1, b: b1 } => cmp_terms_any_type(a1, b1, exact)?,
+      ContinueCompare::Tuple { a: a1, b: b1, index } => unsafe {
+        cmp_tuples(a1, b1, index)
+      },
     };
 
     match eq_result {
@@ -110,15 +203,20 @@ fn cmp_terms_any_type(a: Term, b: Term, exact: bool) -> RtResult<EqResult> {
     return Ok(EqResult::Concluded(a_small.cmp(&b_small)));
   }
 
+  // Integers, at least one of them is a bignum
+  let a_is_integer = a_is_small || a.is_big_int();
+  let b_is_integer = b_is_small || b.is_big_int();
+  if a_is_integer && b_is_integer {
+    return Ok(EqResult::Concluded(cmp_integers(a, b)));
+  }
+
   // Maybe some of a and b are floats
   let a_is_float = a.is_float();
   let b_is_float = b.is_float();
-
-  // If not exact then allow comparing float to int/bigint
-  if !exact && (a_is_float || a_is_small) && (b_is_float || b_is_small) {
-    return Ok(EqResult::Concluded(cmp_numbers_not_exact(a, b)));
-  } else if a_is_float && b_is_float {
+  if a_is_float && b_is_float {
     return Ok(EqResult::Concluded(cmp_floats(a, b)));
+  } else if (a_is_float || a_is_integer) && (b_is_float || b_is_integer) {
+    return Ok(EqResult::Concluded(cmp_numbers(a, b, exact)));
   }
 
   // If types don't compare equal, we can stop comparing here?
@@ -153,8 +251,51 @@ fn cmp_f64_naive(a: f64, b: f64) -> Ordering {
   Ordering::Equal
 }
 
-fn cmp_numbers_not_exact(_a: Term, _b: Term) -> Ordering {
-  unimplemented!("eq_numbers_not_exact")
+/// Compare two integers, small or big, by their values.
+fn cmp_integers(a: Term, b: Term) -> Ordering {
+  match (BigInt::from_term(a), BigInt::from_term(b)) {
+    (Some(a_int), Some(b_int)) => a_int.cmp(&b_int),
+    _ => panic!("cmp_integers: {} and {} must be integers", a, b),
+  }
+}
+
+/// Compare two numbers by their values. If `exact` is set, an integer is
+/// never equal to a float and the float goes first.
+fn cmp_numbers(a: Term, b: Term, exact: bool) -> Ordering {
+  let order = cmp_numbers_not_exact(a, b);
+  if order == Ordering::Equal && exact {
+    return b.is_float().cmp(&a.is_float());
+  }
+  order
+}
+
+fn cmp_numbers_not_exact(a: Term, b: Term) -> Ordering {
+  match (a.is_float(), b.is_float()) {
+    (true, true) => cmp_floats(a, b),
+    (false, false) => cmp_integers(a, b),
+    (true, false) => cmp_integer_float(b, a).reverse(),
+    (false, true) => cmp_integer_float(a, b),
+  }
+}
+
+/// Integers up to this magnitude convert to f64 without rounding.
+const F64_EXACT_INT_MAX: isize = 1 << 53;
+
+/// Compare an integer with a float without losing precision. Like in OTP, a
+/// large integer is not converted to f64, instead the float is converted to
+/// an integer and they are compared exactly.
+fn cmp_integer_float(i: Term, f: Term) -> Ordering {
+  let f = unsafe { f.get_float_unchecked() };
+  if i.is_small() && i.get_small_signed().abs() <= F64_EXACT_INT_MAX {
+    return cmp_f64_naive(i.get_small_signed() as f64, f);
+  }
+  let i_val = match BigInt::from_term(i) {
+    Some(val) => val,
+    None => panic!("cmp_integer_float: {} must be an integer", i),
+  };
+  // A float with a fraction is less than 2^53 in magnitude, it does not
+  // round to `i`, so comparing to its integral part is enough
+  i_val.cmp(&BigInt::from_f64(f))
 }
 
 /// Compare two atoms for equality. Returns the ordering result.
@@ -178,18 +319,17 @@ fn cmp_atoms(a: Term, b: Term) -> Ordering {
   }
 }
 
-/// Compare order of two types without looking into their value.
+/// Compare order of two types without looking into their value. The order
+/// is: number < atom < reference < fun < port < pid < tuple < map < nil <
+/// list < binary.
 fn cmp_type_order(a: Term, b: Term) -> Ordering {
-  if a.is_cons() && b == Term::nil()
-    || a.is_tuple() && b == Term::empty_tuple()
-    || a.is_binary() && b == Term::empty_binary()
-  {
-    return Ordering::Greater;
-  }
-
   let aclass = classify::classify_term(a);
   let bclass = classify::classify_term(b);
-  aclass.cmp(&bclass)
+  if aclass != bclass {
+    return aclass.cmp(&bclass);
+  }
+  // Nil has the class of a list, but goes before other lists
+  (a != Term::nil()).cmp(&(b != Term::nil()))
 }
 
 /// Switch between comparisons for equality by primary tag (immediate or boxes
@@ -202,7 +342,7 @@ fn cmp_terms_primary(a: Term, b: Term, exact: bool) -> RtResult<EqResult> {
   if a_prim_tag != b_prim_tag {
     // different primary types, compare their classes
     // This can be optimized a little but is there any value in optimization?
-    return Ok(EqResult::Concluded(cmp_type_order(a, b)));
+    return Ok(EqResult::Concluded(cmp_mixed_types(a, b, exact)?));
   }
 
   match a_prim_tag {
@@ -210,12 +350,15 @@ fn cmp_terms_primary(a: Term, b: Term, exact: bool) -> RtResult<EqResult> {
       if a.is_cp() || b.is_cp() {
         panic!("eq_terms for CP is unsupported")
       }
-      Ok(Concluded(cmp_terms_immed_box(a, b)?))
+      if a.is_tuple() && b.is_tuple() {
+        return Ok(unsafe { cmp_tuples(a, b, 0) });
+      }
+      Ok(Concluded(cmp_terms_immed_box(a, b, exact)?))
     }
 
     PrimaryTag::CONS_PTR => {
       if !b.is_cons() {
-        return Ok(EqResult::Concluded(cmp_mixed_types(a, b)?));
+        return Ok(EqResult::Concluded(cmp_mixed_types(a, b, exact)?));
       }
 
       Ok(unsafe { cmp_cons(a, b) })
@@ -229,7 +372,7 @@ fn cmp_terms_primary(a: Term, b: Term, exact: bool) -> RtResult<EqResult> {
 }
 
 // TODO: Optimize by doing case on tag bits
-fn cmp_terms_immed(a: Term, b: Term, _exact: bool) -> RtResult<Ordering> {
+fn cmp_terms_immed(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
   if (a == Term::nil() || a == Term::empty_tuple() || a == Term::empty_binary())
     && (a.raw() == b.raw())
   {
@@ -238,31 +381,31 @@ fn cmp_terms_immed(a: Term, b: Term, _exact: bool) -> RtResult<Ordering> {
 
   if a.is_local_port() {
     if b.is_local_port() {
-      unimplemented!("cmp local vs local port")
-    } else if b.is_external_port() {
-      unimplemented!("cmp local vs ext port")
-    } else {
-      return cmp_mixed_types(a, b);
+      // Concluded by comparing raw values
+      return Ok(a.raw().cmp(&b.raw()));
     }
+    return cmp_mixed_types(a, b, exact);
   }
 
   if a.is_local_pid() {
     if b.is_local_pid() {
       // Concluded by comparing raw values
       return Ok(a.raw().cmp(&b.raw()));
-    } else if b.is_external_pid() {
-      unimplemented!("cmp local vs ext pid")
-    } else {
-      return cmp_mixed_types(a, b);
     }
+    return cmp_mixed_types(a, b, exact);
   }
 
   if a.is_boxed() {
-    return cmp_terms_immed_box(a, b);
+    return cmp_terms_immed_box(a, b, exact);
   }
 
   // if both are internal immediates, compare their raw values or their tags
   if a.is_internal_immediate() && b.is_internal_immediate() {
+    // Nil, the empty tuple and the empty binary are of different types
+    let order = cmp_type_order(a, b);
+    if order != Ordering::Equal {
+      return Ok(order);
+    }
     let a_tag = a.get_term_tag();
     let b_tag = b.get_term_tag();
 
@@ -275,149 +418,152 @@ fn cmp_terms_immed(a: Term, b: Term, _exact: bool) -> RtResult<Ordering> {
     return Ok(a_tag.cmp(&b_tag));
   }
 
-  unimplemented!("eq_terms_immed {} {}", a, b)
+  cmp_mixed_types(a, b, exact)
 }
 
 // TODO: Optimize by doing case on tag bits
 #[inline]
-fn cmp_terms_immed_box(a: Term, b: Term) -> RtResult<Ordering> {
-  if a.is_tuple() {
-    if b.is_tuple() {
-      unimplemented!("cmp tuple vs tuple")
-    } else {
-      return cmp_mixed_types(a, b);
-    }
-  } else if a.is_map() {
-    if a.is_flat_map() {
-      if !b.is_flat_map() {
-        if b.is_hash_map() {
-          let b_size = b.map_size();
-          return Ok(a.map_size().cmp(&b_size));
-        }
-      } else {
-        // Compare two flat maps
-        unimplemented!("cmp flatmap vs flatmap (+exact)")
-      }
-    } else if !b.is_hash_map() {
-      if b.is_flat_map() {
-        let b_size = b.map_size();
-        return Ok(a.map_size().cmp(&b_size));
-      }
-    } else {
-      // Compare two hash maps
-      unimplemented!("cmp flatmap vs flatmap (+exact)")
-    }
+fn cmp_terms_immed_box(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
+  if a.is_map() && b.is_map() {
+    return unsafe { cmp_maps(a, b, exact) };
+  }
+  if a.is_float() && b.is_float() {
+    let a_float = a.get_float()?;
+    let b_float = b.get_float()?;
+    return Ok(a_float.partial_cmp(&b_float).unwrap());
+  }
+  if a.is_big_int() && b.is_big_int() {
+    return Ok(cmp_integers(a, b));
+  }
+  if a.is_fun() && b.is_fun() {
+    return unsafe { cmp_funs(a, b, exact) };
+  }
+  if a.is_local_ref() && b.is_local_ref() {
+    return Ok(cmp_local_refs(a, b));
+  }
+  if a.is_external_pid() && (b.is_local_pid() || b.is_external_pid()) {
+    return Ok(cmp_pids(a, b));
+  }
+  if a.is_binary() && b.is_binary() {
+    return unsafe { cmp_binary(a, b) };
+  }
+  cmp_mixed_types(a, b, exact)
+}
 
-  // Hashmap compare strategy:
-  // Phase 1. While keys are identical
-  //    Do synchronous stepping through leafs of both trees in hash
-  //    order. Maintain value compare result of minimal key.
-  //
-  // Phase 2. If key diff was found in phase 1
-  //    Ignore values from now on.
-  //    Continue iterate trees by always advancing the one
-  //    lagging behind hash-wise. Identical keys are skipped.
-  //    A minimal key can only be candidate as tie-breaker if we
-  //    have passed that hash value in the other tree (which means
-  //    the key did not exist in the other tree).
-  } else if a.is_float() {
-    if !b.is_float() {
-      // TODO: If b is integer and we don't do exact comparison?
-      return cmp_mixed_types(a, b);
-    } else {
-      let a_float = a.get_float()?;
-      let b_float = b.get_float()?;
-      return Ok(a_float.partial_cmp(&b_float).unwrap());
-    }
-  } else if a.is_big_int() {
-    if !b.is_big_int() {
-      return cmp_mixed_types(a, b);
-    }
-  } else if a.is_export() {
-    if !b.is_export() {
-      return cmp_mixed_types(a, b);
-    }
-    // Compare two exports: from utils.c line ~2918
-    // cmp atoms a.module and b.module
-    // cmp atoms a.fn and b.fn
-    // cmp arity
-    unimplemented!("compare 2 exports")
-  } else if a.is_boxed() {
-    if a.is_binary() {
-      if b.is_binary() {
-        return unsafe { cmp_binary(a, b) };
-      }
-    }
-    if !a.is_fun() {
-      return cmp_mixed_types(a, b);
-    }
-    // Compare 2 function objects: from utils.c line ~2937
-    // compare a.module, b.module
-    // compare old_index
-    // compare old_uniq
-    // compare num_Free
-    unimplemented!("compare 2 fun objects")
-  } else if a.is_external_pid() {
-    if b.is_local_pid() {
-      unimplemented!("compare ext vs local pid")
-    } else if b.is_external_pid() {
-      unimplemented!("compare ext vs ext pid")
-    } else {
-      return cmp_mixed_types(a, b);
+/// Compare two tuples, first by arity and then element by element starting
+/// from `index`. Like `cmp_cons`, when a pair of elements is not the same
+/// term, returns `EqResult::CompareNested` to compare them and then to
+/// continue with the next index.
+unsafe fn cmp_tuples(a: Term, b: Term, index: usize) -> EqResult {
+  let a_ptr = a.get_tuple_ptr();
+  let b_ptr = b.get_tuple_ptr();
+  let arity = (*a_ptr).get_arity();
+  if index == 0 {
+    let b_arity = (*b_ptr).get_arity();
+    if arity != b_arity {
+      return EqResult::Concluded(arity.cmp(&b_arity));
     }
-  } else if a.is_external_port() {
-    if b.is_local_port() {
-      unimplemented!("compare ext vs local port")
-    } else if b.is_external_port() {
-      unimplemented!("compare ext vs ext port")
-    } else {
-      return cmp_mixed_types(a, b);
+  }
+  for i in index..arity {
+    let a_elem = (*a_ptr).get_element(i);
+    let b_elem = (*b_ptr).get_element(i);
+    if !Term::is_same(a_elem, b_elem) {
+      return EqResult::CompareNested {
+        a: a_elem,
+        b: b_elem,
+        state: ContinueCompare::Tuple { a, b, index: i + 1 },
+      };
     }
-  } else if a.is_local_ref() {
-    if b.is_local_ref() {
-      unimplemented!("compare local vs local ref")
-    } else if b.is_external_ref() {
-      unimplemented!("compare local vs ext ref")
-    } else {
-      return cmp_mixed_types(a, b);
+  }
+  EqResult::Concluded(Ordering::Equal)
+}
+
+/// Compare two maps by size, then their keys in ascending order, then the
+/// values in the order of their keys. Keys are always compared exactly.
+unsafe fn cmp_maps(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
+  let a_ptr = a.get_box_ptr::<boxed::Map>();
+  let b_ptr = b.get_box_ptr::<boxed::Map>();
+  let count = (*a_ptr).get_count();
+  let order = count.cmp(&(*b_ptr).get_count());
+  if order != Ordering::Equal {
+    return Ok(order);
+  }
+  for i in 0..count {
+    let a_key = boxed::Map::get_pair(a_ptr, i).0;
+    let order = cmp_terms(a_key, boxed::Map::get_pair(b_ptr, i).0, true)?;
+    if order != Ordering::Equal {
+      return Ok(order);
     }
-  } else if a.is_external_ref() {
-    if b.is_local_ref() {
-      unimplemented!("compare ext vs local ref")
-    } else if b.is_external_ref() {
-      unimplemented!("compare ext vs ext ref")
-    } else {
-      return cmp_mixed_types(a, b);
+  }
+  for i in 0..count {
+    let a_value = boxed::Map::get_pair(a_ptr, i).1;
+    let order = cmp_terms(a_value, boxed::Map::get_pair(b_ptr, i).1, exact)?;
+    if order != Ordering::Equal {
+      return Ok(order);
     }
-  } else {
-    // must be a binary
-    assert!(a.is_binary());
-    if !b.is_binary() {
-      return cmp_mixed_types(a, b);
+  }
+  Ok(Ordering::Equal)
+}
+
+/// Compare two funs. Exports go before closures, exports are compared by
+/// module, function name and arity. Closures are compared the same way by
+/// their function, then by the count and the values of their frozen terms.
+unsafe fn cmp_funs(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
+  if a.is_export() != b.is_export() {
+    return Ok(b.is_export().cmp(&a.is_export()));
+  }
+  if a.is_export() {
+    let a_mfa = &(*boxed::Export::const_from_term(a)?).exp.mfa;
+    let b_mfa = &(*boxed::Export::const_from_term(b)?).exp.mfa;
+    return Ok(cmp_mfa(a_mfa, b_mfa));
+  }
+  let a_ptr = boxed::Closure::const_from_term(a)?;
+  let b_ptr = boxed::Closure::const_from_term(b)?;
+  let order = cmp_mfa(&(*a_ptr).mfa, &(*b_ptr).mfa)
+    .then((*a_ptr).nfrozen.cmp(&(*b_ptr).nfrozen));
+  if order != Ordering::Equal {
+    return Ok(order);
+  }
+  for (a_val, b_val) in (*a_ptr).get_frozen().iter().zip((*b_ptr).get_frozen()) {
+    let order = cmp_terms(*a_val, *b_val, exact)?;
+    if order != Ordering::Equal {
+      return Ok(order);
     }
-    unimplemented!("cmp binaries")
   }
-  unimplemented!("eq_terms_immed_box {} {}", a, b)
+  Ok(Ordering::Equal)
+}
+
+fn cmp_mfa(a: &ModFunArity, b: &ModFunArity) -> Ordering {
+  cmp_atoms(a.m, b.m)
+    .then_with(|| cmp_atoms(a.f, b.f))
+    .then(a.arity.cmp(&b.arity))
+}
+
+/// Compare two pids by the node name first, then by the process index. Local
+/// pids are on the node `nonode@nohost`.
+fn cmp_pids(a: Term, b: Term) -> Ordering {
+  let (a_node, a_index) = get_pid_node_and_index(a);
+  let (b_node, b_index) = get_pid_node_and_index(b);
+  if a_node != b_node {
+    return cmp_atoms(a_node, b_node);
+  }
+  a_index.cmp(&b_index)
 }
 
+fn get_pid_node_and_index(pid: Term) -> (Term, Word) {
+  if pid.is_local_pid() {
+    return (gen_atoms::NONODE_NOHOST, pid.get_term_val_without_tag());
+  }
+  let pid_ptr = pid.get_box_ptr::<boxed::ExternalPid>();
+  unsafe { ((*pid_ptr).node, (*pid_ptr).id) }
+}
+
+/// Binaries are compared bit by bit, when one binary is the beginning of the
+/// other, the shorter one is less.
 #[inline]
 unsafe fn cmp_binary(a: Term, b: Term) -> RtResult<Ordering> {
   let a_trait = boxed::Binary::get_trait_from_term(a);
   let b_trait = boxed::Binary::get_trait_from_term(b);
-  let a_size = (*a_trait).get_bit_size();
-  let b_size = (*b_trait).get_bit_size();
-  if a_size != b_size {
-    return Ok(a_size.cmp(&b_size));
-  }
-
-  println!("Going to compare {} {}", a, b);
-  println!(
-    "sizes - {} {} vs {} {}",
-    a_size,
-    (*a_trait).get_byte_size(),
-    b_size,
-    (*b_trait).get_byte_size()
-  );
 
   // Try figure out a compatible byte- or bit-reader combination for A arg and
   // B arg and then call a branch function which will do the same for B.
@@ -463,21 +609,16 @@ where
   AReader: TDataReader,
   BReader: TDataReader,
 {
-  //  assert_eq!(
-  //    a_reader.get_bit_size(),
-  //    b_reader.get_bit_size(),
-  //    "Comparing 2 binaries with different bit sizes {} ({}) vs {} ({})",
-  //    a,
-  //    a_reader.get_bit_size(),
-  //    b,
-  //    b_reader.get_bit_size()
-  //  );
-  let size = a_reader.get_bit_size();
-
-  // Use rounded up byte-size, and then because the bit-sizes must be equal,
-  // the last byte bits will have same size
-  let n_bytes = size.get_byte_size_rounded_up().bytes();
+  let a_size = a_reader.get_bit_size();
+  let b_size = b_reader.get_bit_size();
+  let common_size = if a_size.bits < b_size.bits {
+    a_size
+  } else {
+    b_size
+  };
 
+  // Compare whole bytes which both binaries have
+  let n_bytes = common_size.get_bytes_rounded_down();
   for i in 0..n_bytes {
     let a_byte = a_reader.read(i);
     let b_byte = b_reader.read(i);
@@ -485,13 +626,67 @@ where
       return Ok(a_byte.cmp(&b_byte));
     }
   }
-  // No differences we've been able to find
-  return Ok(Ordering::Equal);
+  // Then the bits of the last incomplete byte, they go from the top
+  let last_bits = common_size.get_last_byte_bits();
+  if last_bits != 0 {
+    let mask = !(0xffu8 >> last_bits);
+    let a_byte = a_reader.read(n_bytes) & mask;
+    let b_byte = b_reader.read(n_bytes) & mask;
+    if a_byte != b_byte {
+      return Ok(a_byte.cmp(&b_byte));
+    }
+  }
+  // No differences we've been able to find, the longer one is greater
+  Ok(a_size.bits.cmp(&b_size.bits))
+}
+
+/// Compare two local references, like OTP starting from the most significant
+/// id word.
+fn cmp_local_refs(a: Term, b: Term) -> Ordering {
+  let a_id = boxed::Reference::get_id(a).unwrap();
+  let b_id = boxed::Reference::get_id(b).unwrap();
+  a_id.iter().rev().cmp(b_id.iter().rev())
+}
+
+/// Deeper comparison of two values with different types, or with different
+/// representations of the same type, such as the empty tuple (an immediate)
+/// and a boxed tuple.
+fn cmp_mixed_types(a: Term, b: Term, exact: bool) -> RtResult<Ordering> {
+  let order = cmp_type_order(a, b);
+  if order != Ordering::Equal {
+    return Ok(order);
+  }
+  // Same class but different types
+  let order = match classify::classify_term(a) {
+    classify::CLASS_NUMBER => cmp_numbers(a, b, exact),
+    classify::CLASS_TUPLE => get_tuple_arity(a).cmp(&get_tuple_arity(b)),
+    classify::CLASS_BINARY => get_binary_bits(a).cmp(&get_binary_bits(b)),
+    classify::CLASS_FUN => b.is_export().cmp(&a.is_export()),
+    classify::CLASS_PID => cmp_pids(a, b),
+    classify::CLASS_MAP => a.map_size().cmp(&b.map_size()),
+    // References and ports of other nodes go after the local ones
+    _ => is_external(a).cmp(&is_external(b)),
+  };
+  Ok(order)
+}
+
+#[inline]
+fn is_external(t: Term) -> bool {
+  t.is_external_pid() || t.is_external_port() || t.is_external_ref()
+}
+
+fn get_tuple_arity(t: Term) -> usize {
+  if t == Term::empty_tuple() {
+    return 0;
+  }
+  unsafe { (*t.get_tuple_ptr()).get_arity() }
 }
 
-/// Deeper comparison of two values with different types
-fn cmp_mixed_types(a: Term, b: Term) -> RtResult<Ordering> {
-  unimplemented!("cmp_mixed_types {} vs {}", a, b)
+fn get_binary_bits(t: Term) -> usize {
+  if t == Term::empty_binary() {
+    return 0;
+  }
+  unsafe { (*boxed::Binary::get_trait_from_term(t)).get_bit_size().bits }
 }
 
 /// Compare two cons (list) cells.
@@ -553,3 +748,187 @@ unsafe fn cmp_cons(a: Term, b: Term) -> EqResult {
 //  // TODO: see if cmp_terms_immed_box can be useful
 //  unimplemented!("eq_terms_box")
 //}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    emulator::heap::{Designation, Heap},
+    term::{
+      boxed::reference::RefId,
+      term_builder::{list_builder::build_list_from_slice, tuple_builder::tuple2},
+    },
+  };
+
+  fn make_binary(hp: &mut Heap, data: &[u8]) -> Term {
+    unsafe { (*boxed::Binary::create_with_data(data, hp).unwrap()).make_term() }.unwrap()
+  }
+
+  #[test]
+  fn test_cmp_local_refs() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let mut make_ref = |id: RefId| boxed::Reference::create_into(&mut hp, id).unwrap();
+    let a = make_ref([5, 0, 0]);
+    let a_copy = make_ref([5, 0, 0]);
+    let b = make_ref([1, 1, 0]);
+
+    assert_eq!(cmp_terms(a, a_copy, true).unwrap(), Ordering::Equal);
+    // The most significant id word decides
+    assert_eq!(cmp_terms(a, b, true).unwrap(), Ordering::Less);
+    // Any reference is greater than a number and less than a pid
+    let one = Term::make_small_signed(1);
+    assert_eq!(cmp_terms(a, one, true).unwrap(), Ordering::Greater);
+    assert_eq!(cmp_terms(a, Term::make_local_pid(1), true).unwrap(), Ordering::Less);
+  }
+
+  #[test]
+  fn test_term_order() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let one = Term::make_small_signed(1);
+    let two = Term::make_small_signed(2);
+    let reference = boxed::Reference::create_into(&mut hp, [1, 0, 0]).unwrap();
+    let mfa = ModFunArity::new(gen_atoms::ERLANG, gen_atoms::OK, 0);
+    let export = unsafe { boxed::Export::create_into(&mut hp, &mfa) }.unwrap();
+    let map = Term::make_boxed(boxed::Map::create_into(&mut hp, 0).unwrap());
+    let tuple = tuple2(&mut hp, one, two).unwrap();
+    let list = unsafe { build_list_from_slice(&[one], &mut hp) }.unwrap();
+    let binary = make_binary(&mut hp, &[1]);
+
+    // number < atom < reference < fun < port < pid < tuple < map < nil <
+    // list < binary
+    let ordered = [
+      one,
+      gen_atoms::OK,
+      reference,
+      export,
+      Term::make_local_pid(1),
+      Term::empty_tuple(),
+      tuple,
+      map,
+      Term::nil(),
+      list,
+      Term::empty_binary(),
+      binary,
+    ];
+    for (i, a) in ordered.iter().enumerate() {
+      for (j, b) in ordered.iter().enumerate() {
+        let order = cmp_terms(*a, *b, true).unwrap();
+        assert_eq!(order, i.cmp(&j), "comparing {} and {}", a, b);
+      }
+    }
+  }
+
+  #[test]
+  fn test_cmp_tuples_and_binaries() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let one = Term::make_small_signed(1);
+    let a = tuple2(&mut hp, one, gen_atoms::OK).unwrap();
+    let b = tuple2(&mut hp, one, gen_atoms::ERLANG).unwrap();
+    let b_copy = tuple2(&mut hp, one, gen_atoms::ERLANG).unwrap();
+    // Same arity, the elements decide
+    let order = cmp_atoms(gen_atoms::OK, gen_atoms::ERLANG);
+    assert_eq!(cmp_terms(a, b, true).unwrap(), order);
+    assert_eq!(cmp_terms(b, b_copy, true).unwrap(), Ordering::Equal);
+    // Equal nested tuples, then the next element decides
+    let nested_b = tuple2(&mut hp, b, Term::make_small_signed(2)).unwrap();
+    let nested_b_copy = tuple2(&mut hp, b_copy, one).unwrap();
+    assert_eq!(cmp_terms(nested_b, nested_b_copy, true).unwrap(), Ordering::Greater);
+
+    // Bytes first, then the size
+    let bin12 = make_binary(&mut hp, &[1, 2]);
+    let bin2 = make_binary(&mut hp, &[2]);
+    let bin1 = make_binary(&mut hp, &[1]);
+    assert_eq!(cmp_terms(bin12, bin2, true).unwrap(), Ordering::Less);
+    assert_eq!(cmp_terms(bin1, bin12, true).unwrap(), Ordering::Less);
+    let bin1_copy = make_binary(&mut hp, &[1]);
+    assert_eq!(cmp_terms(bin1, bin1_copy, true).unwrap(), Ordering::Equal);
+  }
+
+  #[test]
+  fn test_cmp_integers() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let mut make_big = |negative: bool, digits: &[Word]| {
+      let val = BigInt {
+        negative,
+        digits: digits.to_vec(),
+      };
+      val.to_term(&mut hp).unwrap()
+    };
+    let ordered = [
+      make_big(true, &[1, 1]),
+      make_big(true, &[0, 1]),
+      Term::make_small_signed(-5),
+      Term::make_small_signed(5),
+      make_big(false, &[0, 1]),
+      make_big(false, &[1, 1]),
+      make_big(false, &[0, 0, 1]),
+    ];
+    for (i, a) in ordered.iter().enumerate() {
+      for (j, b) in ordered.iter().enumerate() {
+        let order = cmp_terms(*a, *b, true).unwrap();
+        assert_eq!(order, i.cmp(&j), "comparing {} and {}", a, b);
+      }
+    }
+    let big_copy = make_big(false, &[1, 1]);
+    assert_eq!(cmp_terms(ordered[5], big_copy, true).unwrap(), Ordering::Equal);
+  }
+
+  #[test]
+  fn test_cmp_integers_and_floats() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let one = Term::make_small_signed(1);
+    let one_float = Term::make_float(&mut hp, 1.0).unwrap();
+    assert_eq!(cmp_terms(one, one_float, false).unwrap(), Ordering::Equal);
+    assert_ne!(cmp_terms(one, one_float, true).unwrap(), Ordering::Equal);
+    assert_ne!(cmp_terms(one_float, one, true).unwrap(), Ordering::Equal);
+
+    let big = BigInt {
+      negative: false,
+      digits: vec![0, 1],
+    };
+    let ordered = [
+      Term::make_float(&mut hp, -2.5).unwrap(),
+      Term::make_small_signed(-2),
+      Term::make_float(&mut hp, 1.5).unwrap(),
+      Term::make_small_signed(2),
+      Term::make_float(&mut hp, 1.0e19).unwrap(),
+      big.to_term(&mut hp).unwrap(),
+      Term::make_float(&mut hp, 1.0e20).unwrap(),
+    ];
+    for (i, a) in ordered.iter().enumerate() {
+      for (j, b) in ordered.iter().enumerate() {
+        for exact in [false, true] {
+          let order = cmp_terms(*a, *b, exact).unwrap();
+          assert_eq!(order, i.cmp(&j), "comparing {} and {}", a, b);
+        }
+      }
+    }
+  }
+
+  /// Integers which f64 can not represent are compared with floats exactly.
+  #[test]
+  fn test_cmp_integers_and_floats_beyond_f64_precision() {
+    let mut hp = Heap::new(Designation::ProcessHeap);
+    let pow53_float = Term::make_float(&mut hp, 9007199254740992.0).unwrap();
+    let pow53_plus_1 = Term::make_small_signed((1 << 53) + 1);
+    assert_eq!(cmp_terms(pow53_plus_1, pow53_float, false).unwrap(), Ordering::Greater);
+    assert_eq!(cmp_terms(pow53_float, pow53_plus_1, false).unwrap(), Ordering::Less);
+
+    let mut make_big = |negative: bool, digits: &[Word]| {
+      let val = BigInt {
+        negative,
+        digits: digits.to_vec(),
+      };
+      val.to_term(&mut hp).unwrap()
+    };
+    let pow64 = make_big(false, &[0, 1]);
+    let pow64_plus_1 = make_big(false, &[1, 1]);
+    let minus_pow64_plus_1 = make_big(true, &[1, 1]);
+    let pow64_float = Term::make_float(&mut hp, 18446744073709551616.0).unwrap();
+    let minus_pow64_float = Term::make_float(&mut hp, -18446744073709551616.0).unwrap();
+    assert_eq!(cmp_terms(pow64, pow64_float, false).unwrap(), Ordering::Equal);
+    assert_eq!(cmp_terms(pow64_plus_1, pow64_float, false).unwrap(), Ordering::Greater);
+    let order = cmp_terms(minus_pow64_plus_1, minus_pow64_float, false).unwrap();
+    assert_eq!(order, Ordering::Less);
+  }
+}
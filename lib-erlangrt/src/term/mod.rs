@@ -5,7 +5,6 @@
 //!     pointers to heap
 //!
 //! As well as operations on terms, such as arithmetic or comparisons.
-pub mod arith; // simple operations on numeric terms
 pub mod boxed;
 pub mod builders; // simple term builder helpers
 pub mod classify; // term ordering (for comparisons)
@@ -41,7 +41,7 @@ impl BinaryBuilder {
     self.write_pos = self.write_pos.add(1);
   }
 
-  pub fn make_term(self) -> Term {
+  pub fn make_term(self) -> RtResult<Term> {
     unsafe { (*self.p).make_term() }
   }
 }
@@ -95,3 +95,15 @@ pub unsafe fn build_erlstr_from_utf8(s: &str, hp: &mut THeap) -> RtResult<Term>
   }
   Ok(lb.make_term())
 }
+
+/// A helper which creates a proper list of `items`, in the same order.
+pub unsafe fn build_list_from_slice(items: &[Term], hp: &mut THeap) -> RtResult<Term> {
+  if items.is_empty() {
+    return Ok(Term::nil());
+  }
+  let mut lb = ListBuilder::new()?;
+  for item in items {
+    lb.append(*item, hp)?;
+  }
+  Ok(lb.make_term())
+}
@@ -1,8 +1,9 @@
 //! Utility functions for handling lists
 use crate::{
+  big::BigInt,
   defs::{exc_type::ExceptionType, sizes::ByteSize},
   emulator::{gen_atoms, heap::heap_trait::THeap},
-  fail::{RtErr, RtResult},
+  fail::{self, RtErr, RtResult},
   term::{boxed, term_builder::ListBuilder, value::Term},
 };
 
@@ -156,42 +157,16 @@ pub unsafe fn rust_str_to_list(s: &String, hp: &mut THeap) -> RtResult<Term> {
   Ok(lb.make_term())
 }
 
-/// Given an integer Term, convert it to a string with `base`.
-pub unsafe fn integer_to_list(val: Term, hp: &mut THeap) -> RtResult<Term> {
-  if val.is_big_int() {
-    panic!("TODO: impl integer_to_list for bigint");
+/// Given an integer Term, convert it to a string with `base` (2 to 36).
+pub unsafe fn integer_to_list(val: Term, base: u32, hp: &mut THeap) -> RtResult<Term> {
+  // Base 10 smalls are formatted directly without making a bignum
+  if val.is_small() && base == 10 {
+    return rust_str_to_list(&val.get_small_signed().to_string(), hp);
+  }
+  match BigInt::from_term(val) {
+    Some(big) => rust_str_to_list(&big.to_str_radix(base), hp),
+    None => fail::create::badarg(),
   }
-
-  let base = 10isize;
-  let mut i_val = val.get_small_signed();
-  let mut lb = ListBuilder::new()?;
-
-  let sign = if i_val < 0 {
-    i_val = -i_val;
-    true
-  } else {
-    false
-  };
-
-  if i_val == 0 {
-    lb.append(Term::make_char('0'), hp)?;
-  } else {
-    loop {
-      let digit = '0' as usize + (i_val % base) as usize;
-      let digit_term = Term::make_small_unsigned(digit);
-      if i_val == 0 {
-        break;
-      }
-      lb.prepend(digit_term, hp)?;
-      i_val /= base;
-    } // loop
-
-    if sign {
-      lb.prepend(Term::make_char('-'), hp)?;
-    }
-  } // if not 0
-
-  return Ok(lb.make_term());
 }
 
 pub fn get_iolist_size(list: Term) -> ByteSize {
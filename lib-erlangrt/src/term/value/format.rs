@@ -108,6 +108,11 @@ unsafe fn format_box_contents(
     boxtype::BOXTYPETAG_EXTERNALPID => write!(f, "ExtPid<>"),
     boxtype::BOXTYPETAG_EXTERNALPORT => write!(f, "ExtPort<>"),
     boxtype::BOXTYPETAG_EXTERNALREF => write!(f, "ExtRef<>"),
+    boxtype::BOXTYPETAG_LOCALREF => {
+      let rptr = trait_ptr as *const boxed::Reference;
+      let id = (*rptr).id;
+      write!(f, "#Ref<0.{}.{}.{}>", id[2], id[1], id[0])
+    }
     boxtype::BOXTYPETAG_IMPORT => {
       let iptr = trait_ptr as *const boxed::Import;
       write!(f, "#Import<{}>", (*iptr).mfarity)
@@ -30,8 +30,10 @@ impl Term {
     if !self.is_boxed() {
       return Err(RtErr::TermIsNotABoxed);
     }
-    let _p = self.get_box_ptr::<boxed::BoxHeader>();
-    unimplemented!("float box")
+    if !self.is_float() {
+      return Err(RtErr::BoxedIsNotAFloat);
+    }
+    Ok(unsafe { self.get_float_unchecked() })
   }
 
   /// Returns float value, performs no extra checks. The caller is responsible
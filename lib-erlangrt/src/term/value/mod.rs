@@ -210,7 +210,7 @@ impl Term {
   }
 
   pub fn is_local_ref(self) -> bool {
-    false
+    self.is_boxed_of_type(boxed::BOXTYPETAG_LOCALREF)
   }
 
   pub fn is_external_ref(self) -> bool {